@@ -0,0 +1,147 @@
+//! Authentication.
+//!
+//! Servers with authentication need credentials in the `authorization`
+//! header of every call.  The client sends its credentials with the
+//! handshake, and if the server issues a token in the handshake response,
+//! later calls send the token instead of the credentials.
+
+use std::sync::{Arc, RwLock};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tonic::metadata::{AsciiMetadataValue, MetadataMap};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::error::{DbClientError, Result};
+
+/// Request and response header carrying credentials
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// The credentials a client authenticates with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    /// A static token of the server
+    Bearer(String),
+    /// A user of the server, which is issued a token by the handshake
+    Basic { username: String, password: String },
+}
+
+impl Credentials {
+    /// The `authorization` header value of the credentials
+    pub fn authorization(&self) -> Result<AsciiMetadataValue> {
+        let value = match self {
+            Credentials::Bearer(token) => format!("Bearer {token}"),
+            Credentials::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{username}:{password}"))
+                )
+            }
+        };
+
+        value
+            .parse()
+            .map_err(|_| DbClientError::CreateClient("Invalid credentials".into()))
+    }
+}
+
+/// The token a handshake response issued, as an `authorization` header value
+pub fn issued_token(metadata: &MetadataMap) -> Option<AsciiMetadataValue> {
+    metadata.get(AUTHORIZATION_HEADER).cloned()
+}
+
+/// A tonic interceptor that sends the `authorization` header with every
+/// call.
+///
+/// Clones share the header, so the token issued by the handshake replaces the
+/// credentials of every clone.
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    authorization: Arc<RwLock<Option<AsciiMetadataValue>>>,
+}
+
+impl AuthInterceptor {
+    pub fn new(authorization: Option<AsciiMetadataValue>) -> Self {
+        Self {
+            authorization: Arc::new(RwLock::new(authorization)),
+        }
+    }
+
+    /// Send an `authorization` header value with later calls
+    pub fn set_authorization(&self, authorization: AsciiMetadataValue) {
+        *self
+            .authorization
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(authorization);
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        let authorization = self.authorization.read().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(authorization) = authorization.as_ref() {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_HEADER, authorization.to_owned());
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn authorization(interceptor: &mut AuthInterceptor) -> Option<AsciiMetadataValue> {
+        let request = interceptor.call(Request::new(())).unwrap();
+        request.metadata().get(AUTHORIZATION_HEADER).cloned()
+    }
+
+    #[test]
+    fn test_credentials_authorization() {
+        let bearer = Credentials::Bearer("secret".into());
+        assert_eq!(bearer.authorization().unwrap(), "Bearer secret");
+
+        let basic = Credentials::Basic {
+            username: "alice".into(),
+            password: "password".into(),
+        };
+        assert_eq!(basic.authorization().unwrap(), "Basic YWxpY2U6cGFzc3dvcmQ=");
+
+        assert!(Credentials::Bearer("new\nline".into())
+            .authorization()
+            .is_err());
+    }
+
+    #[test]
+    fn test_auth_interceptor() {
+        let mut interceptor = AuthInterceptor::default();
+        assert_eq!(authorization(&mut interceptor), None);
+
+        let credentials = Credentials::Basic {
+            username: "alice".into(),
+            password: "password".into(),
+        };
+        let mut interceptor = AuthInterceptor::new(Some(credentials.authorization().unwrap()));
+        let mut clone = interceptor.clone();
+        assert_eq!(
+            authorization(&mut clone),
+            Some(credentials.authorization().unwrap())
+        );
+
+        // the token issued by the handshake replaces the credentials
+        let mut metadata = MetadataMap::new();
+        metadata.insert(AUTHORIZATION_HEADER, "Bearer token".parse().unwrap());
+        interceptor.set_authorization(issued_token(&metadata).unwrap());
+        assert_eq!(
+            authorization(&mut clone),
+            Some("Bearer token".parse().unwrap())
+        );
+        assert_eq!(
+            authorization(&mut interceptor),
+            Some("Bearer token".parse().unwrap())
+        );
+    }
+}
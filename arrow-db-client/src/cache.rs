@@ -0,0 +1,90 @@
+//! Client-side schema cache.
+//!
+//! The server attaches a schema version to its responses.  Schemas are cached
+//! until a response reports a different version, at which point every cached
+//! schema is considered stale and is dropped.
+
+use std::collections::HashMap;
+
+use datafusion::arrow::datatypes::Schema;
+use tonic::metadata::MetadataMap;
+
+pub use arrow_db_core::protocol::SCHEMA_VERSION_HEADER;
+
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    version: Option<u64>,
+    schemas: HashMap<String, Schema>,
+}
+
+impl SchemaCache {
+    /// Get a cached schema
+    pub fn get(&self, key: &str) -> Option<&Schema> {
+        self.schemas.get(key)
+    }
+
+    /// Cache a schema that was fetched at the given version
+    pub fn insert(&mut self, key: &str, version: Option<u64>, schema: Schema) {
+        self.observe_version(version);
+        self.schemas.insert(key.into(), schema);
+    }
+
+    /// Record the version reported by the server, dropping all cached schemas
+    /// if it differs from the version they were fetched at.
+    pub fn observe_version(&mut self, version: Option<u64>) {
+        if version.is_none() || self.version == version {
+            return;
+        }
+
+        self.schemas.clear();
+        self.version = version;
+    }
+
+    /// Drop all cached schemas
+    pub fn invalidate(&mut self) {
+        self.schemas.clear();
+        self.version = None;
+    }
+
+    /// The schema version of the cached schemas
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+}
+
+/// Read the schema version from response metadata
+pub fn schema_version(metadata: &MetadataMap) -> Option<u64> {
+    metadata
+        .get(SCHEMA_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use datafusion::arrow::datatypes::{DataType, Field};
+
+    use super::*;
+
+    #[test]
+    fn test_schema_cache_invalidation() {
+        let schema = Schema::new(vec![Field::new("id", DataType::Int32, true)]);
+        let mut cache = SchemaCache::default();
+
+        cache.insert("users", Some(1), schema.clone());
+        assert_eq!(cache.get("users"), Some(&schema));
+
+        // the same version keeps the cache
+        cache.observe_version(Some(1));
+        assert_eq!(cache.get("users"), Some(&schema));
+
+        // a missing version is ignored
+        cache.observe_version(None);
+        assert_eq!(cache.get("users"), Some(&schema));
+
+        // a new version clears the cache
+        cache.observe_version(Some(2));
+        assert_eq!(cache.get("users"), None);
+        assert_eq!(cache.version(), Some(2));
+    }
+}
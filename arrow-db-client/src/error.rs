@@ -8,9 +8,15 @@ pub enum DbClientError {
     #[error("Error creating Client: {0}")]
     CreateClient(String),
 
+    #[error("Error negotiating the protocol: {0}")]
+    Handshake(String),
+
     #[error("Error executing query: {0}")]
     Query(String),
 
     #[error("Error getting schema: {0}")]
     Schema(String),
+
+    #[error("Error configuring TLS: {0}")]
+    Tls(String),
 }
@@ -1,103 +1,373 @@
+pub mod auth;
+pub mod cache;
 pub mod error;
+pub mod protocol;
+pub mod tls;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use arrow::array::RecordBatch;
-use arrow_flight::flight_descriptor::DescriptorType;
 use datafusion::arrow::datatypes::Schema;
 // use arrow_flight::flight_descriptor;
 use arrow_flight::flight_service_client::FlightServiceClient;
 use arrow_flight::utils::flight_data_to_arrow_batch;
-use arrow_flight::{FlightDescriptor, Ticket};
-use tonic::codegen::StdError;
-use tonic::transport::Channel;
+use arrow_flight::{FlightData, FlightDescriptor, HandshakeRequest, Ticket};
+use datafusion::sql::parser::DFParser;
+use tonic::codegen::{InterceptedService, StdError};
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Code;
 
+use crate::auth::{issued_token, AuthInterceptor, Credentials};
+use crate::cache::{schema_version, SchemaCache};
 use crate::error::{DbClientError, Result};
+use crate::protocol::{ServerInfo, DATABASE_HEADER, MULTI_STATEMENT_TICKETS, PROTOCOL_VERSION};
+use crate::tls::TlsOptions;
+
+/// How to connect to a server
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientOptions {
+    /// Connect with TLS, to an `https://` URL
+    pub tls: Option<TlsOptions>,
+    /// Authenticate with a server with authentication
+    pub credentials: Option<Credentials>,
+}
 
 pub struct Client {
-    inner: FlightServiceClient<Channel>,
+    inner: FlightServiceClient<InterceptedService<Channel, AuthInterceptor>>,
+    /// Sends the credentials, or the token issued by the handshake, with
+    /// every call
+    auth: AuthInterceptor,
+    schema_cache: SchemaCache,
+    server: ServerInfo,
+    /// The database requests run against, or the default database of the
+    /// server
+    database: Option<AsciiMetadataValue>,
 }
 
 impl Client {
-    /// Create a new client
+    /// Create a new client, and negotiate the protocol with the server
     pub async fn new<D>(endpoint: D) -> Result<Self>
     where
-        D: TryInto<tonic::transport::Endpoint>,
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError> + Send + Sync + 'static,
+    {
+        Self::new_with_options(endpoint, &ClientOptions::default()).await
+    }
+
+    /// Create a new client that connects to the `https://` URL of a server
+    /// with TLS, and negotiate the protocol with the server
+    pub async fn new_with_tls<D>(endpoint: D, tls: &TlsOptions) -> Result<Self>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError> + Send + Sync + 'static,
+    {
+        let options = ClientOptions {
+            tls: Some(tls.to_owned()),
+            ..Default::default()
+        };
+
+        Self::new_with_options(endpoint, &options).await
+    }
+
+    /// Create a new client, and negotiate the protocol with the server,
+    /// sending any credentials with the handshake
+    pub async fn new_with_options<D>(endpoint: D, options: &ClientOptions) -> Result<Self>
+    where
+        D: TryInto<Endpoint>,
         D::Error: Into<StdError> + Send + Sync + 'static,
     {
-        let client = FlightServiceClient::connect(endpoint)
+        let mut endpoint = endpoint
+            .try_into()
+            .map_err(|e| DbClientError::CreateClient(Into::<StdError>::into(e).to_string()))?;
+
+        if let Some(tls) = &options.tls {
+            endpoint = endpoint
+                .tls_config(tls.client_tls_config()?)
+                .map_err(|e| DbClientError::Tls(e.to_string()))?;
+        }
+
+        let channel = endpoint
+            .connect()
             .await
             .map_err(|e| DbClientError::CreateClient(e.to_string()))?;
+        let authorization = options
+            .credentials
+            .as_ref()
+            .map(Credentials::authorization)
+            .transpose()?;
 
-        Ok(Client { inner: client })
+        Self::negotiate(channel, AuthInterceptor::new(authorization)).await
     }
 
-    /// Get the schema of the RecordBatch
-    pub async fn schema(&mut self) -> Result<Schema> {
-        // Call get_schema to get the schema of the RecordBatch
-        let request = tonic::Request::new(FlightDescriptor {
-            r#type: DescriptorType::Cmd as i32, // Changed from Path to Cmd
-            cmd: "get_schema".into(),           // Added command
-            path: vec![],                       // Empty path since we're not using files
-        });
+    /// Negotiate the protocol with the server of a connected channel
+    async fn negotiate(channel: Channel, auth: AuthInterceptor) -> Result<Self> {
+        let mut client = Client {
+            inner: FlightServiceClient::with_interceptor(channel, auth.clone()),
+            auth,
+            schema_cache: SchemaCache::default(),
+            server: ServerInfo::default(),
+            database: None,
+        };
+        client.server = client.handshake().await?;
+
+        Ok(client)
+    }
+
+    /// The protocol version and capabilities negotiated with the server
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server
+    }
 
-        let schema_result = self
+    /// Send the client protocol version, and receive the negotiated version
+    /// and the server capabilities
+    ///
+    /// The credentials of the client are sent with the handshake, and a token
+    /// the server issues is sent with later calls instead.
+    async fn handshake(&mut self) -> Result<ServerInfo> {
+        let request = HandshakeRequest {
+            protocol_version: PROTOCOL_VERSION,
+            payload: Default::default(),
+        };
+
+        let response = match self
+            .inner
+            .handshake(futures::stream::iter(vec![request]))
+            .await
+        {
+            Ok(response) => response,
+            // servers that predate the handshake
+            Err(status) if status.code() == Code::Unimplemented => return Ok(ServerInfo::default()),
+            Err(e) => return Err(DbClientError::Handshake(e.to_string())),
+        };
+
+        if let Some(token) = issued_token(response.metadata()) {
+            self.auth.set_authorization(token);
+        }
+
+        let response = response
+            .into_inner()
+            .message()
+            .await
+            .map_err(|e| DbClientError::Handshake(e.to_string()))?
+            .ok_or(DbClientError::Handshake(
+                "No handshake response".to_string(),
+            ))?;
+
+        Ok(ServerInfo::from_handshake(
+            response.protocol_version,
+            &response.payload,
+        ))
+    }
+
+    /// Run later requests against a database of a server with several
+    /// databases, instead of its default database
+    pub fn use_database(&mut self, database_name: &str) -> Result<()> {
+        let database = database_name
+            .parse()
+            .map_err(|_| DbClientError::Query(format!("Invalid database {database_name}")))?;
+
+        self.database = Some(database);
+        self.schema_cache.invalidate();
+
+        Ok(())
+    }
+
+    /// Wrap a message in a request to the database of the client
+    fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+
+        if let Some(database) = &self.database {
+            request
+                .metadata_mut()
+                .insert(DATABASE_HEADER, database.to_owned());
+        }
+
+        request
+    }
+
+    /// Get the schema of a table
+    ///
+    /// Schemas are cached until the server reports a new schema version.
+    pub async fn schema(&mut self, table_name: &str) -> Result<Schema> {
+        if let Some(schema) = self.schema_cache.get(table_name) {
+            return Ok(schema.to_owned());
+        }
+
+        let request = self.request(FlightDescriptor::new_path(vec![table_name.into()]));
+
+        let response = self
             .inner
             .get_schema(request)
             .await
-            .map_err(|e| DbClientError::Schema(e.to_string()))?
-            .into_inner();
+            .map_err(|e| DbClientError::Schema(e.to_string()))?;
+
+        let version = schema_version(response.metadata());
+        let schema_result = response.into_inner();
 
         let schema =
             Schema::try_from(&schema_result).map_err(|e| DbClientError::Schema(e.to_string()))?;
 
+        self.schema_cache
+            .insert(table_name, version, schema.to_owned());
+
         Ok(schema)
     }
 
+    /// Drop all cached schemas, forcing the next lookup to hit the server
+    pub fn invalidate_schema_cache(&mut self) {
+        self.schema_cache.invalidate();
+    }
+
     /// Execute a SQL query and receive results
+    ///
+    /// If the query contains several statements, the results of the last
+    /// statement are returned.
     pub async fn query(&mut self, sql: &str) -> Result<Vec<RecordBatch>> {
+        Ok(self.query_many(sql).await?.pop().unwrap_or_default())
+    }
+
+    /// Execute several `;`-separated SQL statements in one round trip, and
+    /// receive the results of each statement in order
+    ///
+    /// Servers without multi-statement tickets receive one ticket per
+    /// statement instead.
+    pub async fn query_many(&mut self, sql: &str) -> Result<Vec<Vec<RecordBatch>>> {
+        if self.server.supports(MULTI_STATEMENT_TICKETS) {
+            return self.do_get(sql).await;
+        }
+
+        let statements =
+            DFParser::parse_sql(sql).map_err(|e| DbClientError::Query(e.to_string()))?;
+
+        if statements.len() <= 1 {
+            return self.do_get(sql).await;
+        }
+
+        let mut results = vec![];
+
+        for statement in statements {
+            results.extend(self.do_get(&statement.to_string()).await?);
+        }
+
+        Ok(results)
+    }
+
+    /// Send a ticket, and receive the results of each of its statements
+    async fn do_get(&mut self, sql: &str) -> Result<Vec<Vec<RecordBatch>>> {
         // Call do_get to execute a SQL query and receive results
-        let request = tonic::Request::new(Ticket {
+        let request = self.request(Ticket {
             ticket: sql.to_string().into(),
         });
 
-        let mut stream = self
+        let response = self
             .inner
             .do_get(request)
             .await
-            .map_err(|e| DbClientError::Query(e.to_string()))?
-            .into_inner();
+            .map_err(|e| DbClientError::Query(e.to_string()))?;
 
-        // convert FlightData to a stream
-        let flight_data = stream
-            .message()
-            .await
-            .map_err(|e| DbClientError::Query(e.to_string()))?
-            .ok_or(DbClientError::Query("No flight data returned".to_string()))?;
+        // a query may have altered the schema (e.g. CREATE TABLE)
+        self.schema_cache
+            .observe_version(schema_version(response.metadata()));
 
-        // the schema should be the first message returned, else client should error
-        let schema = Arc::new(
-            Schema::try_from(&flight_data).map_err(|e| DbClientError::Query(e.to_string()))?,
-        );
-        // println!("Schema: {schema:?}");
-
-        // all the remaining stream messages should be dictionary and record batches
-        let mut results = vec![];
-        let dictionaries_by_field = HashMap::new();
+        let mut stream = response.into_inner();
+        let mut messages = vec![];
 
         while let Some(flight_data) = stream
             .message()
             .await
             .map_err(|e| DbClientError::Query(e.to_string()))?
         {
-            let record_batch =
-                flight_data_to_arrow_batch(&flight_data, schema.clone(), &dictionaries_by_field)
-                    .map_err(|e| DbClientError::Query(e.to_string()))?;
+            messages.push(flight_data);
+        }
 
-            results.push(record_batch);
+        decode_results(messages)
+    }
+}
+
+/// Split the FlightData messages of a ticket into the results of each
+/// statement.
+///
+/// The results of each statement start with a schema message, which the
+/// server tags with the index of the statement in `app_metadata`.  All other
+/// messages are record batches of the most recent schema.
+fn decode_results(messages: Vec<FlightData>) -> Result<Vec<Vec<RecordBatch>>> {
+    let mut messages = messages.into_iter();
+
+    // the schema should be the first message returned, else client should error
+    let flight_data = messages
+        .next()
+        .ok_or(DbClientError::Query("No flight data returned".to_string()))?;
+    let mut schema =
+        Arc::new(Schema::try_from(&flight_data).map_err(|e| DbClientError::Query(e.to_string()))?);
+
+    let mut results = vec![vec![]];
+    let dictionaries_by_field = HashMap::new();
+
+    for flight_data in messages {
+        if !flight_data.app_metadata.is_empty() {
+            schema = Arc::new(
+                Schema::try_from(&flight_data).map_err(|e| DbClientError::Query(e.to_string()))?,
+            );
+            results.push(vec![]);
+            continue;
         }
 
-        Ok(results)
+        let record_batch =
+            flight_data_to_arrow_batch(&flight_data, schema.clone(), &dictionaries_by_field)
+                .map_err(|e| DbClientError::Query(e.to_string()))?;
+
+        if let Some(statement_results) = results.last_mut() {
+            statement_results.push(record_batch);
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::Int32Array;
+    use arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+    use arrow_flight::SchemaAsIpc;
+    use datafusion::arrow::datatypes::{DataType, Field};
+
+    use super::*;
+
+    /// Encode the results of a statement the way the server does
+    fn encode_results(index: usize, batches: &[RecordBatch]) -> Vec<FlightData> {
+        let options = IpcWriteOptions::default();
+        let schema = batches[0].schema();
+        let mut flights = vec![FlightData::from(SchemaAsIpc::new(&schema, &options))
+            .with_app_metadata(index.to_string())];
+        let mut tracker = DictionaryTracker::new(false);
+
+        for batch in batches {
+            let (_, flight_batch) = IpcDataGenerator::default()
+                .encoded_batch(batch, &mut tracker, &options)
+                .unwrap();
+            flights.push(flight_batch.into());
+        }
+
+        flights
+    }
+
+    #[test]
+    fn test_decode_multiple_statements() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+
+        let first = vec![batch(vec![1, 2]), batch(vec![3])];
+        let second = vec![batch(vec![4])];
+        let mut messages = encode_results(0, &first);
+        messages.extend(encode_results(1, &second));
+
+        let results = decode_results(messages).unwrap();
+        assert_eq!(results, vec![first, second]);
+
+        assert!(decode_results(vec![]).is_err());
     }
 }
@@ -0,0 +1,71 @@
+//! Protocol negotiation.
+//!
+//! The client and server exchange protocol versions in a Flight handshake when
+//! the client connects, and the server advertises the features it supports.
+//! Features are only used if the server advertises them, so newer clients
+//! still work against older servers.  Servers that predate the handshake are
+//! treated as version 0 with no capabilities.
+
+use std::collections::HashSet;
+
+pub use arrow_db_core::protocol::DATABASE_HEADER;
+
+/// The newest protocol version the client speaks
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// A do_get ticket may contain several `;`-separated statements
+pub const MULTI_STATEMENT_TICKETS: &str = "multi_statement_tickets";
+
+/// Responses carry the schema version header
+pub const SCHEMA_VERSION: &str = "schema_version";
+
+/// What the server negotiated in the handshake
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerInfo {
+    /// The protocol version both sides speak
+    pub protocol_version: u64,
+    pub capabilities: HashSet<String>,
+}
+
+impl ServerInfo {
+    /// Read the negotiated version and the comma-separated capabilities of a
+    /// handshake response
+    pub fn from_handshake(protocol_version: u64, payload: &[u8]) -> ServerInfo {
+        let capabilities = String::from_utf8_lossy(payload)
+            .split(',')
+            .map(str::trim)
+            .filter(|capability| !capability.is_empty())
+            .map(Into::into)
+            .collect();
+
+        ServerInfo {
+            protocol_version,
+            capabilities,
+        }
+    }
+
+    /// Check if the server advertised a capability
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_info_from_handshake() {
+        let server = ServerInfo::from_handshake(1, b"multi_statement_tickets, schema_version");
+        assert_eq!(server.protocol_version, 1);
+        assert!(server.supports(MULTI_STATEMENT_TICKETS));
+        assert!(server.supports(SCHEMA_VERSION));
+        assert!(!server.supports("flight_sql"));
+
+        // servers without a handshake support nothing
+        let legacy = ServerInfo::default();
+        assert_eq!(legacy.protocol_version, 0);
+        assert!(!legacy.supports(MULTI_STATEMENT_TICKETS));
+        assert_eq!(ServerInfo::from_handshake(0, b""), legacy);
+    }
+}
@@ -0,0 +1,61 @@
+//! TLS options.
+//!
+//! Servers with TLS are reached at `https://` URLs.  The server certificate
+//! is verified with a CA certificate, or with the native roots of the system
+//! if there isn't one, and servers that require mutual TLS need a client
+//! certificate and key.
+
+use std::path::{Path, PathBuf};
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+use crate::error::{DbClientError, Result};
+
+/// How to connect to a server with TLS, from the paths of PEM files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsOptions {
+    /// The CA certificate that signed the server certificate
+    pub ca_certificate: Option<PathBuf>,
+    /// The client certificate, for mutual TLS
+    pub client_certificate: Option<PathBuf>,
+    /// The private key of the client certificate
+    pub client_key: Option<PathBuf>,
+    /// The name the server certificate is verified against, if it isn't the
+    /// host of the URL
+    pub domain_name: Option<String>,
+}
+
+impl TlsOptions {
+    /// The tonic TLS configuration of the options
+    pub fn client_tls_config(&self) -> Result<ClientTlsConfig> {
+        let mut config = match &self.ca_certificate {
+            Some(ca_certificate) => ClientTlsConfig::new()
+                .ca_certificate(Certificate::from_pem(read_pem(ca_certificate)?)),
+            None => ClientTlsConfig::new().with_native_roots(),
+        };
+
+        match (&self.client_certificate, &self.client_key) {
+            (Some(certificate), Some(key)) => {
+                config =
+                    config.identity(Identity::from_pem(read_pem(certificate)?, read_pem(key)?));
+            }
+            (None, None) => {}
+            _ => {
+                return Err(DbClientError::Tls(
+                    "Mutual TLS needs both a client certificate and a client key".into(),
+                ))
+            }
+        }
+
+        if let Some(domain_name) = &self.domain_name {
+            config = config.domain_name(domain_name);
+        }
+
+        Ok(config)
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| DbClientError::Tls(format!("Cannot read {}: {e}", path.display())))
+}
@@ -0,0 +1,119 @@
+//! Core benchmarks over generated datasets.
+//!
+//! Run with `cargo bench -p arrow-db-core --features bench`.
+
+use arrow::ipc::CompressionType;
+use arrow_db_core::bench::{benchmark_database, flights_table, DEFAULT_SEED, FLIGHTS_TABLE};
+use arrow_db_core::export::ExportOptions;
+use arrow_db_core::import::DEFAULT_BATCH_SIZE;
+use arrow_db_core::table::Table;
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tokio::runtime::Runtime;
+
+/// Rows in the table used for import, context registration and SELECT
+const ROWS: usize = 100_000;
+
+/// Rows in the table used for DML, which is applied row by row
+const DML_ROWS: usize = 10_000;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn bench_import(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut table = flights_table(FLIGHTS_TABLE, ROWS, DEFAULT_SEED).unwrap();
+    let mut buffer = vec![];
+    runtime
+        .block_on(table.export_parquet_to_bytes(&mut buffer, &ExportOptions::default()))
+        .unwrap();
+    let bytes = Bytes::from(buffer);
+
+    c.bench_function("import_parquet_from_bytes", |b| {
+        b.iter(|| {
+            let mut table = Table::new(FLIGHTS_TABLE);
+            table
+                .import_parquet_from_bytes(bytes.clone(), DEFAULT_BATCH_SIZE)
+                .unwrap();
+        })
+    });
+
+    let bytes = Bytes::from(
+        table
+            .export_ipc_compressed_to_bytes(Some(CompressionType::LZ4_FRAME))
+            .unwrap(),
+    );
+
+    c.bench_function("import_ipc_lz4_from_bytes", |b| {
+        b.iter(|| {
+            let mut table = Table::new(FLIGHTS_TABLE);
+            table.import_ipc_from_bytes(bytes.clone()).unwrap();
+        })
+    });
+}
+
+fn bench_context_registration(c: &mut Criterion) {
+    let database = benchmark_database(ROWS, DEFAULT_SEED).unwrap();
+
+    c.bench_function("add_table_context", |b| {
+        b.iter(|| database.add_table_context(FLIGHTS_TABLE).unwrap())
+    });
+}
+
+fn bench_select(c: &mut Criterion) {
+    let runtime = runtime();
+    let database = benchmark_database(ROWS, DEFAULT_SEED).unwrap();
+    let sql = r#"select * from flights where "DISTANCE" > 1000 and "DISTANCE" < 3000 limit 100"#;
+
+    c.bench_function("select_range_limit", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { database.query(sql).await.unwrap().collect().await.unwrap() })
+    });
+
+    let sql = r#"select "FL_DATE", avg("DEP_DELAY") from flights group by "FL_DATE""#;
+
+    c.bench_function("select_group_by", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { database.query(sql).await.unwrap().collect().await.unwrap() })
+    });
+}
+
+fn bench_dml(c: &mut Criterion) {
+    let runtime = runtime();
+    let database = benchmark_database(DML_ROWS, DEFAULT_SEED).unwrap();
+
+    let statements = [
+        (
+            "insert",
+            r#"insert into flights values (-1, '2006-01-01', 0, 0, 60, 500, 12.0, 13.0)"#,
+        ),
+        (
+            "update",
+            r#"update flights set "DEP_DELAY" = 0 where "DISTANCE" > 4900"#,
+        ),
+        ("delete", r#"delete from flights where "DISTANCE" > 4900"#),
+    ];
+
+    for (name, sql) in statements {
+        c.bench_function(name, |b| {
+            b.to_async(&runtime).iter_batched(
+                || database.clone(),
+                |database| async move { database.query(sql).await.unwrap() },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_import,
+    bench_context_registration,
+    bench_select,
+    bench_dml
+);
+criterion_main!(benches);
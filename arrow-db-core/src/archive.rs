@@ -0,0 +1,300 @@
+//! Database archives.
+//!
+//! An archive packs every table of a database into a single blob, so a whole
+//! database can be backed up and restored in one call, e.g. in the browser.
+//!
+//! An archive starts with a magic header and a version, followed by one entry
+//! per table:
+//!
+//! ```text
+//! "ARROWDB" version:u8 (name_len:u32 name ipc_len:u64 ipc)*
+//! ```
+//!
+//! Each table is stored as an Arrow IPC file.  Catalog metadata, like the
+//! primary key and the state of auto-increment sequences, is kept in the
+//! schema metadata of the file.
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arrow::ipc::{reader::FileReader, writer::FileWriter};
+use arrow_schema::Schema;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    sequence::Sequence,
+    table::Table,
+};
+
+const ARCHIVE_MAGIC: &[u8; 7] = b"ARROWDB";
+const ARCHIVE_VERSION: u8 = 1;
+
+/// Schema metadata key for the primary key column
+const PRIMARY_KEY_METADATA: &str = "arrow_db.primary_key";
+
+/// Schema metadata key for auto-increment sequences, as `column=next` pairs
+const SEQUENCES_METADATA: &str = "arrow_db.sequences";
+
+impl Table {
+    /// Export the table and its catalog metadata to an Arrow IPC file
+    pub fn export_archive_entry(&self) -> Result<Vec<u8>> {
+        let export_error = |e: arrow_schema::ArrowError| {
+            DbError::TableExportError(self.name.to_owned(), e.to_string())
+        };
+
+        let mut metadata = self.schema().metadata().to_owned();
+
+        if let Some(primary_key) = &self.primary_key {
+            metadata.insert(
+                PRIMARY_KEY_METADATA.into(),
+                primary_key.column_name.to_owned(),
+            );
+        }
+
+        if !self.sequences.is_empty() {
+            let sequences = self
+                .sequences
+                .iter()
+                .map(|(column_name, sequence)| format!("{column_name}={}", sequence.peek()))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            metadata.insert(SEQUENCES_METADATA.into(), sequences);
+        }
+
+        let schema = Arc::new(self.schema().as_ref().to_owned().with_metadata(metadata));
+        let mut writer = FileWriter::try_new(vec![], &schema).map_err(export_error)?;
+
+        for chunk in self.chunks.iter() {
+            let chunk = chunk
+                .to_owned()
+                .with_schema(schema.to_owned())
+                .map_err(export_error)?;
+            writer.write(&chunk).map_err(export_error)?;
+        }
+
+        writer.finish().map_err(export_error)?;
+        writer.into_inner().map_err(export_error)
+    }
+
+    /// Import the table and its catalog metadata from an Arrow IPC file
+    pub fn import_archive_entry(&mut self, bytes: &[u8]) -> Result<()> {
+        let import_error = |e: arrow_schema::ArrowError| {
+            DbError::TableImportError(self.name.to_owned(), e.to_string())
+        };
+
+        let reader = FileReader::try_new(Cursor::new(bytes), None).map_err(import_error)?;
+        let schema = reader.schema();
+        let record_batches = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(import_error)?;
+
+        // catalog metadata is restored into the table, not kept in the schema
+        let mut metadata = schema.metadata().to_owned();
+        let primary_key = metadata.remove(PRIMARY_KEY_METADATA);
+        let sequences = metadata.remove(SEQUENCES_METADATA);
+        let schema = Arc::new(Schema::new_with_metadata(
+            schema.fields().to_owned(),
+            metadata,
+        ));
+
+        self.set_record_batches(schema, &record_batches)?;
+
+        if let Some(column_name) = primary_key {
+            self.set_primary_key(&column_name)?;
+        }
+
+        for sequence in sequences.iter().flat_map(|sequences| sequences.split(',')) {
+            let (column_name, next) = sequence
+                .split_once('=')
+                .and_then(|(column_name, next)| Some((column_name, next.parse().ok()?)))
+                .ok_or_else(|| {
+                    DbError::TableImportError(
+                        self.name.to_owned(),
+                        format!("Invalid sequence {sequence}"),
+                    )
+                })?;
+
+            self.column_index(column_name)?;
+            self.sequences
+                .insert(column_name.into(), Sequence::new(next));
+        }
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Export every table of the database into a single archive
+    pub fn export_archive(&self) -> Result<Vec<u8>> {
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.push(ARCHIVE_VERSION);
+
+        for table in self.tables.iter() {
+            let name = table.name.as_bytes();
+            let entry = table.unspilled()?.export_archive_entry()?;
+
+            archive.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            archive.extend_from_slice(name);
+            archive.extend_from_slice(&(entry.len() as u64).to_le_bytes());
+            archive.extend_from_slice(&entry);
+        }
+
+        Ok(archive)
+    }
+
+    /// Add every table of an archive to the database, returning the names of
+    /// the tables.
+    ///
+    /// The whole archive is read before any table is added, so a malformed
+    /// archive or a table that already exists leaves the database untouched.
+    pub fn load_archive(&mut self, archive: &[u8]) -> Result<Vec<String>> {
+        let mut reader = ArchiveReader::new(archive)?;
+        let mut tables = vec![];
+
+        while let Some((name, entry)) = reader.next_entry()? {
+            if self.tables.contains_key(name.as_str())
+                || tables.iter().any(|table: &Table| table.name == name)
+            {
+                return Err(DbError::TableAlreadyExists(name));
+            }
+
+            let mut table = Table::new(name);
+            table.import_archive_entry(entry)?;
+            tables.push(table);
+        }
+
+        let names = tables.iter().map(|table| table.name.to_string()).collect();
+
+        for table in tables {
+            self.add_table(table)?;
+        }
+
+        Ok(names)
+    }
+}
+
+/// Reads the entries of an archive in order
+struct ArchiveReader<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> ArchiveReader<'b> {
+    /// Check the header of an archive
+    fn new(bytes: &'b [u8]) -> Result<ArchiveReader<'b>> {
+        let mut reader = ArchiveReader { bytes, offset: 0 };
+
+        if reader.read(ARCHIVE_MAGIC.len())? != ARCHIVE_MAGIC {
+            return Err(DbError::Archive("Not an Arrow DB archive".into()));
+        }
+
+        let version = reader.read(1)?[0];
+
+        if version != ARCHIVE_VERSION {
+            return Err(DbError::Archive(format!(
+                "Unsupported archive version {version}"
+            )));
+        }
+
+        Ok(reader)
+    }
+
+    /// Read the next table name and IPC file, if any remain
+    fn next_entry(&mut self) -> Result<Option<(String, &'b [u8])>> {
+        if self.offset == self.bytes.len() {
+            return Ok(None);
+        }
+
+        let name_len = u32::from_le_bytes(self.read(4)?.try_into().unwrap_or_default());
+        let name = String::from_utf8(self.read(name_len as usize)?.to_vec())
+            .map_err(|e| DbError::Archive(format!("Invalid table name: {e}")))?;
+        let entry_len = u64::from_le_bytes(self.read(8)?.try_into().unwrap_or_default());
+        let entry = self.read(entry_len as usize)?;
+
+        Ok(Some((name, entry)))
+    }
+
+    /// Read a number of bytes, failing if the archive is truncated
+    fn read(&mut self, len: usize) -> Result<&'b [u8]> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or_else(|| DbError::Archive("Archive is truncated".into()))?;
+
+        self.offset += len;
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use datafusion::scalar::ScalarValue;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archive_round_trip() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        {
+            let mut users = get_mut_table!(database, "users").unwrap();
+            users.set_primary_key("id").unwrap();
+            users.set_auto_increment("id").unwrap();
+        }
+
+        let archive = database.export_archive().unwrap();
+
+        let mut restored = Database::new("Restored").unwrap();
+        let mut names = restored.load_archive(&archive).unwrap();
+        names.sort();
+        assert_eq!(names, vec!["user_role", "users"]);
+
+        {
+            let original = get_table!(database, "users").unwrap();
+            let users = get_table!(restored, "users").unwrap();
+            assert_eq!(users.chunks, original.chunks);
+            assert_eq!(users.primary_key, original.primary_key);
+            assert_eq!(users.sequences, original.sequences);
+        }
+
+        // the restored catalog is enforced
+        restored.add_all_table_contexts().unwrap();
+        assert!(restored
+            .query("insert into users values (1, 'Alice2')")
+            .await
+            .is_err());
+        restored
+            .query("insert into users (name) values ('Eve')")
+            .await
+            .unwrap();
+        assert!(get_table!(restored, "users")
+            .unwrap()
+            .primary_key
+            .as_ref()
+            .unwrap()
+            .contains(&ScalarValue::Int32(Some(5))));
+
+        // loading the same tables again fails without changing anything
+        assert!(matches!(
+            restored.load_archive(&archive),
+            Err(DbError::TableAlreadyExists(_))
+        ));
+        assert_eq!(restored.tables.len(), 2);
+
+        let mut truncated = Database::new("Truncated").unwrap();
+        assert!(matches!(
+            truncated.load_archive(&archive[..archive.len() - 1]),
+            Err(DbError::Archive(_))
+        ));
+        assert!(truncated.tables.is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+//! Tables defined by Rust structs.
+//!
+//! A struct that implements `ArrowTable` defines the schema, constraints and
+//! column order of a table, and reads and writes its rows as structs.  With
+//! the `derive` feature, `#[derive(ArrowTable)]` implements it from the fields
+//! of the struct:
+//!
+//! ```rust,ignore
+//! use arrow_db_core::arrow_table::ArrowTable;
+//! use datafusion::prelude::{col, lit};
+//!
+//! #[derive(ArrowTable, Serialize, Deserialize)]
+//! #[arrow_table(name = "users")]
+//! struct User {
+//!     #[arrow_table(primary_key)]
+//!     id: i32,
+//!     #[arrow_table(check = "age >= 0")]
+//!     age: i32,
+//!     name: Option<String>,
+//! }
+//!
+//! User::create_table(&mut database)?;
+//! User::insert(&database, &[User { id: 1, age: 42, name: None }])?;
+//!
+//! let users = User::select_where(&database, col("age").gt(lit(40))).await?;
+//! ```
+//!
+//! Each field is a column, in field order, whose data type comes from the
+//! `ArrowField` implementation of the Rust type of the field.  `Option` fields
+//! are nullable, and every other field is NOT NULL.  Rows are converted with
+//! `insert_structs` and `query_as`, so fields must not be renamed by serde.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::RecordBatch,
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use datafusion::logical_expr::Expr;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    database::Database, error::Result, json::json_field, table::Table, typed::deserialize_rows,
+};
+
+#[cfg(feature = "derive")]
+pub use arrow_db_derive::ArrowTable;
+
+/// A column of a table defined by a struct
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: &'static str,
+    pub data_type: DataType,
+    pub nullable: bool,
+    pub primary_key: bool,
+    pub json: bool,
+    /// The SQL expression of a CHECK constraint on the column
+    pub check: Option<&'static str>,
+}
+
+impl ColumnDef {
+    /// The name of the CHECK constraint on the column
+    pub fn check_name(&self, table_name: &str) -> String {
+        format!("{table_name}_{}_check", self.name)
+    }
+}
+
+/// A Rust type that's stored in a column
+pub trait ArrowField {
+    /// The data type of the column
+    fn data_type() -> DataType;
+
+    /// Whether the column holds NULL values
+    fn nullable() -> bool {
+        false
+    }
+}
+
+macro_rules! arrow_field {
+    ($($ty:ty => $data_type:expr),* $(,)?) => {
+        $(impl ArrowField for $ty {
+            fn data_type() -> DataType {
+                $data_type
+            }
+        })*
+    };
+}
+
+arrow_field!(
+    bool => DataType::Boolean,
+    i8 => DataType::Int8,
+    i16 => DataType::Int16,
+    i32 => DataType::Int32,
+    i64 => DataType::Int64,
+    u8 => DataType::UInt8,
+    u16 => DataType::UInt16,
+    u32 => DataType::UInt32,
+    u64 => DataType::UInt64,
+    f32 => DataType::Float32,
+    f64 => DataType::Float64,
+    String => DataType::Utf8,
+    NaiveDate => DataType::Date32,
+    NaiveDateTime => DataType::Timestamp(TimeUnit::Microsecond, None),
+    DateTime<Utc> => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+);
+
+impl<T: ArrowField> ArrowField for Option<T> {
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+}
+
+/// A table defined by a struct, whose rows are read and written as the struct
+#[async_trait]
+pub trait ArrowTable: Serialize + DeserializeOwned {
+    /// The name of the table
+    const TABLE_NAME: &'static str;
+
+    /// The columns of the table, in order
+    fn columns() -> Vec<ColumnDef>;
+
+    /// The schema of the table.
+    ///
+    /// Every field is nullable, as NOT NULL columns are constraints of the
+    /// table.
+    fn schema() -> SchemaRef {
+        let fields = Self::columns()
+            .iter()
+            .map(|column| match column.json {
+                true => json_field(column.name, true),
+                false => Field::new(column.name, column.data_type.to_owned(), true),
+            })
+            .collect::<Vec<_>>();
+
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Add the empty table with its constraints to a database, registering it
+    /// with the DataFusion context
+    fn create_table(database: &mut Database) -> Result<()> {
+        let columns = Self::columns();
+        let mut table = Table::new(Self::TABLE_NAME);
+        table.set_record_batch(RecordBatch::new_empty(Self::schema()));
+
+        for column in columns.iter() {
+            if column.primary_key {
+                table.set_primary_key(column.name)?;
+            }
+
+            if !column.nullable {
+                table.set_not_null(column.name)?;
+            }
+
+            if column.json {
+                table.set_json_column(column.name)?;
+            }
+        }
+
+        database.add_table(table)?;
+
+        for column in columns.iter() {
+            if let Some(check) = column.check {
+                database.add_check_constraint(
+                    Self::TABLE_NAME,
+                    &column.check_name(Self::TABLE_NAME),
+                    check,
+                )?;
+            }
+        }
+
+        database.add_table_context(Self::TABLE_NAME)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        database.schedule_write(Self::TABLE_NAME)?;
+
+        Ok(())
+    }
+
+    /// Append rows to the table, returning the number of rows appended, see
+    /// `Database::insert_structs`
+    fn insert(database: &Database, rows: &[Self]) -> Result<usize> {
+        database.insert_structs(Self::TABLE_NAME, rows)
+    }
+
+    /// Read every row of the table
+    async fn select(database: &Database) -> Result<Vec<Self>> {
+        let record_batches = database
+            .table(Self::TABLE_NAME)?
+            .select_columns(&column_names::<Self>())?
+            .collect()
+            .await?;
+
+        deserialize_rows(&record_batches)
+    }
+
+    /// Read the rows of the table for which a predicate is true
+    async fn select_where(database: &Database, predicate: Expr) -> Result<Vec<Self>> {
+        let record_batches = database
+            .table(Self::TABLE_NAME)?
+            .filter(predicate)?
+            .select_columns(&column_names::<Self>())?
+            .collect()
+            .await?;
+
+        deserialize_rows(&record_batches)
+    }
+}
+
+/// The names of the columns of a table defined by a struct
+fn column_names<T: ArrowTable>() -> Vec<&'static str> {
+    T::columns().iter().map(|column| column.name).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use datafusion::prelude::{col, lit};
+    use serde::Deserialize;
+
+    use crate::{database::tests::create_database, error::DbError};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        id: i64,
+        balance: f64,
+        owner: Option<String>,
+    }
+
+    impl ArrowTable for Account {
+        const TABLE_NAME: &'static str = "accounts";
+
+        fn columns() -> Vec<ColumnDef> {
+            vec![
+                ColumnDef {
+                    name: "id",
+                    data_type: i64::data_type(),
+                    nullable: i64::nullable(),
+                    primary_key: true,
+                    json: false,
+                    check: None,
+                },
+                ColumnDef {
+                    name: "balance",
+                    data_type: f64::data_type(),
+                    nullable: f64::nullable(),
+                    primary_key: false,
+                    json: false,
+                    check: Some("balance >= 0"),
+                },
+                ColumnDef {
+                    name: "owner",
+                    data_type: <Option<String>>::data_type(),
+                    nullable: <Option<String>>::nullable(),
+                    primary_key: false,
+                    json: false,
+                    check: None,
+                },
+            ]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_arrow_table() {
+        let (mut database, _) = create_database();
+        Account::create_table(&mut database).unwrap();
+
+        let schema = database.tables.get("accounts").unwrap().schema();
+        assert_eq!(schema, Account::schema());
+        assert_eq!(schema.field(1).data_type(), &DataType::Float64);
+        assert!(matches!(
+            Account::create_table(&mut database),
+            Err(DbError::TableAlreadyExists(_))
+        ));
+
+        let accounts = vec![
+            Account {
+                id: 1,
+                balance: 10.5,
+                owner: Some("Alice".into()),
+            },
+            Account {
+                id: 2,
+                balance: 0.0,
+                owner: None,
+            },
+        ];
+        assert_eq!(Account::insert(&database, &accounts).unwrap(), 2);
+        assert_eq!(Account::select(&database).await.unwrap(), accounts);
+        assert_eq!(
+            Account::select_where(&database, col("balance").gt(lit(1.0)))
+                .await
+                .unwrap(),
+            accounts[..1]
+        );
+
+        // the primary key and CHECK constraints of the struct are enforced
+        let duplicate = Account {
+            id: 1,
+            balance: 1.0,
+            owner: None,
+        };
+        let overdrawn = Account {
+            id: 3,
+            balance: -1.0,
+            owner: None,
+        };
+        assert!(Account::insert(&database, &[duplicate]).is_err());
+        assert!(Account::insert(&database, &[overdrawn]).is_err());
+        assert_eq!(Account::select(&database).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,234 @@
+//! Batch builders.
+//!
+//! A `RowBatchBuilder` assembles rows for a table into a single record batch
+//! with the schema of the table, so thousands of rows can be loaded with one
+//! `insert_batch` instead of an INSERT statement per row.  Rows can be pushed
+//! one at a time, or a column at a time from Arrow arrays, and every value is
+//! cast to the type of its column as it's pushed.
+
+use arrow::{
+    array::{new_empty_array, ArrayRef, RecordBatch},
+    compute::{cast, concat},
+};
+use arrow_schema::SchemaRef;
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_table,
+    row::cast_scalar,
+    table::Table,
+};
+
+/// Builds a record batch with the schema of a table
+#[derive(Debug, Clone)]
+pub struct RowBatchBuilder {
+    table_name: String,
+    schema: SchemaRef,
+    /// The arrays pushed to each column so far
+    columns: Vec<Vec<ArrayRef>>,
+    /// The values pushed to each column since its last array
+    values: Vec<Vec<ScalarValue>>,
+}
+
+impl RowBatchBuilder {
+    /// A builder for rows with the schema of a table
+    pub fn new(table: &Table) -> RowBatchBuilder {
+        let schema = table.schema();
+        let num_columns = schema.fields().len();
+
+        RowBatchBuilder {
+            table_name: table.name.to_owned(),
+            schema,
+            columns: vec![vec![]; num_columns],
+            values: vec![vec![]; num_columns],
+        }
+    }
+
+    /// The schema of the batch
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.to_owned()
+    }
+
+    /// The number of rows pushed to a column
+    pub fn num_rows(&self, column_name: &str) -> Result<usize> {
+        let column_index = self.column_index(column_name)?;
+        let arrays = self.columns[column_index].iter().map(|array| array.len());
+
+        Ok(arrays.sum::<usize>() + self.values[column_index].len())
+    }
+
+    /// Push a row of values, one value per column in table order
+    pub fn push_row(&mut self, values: Vec<ScalarValue>) -> Result<&mut Self> {
+        if values.len() != self.schema.fields().len() {
+            return Err(DbError::DataType(format!(
+                "Table {} has {} columns, but the row has {} values",
+                self.table_name,
+                self.schema.fields().len(),
+                values.len()
+            )));
+        }
+
+        // cast the whole row first, so a failed push leaves the builder as is
+        let values = self
+            .schema
+            .fields()
+            .iter()
+            .zip(values.iter())
+            .map(|(field, value)| cast_scalar(value, field.data_type()))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (column, value) in self.values.iter_mut().zip(values) {
+            column.push(value);
+        }
+
+        Ok(self)
+    }
+
+    /// Push the values of an array to a column
+    pub fn push_column(&mut self, column_name: &str, column: ArrayRef) -> Result<&mut Self> {
+        let column_index = self.column_index(column_name)?;
+        let data_type = self.schema.field(column_index).data_type();
+        let column = cast(&column, data_type)
+            .map_err(|e| DbError::DataType(format!("Cannot write column {column_name}: {e}")))?;
+
+        self.flush_values(column_index)?;
+        self.columns[column_index].push(column);
+
+        Ok(self)
+    }
+
+    /// Assemble the pushed rows into a record batch, emptying the builder.
+    ///
+    /// Fails if the columns have different numbers of rows.
+    pub fn finish(&mut self) -> Result<RecordBatch> {
+        let num_rows = (0..self.columns.len())
+            .map(|column_index| {
+                self.flush_values(column_index)?;
+                Ok(self.columns[column_index]
+                    .iter()
+                    .map(|array| array.len())
+                    .sum())
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        let expected = num_rows.first().copied().unwrap_or(0);
+
+        for (field, num_rows) in self.schema.fields().iter().zip(num_rows) {
+            if num_rows != expected {
+                return Err(DbError::ColumnLengthMismatch(
+                    field.name().to_owned(),
+                    num_rows,
+                    self.table_name.to_owned(),
+                    expected,
+                ));
+            }
+        }
+
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .zip(self.columns.iter_mut())
+            .map(|(field, arrays)| {
+                let arrays = std::mem::take(arrays);
+                let arrays = arrays
+                    .iter()
+                    .map(|array| array.as_ref())
+                    .collect::<Vec<_>>();
+
+                match arrays.is_empty() {
+                    true => Ok(new_empty_array(field.data_type())),
+                    false => concat(&arrays).map_err(|e| DbError::ArrayData(e.to_string())),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Table::new_record_batch(self.schema(), columns)
+    }
+
+    fn column_index(&self, column_name: &str) -> Result<usize> {
+        self.schema
+            .index_of(column_name)
+            .map_err(|_| DbError::ColumnNotFound(column_name.into(), self.table_name.to_owned()))
+    }
+
+    /// Move the values pushed to a column into an array
+    fn flush_values(&mut self, column_index: usize) -> Result<()> {
+        if self.values[column_index].is_empty() {
+            return Ok(());
+        }
+
+        let values = std::mem::take(&mut self.values[column_index]);
+        let array = ScalarValue::iter_to_array(values)
+            .map_err(|e| DbError::ArrayData(format!("Error building column: {e}")))?;
+        self.columns[column_index].push(array);
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// A builder for a batch of rows with the schema of a table
+    pub fn row_batch_builder(&self, table_name: &str) -> Result<RowBatchBuilder> {
+        Ok(RowBatchBuilder::new(&*get_table!(self, table_name)?))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int64Array, StringArray};
+    use arrow::datatypes::Int32Type;
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_row_batch_builder() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let mut builder = database.row_batch_builder("users").unwrap();
+        builder
+            .push_row(vec![
+                ScalarValue::Int64(Some(5)),
+                ScalarValue::Utf8(Some("Eve".into())),
+            ])
+            .unwrap();
+        assert!(builder.push_row(vec![ScalarValue::Int32(Some(6))]).is_err());
+        assert!(builder
+            .push_column("missing", Arc::new(Int64Array::from(vec![6])))
+            .is_err());
+
+        // columns are cast to the types of the table
+        builder
+            .push_column("id", Arc::new(Int64Array::from(vec![6, 7])))
+            .unwrap();
+        assert_eq!(builder.num_rows("id").unwrap(), 3);
+        assert!(matches!(
+            builder.finish(),
+            Err(DbError::ColumnLengthMismatch(..))
+        ));
+
+        builder
+            .push_column("name", Arc::new(StringArray::from(vec!["Frank", "Grace"])))
+            .unwrap();
+        let batch = builder.finish().unwrap();
+        assert_eq!(builder.num_rows("id").unwrap(), 0);
+        assert_eq!(
+            batch.column(0).as_primitive::<Int32Type>().values(),
+            &[5, 6, 7]
+        );
+        assert_eq!(
+            batch.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Eve", "Frank", "Grace"])
+        );
+
+        assert_eq!(database.insert_batch("users", batch).unwrap(), 3);
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 7);
+    }
+}
@@ -0,0 +1,145 @@
+//! Benchmark datasets.
+//!
+//! Generates reproducible datasets modeled on the LargeDB `flights_1m` table,
+//! so benchmarks don't depend on parquet fixtures that live outside of the
+//! repository.  The same seed always produces the same data.
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, Int32Array, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::{database::Database, error::Result, table::Table};
+
+/// The name of the generated flights table
+pub const FLIGHTS_TABLE: &str = "flights";
+
+/// The seed used by the benchmarks
+pub const DEFAULT_SEED: u64 = 42;
+
+/// A small seeded pseudo-random number generator (SplitMix64).
+///
+/// Good enough to generate realistic looking data, and stable across
+/// platforms and releases, unlike the generators in external crates.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    /// Generate the next random number
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        value ^ (value >> 31)
+    }
+
+    /// Generate a random number in the range `low..high`
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        let span = (high - low) as u64;
+        low + (self.next_u64() % span) as i32
+    }
+
+    /// Generate a random number in the range `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Generate a flights table with the given number of rows.
+///
+/// The columns mirror the LargeDB `flights_1m` table, with an added `ID`
+/// column to address individual rows in DML benchmarks.
+pub fn flights_table(name: &str, rows: usize, seed: u64) -> Result<Table> {
+    let mut rng = Rng::new(seed);
+
+    let mut ids = Vec::with_capacity(rows);
+    let mut dates = Vec::with_capacity(rows);
+    let mut dep_delays = Vec::with_capacity(rows);
+    let mut arr_delays = Vec::with_capacity(rows);
+    let mut air_times = Vec::with_capacity(rows);
+    let mut distances = Vec::with_capacity(rows);
+    let mut dep_times = Vec::with_capacity(rows);
+    let mut arr_times = Vec::with_capacity(rows);
+
+    for id in 0..rows {
+        let distance = rng.range(50, 5000);
+        let air_time = distance / 8 + rng.range(0, 30);
+        let dep_delay = rng.range(-15, 120);
+        let dep_time = rng.range(0, 24) as f64 + rng.next_f64();
+
+        ids.push(id as i32);
+        dates.push(format!(
+            "2006-{:02}-{:02}",
+            rng.range(1, 13),
+            rng.range(1, 29)
+        ));
+        dep_delays.push(dep_delay);
+        arr_delays.push(dep_delay + rng.range(-20, 20));
+        air_times.push(air_time);
+        distances.push(distance);
+        dep_times.push(dep_time);
+        arr_times.push((dep_time + air_time as f64 / 60.0) % 24.0);
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("ID", DataType::Int32, true),
+        Field::new("FL_DATE", DataType::Utf8, true),
+        Field::new("DEP_DELAY", DataType::Int32, true),
+        Field::new("ARR_DELAY", DataType::Int32, true),
+        Field::new("AIR_TIME", DataType::Int32, true),
+        Field::new("DISTANCE", DataType::Int32, true),
+        Field::new("DEP_TIME", DataType::Float64, true),
+        Field::new("ARR_TIME", DataType::Float64, true),
+    ]));
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int32Array::from(ids)),
+        Arc::new(StringArray::from(dates)),
+        Arc::new(Int32Array::from(dep_delays)),
+        Arc::new(Int32Array::from(arr_delays)),
+        Arc::new(Int32Array::from(air_times)),
+        Arc::new(Int32Array::from(distances)),
+        Arc::new(Float64Array::from(dep_times)),
+        Arc::new(Float64Array::from(arr_times)),
+    ];
+
+    let mut table = Table::new(name);
+    table.set_record_batch(Table::new_record_batch(schema, columns)?);
+
+    Ok(table)
+}
+
+/// Generate a database containing a flights table with the given number of
+/// rows, with all tables registered in the context.
+pub fn benchmark_database(rows: usize, seed: u64) -> Result<Database> {
+    let mut database = Database::new("BenchDB")?;
+    database.add_table(flights_table(FLIGHTS_TABLE, rows, seed)?)?;
+    database.add_all_table_contexts()?;
+
+    Ok(database)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flights_table_is_reproducible() {
+        let table = flights_table(FLIGHTS_TABLE, 1000, DEFAULT_SEED).unwrap();
+        assert_eq!(table.num_rows(), 1000);
+        assert_eq!(table.num_columns(), 8);
+
+        let same_seed = flights_table(FLIGHTS_TABLE, 1000, DEFAULT_SEED).unwrap();
+        assert_eq!(table, same_seed);
+
+        let other_seed = flights_table(FLIGHTS_TABLE, 1000, DEFAULT_SEED + 1).unwrap();
+        assert_ne!(table, other_seed);
+    }
+}
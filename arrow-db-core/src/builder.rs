@@ -0,0 +1,281 @@
+//! Fluent query builder.
+//!
+//! `Database::table` starts a query against a registered table that's built
+//! from DataFusion expressions instead of a SQL string:
+//!
+//! ```rust,ignore
+//! use datafusion::prelude::{col, lit};
+//!
+//! let record_batches = database
+//!     .table("users")?
+//!     .filter(col("id").gt(lit(2)))?
+//!     .select(vec![col("name")])?
+//!     .collect()
+//!     .await?;
+//! ```
+//!
+//! Each step extends a DataFusion plan directly, so mistakes like unknown
+//! columns are reported by the step that made them.  System columns are
+//! hidden from the results unless they're selected.
+
+use arrow::array::RecordBatch;
+use datafusion::{
+    common::{Column, JoinType},
+    datasource::provider_as_source,
+    logical_expr::{Expr, LogicalPlan, LogicalPlanBuilder, SortExpr},
+    prelude::DataFrame,
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    system::is_system_column,
+};
+
+/// A query built step by step against the tables of a database
+#[derive(Debug, Clone)]
+pub struct QueryBuilder<'a> {
+    database: &'a Database,
+    data_frame: DataFrame,
+    /// Whether the columns were selected, which keeps selected system columns
+    selected: bool,
+}
+
+impl Database {
+    /// Start a query that reads every row of a table
+    pub fn table(&self, table_name: &str) -> Result<QueryBuilder<'_>> {
+        if !self.providers.contains_key(table_name) {
+            self.add_table_context(table_name)?;
+        }
+
+        let provider = self
+            .providers
+            .get(table_name)
+            .map(|provider| provider.to_owned())
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+        let plan = LogicalPlanBuilder::scan(table_name, provider_as_source(provider), None)
+            .and_then(|builder| builder.build())
+            .map_err(|e| DbError::Query(format!("scan {table_name}"), e.to_string()))?;
+
+        Ok(QueryBuilder {
+            database: self,
+            data_frame: DataFrame::new(self.ctx.state(), plan),
+            selected: false,
+        })
+    }
+}
+
+impl QueryBuilder<'_> {
+    /// Keep the rows for which a predicate is true
+    pub fn filter(self, predicate: Expr) -> Result<Self> {
+        let step = format!("filter {predicate}");
+
+        self.map(&step, |data_frame| data_frame.filter(predicate))
+    }
+
+    /// Compute the columns of the results from expressions
+    pub fn select(mut self, exprs: Vec<Expr>) -> Result<Self> {
+        let step = format!("select {exprs:?}");
+        self.selected = true;
+
+        self.map(&step, |data_frame| data_frame.select(exprs))
+    }
+
+    /// Keep some of the columns
+    pub fn select_columns(mut self, column_names: &[&str]) -> Result<Self> {
+        let step = format!("select {column_names:?}");
+        self.selected = true;
+
+        self.map(&step, |data_frame| data_frame.select_columns(column_names))
+    }
+
+    /// Group the rows by expressions and compute aggregates of each group
+    pub fn aggregate(mut self, group_by: Vec<Expr>, aggregates: Vec<Expr>) -> Result<Self> {
+        let step = format!("aggregate {aggregates:?} by {group_by:?}");
+        self.selected = true;
+
+        self.map(&step, |data_frame| {
+            data_frame.aggregate(group_by, aggregates)
+        })
+    }
+
+    /// Sort the rows, e.g. by `col("id").sort(true, false)`
+    pub fn sort(self, exprs: Vec<SortExpr>) -> Result<Self> {
+        let step = format!("sort {exprs:?}");
+
+        self.map(&step, |data_frame| data_frame.sort(exprs))
+    }
+
+    /// Skip rows and keep up to `fetch` of the rest
+    pub fn limit(self, skip: usize, fetch: Option<usize>) -> Result<Self> {
+        self.map("limit", |data_frame| data_frame.limit(skip, fetch))
+    }
+
+    /// Remove duplicate rows
+    pub fn distinct(self) -> Result<Self> {
+        self.map("distinct", |data_frame| data_frame.distinct())
+    }
+
+    /// Join with another query on columns that are equal
+    pub fn join(
+        self,
+        right: QueryBuilder<'_>,
+        join_type: JoinType,
+        left_columns: &[&str],
+        right_columns: &[&str],
+    ) -> Result<Self> {
+        let selected = self.selected || right.selected;
+        let right = right.data_frame;
+        let mut joined = self.map("join", |data_frame| {
+            data_frame.join(right, join_type, left_columns, right_columns, None)
+        })?;
+        joined.selected = selected;
+
+        Ok(joined)
+    }
+
+    /// The plan of the query
+    pub fn logical_plan(&self) -> &LogicalPlan {
+        self.data_frame.logical_plan()
+    }
+
+    /// The query as a `DataFrame`, limited to the maximum rows of the query
+    /// options
+    pub fn into_data_frame(self) -> Result<DataFrame> {
+        let mut data_frame = self.data_frame;
+
+        if !self.selected {
+            let schema = data_frame.schema().to_owned();
+
+            if schema
+                .fields()
+                .iter()
+                .any(|field| is_system_column(field.name()))
+            {
+                let columns = schema
+                    .iter()
+                    .filter(|(_, field)| !is_system_column(field.name()))
+                    .map(|(qualifier, field)| {
+                        Expr::Column(Column::new(qualifier.cloned(), field.name()))
+                    })
+                    .collect();
+
+                data_frame = data_frame
+                    .select(columns)
+                    .map_err(|e| DbError::Query("hide system columns".into(), e.to_string()))?;
+            }
+        }
+
+        match self.database.options().max_rows {
+            Some(max_rows) => data_frame
+                .limit(0, Some(max_rows))
+                .map_err(|e| DbError::Query("limit rows".into(), e.to_string())),
+            None => Ok(data_frame),
+        }
+    }
+
+    /// Run the query, collecting its results
+    pub async fn collect(self) -> Result<Vec<RecordBatch>> {
+        let data_frame = self.into_data_frame()?;
+        let plan = data_frame.logical_plan().to_string();
+
+        data_frame
+            .collect()
+            .await
+            .map_err(|e| DbError::Query(plan, e.to_string()))
+    }
+
+    /// Apply a step to the plan, naming the step in errors
+    fn map(
+        self,
+        step: &str,
+        f: impl FnOnce(DataFrame) -> datafusion::error::Result<DataFrame>,
+    ) -> Result<Self> {
+        Ok(QueryBuilder {
+            data_frame: f(self.data_frame)
+                .map_err(|e| DbError::Query(step.into(), e.to_string()))?,
+            ..self
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, StringArray};
+    use datafusion::{
+        functions_aggregate::expr_fn::count,
+        prelude::{col, lit},
+    };
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_builder() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let names = |record_batches: Vec<RecordBatch>| {
+            record_batches
+                .iter()
+                .flat_map(|batch| {
+                    batch
+                        .column(0)
+                        .as_string::<i32>()
+                        .iter()
+                        .flatten()
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let record_batches = database
+            .table("users")
+            .unwrap()
+            .filter(col("id").gt(lit(2)))
+            .unwrap()
+            .select(vec![col("name")])
+            .unwrap()
+            .sort(vec![col("name").sort(false, false)])
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(names(record_batches), vec!["David", "Charlie"]);
+
+        let record_batches = database
+            .table("user_role")
+            .unwrap()
+            .join(
+                database.table("users").unwrap(),
+                JoinType::Inner,
+                &["user_id"],
+                &["id"],
+            )
+            .unwrap()
+            .aggregate(vec![col("role")], vec![count(col("id")).alias("count")])
+            .unwrap()
+            .sort(vec![col("role").sort(true, false)])
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            record_batches[0].column(0).as_string::<i32>(),
+            &StringArray::from(vec!["admin", "employee", "manager"])
+        );
+
+        // mistakes are reported by the step that made them
+        assert!(database.table("missing").is_err());
+        assert!(matches!(
+            database
+                .table("users")
+                .unwrap()
+                .filter(col("missing").eq(lit(1))),
+            Err(DbError::Query(_, _))
+        ));
+    }
+}
@@ -0,0 +1,601 @@
+//! Bulk loading.
+//!
+//! `bulk_load` appends record batches to a table, like a COPY statement.  The
+//! whole load is validated before anything is written: every row is cast to
+//! the types of the table and checked against its constraints, and all
+//! violations are reported together.  A load with any violation appends
+//! nothing.
+//!
+//! `insert_batch` is the fast path for programmatic loaders whose batches
+//! already have the columns of the table: the batch is checked against the
+//! constraints column by column and appended in one write.
+
+use std::collections::HashSet;
+
+use arrow::{
+    array::{new_null_array, Array, ArrayRef, AsArray, RecordBatch},
+    compute::{cast, cast_with_options, max, CastOptions},
+    datatypes::{DataType, Int64Type},
+};
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    change::TableChange,
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    row::cast_scalar,
+    table::Table,
+};
+
+/// Options of a bulk load
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkLoadOptions {
+    /// Cast columns to the types of the table, rejecting values that can't be
+    /// cast.  Otherwise the types must match exactly.
+    pub cast: bool,
+    /// Stop validating after this many violations, or `None` to find them all
+    pub max_violations: Option<usize>,
+    /// Validate the load without appending anything
+    pub dry_run: bool,
+}
+
+impl Default for BulkLoadOptions {
+    fn default() -> Self {
+        BulkLoadOptions {
+            cast: true,
+            max_violations: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// A row or column that can't be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkLoadViolation {
+    /// The row number across all batches of the load, or `None` if the whole
+    /// column is rejected
+    pub row: Option<usize>,
+    pub column: Option<String>,
+    pub reason: String,
+}
+
+/// The outcome of a bulk load
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BulkLoadReport {
+    /// The number of rows validated
+    pub rows_validated: usize,
+    /// The number of rows appended, which is zero unless every row is valid
+    pub rows_loaded: usize,
+    pub violations: Vec<BulkLoadViolation>,
+}
+
+impl BulkLoadReport {
+    /// Check if the load has no violations
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// The violations as a single message, e.g. for an error
+    pub fn violations_message(&self) -> String {
+        self.violations
+            .iter()
+            .map(|violation| match (violation.row, &violation.column) {
+                (Some(row), _) => format!("row {row}: {}", violation.reason),
+                (None, Some(column)) => format!("column {column}: {}", violation.reason),
+                (None, None) => violation.reason.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn violation(&mut self, row: Option<usize>, column: Option<&str>, reason: String) {
+        self.violations.push(BulkLoadViolation {
+            row,
+            column: column.map(Into::into),
+            reason,
+        });
+    }
+
+    /// Check if validation should stop
+    fn is_full(&self, options: &BulkLoadOptions) -> bool {
+        options
+            .max_violations
+            .is_some_and(|max_violations| self.violations.len() >= max_violations)
+    }
+}
+
+impl Database {
+    /// Append record batches to a table, validating the whole load first.
+    ///
+    /// Columns are matched by name.  Table columns missing from the batches
+    /// are loaded as NULL, so auto-increment and system columns are filled in
+    /// as they are for INSERTs.  If any row can't be cast to the table or
+    /// violates its constraints, nothing is appended and the report lists
+    /// every violation.
+    pub fn bulk_load(
+        &self,
+        table_name: &str,
+        batches: &[RecordBatch],
+        options: BulkLoadOptions,
+    ) -> Result<BulkLoadReport> {
+        let mut report = BulkLoadReport::default();
+
+        let change = {
+            let mut table = get_mut_table!(self, table_name)?;
+
+            // rows are staged on a copy, so sequences and the primary key
+            // index only change if the load is applied
+            let mut staged = table.to_owned();
+            let mut rows = vec![];
+            let mut offset = 0;
+
+            for batch in batches {
+                if report.is_full(&options) {
+                    break;
+                }
+
+                let aligned = align_columns(&staged, batch, offset, &options, &mut report)?;
+
+                if let Some((columns, rejected)) = aligned {
+                    for row in 0..batch.num_rows() {
+                        if report.is_full(&options) {
+                            break;
+                        }
+
+                        report.rows_validated += 1;
+
+                        if rejected.contains(&row) {
+                            continue;
+                        }
+
+                        let staged_row = columns
+                            .iter()
+                            .map(|column| {
+                                ScalarValue::try_from_array(column, row)
+                                    .map_err(|e| DbError::DataType(e.to_string()))
+                            })
+                            .collect::<Result<Vec<_>>>()
+                            .and_then(|values| staged.stage_row(values));
+
+                        match staged_row {
+                            Ok(values) => rows.push(values),
+                            Err(e) => report.violation(Some(offset + row), None, e.to_string()),
+                        }
+                    }
+                }
+
+                offset += batch.num_rows();
+            }
+
+            if let Some(max_violations) = options.max_violations {
+                report.violations.truncate(max_violations);
+            }
+
+            if !report.is_valid() || options.dry_run || rows.is_empty() {
+                return Ok(report);
+            }
+
+            let columns = (0..staged.num_columns())
+                .map(|column_index| {
+                    ScalarValue::iter_to_array(
+                        rows.iter().map(|values| values[column_index].to_owned()),
+                    )
+                    .map_err(|e| DbError::ArrayData(format!("Error loading rows: {e}")))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            staged.append_record_batch(Table::new_record_batch(staged.schema(), columns)?)?;
+            report.rows_loaded = rows.len();
+
+            let before = std::mem::replace(&mut *table, staged).chunks;
+            TableChange::inserted(before, &table.chunks)
+        };
+
+        let version = self.commit_table(table_name)?;
+        self.publish_change(table_name, version, &change)?;
+
+        Ok(report)
+    }
+
+    /// Append a record batch with the columns of a table in one write,
+    /// returning the number of rows appended.
+    ///
+    /// See `Table::append_batch`.
+    pub fn insert_batch(&self, table_name: &str, batch: RecordBatch) -> Result<usize> {
+        let change = {
+            let mut table = get_mut_table!(self, table_name)?;
+            let before = table.chunks.to_owned();
+            table.append_batch(batch)?;
+
+            TableChange::inserted(before, &table.chunks)
+        };
+
+        if change.rows.is_empty() {
+            return Ok(0);
+        }
+
+        let version = self.commit_table(table_name)?;
+        self.publish_change(table_name, version, &change)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(change.rows.len())
+    }
+}
+
+impl Table {
+    /// Append the rows of a record batch in one write, without casting or
+    /// filling in values row by row.
+    ///
+    /// The columns of the batch must have the names and types of the columns
+    /// of the table, in order.  The rows are checked against the constraints
+    /// of the table, and nothing is appended if any row violates them.  NULLs
+    /// in auto-increment and system columns are appended as NULL, so use
+    /// `Database::bulk_load` to fill them in.
+    pub fn append_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let schema = self.schema();
+        let batch_schema = batch.schema();
+
+        if batch_schema.fields().len() != schema.fields().len() {
+            return Err(DbError::DataType(format!(
+                "Table {} has {} columns, but the batch has {}",
+                self.name,
+                schema.fields().len(),
+                batch_schema.fields().len()
+            )));
+        }
+
+        for (field, batch_field) in schema.fields().iter().zip(batch_schema.fields()) {
+            if field.name() != batch_field.name() || field.data_type() != batch_field.data_type() {
+                return Err(DbError::DataType(format!(
+                    "Expected column {} of type {}, found {} of type {}",
+                    field.name(),
+                    field.data_type(),
+                    batch_field.name(),
+                    batch_field.data_type()
+                )));
+            }
+        }
+
+        let keys = self.check_batch(&batch)?;
+
+        // the batch takes the schema of the table, which fails on NULLs in
+        // non-nullable columns
+        let batch = Table::new_record_batch(schema, batch.columns().to_vec())?;
+
+        // explicit values advance the sequences past them, so they're never
+        // allocated again.  They're read before appending, so a column that
+        // can't be cast leaves the table unchanged.
+        let mut sequence_values = Vec::with_capacity(self.sequences.len());
+
+        for column_name in self.sequences.keys() {
+            let column = cast(
+                batch.column(self.column_index(column_name)?),
+                &DataType::Int64,
+            )
+            .map_err(|e| DbError::DataType(e.to_string()))?;
+
+            if let Some(value) = max(column.as_primitive::<Int64Type>()) {
+                sequence_values.push((column_name.to_owned(), value));
+            }
+        }
+
+        self.append_record_batch(batch)?;
+
+        for (column_name, value) in sequence_values {
+            if let Some(sequence) = self.sequences.get(&column_name) {
+                sequence.advance_past(value);
+            }
+        }
+
+        for key in keys {
+            self.insert_primary_key(key);
+        }
+
+        Ok(())
+    }
+
+    /// Fill, cast and check a row as `append_row` does, without appending it.
+    ///
+    /// The primary key of the row is added to the index, so later rows of the
+    /// same load can't reuse it.
+    fn stage_row(&mut self, mut values: Vec<ScalarValue>) -> Result<Vec<ScalarValue>> {
+        self.fill_auto_increment(&mut values)?;
+        self.fill_system_columns(&mut values)?;
+
+        let values = self
+            .schema()
+            .fields()
+            .iter()
+            .zip(values.iter())
+            .map(|(field, value)| cast_scalar(value, field.data_type()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let primary_key_index = self.primary_key_index()?;
+
+        if let Some(column_index) = primary_key_index {
+            self.check_primary_key(&values[column_index])?;
+        }
+
+        self.check_constraints(&values)?;
+
+        if let Some(column_index) = primary_key_index {
+            self.insert_primary_key(values[column_index].to_owned());
+        }
+
+        Ok(values)
+    }
+}
+
+/// Arrange the columns of a batch in table order, cast to the types of the
+/// table, along with the rows whose values can't be cast.
+///
+/// Returns `None` if the columns of the batch don't fit the table.
+fn align_columns(
+    table: &Table,
+    batch: &RecordBatch,
+    offset: usize,
+    options: &BulkLoadOptions,
+    report: &mut BulkLoadReport,
+) -> Result<Option<(Vec<ArrayRef>, HashSet<usize>)>> {
+    let violations = report.violations.len();
+    let mut columns = vec![];
+    let mut rejected = HashSet::new();
+
+    for field in batch.schema().fields() {
+        if table.column_index(field.name()).is_err() {
+            report.violation(
+                None,
+                Some(field.name()),
+                format!("Column is not in Table {}", table.name),
+            );
+        }
+    }
+
+    for field in table.schema().fields() {
+        let data_type = field.data_type();
+
+        let Some(column) = batch.column_by_name(field.name()) else {
+            columns.push(new_null_array(data_type, batch.num_rows()));
+            continue;
+        };
+
+        if column.data_type() == data_type {
+            columns.push(column.to_owned());
+            continue;
+        }
+
+        if !options.cast {
+            report.violation(
+                None,
+                Some(field.name()),
+                format!("Expected {data_type}, found {}", column.data_type()),
+            );
+            continue;
+        }
+
+        // unsafe casts fail on the first bad value, safe casts null it out
+        let cast_options = CastOptions {
+            safe: true,
+            ..Default::default()
+        };
+
+        let cast = match cast_with_options(column, data_type, &cast_options) {
+            Ok(cast) => cast,
+            Err(e) => {
+                report.violation(None, Some(field.name()), e.to_string());
+                continue;
+            }
+        };
+
+        for row in 0..column.len() {
+            if column.is_valid(row) && cast.is_null(row) {
+                let value = ScalarValue::try_from_array(column, row)
+                    .map_err(|e| DbError::DataType(e.to_string()))?;
+
+                report.violation(
+                    Some(offset + row),
+                    Some(field.name()),
+                    format!("Cannot cast {value} to {data_type}"),
+                );
+                rejected.insert(row);
+            }
+        }
+
+        columns.push(cast);
+    }
+
+    let fits = report.violations[violations..]
+        .iter()
+        .all(|violation| violation.row.is_some());
+
+    Ok(fits.then_some((columns, rejected)))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int32Array, Int64Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use arrow_schema::{DataType, Field, Schema};
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    fn users_batch(ids: ArrayRef, names: Vec<Option<&str>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", ids.data_type().to_owned(), true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+
+        RecordBatch::try_new(schema, vec![ids, Arc::new(StringArray::from(names))]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bulk_load() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        {
+            let mut users = get_mut_table!(database, "users").unwrap();
+            users.set_primary_key("id").unwrap();
+            users.set_not_null("name").unwrap();
+        }
+        database.add_all_table_contexts().unwrap();
+
+        // every violation is reported, and nothing is loaded
+        let invalid = users_batch(
+            Arc::new(Int32Array::from(vec![5, 1, 6, 5])),
+            vec![Some("Eve"), Some("Alice2"), None, Some("Eve2")],
+        );
+        let uncastable = users_batch(
+            Arc::new(StringArray::from(vec!["7", "seven"])),
+            vec![Some("Grace"), Some("Heidi")],
+        );
+        let report = database
+            .bulk_load(
+                "users",
+                &[invalid.to_owned(), uncastable],
+                BulkLoadOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(report.rows_validated, 6);
+        assert_eq!(report.rows_loaded, 0);
+        assert_eq!(
+            report
+                .violations
+                .iter()
+                .map(|violation| violation.row)
+                .collect::<Vec<_>>(),
+            vec![Some(1), Some(2), Some(3), Some(5)]
+        );
+        assert_eq!(report.violations[3].column.as_deref(), Some("id"));
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 4);
+
+        let report = database
+            .bulk_load(
+                "users",
+                &[invalid],
+                BulkLoadOptions {
+                    max_violations: Some(1),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(report.violations.len(), 1);
+
+        // columns are cast to the table types
+        let valid = users_batch(
+            Arc::new(Int64Array::from(vec![5, 6])),
+            vec![Some("Eve"), Some("Frank")],
+        );
+        let report = database
+            .bulk_load(
+                "users",
+                &[valid.to_owned()],
+                BulkLoadOptions {
+                    dry_run: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.rows_loaded, 0);
+
+        assert!(!database
+            .bulk_load(
+                "users",
+                &[valid.to_owned()],
+                BulkLoadOptions {
+                    cast: false,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .is_valid());
+
+        let report = database
+            .bulk_load("users", &[valid], BulkLoadOptions::default())
+            .unwrap();
+        assert_eq!(report.rows_loaded, 2);
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 2, 3, 4, 5, 6])
+        );
+        assert!(table
+            .primary_key
+            .as_ref()
+            .unwrap()
+            .contains(&ScalarValue::Int32(Some(6))));
+        drop(table);
+
+        let results = database
+            .query("select * from users where id > 4")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_insert_batch() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        // columns must match the table exactly
+        let uncast = users_batch(Arc::new(Int64Array::from(vec![5])), vec![Some("Eve")]);
+        assert!(matches!(
+            database.insert_batch("users", uncast),
+            Err(DbError::DataType(_))
+        ));
+
+        let duplicate = users_batch(
+            Arc::new(Int32Array::from(vec![5, 5])),
+            vec![Some("Eve"), Some("Eve2")],
+        );
+        assert!(matches!(
+            database.insert_batch("users", duplicate),
+            Err(DbError::ConstraintViolation(_))
+        ));
+        assert_eq!(database.table_version("users").unwrap(), 0);
+
+        let batch = users_batch(
+            Arc::new(Int32Array::from(vec![5, 6])),
+            vec![Some("Eve"), Some("Frank")],
+        );
+        assert_eq!(database.insert_batch("users", batch.to_owned()).unwrap(), 2);
+        assert!(database.insert_batch("users", batch).is_err());
+        assert_eq!(
+            database
+                .get_row("users", &ScalarValue::Int32(Some(6)))
+                .unwrap()
+                .unwrap()
+                .get("name"),
+            Some(&ScalarValue::Utf8(Some("Frank".into())))
+        );
+
+        let results = database
+            .query("select * from users where id > 4")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 2);
+    }
+}
@@ -0,0 +1,343 @@
+//! Catalogs of databases.
+//!
+//! A catalog groups databases as schemas, so the tables of each database are
+//! isolated from the others but can be queried together with qualified names,
+//! like `SELECT * FROM tenant_a.users JOIN tenant_b.users USING (id)`.  Each
+//! schema is named after its database, and unqualified names resolve to the
+//! `public` schema.
+//!
+//! Queries are planned in the catalog's own context against the providers of
+//! the databases, so they see committed writes without copying any rows.
+//! INSERT, UPDATE and DELETE statements are run by the database of the table
+//! they write to, with its constraints, triggers and system columns.
+//! Functions registered with a database are only available in its own
+//! queries.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use datafusion::{
+    prelude::{DataFrame, SessionConfig, SessionContext},
+    sql::{
+        parser::Statement,
+        sqlparser::ast::{
+            Delete, FromTable, Ident, Insert, ObjectName, Statement as SqlStatement, TableFactor,
+        },
+        TableReference,
+    },
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    json::register_json_functions,
+    system::hide_system_columns,
+};
+
+/// The schema unqualified table names resolve to
+pub const DEFAULT_SCHEMA: &str = "public";
+
+pub struct Catalog {
+    pub name: String,
+    /// The databases of the catalog, keyed by schema name
+    schemas: DashMap<String, Arc<Database>>,
+    ctx: SessionContext,
+}
+
+impl Catalog {
+    pub fn new(name: impl Into<String>) -> Result<Catalog> {
+        let name = name.into();
+
+        if name.contains(" ") {
+            return Err(DbError::CreateDatabase(
+                "Catalog name cannot contain spaces".into(),
+            ));
+        }
+
+        let config = SessionConfig::new()
+            .with_information_schema(true)
+            .with_default_catalog_and_schema(name.as_str(), DEFAULT_SCHEMA);
+
+        let ctx = SessionContext::new_with_config(config);
+        register_json_functions(&ctx);
+
+        Ok(Catalog {
+            name,
+            schemas: DashMap::new(),
+            ctx,
+        })
+    }
+
+    /// Add a database to the catalog as a schema named after it
+    pub async fn add_schema(&self, database: Database) -> Result<Arc<Database>> {
+        let schema_name = database.name.to_owned();
+
+        if self.schemas.contains_key(&schema_name) {
+            return Err(DbError::SchemaAlreadyExists(schema_name));
+        }
+
+        // the schema outlives a removed database, so it may already exist
+        let sql = format!(
+            "CREATE SCHEMA IF NOT EXISTS {}",
+            Ident::with_quote('"', schema_name.as_str())
+        );
+        self.ctx
+            .sql(&sql)
+            .await
+            .map_err(|e| DbError::Query(sql, e.to_string()))?;
+
+        database.add_all_table_contexts()?;
+
+        let database = Arc::new(database);
+        self.schemas.insert(schema_name, database.to_owned());
+
+        Ok(database)
+    }
+
+    /// The database of a schema
+    pub fn schema(&self, schema_name: &str) -> Result<Arc<Database>> {
+        self.schemas
+            .get(schema_name)
+            .or_else(|| self.schemas.get(schema_name.to_lowercase().as_str()))
+            .map(|database| database.to_owned())
+            .ok_or_else(|| DbError::SchemaNotFound(schema_name.into()))
+    }
+
+    /// Remove a schema from the catalog, returning its database
+    pub fn remove_schema(&self, schema_name: &str) -> Result<Arc<Database>> {
+        let (schema_name, database) = self
+            .schemas
+            .remove(schema_name)
+            .ok_or_else(|| DbError::SchemaNotFound(schema_name.into()))?;
+
+        for table_name in self.registered_table_names(&schema_name) {
+            self.deregister_table(&schema_name, &table_name)?;
+        }
+
+        Ok(database)
+    }
+
+    /// The names of the schemas, sorted
+    pub fn schema_names(&self) -> Vec<String> {
+        let mut schema_names = self
+            .schemas
+            .iter()
+            .map(|schema| schema.key().to_owned())
+            .collect::<Vec<_>>();
+        schema_names.sort();
+
+        schema_names
+    }
+
+    /// Execute a SQL query against the schemas of the catalog
+    pub async fn query(&self, sql: &str) -> Result<DataFrame> {
+        let state = self.ctx.state();
+        let dialect = state.config().options().sql_parser.dialect.to_owned();
+        let mut statement = state
+            .sql_to_statement(sql, &dialect)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        // writes are run by the database of the table, with the schema
+        // stripped from its name
+        if let Some(schema_name) = unqualify_dml_table(&mut statement) {
+            return self
+                .schema(&schema_name)?
+                .query(&statement.to_string())
+                .await;
+        }
+
+        self.register_tables()?;
+
+        let plan = self
+            .ctx
+            .state()
+            .statement_to_plan(statement)
+            .await
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        self.ctx
+            .execute_logical_plan(hide_system_columns(plan)?)
+            .await
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))
+    }
+
+    /// Register the providers of the tables of every schema with the context,
+    /// dropping tables that were removed from their database
+    fn register_tables(&self) -> Result<()> {
+        for schema in self.schemas.iter() {
+            let (schema_name, database) = schema.pair();
+
+            for table_name in self.registered_table_names(schema_name) {
+                if !database.providers.contains_key(&table_name) {
+                    self.deregister_table(schema_name, &table_name)?;
+                }
+            }
+
+            for provider in database.providers.iter() {
+                let table = TableReference::partial(schema_name.as_str(), provider.key().as_str());
+
+                self.ctx
+                    .deregister_table(table.to_owned())
+                    .and_then(|_| self.ctx.register_table(table, provider.value().to_owned()))
+                    .map_err(|e| {
+                        DbError::Query(format!("register {schema_name}"), e.to_string())
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn registered_table_names(&self, schema_name: &str) -> Vec<String> {
+        self.ctx
+            .catalog(&self.name)
+            .and_then(|catalog| catalog.schema(schema_name))
+            .map(|schema| schema.table_names())
+            .unwrap_or_default()
+    }
+
+    fn deregister_table(&self, schema_name: &str, table_name: &str) -> Result<()> {
+        self.ctx
+            .deregister_table(TableReference::partial(schema_name, table_name))
+            .map(|_| ())
+            .map_err(|e| DbError::Query(format!("deregister {schema_name}"), e.to_string()))
+    }
+}
+
+/// Strip the schema from the table name of an INSERT, UPDATE or DELETE
+/// statement, returning the schema it writes to
+fn unqualify_dml_table(statement: &mut Statement) -> Option<String> {
+    let table_name = dml_table_name(statement)?;
+    let (name, qualifiers) = table_name.0.split_last()?;
+    let schema_name = qualifiers
+        .last()
+        .map_or(DEFAULT_SCHEMA.into(), |schema| schema.value.to_owned());
+    table_name.0 = vec![name.to_owned()];
+
+    Some(schema_name)
+}
+
+/// The name of the table an INSERT, UPDATE or DELETE statement writes to
+fn dml_table_name(statement: &mut Statement) -> Option<&mut ObjectName> {
+    let Statement::Statement(sql_statement) = statement else {
+        return None;
+    };
+
+    let relation = match sql_statement.as_mut() {
+        SqlStatement::Insert(Insert { table_name, .. }) => return Some(table_name),
+        SqlStatement::Update { table, .. } => &mut table.relation,
+        SqlStatement::Delete(Delete {
+            from: FromTable::WithFromKeyword(tables) | FromTable::WithoutKeyword(tables),
+            ..
+        }) => &mut tables.first_mut()?.relation,
+        _ => return None,
+    };
+
+    match relation {
+        TableFactor::Table { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, RecordBatch, StringArray};
+    use arrow::compute::concat_batches;
+    use arrow::datatypes::Int64Type;
+
+    use crate::{database::tests::seed_database, table::Table};
+
+    use super::*;
+
+    fn create_tenant(name: &str) -> Database {
+        let mut database = Database::new(name).unwrap();
+        database.add_table(Table::new("users")).unwrap();
+        database.add_table(Table::new("user_role")).unwrap();
+        seed_database(&mut database);
+
+        database
+    }
+
+    async fn collect(catalog: &Catalog, sql: &str) -> RecordBatch {
+        let df = catalog.query(sql).await.unwrap();
+        let schema = Arc::new(df.schema().as_arrow().to_owned());
+
+        concat_batches(&schema, &df.collect().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_catalog() {
+        let catalog = Catalog::new("tenants").unwrap();
+
+        catalog.add_schema(create_tenant("tenant_a")).await.unwrap();
+        let tenant_b = catalog.add_schema(create_tenant("tenant_b")).await.unwrap();
+
+        assert_eq!(catalog.schema_names(), vec!["tenant_a", "tenant_b"]);
+        assert!(matches!(
+            catalog.add_schema(create_tenant("tenant_a")).await,
+            Err(DbError::SchemaAlreadyExists(_))
+        ));
+
+        // writes are isolated to the schema they name
+        catalog
+            .query("insert into tenant_b.users values (5, 'Eve')")
+            .await
+            .unwrap();
+        catalog
+            .query("delete from tenant_a.users where id = 1")
+            .await
+            .unwrap();
+        assert_eq!(tenant_b.tables.get("users").unwrap().num_rows(), 5);
+        assert_eq!(
+            catalog
+                .schema("tenant_a")
+                .unwrap()
+                .tables
+                .get("users")
+                .unwrap()
+                .num_rows(),
+            3
+        );
+
+        // qualified names query across schemas
+        let joined = collect(
+            &catalog,
+            "select b.name, count(a.id) as a_count \
+             from tenant_b.users b left join tenant_a.users a on a.id = b.id \
+             group by b.name order by b.name",
+        )
+        .await;
+        assert_eq!(
+            joined.column(0).as_string::<i32>(),
+            &StringArray::from(vec!["Alice", "Bob", "Charlie", "David", "Eve"])
+        );
+        assert_eq!(
+            joined.column(1).as_primitive::<Int64Type>().values(),
+            &[0, 1, 1, 1, 0]
+        );
+
+        // unqualified names resolve to the public schema
+        assert!(catalog.query("select * from users").await.is_err());
+        catalog
+            .add_schema(create_tenant(DEFAULT_SCHEMA))
+            .await
+            .unwrap();
+        catalog
+            .query("delete from users where id > 1")
+            .await
+            .unwrap();
+        let public = collect(&catalog, "select name from users").await;
+        assert_eq!(
+            public.column(0).as_string::<i32>(),
+            &StringArray::from(vec!["Alice"])
+        );
+
+        // removed schemas can't be queried
+        catalog.remove_schema("tenant_a").unwrap();
+        assert!(catalog.query("select * from tenant_a.users").await.is_err());
+        assert!(matches!(
+            catalog.query("delete from tenant_a.users").await,
+            Err(DbError::SchemaNotFound(_))
+        ));
+    }
+}
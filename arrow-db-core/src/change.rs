@@ -0,0 +1,210 @@
+//! Change data capture.
+//!
+//! Subscribers to a table receive a `ChangeEvent` for every committed
+//! statement or bulk load that changes its rows, with the rows before and
+//! after the change, so UIs and downstream systems can react to writes.
+//!
+//! Events are sent on unbounded channels, so writes never wait on a slow
+//! subscriber.  Dropping the receiver ends the subscription.
+
+use arrow::array::RecordBatch;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    chunk::take_rows,
+    database::Database,
+    error::{DbError, Result},
+};
+
+/// The kind of change made to the rows of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// The rows changed by a statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    pub table_name: String,
+    pub kind: ChangeKind,
+    /// The version of the table the change was committed as
+    pub version: u64,
+    /// The indexes of the changed rows in the table, after the change for
+    /// inserts and updates, and before the change for deletes
+    pub rows: Vec<usize>,
+    /// The changed rows before the change, for updates and deletes
+    pub before: Option<RecordBatch>,
+    /// The changed rows after the change, for inserts and updates
+    pub after: Option<RecordBatch>,
+}
+
+/// A stream of the changes to a table
+pub type ChangeStream = UnboundedReceiver<ChangeEvent>;
+
+/// The chunks of a table before and after a write, and the indexes of the
+/// rows it changed.  Updated rows keep their index, and inserted rows are
+/// indexes into `after`.
+#[derive(Debug)]
+pub(crate) struct TableChange {
+    pub(crate) kind: ChangeKind,
+    pub(crate) rows: Vec<usize>,
+    pub(crate) before: Vec<RecordBatch>,
+    pub(crate) after: Vec<RecordBatch>,
+}
+
+impl TableChange {
+    /// The rows appended to a table since it was `before`
+    pub(crate) fn inserted(before: Vec<RecordBatch>, after: &[RecordBatch]) -> TableChange {
+        let num_rows = |chunks: &[RecordBatch]| chunks.iter().map(|chunk| chunk.num_rows()).sum();
+
+        TableChange {
+            kind: ChangeKind::Insert,
+            rows: (num_rows(&before)..num_rows(after)).collect(),
+            before,
+            after: after.to_vec(),
+        }
+    }
+
+    /// The event of the change, with the changed rows
+    pub(crate) fn event(&self, table_name: &str, version: u64) -> Result<ChangeEvent> {
+        let rows = |chunks: &[RecordBatch]| take_rows(chunks, &self.rows);
+
+        Ok(ChangeEvent {
+            table_name: table_name.into(),
+            kind: self.kind,
+            version,
+            rows: self.rows.to_owned(),
+            before: match self.kind {
+                ChangeKind::Insert => None,
+                ChangeKind::Update | ChangeKind::Delete => Some(rows(&self.before)?),
+            },
+            after: match self.kind {
+                ChangeKind::Delete => None,
+                ChangeKind::Insert | ChangeKind::Update => Some(rows(&self.after)?),
+            },
+        })
+    }
+}
+
+impl Database {
+    /// Subscribe to the changes of a table, returning a stream of events
+    pub fn subscribe(&self, table_name: &str) -> Result<ChangeStream> {
+        if !self.tables.contains_key(table_name) {
+            return Err(DbError::TableNotFound(table_name.into()));
+        }
+
+        let (sender, receiver) = unbounded();
+        self.subscribers
+            .entry(table_name.into())
+            .or_default()
+            .push(sender);
+
+        Ok(receiver)
+    }
+
+    /// Send a committed change to the subscribers of a table, dropping
+    /// subscribers whose stream was dropped, and apply it to the sketch of the
+    /// table
+    pub(crate) fn publish_change(
+        &self,
+        table_name: &str,
+        version: u64,
+        change: &TableChange,
+    ) -> Result<()> {
+        self.update_sketch(table_name, version, change)?;
+
+        let Some(mut subscribers) = self.subscribers.get_mut(table_name) else {
+            return Ok(());
+        };
+
+        subscribers.retain(|sender| !sender.is_closed());
+
+        if subscribers.is_empty() || change.rows.is_empty() {
+            return Ok(());
+        }
+
+        let event = change.event(table_name, version)?;
+
+        subscribers.retain(|sender: &UnboundedSender<ChangeEvent>| {
+            sender.unbounded_send(event.to_owned()).is_ok()
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use futures::StreamExt;
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    fn ids(batch: &Option<RecordBatch>) -> Int32Array {
+        batch
+            .as_ref()
+            .unwrap()
+            .column(0)
+            .as_primitive::<Int32Type>()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let mut changes = database.subscribe("users").unwrap();
+        assert!(database.subscribe("missing").is_err());
+
+        database
+            .query("insert into users values (5, 'Eve'), (6, 'Frank')")
+            .await
+            .unwrap();
+        let event = changes.next().await.unwrap();
+        assert_eq!(event.kind, ChangeKind::Insert);
+        assert_eq!(event.version, 1);
+        assert_eq!(event.before, None);
+        assert_eq!(ids(&event.after), Int32Array::from(vec![5, 6]));
+
+        database
+            .query("update users set name = 'Bobby' where id = 2")
+            .await
+            .unwrap();
+        let event = changes.next().await.unwrap();
+        assert_eq!(event.kind, ChangeKind::Update);
+        assert_eq!(
+            event.before.as_ref().unwrap().column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Bob"])
+        );
+        assert_eq!(
+            event.after.as_ref().unwrap().column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Bobby"])
+        );
+
+        // statements that change no rows send no events
+        database
+            .query("delete from users where id > 100")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id > 4")
+            .await
+            .unwrap();
+        let event = changes.next().await.unwrap();
+        assert_eq!(event.kind, ChangeKind::Delete);
+        assert_eq!(ids(&event.before), Int32Array::from(vec![5, 6]));
+        assert_eq!(event.after, None);
+
+        // dropped streams are unsubscribed
+        drop(changes);
+        database.query("delete from users").await.unwrap();
+        assert!(database.subscribers.get("users").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,430 @@
+//! Checkpoints.
+//!
+//! A checkpoint writes every table of a database to a numbered directory,
+//! along with a manifest of the tables, so the database can be rolled back to
+//! any earlier checkpoint with `restore`.
+//!
+//! ```text
+//! checkpoints/
+//!   1/
+//!     manifest.json
+//!     users.arrow
+//!     user_role.arrow
+//!   2/
+//!     ...
+//! ```
+//!
+//! Tables are stored as archive entries, so primary keys and auto-increment
+//! sequences are restored with the data.  A checkpoint is written to a
+//! temporary directory and renamed into place, so a failed checkpoint never
+//! leaves a partial version behind.
+//!
+//! `query_at` runs a query against the tables of a checkpoint, chosen by
+//! version or by time, without restoring the database.  Only the tables the
+//! query references are loaded.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use datafusion::prelude::DataFrame;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The tables of a checkpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointManifest {
+    pub version: u64,
+    /// When the checkpoint was taken, in milliseconds since the Unix epoch
+    pub created_at: i64,
+    pub tables: Vec<CheckpointTable>,
+}
+
+/// A table of a checkpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointTable {
+    pub name: String,
+    pub num_rows: usize,
+    /// The schema of the table when the checkpoint was taken
+    pub schema: String,
+}
+
+/// The checkpoint to run a query against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsOf {
+    /// The checkpoint with a version
+    Version(u64),
+    /// The latest checkpoint taken at or before a time
+    Timestamp(DateTime<Utc>),
+}
+
+impl Database {
+    /// Write every table to a new checkpoint in the directory of the database,
+    /// returning its manifest
+    pub async fn checkpoint(&self) -> Result<CheckpointManifest> {
+        self.checkpoint_to(&self.checkpoint_path()).await
+    }
+
+    /// Write every table to a new checkpoint in a directory, returning its
+    /// manifest
+    pub async fn checkpoint_to(&self, path: &str) -> Result<CheckpointManifest> {
+        // tables are copied before any file is written, so the checkpoint is
+        // a consistent snapshot and no table is locked while writing
+        let mut tables = vec![];
+        let mut entries = vec![];
+
+        for table in self.tables.iter() {
+            let table = table.unspilled()?;
+            tables.push(CheckpointTable {
+                name: table.name.to_owned(),
+                num_rows: table.num_rows(),
+                schema: table.schema().to_string(),
+            });
+            entries.push(table.export_archive_entry()?);
+        }
+
+        let version = checkpoint_versions(path).await?.last().unwrap_or(&0) + 1;
+        let manifest = CheckpointManifest {
+            version,
+            created_at: Utc::now().timestamp_millis(),
+            tables,
+        };
+        let manifest_json =
+            serde_json::to_vec_pretty(&manifest).map_err(|e| checkpoint_error(version, e))?;
+
+        let temp_path = format!("{path}/{version}.tmp");
+        create_dir(&temp_path, version).await?;
+
+        for (table, entry) in manifest.tables.iter().zip(entries) {
+            write_file(&format!("{temp_path}/{}.arrow", table.name), entry, version).await?;
+        }
+
+        write_file(
+            &format!("{temp_path}/{MANIFEST_FILE}"),
+            manifest_json,
+            version,
+        )
+        .await?;
+
+        tokio::fs::rename(&temp_path, format!("{path}/{version}"))
+            .await
+            .map_err(|e| checkpoint_error(version, e))?;
+
+        Ok(manifest)
+    }
+
+    /// Replace every table of the database with the tables of a checkpoint in
+    /// the directory of the database
+    pub async fn restore(&self, version: u64) -> Result<CheckpointManifest> {
+        self.restore_from(&self.checkpoint_path(), version).await
+    }
+
+    /// Replace every table of the database with the tables of a checkpoint in
+    /// a directory.
+    ///
+    /// The whole checkpoint is read before any table is replaced, so a missing
+    /// or malformed checkpoint leaves the database untouched.  Linked tables
+    /// and materialized views that aren't in the checkpoint are dropped.
+    pub async fn restore_from(&self, path: &str, version: u64) -> Result<CheckpointManifest> {
+        let (manifest, tables) = read_checkpoint(path, version).await?;
+
+        for table in self.tables.iter() {
+            self.ctx
+                .deregister_table(table.name.as_str())
+                .map_err(|e| checkpoint_error(version, e))?;
+        }
+
+        self.tables.clear();
+
+        for table in tables {
+            self.tables.insert(table.name.to_owned(), table);
+        }
+
+        self.linked_tables
+            .retain(|table_name, _| self.tables.contains_key(table_name.as_str()));
+        self.views
+            .retain(|table_name, _| self.tables.contains_key(table_name.as_str()));
+
+        self.add_all_table_contexts()?;
+
+        Ok(manifest)
+    }
+
+    /// Run a query against a checkpoint in the directory of the database
+    pub async fn query_at(&self, sql: &str, as_of: AsOf) -> Result<DataFrame> {
+        self.query_at_from(&self.checkpoint_path(), sql, as_of)
+            .await
+    }
+
+    /// Run a query against a checkpoint in a directory, returning a
+    /// `DataFrame`.
+    ///
+    /// The tables the query references are loaded from the checkpoint into a
+    /// separate database with the same options and functions, so the tables
+    /// of this database are untouched.  Only queries are allowed, since writes
+    /// to a checkpoint would be lost.
+    pub async fn query_at_from(&self, path: &str, sql: &str, as_of: AsOf) -> Result<DataFrame> {
+        let statement = self.read_only_statement(sql)?;
+        let table_names = self
+            .ctx
+            .state()
+            .resolve_table_references(&statement)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?
+            .iter()
+            .map(|reference| reference.table().to_owned())
+            .collect::<Vec<_>>();
+
+        let version = match as_of {
+            AsOf::Version(version) => version,
+            AsOf::Timestamp(timestamp) => checkpoint_at(path, timestamp).await?,
+        };
+        let manifest = read_manifest(path, version).await?;
+        let snapshot = self.read_only_database()?;
+
+        // tables that aren't in the checkpoint fail to plan, like any unknown
+        // table
+        for checkpoint_table in manifest.tables.iter() {
+            if table_names.contains(&checkpoint_table.name) {
+                let table = read_checkpoint_table(path, version, &checkpoint_table.name).await?;
+                snapshot.tables.insert(table.name.to_owned(), table);
+            }
+        }
+
+        snapshot.add_all_table_contexts()?;
+        snapshot.query(sql).await
+    }
+
+    /// The versions of the checkpoints in the directory of the database, in
+    /// ascending order
+    pub async fn checkpoints(&self) -> Result<Vec<u64>> {
+        checkpoint_versions(&self.checkpoint_path()).await
+    }
+
+    fn checkpoint_path(&self) -> String {
+        format!("{}/checkpoints", self.disk_path())
+    }
+}
+
+/// Read the manifest and the tables of a checkpoint
+pub(crate) async fn read_checkpoint(
+    path: &str,
+    version: u64,
+) -> Result<(CheckpointManifest, Vec<Table>)> {
+    let manifest = read_manifest(path, version).await?;
+    let mut tables = vec![];
+
+    for checkpoint_table in manifest.tables.iter() {
+        tables.push(read_checkpoint_table(path, version, &checkpoint_table.name).await?);
+    }
+
+    Ok((manifest, tables))
+}
+
+/// Read the manifest of a checkpoint
+pub(crate) async fn read_manifest(path: &str, version: u64) -> Result<CheckpointManifest> {
+    let manifest = read_file(&format!("{path}/{version}/{MANIFEST_FILE}"), version).await?;
+
+    serde_json::from_slice(&manifest).map_err(|e| checkpoint_error(version, e))
+}
+
+/// Read a table of a checkpoint
+async fn read_checkpoint_table(path: &str, version: u64, name: &str) -> Result<Table> {
+    let entry = read_file(&format!("{path}/{version}/{name}.arrow"), version).await?;
+    let mut table = Table::new(name);
+    table.import_archive_entry(&entry)?;
+
+    Ok(table)
+}
+
+/// The version of the latest checkpoint taken at or before a time
+async fn checkpoint_at(path: &str, timestamp: DateTime<Utc>) -> Result<u64> {
+    for version in checkpoint_versions(path).await?.into_iter().rev() {
+        if read_manifest(path, version).await?.created_at <= timestamp.timestamp_millis() {
+            return Ok(version);
+        }
+    }
+
+    Err(DbError::Checkpoint(format!(
+        "no checkpoint at or before {timestamp}"
+    )))
+}
+
+/// The versions of the checkpoints in a directory, in ascending order.
+/// Temporary directories of unfinished checkpoints are skipped.
+pub(crate) async fn checkpoint_versions(path: &str) -> Result<Vec<u64>> {
+    if !Path::new(path).exists() {
+        return Ok(vec![]);
+    }
+
+    let mut entries = tokio::fs::read_dir(path)
+        .await
+        .map_err(|e| DbError::Checkpoint(format!("directory {path}: {e}")))?;
+    let mut versions = vec![];
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Some(version) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            versions.push(version);
+        }
+    }
+
+    versions.sort();
+
+    Ok(versions)
+}
+
+async fn create_dir(path: &str, version: u64) -> Result<()> {
+    tokio::fs::create_dir_all(path)
+        .await
+        .map_err(|e| checkpoint_error(version, e))
+}
+
+async fn write_file(file_name: &str, bytes: Vec<u8>, version: u64) -> Result<()> {
+    tokio::fs::write(file_name, bytes)
+        .await
+        .map_err(|e| checkpoint_error(version, e))
+}
+
+async fn read_file(file_name: &str, version: u64) -> Result<Vec<u8>> {
+    tokio::fs::read(file_name)
+        .await
+        .map_err(|e| checkpoint_error(version, e))
+}
+
+fn checkpoint_error(version: u64, error: impl ToString) -> DbError {
+    DbError::Checkpoint(format!("version {version}: {}", error.to_string()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::{array::AsArray, datatypes::Int64Type};
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        let path = std::env::temp_dir()
+            .join("arrow_db_checkpoints")
+            .display()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&path);
+
+        let first = database.checkpoint_to(&path).await.unwrap();
+        assert_eq!(first.version, 1);
+        assert_eq!(first.tables.len(), 2);
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database.tables.remove("user_role");
+        database.ctx.deregister_table("user_role").unwrap();
+
+        let second = database.checkpoint_to(&path).await.unwrap();
+        assert_eq!(second.version, 2);
+        assert_eq!(second.tables[0].num_rows, 5);
+        assert_eq!(checkpoint_versions(&path).await.unwrap(), vec![1, 2]);
+
+        // roll back to the first checkpoint
+        database.restore_from(&path, 1).await.unwrap();
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 4);
+        let results = database
+            .query("select * from user_role")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 4);
+
+        // primary keys are restored
+        assert!(database
+            .query("insert into users values (1, 'Alice2')")
+            .await
+            .is_err());
+
+        // a missing checkpoint leaves the database untouched
+        assert!(matches!(
+            database.restore_from(&path, 3).await.unwrap_err(),
+            DbError::Checkpoint(_)
+        ));
+        assert_eq!(database.tables.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_at() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let path = std::env::temp_dir()
+            .join("arrow_db_query_at")
+            .display()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&path);
+
+        let count_users = |as_of| {
+            let (database, path) = (&database, &path);
+            async move {
+                let results = database
+                    .query_at_from(path, "select count(*) from users", as_of)
+                    .await?
+                    .collect()
+                    .await
+                    .unwrap();
+
+                Ok::<_, DbError>(results[0].column(0).as_primitive::<Int64Type>().value(0))
+            }
+        };
+
+        database.checkpoint_to(&path).await.unwrap();
+        let first = Utc::now();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database.checkpoint_to(&path).await.unwrap();
+        database.query("delete from users").await.unwrap();
+
+        assert_eq!(count_users(AsOf::Version(1)).await.unwrap(), 4);
+        assert_eq!(count_users(AsOf::Version(2)).await.unwrap(), 5);
+        assert_eq!(count_users(AsOf::Timestamp(first)).await.unwrap(), 4);
+        assert_eq!(count_users(AsOf::Timestamp(Utc::now())).await.unwrap(), 5);
+        assert!(
+            count_users(AsOf::Timestamp(first - chrono::Duration::days(1)))
+                .await
+                .is_err()
+        );
+
+        // the database itself is untouched, and checkpoints can't be written
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 0);
+        assert!(database
+            .query_at_from(&path, "delete from users", AsOf::Version(1))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,456 @@
+//! Chunked storage.
+//!
+//! The rows of a table are stored in chunks, each a `RecordBatch` of up to
+//! `chunk_size` rows with the schema of the table.  Appending rows only
+//! rewrites the last chunk, so an append costs the size of a chunk rather than
+//! the size of the table, and imported batches are kept as chunks instead of
+//! being concatenated.
+//!
+//! Deletes and updates rewrite only the chunks with affected rows.  Operations
+//! that need every row of a column at once, like the column API, concatenate
+//! the chunks with `record_batch`.
+//!
+//! A table always has at least one chunk, so an empty table keeps its schema.
+
+use std::ops::Range;
+
+use arrow::{
+    array::{ArrayRef, RecordBatch, UInt64Array},
+    compute::{concat, concat_batches, take_record_batch},
+};
+use arrow_schema::SchemaRef;
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_table,
+    merge::{align_batch, merge_schemas},
+    row::get_column_value,
+    table::Table,
+};
+
+/// The default number of rows per chunk
+pub const DEFAULT_CHUNK_SIZE: usize = 65_536;
+
+impl Table {
+    /// The schema of the table
+    pub fn schema(&self) -> SchemaRef {
+        self.chunks[0].schema()
+    }
+
+    /// The number of rows in the table
+    pub fn num_rows(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.num_rows()).sum()
+    }
+
+    /// The number of columns in the table
+    pub fn num_columns(&self) -> usize {
+        self.chunks[0].num_columns()
+    }
+
+    /// The rows of the table as a single `RecordBatch`.
+    ///
+    /// Tables with more than one chunk are concatenated, which copies them.
+    pub fn record_batch(&self) -> Result<RecordBatch> {
+        match self.chunks.as_slice() {
+            [chunk] => Ok(chunk.to_owned()),
+            chunks => concat_batches(&self.schema(), chunks)
+                .map_err(|e| DbError::ArrayData(format!("Error concatenating chunks: {e}"))),
+        }
+    }
+
+    /// The rows from `offset` to `offset + length` as a single `RecordBatch`.
+    ///
+    /// Rows within a chunk are sliced without copying, and only windows that
+    /// span chunks are concatenated.  Rows past the end of the table are
+    /// ignored, so the batch can have fewer than `length` rows.
+    pub fn slice(&self, offset: usize, length: usize) -> Result<RecordBatch> {
+        combine(&self.schema(), &slice_chunks(&self.chunks, offset, length))
+    }
+
+    /// Every row of a column as a single array
+    pub fn column(&self, column_index: usize) -> Result<ArrayRef> {
+        if column_index >= self.num_columns() {
+            return Err(DbError::ColumnIndexOutOfBounds(
+                column_index,
+                self.name.to_owned(),
+            ));
+        }
+
+        match self.chunks.as_slice() {
+            [chunk] => Ok(chunk.column(column_index).to_owned()),
+            chunks => {
+                let columns = chunks
+                    .iter()
+                    .map(|chunk| chunk.column(column_index).as_ref())
+                    .collect::<Vec<_>>();
+
+                concat(&columns)
+                    .map_err(|e| DbError::ArrayData(format!("Error concatenating chunks: {e}")))
+            }
+        }
+    }
+
+    /// The value of a column at a row
+    pub fn cell(&self, column_index: usize, row: usize) -> Result<ScalarValue> {
+        let (chunk, offset) = self.locate(row)?;
+        get_column_value(self.chunks[chunk].column(column_index), offset)
+    }
+
+    /// The values of every column at a row
+    pub fn row_values(&self, row: usize) -> Result<Vec<ScalarValue>> {
+        let (chunk, offset) = self.locate(row)?;
+
+        self.chunks[chunk]
+            .columns()
+            .iter()
+            .map(|column| get_column_value(column, offset))
+            .collect()
+    }
+
+    /// Replace the rows of the table, splitting them into chunks.
+    ///
+    /// The chunks are slices of the record batch, so nothing is copied.
+    pub fn set_record_batch(&mut self, record_batch: RecordBatch) {
+        let num_rows = record_batch.num_rows();
+
+        self.chunks = match num_rows {
+            0 => vec![record_batch],
+            _ => (0..num_rows)
+                .step_by(self.chunk_size)
+                .map(|offset| record_batch.slice(offset, self.chunk_size.min(num_rows - offset)))
+                .collect(),
+        };
+    }
+
+    /// Replace the rows of the table with record batches of a schema,
+    /// combining small batches into chunks.
+    ///
+    /// Batches with other compatible schemas are merged into the schema, see
+    /// `merge_schemas`.
+    pub fn set_record_batches(
+        &mut self,
+        schema: SchemaRef,
+        record_batches: &[RecordBatch],
+    ) -> Result<()> {
+        let schema = merge_schemas(
+            std::iter::once(&schema).chain(record_batches.iter().map(RecordBatch::schema_ref)),
+        )?;
+        let record_batches = record_batches
+            .iter()
+            .map(|record_batch| align_batch(record_batch, &schema))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.chunks = chunk_batches(&schema, &record_batches, self.chunk_size)?;
+        Ok(())
+    }
+
+    /// Append the rows of a record batch with the schema of the table.
+    ///
+    /// Only the last chunk is rewritten, and the rows are added to the
+    /// indexes of the table.
+    pub fn append_record_batch(&mut self, record_batch: RecordBatch) -> Result<()> {
+        self.index_appended(self.num_rows(), &record_batch)?;
+
+        let last = self.chunks[self.chunks.len() - 1].to_owned();
+        let chunks = chunk_batches(&self.schema(), &[last, record_batch], self.chunk_size)?;
+
+        self.chunks.pop();
+        self.chunks.extend(chunks);
+
+        Ok(())
+    }
+
+    /// Change the number of rows per chunk, rewriting the chunks
+    pub fn set_chunk_size(&mut self, chunk_size: usize) -> Result<()> {
+        if chunk_size == 0 {
+            return Err(DbError::ArrayData("Chunk size must be positive".into()));
+        }
+
+        self.chunk_size = chunk_size;
+        self.compact()
+    }
+
+    /// Combine chunks that shrank after deletes, so every chunk but the last
+    /// is full
+    pub fn compact(&mut self) -> Result<()> {
+        self.chunks = chunk_batches(&self.schema(), &self.chunks, self.chunk_size)?;
+        Ok(())
+    }
+
+    /// The chunk of a row, and the offset of the row in the chunk
+    pub(crate) fn locate(&self, row: usize) -> Result<(usize, usize)> {
+        let mut offset = row;
+
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if offset < chunk.num_rows() {
+                return Ok((index, offset));
+            }
+
+            offset -= chunk.num_rows();
+        }
+
+        Err(DbError::Dml(format!(
+            "Row {row} is out of bounds for Table {}",
+            self.name
+        )))
+    }
+}
+
+impl Database {
+    /// Read a range of rows of a table without running a query, e.g. to
+    /// page through it.  See `Table::slice`.
+    pub fn read_rows(&self, table_name: &str, rows: Range<usize>) -> Result<RecordBatch> {
+        let length = rows.end.saturating_sub(rows.start);
+
+        get_table!(self, table_name)?.slice(rows.start, length)
+    }
+}
+
+/// Split and combine record batches into chunks of up to `chunk_size` rows.
+///
+/// Batches that fill a chunk on their own are sliced without copying.
+pub(crate) fn chunk_batches(
+    schema: &SchemaRef,
+    record_batches: &[RecordBatch],
+    chunk_size: usize,
+) -> Result<Vec<RecordBatch>> {
+    let chunk_size = chunk_size.max(1);
+    let mut chunks = vec![];
+    let mut pending = vec![];
+    let mut pending_rows = 0;
+
+    for record_batch in record_batches {
+        let mut offset = 0;
+
+        while offset < record_batch.num_rows() {
+            let length = (chunk_size - pending_rows).min(record_batch.num_rows() - offset);
+            pending.push(record_batch.slice(offset, length));
+            pending_rows += length;
+            offset += length;
+
+            if pending_rows == chunk_size {
+                chunks.push(combine(schema, &pending)?);
+                pending.clear();
+                pending_rows = 0;
+            }
+        }
+    }
+
+    if !pending.is_empty() || chunks.is_empty() {
+        chunks.push(combine(schema, &pending)?);
+    }
+
+    Ok(chunks)
+}
+
+/// Combine record batches into a single chunk with a schema
+fn combine(schema: &SchemaRef, record_batches: &[RecordBatch]) -> Result<RecordBatch> {
+    let record_batch = match record_batches {
+        [] => return Ok(RecordBatch::new_empty(schema.to_owned())),
+        [record_batch] => RecordBatch::try_new(schema.to_owned(), record_batch.columns().to_vec()),
+        record_batches => concat_batches(schema, record_batches),
+    };
+
+    record_batch.map_err(|e| DbError::ArrayData(format!("Error combining chunks: {e}")))
+}
+
+/// The slices of chunks with the rows from `offset` to `offset + length`.
+///
+/// Always returns at least one slice, so the schema is kept.
+pub(crate) fn slice_chunks(
+    chunks: &[RecordBatch],
+    offset: usize,
+    length: usize,
+) -> Vec<RecordBatch> {
+    let mut slices = vec![];
+    let mut start = 0;
+
+    for chunk in chunks {
+        let end = start + chunk.num_rows();
+        let from = offset.max(start);
+        let to = (offset + length).min(end);
+
+        if from < to {
+            slices.push(chunk.slice(from - start, to - from));
+        }
+
+        start = end;
+    }
+
+    if slices.is_empty() {
+        slices.push(chunks[0].slice(0, 0));
+    }
+
+    slices
+}
+
+/// Take rows of chunks by their index in the table, in the order of the
+/// chunks
+pub(crate) fn take_rows(chunks: &[RecordBatch], rows: &[usize]) -> Result<RecordBatch> {
+    let schema = chunks[0].schema();
+    let mut taken = vec![];
+    let mut start = 0;
+
+    for chunk in chunks {
+        let end = start + chunk.num_rows();
+        let indices = UInt64Array::from_iter_values(
+            rows.iter()
+                .filter(|row| (start..end).contains(*row))
+                .map(|row| (row - start) as u64),
+        );
+
+        if !indices.is_empty() {
+            taken.push(
+                take_record_batch(chunk, &indices)
+                    .map_err(|e| DbError::ArrayData(format!("Error taking rows: {e}")))?,
+            );
+        }
+
+        start = end;
+    }
+
+    combine(&schema, &taken)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use arrow_schema::DataType;
+
+    use crate::database::tests::{create_database, seed_database};
+    use crate::get_mut_table;
+
+    use super::*;
+
+    fn ids(table: &Table) -> Vec<i32> {
+        table
+            .column(0)
+            .unwrap()
+            .as_primitive::<Int32Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_chunks() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        let mut users = get_mut_table!(database, "users").unwrap();
+
+        users.set_chunk_size(3).unwrap();
+        assert_eq!(
+            users
+                .chunks
+                .iter()
+                .map(|chunk| chunk.num_rows())
+                .collect::<Vec<_>>(),
+            vec![3, 1]
+        );
+
+        // appends only fill the last chunk
+        users
+            .append_row(vec![
+                ScalarValue::Int32(Some(5)),
+                ScalarValue::Utf8(Some("Eve".into())),
+            ])
+            .unwrap();
+        users
+            .append_row(vec![
+                ScalarValue::Int32(Some(6)),
+                ScalarValue::Utf8(Some("Frank".into())),
+            ])
+            .unwrap();
+        users
+            .append_row(vec![
+                ScalarValue::Int32(Some(7)),
+                ScalarValue::Utf8(Some("Grace".into())),
+            ])
+            .unwrap();
+        assert_eq!(users.chunks.len(), 3);
+        assert_eq!(users.num_rows(), 7);
+        assert_eq!(ids(&users), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(
+            users.cell(1, 5).unwrap(),
+            ScalarValue::Utf8(Some("Frank".into()))
+        );
+
+        // deletes and updates find rows across chunks
+        users.delete_rows(&[1, 3, 4]).unwrap();
+        users
+            .update_row(2, &[(1, ScalarValue::Utf8(Some("Francis".into())))])
+            .unwrap();
+        assert_eq!(ids(&users), vec![1, 3, 6, 7]);
+        assert_eq!(
+            users.column(1).unwrap().as_string::<i32>(),
+            &StringArray::from(vec!["Alice", "Charlie", "Francis", "Grace"])
+        );
+        assert!(users.cell(0, 4).is_err());
+
+        users.compact().unwrap();
+        assert_eq!(users.chunks.len(), 2);
+        assert_eq!(ids(&users), vec![1, 3, 6, 7]);
+
+        // the column API sees every chunk
+        users
+            .add_column::<Int32Array>(
+                2,
+                "age",
+                DataType::Int32,
+                Int32Array::from(vec![30, 40, 50, 60]).into(),
+            )
+            .unwrap();
+        assert_eq!(users.chunks.len(), 2);
+        assert_eq!(
+            take_rows(&users.chunks, &[0, 3])
+                .unwrap()
+                .column(2)
+                .as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![30, 60])
+        );
+
+        // empty tables keep their schema
+        users.delete_rows(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(users.chunks.len(), 1);
+        assert_eq!(users.num_rows(), 0);
+        assert_eq!(users.num_columns(), 3);
+    }
+
+    #[test]
+    fn test_slice() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_chunk_size(2)
+            .unwrap();
+
+        let users = get_table!(database, "users").unwrap();
+        let within = users.slice(2, 2).unwrap();
+        assert_eq!(
+            within.column(0).as_primitive::<Int32Type>().values(),
+            &[3, 4]
+        );
+        assert!(within
+            .column(0)
+            .to_data()
+            .ptr_eq(&users.chunks[1].column(0).to_data()));
+        drop(users);
+
+        let across = database.read_rows("users", 1..3).unwrap();
+        assert_eq!(
+            across.column(0).as_primitive::<Int32Type>().values(),
+            &[2, 3]
+        );
+
+        // windows past the end are cut short
+        assert_eq!(database.read_rows("users", 3..10).unwrap().num_rows(), 1);
+        assert_eq!(database.read_rows("users", 10..20).unwrap().num_rows(), 0);
+        assert_eq!(
+            database.read_rows("users", 10..20).unwrap().num_columns(),
+            2
+        );
+    }
+}
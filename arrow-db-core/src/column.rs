@@ -10,14 +10,24 @@ use std::convert::From;
 use std::sync::Arc;
 
 use arrow::{
-    array::{Array, ArrayData, ArrayDataBuilder, ArrayRef, RecordBatch},
-    buffer::{Buffer, MutableBuffer},
+    array::{
+        make_array, Array, ArrayData, ArrayDataBuilder, ArrayRef, BooleanBufferBuilder, RecordBatch,
+    },
+    buffer::{Buffer, MutableBuffer, NullBuffer},
+    compute::concat,
     datatypes::DataType,
 };
-use arrow_schema::{Field, Schema};
+use arrow_schema::{Field, FieldRef, Schema};
+use datafusion::{
+    common::tree_node::{Transformed, TreeNode},
+    logical_expr::Expr,
+};
 
 use crate::{
+    database::Database,
     error::{DbError, Result},
+    get_mut_table,
+    system::is_system_column,
     table::Table,
 };
 
@@ -40,23 +50,13 @@ impl SetKind {
     }
 }
 
-impl<'a> Table<'a> {
-    /// Get the primitive width of a data type
-    fn column_primitive_width(&self, data: &DataType) -> Result<usize> {
-        data.primitive_width().ok_or_else(|| {
-            DbError::DataType(format!(
-                "Data type {:?} does not have a primitive width",
-                data,
-            ))
-        })
-    }
-
+impl Table {
     /// Ensure that the column index is within the bounds of the table schema.
     pub fn column_index_in_bounds(&self, column_index: usize) -> Result<()> {
-        if column_index > self.record_batch.schema_ref().fields().len() {
+        if column_index > self.num_columns() {
             return Err(DbError::ColumnIndexOutOfBounds(
                 column_index,
-                self.name.into(),
+                self.name.to_owned(),
             ));
         }
 
@@ -75,27 +75,41 @@ impl<'a> Table<'a> {
     /// Add a column to the table schema at a given index.
     /// If the index is out of bounds, return an error.
     ///
+    /// The column data is inserted at the same index as the field, so later
+    /// columns shift to the right.  Unless the table has no columns yet, the
+    /// data must have the same number of rows as the table.
+    ///
     /// Since the schema is immutable, we need to create a new schema with the
     /// new field.
     pub fn add_column<T: From<ArrayData> + Array + 'static>(
         &mut self,
         column_index: usize,
-        name: &'a str,
+        name: &str,
         data_type: DataType,
         data: ArrayData,
     ) -> Result<()> {
         self.column_index_in_bounds(column_index)?;
 
+        if self.num_columns() > 0 && data.len() != self.num_rows() {
+            return Err(DbError::ColumnLengthMismatch(
+                name.into(),
+                data.len(),
+                self.name.to_owned(),
+                self.num_rows(),
+            ));
+        }
+
+        let record_batch = self.record_batch()?;
         let new_field = Field::new(name, data_type.to_owned(), true);
-        let mut fields = self.record_batch.schema().fields().to_vec();
+        let mut fields = record_batch.schema().fields().to_vec();
         fields.insert(column_index, Arc::new(new_field));
 
-        let mut columns = self.record_batch.columns().to_vec();
+        let mut columns = record_batch.columns().to_vec();
         let column: ArrayRef = Arc::<T>::new(data.into());
-        columns.push(Arc::new(column));
+        columns.insert(column_index, column);
 
         let schema = Arc::new(Schema::new(fields));
-        self.record_batch = Self::new_record_batch(schema, columns)?;
+        self.set_record_batch(Self::new_record_batch(schema, columns)?);
 
         Ok(())
     }
@@ -106,8 +120,7 @@ impl<'a> Table<'a> {
         column_index: usize,
         data: ArrayData,
     ) -> Result<()> {
-        let column = self.record_batch.column(column_index);
-        let end = column.to_data().len();
+        let end = self.column(column_index)?.len();
 
         self.insert_column_data::<T>(column_index, end, data)
     }
@@ -145,6 +158,13 @@ impl<'a> Table<'a> {
     }
 
     /// Set ArrayData column in the table at a specified row index.
+    ///
+    /// The values and the validity of the rows are spliced together, so
+    /// columns with NULLs keep each NULL on its row.  An update replaces as
+    /// many rows as the data has.
+    ///
+    /// Fixed-width values are copied buffer to buffer.  Other columns, like
+    /// strings and binary, are rebuilt from slices of the column.
     pub fn set_column_data<T: From<ArrayData> + Array + 'static>(
         &mut self,
         column_index: usize,
@@ -153,52 +173,57 @@ impl<'a> Table<'a> {
     ) -> Result<()> {
         let data = set_kind.get_data();
 
-        let column = self.record_batch.column(column_index);
+        let column = self.column(column_index)?;
         let column_data = column.to_data();
         let column_len: usize = column.len();
-        let new_len = match set_kind {
-            SetKind::Append(ref data) => column_len + data.len(),
-            SetKind::InsertAt(ref data) => column_len + data.len(),
-            SetKind::Update(_) => column_len,
-            SetKind::Remove => column_len - 1,
+        let removed = match set_kind {
+            SetKind::Append(_) | SetKind::InsertAt(_) => 0,
+            SetKind::Update(ref data) => data.len(),
+            SetKind::Remove => 1,
         };
 
-        // ignore the empty single buffer of a newly created column
-        let buffers = if column_len == 0 {
-            data.map_or_else(|| vec![], |data| data.buffers().to_vec())
-        } else {
-            let column_buffer = &column_data.buffers()[0];
-            let mut buffer = MutableBuffer::new(new_len);
-
-            let width = self.column_primitive_width(&column.data_type())?;
-            let adjusted_index = row_index * width;
-            let spliced = column_buffer.split_at(adjusted_index);
-
-            let end = match set_kind {
-                SetKind::Append(_) => &spliced.1,
-                SetKind::InsertAt(_) => &spliced.1,
-                SetKind::Update(_) => &spliced.1[width..],
-                SetKind::Remove => &spliced.1[width..],
-            };
-
-            buffer.extend_from_slice(spliced.0);
-
-            if let Some(data) = data {
-                let data_buffer = &data.buffers()[0];
-                buffer.extend_from_slice(data_buffer.as_slice());
-            }
-
-            buffer.extend_from_slice(end);
+        if row_index + removed > column_len {
+            return Err(DbError::ArrayData(format!(
+                "Row {row_index} is out of bounds for Table {}",
+                self.name
+            )));
+        }
 
-            vec![Buffer::from(buffer)]
+        let new_len = column_len + data.map_or(0, |data| data.len()) - removed;
+
+        let array_data = match (data, column.data_type().primitive_width()) {
+            // a newly created column is replaced by the data
+            (Some(data), _) if column_len == 0 => data.to_owned(),
+            // variable-length and bit-packed values are spliced as arrays,
+            // which rebuilds their offsets and validity
+            (_, None) => splice_arrays(&column, row_index, removed, data)?.to_data(),
+            (_, Some(width)) => {
+                let mut buffer = MutableBuffer::new(new_len * width);
+
+                // the column may be a slice of a chunk's buffer
+                let start = column_data.offset() * width;
+                let column_buffer = &column_data.buffers()[0][start..start + column_len * width];
+                let (before, after) = column_buffer.split_at(row_index * width);
+
+                buffer.extend_from_slice(before);
+
+                if let Some(data) = data {
+                    let start = data.offset() * width;
+                    buffer.extend_from_slice(&data.buffers()[0][start..start + data.len() * width]);
+                }
+
+                buffer.extend_from_slice(&after[removed * width..]);
+
+                ArrayDataBuilder::from(column_data.to_owned())
+                    .len(new_len)
+                    .offset(0)
+                    .buffers(vec![Buffer::from(buffer)])
+                    .nulls(splice_nulls(&column_data, row_index, removed, data))
+                    .build()
+                    .map_err(|e| DbError::ArrayData(format!("Error building data: {e}")))?
+            }
         };
 
-        let array_data = ArrayDataBuilder::from(column_data)
-            .len(new_len)
-            .buffers(buffers)
-            .build()
-            .map_err(|e| DbError::ArrayData(format!("Error building data: {e}")))?;
-
         self.replace_column_data(column_index, Arc::<T>::new(array_data.into()))?;
 
         Ok(())
@@ -206,18 +231,164 @@ impl<'a> Table<'a> {
 
     /// Replace a column in the table with a new `ArrayRef`
     pub fn replace_column_data(&mut self, column_index: usize, data: ArrayRef) -> Result<()> {
-        let mut columns = self.record_batch.columns().to_vec();
+        let record_batch = self.record_batch()?;
+        let mut columns = record_batch.columns().to_vec();
         columns[column_index] = data;
 
-        let schema = self.record_batch.schema();
-        self.record_batch = Self::new_record_batch(schema, columns)?;
+        self.set_record_batch(Self::new_record_batch(record_batch.schema(), columns)?);
+
+        Ok(())
+    }
+
+    /// Rename a column, along with the constraints, defaults, indexes,
+    /// sequences and statistics that refer to it.
+    ///
+    /// System columns can't be renamed.
+    pub fn rename_column(&mut self, column_name: &str, new_name: &str) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+
+        if column_name == new_name {
+            return Ok(());
+        }
+
+        if self.column_index(new_name).is_ok() {
+            return Err(DbError::ColumnAlreadyExists(
+                new_name.into(),
+                self.name.to_owned(),
+            ));
+        }
+
+        if is_system_column(column_name) || is_system_column(new_name) {
+            return Err(DbError::DataType(format!(
+                "Cannot rename {column_name} to {new_name}, system columns can't be renamed"
+            )));
+        }
+
+        let schema = self.schema();
+        let mut fields = schema.fields().to_vec();
+        fields[column_index] =
+            Arc::new(fields[column_index].as_ref().to_owned().with_name(new_name));
+        let order = (0..fields.len()).collect::<Vec<_>>();
+        self.reshape_columns(fields, &order)?;
+
+        let rename = |name: &mut String| {
+            if name == column_name {
+                *name = new_name.into();
+            }
+        };
+
+        if let Some(sequence) = self.sequences.remove(column_name) {
+            self.sequences.insert(new_name.into(), sequence);
+        }
+
+        if let Some(default) = self.defaults.remove(column_name) {
+            self.defaults.insert(new_name.into(), default);
+        }
+
+        if let Some(mut index) = self.indexes.remove(column_name) {
+            index.column_name = new_name.into();
+            self.indexes.insert(new_name.into(), index);
+        }
+
+        if self.not_null.remove(column_name) {
+            self.not_null.insert(new_name.into());
+        }
+
+        if let Some(primary_key) = self.primary_key.as_mut() {
+            rename(&mut primary_key.column_name);
+        }
+
+        if let Some(ttl) = self.ttl.as_mut() {
+            rename(&mut ttl.column_name);
+        }
+
+        if let Some(statistics) = self.statistics.as_mut() {
+            statistics
+                .columns
+                .iter_mut()
+                .for_each(|column| rename(&mut column.column_name));
+        }
+
+        for check in self.checks.iter_mut() {
+            check.expr = check
+                .expr
+                .to_owned()
+                .transform(|expr| match expr {
+                    Expr::Column(mut column) if column.name == column_name => {
+                        column.name = new_name.into();
+                        Ok(Transformed::yes(Expr::Column(column)))
+                    }
+                    expr => Ok(Transformed::no(expr)),
+                })
+                .map_err(|e| DbError::DataType(e.to_string()))?
+                .data;
+        }
+
+        Ok(())
+    }
+
+    /// Move a column to another index, shifting the columns between them
+    pub fn move_column(&mut self, from: usize, to: usize) -> Result<()> {
+        for column_index in [from, to] {
+            if column_index >= self.num_columns() {
+                return Err(DbError::ColumnIndexOutOfBounds(
+                    column_index,
+                    self.name.to_owned(),
+                ));
+            }
+        }
+
+        let mut order = (0..self.num_columns()).collect::<Vec<_>>();
+        let moved = order.remove(from);
+        order.insert(to, moved);
+
+        let schema = self.schema();
+        let fields = order
+            .iter()
+            .map(|column_index| schema.fields()[*column_index].to_owned())
+            .collect();
+        self.reshape_columns(fields, &order)?;
+
+        if let Some(statistics) = self.statistics.as_mut() {
+            let columns = std::mem::take(&mut statistics.columns);
+            let mut columns = columns.into_iter().map(Some).collect::<Vec<_>>();
+
+            statistics.columns = order
+                .iter()
+                .filter_map(|column_index| columns.get_mut(*column_index)?.take())
+                .collect();
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild the chunks with new fields, taking the columns of each chunk in
+    /// an order.  The arrays are shared, so nothing is copied.
+    pub(crate) fn reshape_columns(&mut self, fields: Vec<FieldRef>, order: &[usize]) -> Result<()> {
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            self.schema().metadata().to_owned(),
+        ));
+
+        self.chunks = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let columns = order
+                    .iter()
+                    .map(|column_index| chunk.column(*column_index).to_owned())
+                    .collect();
+
+                Self::new_record_batch(schema.to_owned(), columns)
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(())
     }
 
     #[cfg(test)]
     pub fn print_column(&self, column_index: usize) {
-        let column = self.record_batch.column(column_index).to_owned();
+        let column = self.column(column_index).unwrap();
         let schema = Schema::new(vec![Field::new(
             column.data_type().to_string(),
             column.data_type().to_owned(),
@@ -228,10 +399,94 @@ impl<'a> Table<'a> {
     }
 }
 
+/// A column with `removed` rows at a row index replaced by the rows of `data`
+fn splice_arrays(
+    column: &ArrayRef,
+    row_index: usize,
+    removed: usize,
+    data: Option<&ArrayData>,
+) -> Result<ArrayRef> {
+    let end = row_index + removed;
+    let before = column.slice(0, row_index);
+    let after = column.slice(end, column.len() - end);
+    let data = data.map(|data| make_array(data.to_owned()));
+
+    let mut arrays = vec![before.as_ref()];
+    arrays.extend(data.as_deref());
+    arrays.push(after.as_ref());
+
+    concat(&arrays).map_err(|e| DbError::ArrayData(format!("Error building data: {e}")))
+}
+
+/// The validity of a column with `removed` rows at a row index replaced by the
+/// rows of `data`, or `None` if every row is valid
+fn splice_nulls(
+    column: &ArrayData,
+    row_index: usize,
+    removed: usize,
+    data: Option<&ArrayData>,
+) -> Option<NullBuffer> {
+    if column.nulls().is_none() && data.and_then(|data| data.nulls()).is_none() {
+        return None;
+    }
+
+    let data_len = data.map_or(0, |data| data.len());
+    let mut builder = BooleanBufferBuilder::new(column.len() + data_len - removed);
+    let mut append = |nulls: Option<&NullBuffer>, offset: usize, len: usize| match nulls {
+        Some(nulls) => builder.append_buffer(&nulls.inner().slice(offset, len)),
+        None => builder.append_n(len, true),
+    };
+
+    append(column.nulls(), 0, row_index);
+
+    if let Some(data) = data {
+        append(data.nulls(), 0, data_len);
+    }
+
+    let end = row_index + removed;
+    append(column.nulls(), end, column.len() - end);
+
+    let nulls = NullBuffer::new(builder.finish());
+
+    (nulls.null_count() > 0).then_some(nulls)
+}
+
+impl Database {
+    /// Rename a column of a table, registering the new schema with the
+    /// DataFusion context
+    pub fn rename_column(&self, table_name: &str, column_name: &str, new_name: &str) -> Result<()> {
+        get_mut_table!(self, table_name)?.rename_column(column_name, new_name)?;
+        self.commit_table(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(())
+    }
+
+    /// Move a column of a table to another index, registering the new schema
+    /// with the DataFusion context
+    pub fn move_column(&self, table_name: &str, from: usize, to: usize) -> Result<()> {
+        get_mut_table!(self, table_name)?.move_column(from, to)?;
+        self.commit_table(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
-    use arrow::array::{Int32Array, StringArray /*, UnionArray */};
+    use arrow::array::{BinaryArray, Int32Array, StringArray /*, UnionArray */};
     // use arrow_schema::{UnionFields, UnionMode};
+    use datafusion::prelude::{col, lit};
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
 
     use super::*;
 
@@ -267,16 +522,58 @@ pub mod tests {
         table.print_column(0);
 
         let expected = Int32Array::from(vec![1, 5, 4, 3]).to_data();
-        let data = table.record_batch.column(0).to_data();
+        let data = table.column(0).unwrap().to_data();
         assert_eq!(expected, data);
 
         // remove data at a specific index in the column
         table.remove_column_data::<Int32Array>(0, 1).unwrap();
         let expected = Int32Array::from(vec![1, 4, 3]).to_data();
-        let data = table.record_batch.column(0).to_data();
+        let data = table.column(0).unwrap().to_data();
         assert_eq!(expected, data);
     }
 
+    #[test]
+    fn test_nullable_column() {
+        let mut table = Table::new("users");
+        table
+            .add_column::<Int32Array>(
+                0,
+                "age",
+                DataType::Int32,
+                Int32Array::from(vec![Some(1), None, Some(3)]).into(),
+            )
+            .unwrap();
+
+        // each NULL stays on its row
+        table
+            .insert_column_data::<Int32Array>(0, 1, Int32Array::from(vec![None, Some(4)]).into())
+            .unwrap();
+        table
+            .append_column_data::<Int32Array>(0, Int32Array::from(vec![Some(5)]).into())
+            .unwrap();
+        table.remove_column_data::<Int32Array>(0, 0).unwrap();
+        table
+            .update_column_data::<Int32Array>(0, 2, Int32Array::from(vec![Some(6), None]).into())
+            .unwrap();
+
+        let expected = Int32Array::from(vec![None, Some(4), Some(6), None, Some(5)]);
+        assert_eq!(table.column(0).unwrap().to_data(), expected.to_data());
+
+        // without NULLs, the column has no validity
+        table
+            .update_column_data::<Int32Array>(0, 0, Int32Array::from(vec![7]).into())
+            .unwrap();
+        table
+            .update_column_data::<Int32Array>(0, 3, Int32Array::from(vec![8]).into())
+            .unwrap();
+        assert_eq!(table.column(0).unwrap().null_count(), 0);
+
+        assert!(table
+            .update_column_data::<Int32Array>(0, 4, Int32Array::from(vec![9, 10]).into())
+            .is_err());
+        assert!(table.remove_column_data::<Int32Array>(0, 5).is_err());
+    }
+
     #[test]
     fn test_string_column() {
         let mut table = Table::new("users");
@@ -292,8 +589,160 @@ pub mod tests {
         table.print_column(0);
 
         let expected = StringArray::from(vec!["Alice", "Bob", "Charlie", "David"]).to_data();
-        let data = table.record_batch.column(0).to_data();
+        let data = table.column(0).unwrap().to_data();
         assert_eq!(expected, data);
+
+        // values of any length are spliced in
+        table
+            .append_column_data::<StringArray>(0, StringArray::from(vec![Some("Eve")]).into())
+            .unwrap();
+        table
+            .insert_column_data::<StringArray>(
+                0,
+                1,
+                StringArray::from(vec![None, Some("Maximilian")]).into(),
+            )
+            .unwrap();
+        table
+            .update_column_data::<StringArray>(0, 4, StringArray::from(vec!["Cy"]).into())
+            .unwrap();
+        table.remove_column_data::<StringArray>(0, 0).unwrap();
+
+        let expected = StringArray::from(vec![
+            None,
+            Some("Maximilian"),
+            Some("Bob"),
+            Some("Cy"),
+            Some("David"),
+            Some("Eve"),
+        ]);
+        assert_eq!(table.column(0).unwrap().to_data(), expected.to_data());
+    }
+
+    #[test]
+    fn test_binary_column() {
+        let mut table = Table::new("files");
+        table
+            .add_column::<BinaryArray>(
+                0,
+                "contents",
+                DataType::Binary,
+                BinaryArray::from(vec![b"ab".as_ref(), b"cde".as_ref()]).into(),
+            )
+            .unwrap();
+
+        table
+            .insert_column_data::<BinaryArray>(0, 1, BinaryArray::from(vec![b"f".as_ref()]).into())
+            .unwrap();
+        table.remove_column_data::<BinaryArray>(0, 0).unwrap();
+
+        let expected = BinaryArray::from(vec![b"f".as_ref(), b"cde".as_ref()]);
+        assert_eq!(table.column(0).unwrap().to_data(), expected.to_data());
+    }
+
+    #[test]
+    fn test_add_column_at_index() {
+        let mut table = Table::new("users");
+        table
+            .add_column::<StringArray>(
+                0,
+                "name",
+                DataType::Utf8,
+                StringArray::from(vec!["Alice", "Bob"]).into(),
+            )
+            .unwrap();
+
+        // insert a column before the existing one
+        table
+            .add_column::<Int32Array>(
+                0,
+                "id",
+                DataType::Int32,
+                Int32Array::from(vec![1, 2]).into(),
+            )
+            .unwrap();
+
+        let schema = table.schema();
+        assert_eq!(schema.field(0).name(), "id");
+        assert_eq!(schema.field(1).name(), "name");
+        assert_eq!(
+            table.column(0).unwrap().to_data(),
+            Int32Array::from(vec![1, 2]).to_data()
+        );
+        assert_eq!(
+            table.column(1).unwrap().to_data(),
+            StringArray::from(vec!["Alice", "Bob"]).to_data()
+        );
+
+        // columns must have the same number of rows as the table
+        assert_eq!(
+            table.add_column::<Int32Array>(
+                2,
+                "age",
+                DataType::Int32,
+                Int32Array::from(vec![30]).into(),
+            ),
+            Err(DbError::ColumnLengthMismatch(
+                "age".into(),
+                1,
+                "users".into(),
+                2
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_and_move_columns() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        {
+            let mut users = get_mut_table!(database, "users").unwrap();
+            users.set_primary_key("id").unwrap();
+            users
+                .add_check_constraint("positive_id", col("id").gt(lit(0)))
+                .unwrap();
+        }
+        database.add_all_table_contexts().unwrap();
+
+        assert!(matches!(
+            database.rename_column("users", "id", "name"),
+            Err(DbError::ColumnAlreadyExists(..))
+        ));
+        database.rename_column("users", "id", "user_id").unwrap();
+        database.move_column("users", 0, 1).unwrap();
+        assert!(database.move_column("users", 0, 2).is_err());
+
+        let users = get_table!(database, "users").unwrap();
+        assert_eq!(users.schema().field(0).name(), "name");
+        assert_eq!(users.schema().field(1).name(), "user_id");
+        assert_eq!(
+            users.column(1).unwrap().to_data(),
+            Int32Array::from(vec![1, 2, 3, 4]).to_data()
+        );
+        assert_eq!(users.primary_key.as_ref().unwrap().column_name, "user_id");
+        assert!(users.indexes.contains_key("user_id"));
+        drop(users);
+
+        // constraints follow the column, and queries see the new layout
+        assert!(database
+            .query("insert into users values ('Eve', -1)")
+            .await
+            .is_err());
+        database
+            .query("insert into users values ('Eve', 5)")
+            .await
+            .unwrap();
+        let results = database
+            .query("select user_id from users where name = 'Eve'")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0].column(0).to_data(),
+            Int32Array::from(vec![5]).to_data()
+        );
     }
 
     // #[test]
@@ -318,7 +767,7 @@ pub mod tests {
     //     table.print_column(0);
 
     //     let expected = StringArray::from(vec!["Alice", "Bob", "Charlie", "David"]).to_data();
-    //     let data = table.record_batch.column(0).to_data();
+    //     let data = table.column(0).unwrap().to_data();
     //     assert_eq!(expected, data);
     // }
 }
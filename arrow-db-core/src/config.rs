@@ -0,0 +1,145 @@
+//! Database configuration.
+//!
+//! A `DatabaseConfig` sets up the DataFusion `SessionContext` of a database
+//! when it's created with `Database::new_with_config`: how many partitions
+//! queries run on, the rows per batch, the memory queries may use, whether
+//! `information_schema` is available and the default time zone.
+//!
+//! Settings that can change later, like the batch size and time zone, are
+//! also the initial query options of the database, so `SET` statements
+//! continue from them.
+
+use std::sync::Arc;
+
+use datafusion::{
+    execution::runtime_env::{RuntimeConfig, RuntimeEnv},
+    prelude::{SessionConfig, SessionContext},
+};
+
+use crate::{
+    error::{DbError, Result},
+    import::DEFAULT_BATCH_SIZE,
+    json::register_json_functions,
+    options::QueryOptions,
+};
+
+/// Settings of the `SessionContext` of a database
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    /// The number of partitions queries are planned with, which defaults to
+    /// the number of CPUs
+    pub target_partitions: usize,
+    /// The number of rows per batch when registering tables and returning
+    /// results
+    pub batch_size: usize,
+    /// The number of bytes of memory queries may use for sorts, joins and
+    /// aggregates, or `None` for no limit
+    pub memory_pool_size: Option<usize>,
+    /// Whether `information_schema`, which backs `SHOW TABLES` and `SHOW
+    /// COLUMNS`, is available
+    pub information_schema: bool,
+    /// The time zone used for timestamps, e.g. `+00:00` or `America/New_York`
+    pub timezone: Option<String>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            target_partitions: SessionConfig::new().target_partitions(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            memory_pool_size: None,
+            information_schema: true,
+            timezone: None,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Create a `SessionContext` with these settings
+    pub fn session_context(&self) -> Result<SessionContext> {
+        let config_error = |error: &str| DbError::CreateDatabase(error.into());
+
+        if self.target_partitions == 0 {
+            return Err(config_error("Target partitions must be positive"));
+        }
+
+        if self.batch_size == 0 {
+            return Err(config_error("Batch size must be positive"));
+        }
+
+        let mut config = SessionConfig::new()
+            .with_target_partitions(self.target_partitions)
+            .with_batch_size(self.batch_size)
+            .with_information_schema(self.information_schema);
+
+        if let Some(timezone) = &self.timezone {
+            config = config.set_str("datafusion.execution.time_zone", timezone);
+        }
+
+        let runtime_config = match self.memory_pool_size {
+            Some(memory_pool_size) => RuntimeConfig::new().with_memory_limit(memory_pool_size, 1.0),
+            None => RuntimeConfig::new(),
+        };
+        let runtime = RuntimeEnv::try_new(runtime_config)
+            .map_err(|e| config_error(&format!("Error creating runtime: {e}")))?;
+
+        let ctx = SessionContext::new_with_config_rt(config, Arc::new(runtime));
+        register_json_functions(&ctx);
+
+        Ok(ctx)
+    }
+
+    /// The initial query options of a database with these settings
+    pub fn query_options(&self) -> QueryOptions {
+        QueryOptions {
+            batch_size: self.batch_size,
+            timezone: self.timezone.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::Database;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_database_config() {
+        let config = DatabaseConfig {
+            target_partitions: 2,
+            batch_size: 1024,
+            memory_pool_size: Some(64 * 1024 * 1024),
+            information_schema: false,
+            timezone: Some("+05:00".into()),
+        };
+        let database = Database::new_with_config("MyDB", config).unwrap();
+
+        let state = database.ctx.state();
+        let options = state.config().options();
+        assert_eq!(options.execution.target_partitions, 2);
+        assert_eq!(options.execution.batch_size, 1024);
+        assert_eq!(options.execution.time_zone, Some("+05:00".into()));
+        assert!(!options.catalog.information_schema);
+        assert_eq!(database.options().batch_size, 1024);
+
+        // the clone shares the memory pool of the original
+        let clone = database.clone();
+        assert!(Arc::ptr_eq(
+            &database.ctx.runtime_env(),
+            &clone.ctx.runtime_env()
+        ));
+
+        assert!(database.query("show tables").await.is_err());
+
+        let invalid = DatabaseConfig {
+            batch_size: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Database::new_with_config("MyDB", invalid),
+            Err(DbError::CreateDatabase(_))
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! Constraint operations.
+//!
+//! A primary key is a column whose values must be unique and non-null.  The
+//! keys are kept in a hash index, so checking a new row doesn't scan the
+//! column, and the column is indexed so `Database::get_row` reads the row
+//! with a key without SQL.
+//!
+//! NOT NULL columns reject NULL values, CHECK constraints are boolean
+//! expressions that every row must satisfy, and JSON columns reject values
+//! that aren't valid JSON.  All three are evaluated on the new values of a
+//! row before an INSERT or UPDATE writes them.
+//!
+//! The index is maintained by the row operations used by DML statements.
+//! Writes through the column API bypass it, so call `set_primary_key` again
+//! after changing a primary key column that way.
+
+use std::collections::HashSet;
+
+use arrow::array::RecordBatch;
+use arrow_schema::SchemaRef;
+use datafusion::{common::DFSchema, logical_expr::Expr, scalar::ScalarValue};
+
+use crate::{
+    database::Database,
+    dml::{evaluate_functions, evaluate_where_condition},
+    error::{DbError, Result},
+    get_mut_table, get_table,
+    row::{get_column_value, scalar_to_array_ref},
+    table::Table,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrimaryKey {
+    pub column_name: String,
+    keys: HashSet<ScalarValue>,
+}
+
+impl PrimaryKey {
+    /// The number of keys in the index
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Check if the index has no keys
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Check if a key is in the index
+    pub fn contains(&self, key: &ScalarValue) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// A row of a table read by its primary key
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowView {
+    pub schema: SchemaRef,
+    pub values: Vec<ScalarValue>,
+}
+
+impl RowView {
+    /// The value of a column of the row
+    pub fn get(&self, column_name: &str) -> Option<&ScalarValue> {
+        self.schema
+            .index_of(column_name)
+            .ok()
+            .map(|column_index| &self.values[column_index])
+    }
+}
+
+/// A boolean expression that every row of a table must satisfy
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl Table {
+    /// Declare a column as the primary key of the table.
+    ///
+    /// Fails if the column already contains NULL or duplicate values.
+    pub fn set_primary_key(&mut self, column_name: &str) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+        let column = self.column(column_index)?;
+        let mut keys = HashSet::with_capacity(column.len());
+
+        for row in 0..column.len() {
+            let key = get_column_value(&column, row)?;
+            self.check_key(column_name, &key, &keys)?;
+            keys.insert(key);
+        }
+
+        self.create_index(column_name)?;
+        self.primary_key = Some(PrimaryKey {
+            column_name: column_name.into(),
+            keys,
+        });
+
+        Ok(())
+    }
+
+    /// Remove the primary key of the table and the index of its column
+    pub fn drop_primary_key(&mut self) {
+        if let Some(primary_key) = self.primary_key.take() {
+            self.indexes.remove(&primary_key.column_name);
+        }
+    }
+
+    /// Get the column index of the primary key, if the table has one
+    pub fn primary_key_index(&self) -> Result<Option<usize>> {
+        self.primary_key
+            .as_ref()
+            .map(|primary_key| self.column_index(&primary_key.column_name))
+            .transpose()
+    }
+
+    /// Ensure a key written to the primary key column is non-null and unique.
+    ///
+    /// The key must already be cast to the type of the column.
+    pub fn check_primary_key(&self, key: &ScalarValue) -> Result<()> {
+        match &self.primary_key {
+            Some(primary_key) => self.check_key(&primary_key.column_name, key, &primary_key.keys),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a key written to the table
+    pub(crate) fn insert_primary_key(&mut self, key: ScalarValue) {
+        if let Some(primary_key) = self.primary_key.as_mut() {
+            primary_key.keys.insert(key);
+        }
+    }
+
+    /// Forget a key removed from the table
+    pub(crate) fn remove_primary_key(&mut self, key: &ScalarValue) {
+        if let Some(primary_key) = self.primary_key.as_mut() {
+            primary_key.keys.remove(key);
+        }
+    }
+
+    /// Declare a column as NOT NULL.
+    ///
+    /// Fails if the column already contains NULL values.
+    pub fn set_not_null(&mut self, column_name: &str) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+
+        if self
+            .chunks
+            .iter()
+            .any(|chunk| chunk.column(column_index).null_count() > 0)
+        {
+            return Err(DbError::ConstraintViolation(format!(
+                "Column {column_name} of Table {} contains NULL values",
+                self.name
+            )));
+        }
+
+        self.not_null.insert(column_name.into());
+
+        Ok(())
+    }
+
+    /// Allow NULL values in a column again
+    pub fn drop_not_null(&mut self, column_name: &str) {
+        self.not_null.remove(column_name);
+    }
+
+    /// Add a CHECK constraint to the table.
+    ///
+    /// A row satisfies the constraint only if the expression is true, so
+    /// nullable columns should be allowed for explicitly (`x IS NULL OR ...`).
+    /// Fails if an existing row doesn't satisfy the constraint.
+    pub fn add_check_constraint(&mut self, name: &str, expr: Expr) -> Result<()> {
+        if self.checks.iter().any(|check| check.name == name) {
+            return Err(DbError::ConstraintViolation(format!(
+                "Constraint {name} already exists on Table {}",
+                self.name
+            )));
+        }
+
+        let check = CheckConstraint {
+            name: name.into(),
+            expr,
+        };
+
+        for chunk in self.chunks.iter() {
+            self.check_rows(&check, chunk)?;
+        }
+
+        self.checks.push(check);
+
+        Ok(())
+    }
+
+    /// Remove a CHECK constraint from the table
+    pub fn drop_check_constraint(&mut self, name: &str) -> Result<()> {
+        let len = self.checks.len();
+        self.checks.retain(|check| check.name != name);
+
+        if self.checks.len() == len {
+            return Err(DbError::ConstraintViolation(format!(
+                "Constraint {name} not found on Table {}",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the values of a row satisfy the NOT NULL and CHECK constraints.
+    ///
+    /// Values must already be cast to the types of their columns.
+    pub fn check_constraints(&self, values: &[ScalarValue]) -> Result<()> {
+        self.check_json_values(values)?;
+
+        for column_name in self.not_null.iter() {
+            if values[self.column_index(column_name)?].is_null() {
+                return Err(DbError::ConstraintViolation(format!(
+                    "Column {column_name} of Table {} cannot be NULL",
+                    self.name
+                )));
+            }
+        }
+
+        if self.checks.is_empty() {
+            return Ok(());
+        }
+
+        let columns = values
+            .iter()
+            .map(scalar_to_array_ref)
+            .collect::<Result<Vec<_>>>()?;
+        let row = Self::new_record_batch(self.schema(), columns)?;
+
+        for check in self.checks.iter() {
+            self.check_rows(check, &row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the rows of a record batch with the columns of the table satisfy
+    /// its constraints, returning the primary keys of the rows
+    pub(crate) fn check_batch(&self, batch: &RecordBatch) -> Result<Vec<ScalarValue>> {
+        self.check_json_batch(batch)?;
+
+        for column_name in self.not_null.iter() {
+            if batch.column(self.column_index(column_name)?).null_count() > 0 {
+                return Err(DbError::ConstraintViolation(format!(
+                    "Column {column_name} of Table {} cannot be NULL",
+                    self.name
+                )));
+            }
+        }
+
+        for check in self.checks.iter() {
+            self.check_rows(check, batch)?;
+        }
+
+        let Some(primary_key) = &self.primary_key else {
+            return Ok(vec![]);
+        };
+
+        let column = batch.column(self.column_index(&primary_key.column_name)?);
+        let mut keys = HashSet::with_capacity(column.len());
+
+        for row in 0..column.len() {
+            let key = get_column_value(column, row)?;
+            self.check_key(&primary_key.column_name, &key, &primary_key.keys)?;
+            self.check_key(&primary_key.column_name, &key, &keys)?;
+            keys.insert(key);
+        }
+
+        Ok(keys.into_iter().collect())
+    }
+
+    /// Ensure every row of a `RecordBatch` satisfies a CHECK constraint
+    fn check_rows(&self, check: &CheckConstraint, batch: &RecordBatch) -> Result<()> {
+        let (batch, expr) = evaluate_functions(batch, &check.expr)?;
+
+        for row in 0..batch.num_rows() {
+            if !evaluate_where_condition(&batch, row, &expr)? {
+                return Err(DbError::ConstraintViolation(format!(
+                    "Row violates CHECK constraint {} ({}) of Table {}",
+                    check.name, check.expr, self.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensure a key is non-null and not already in the index
+    fn check_key(
+        &self,
+        column_name: &str,
+        key: &ScalarValue,
+        keys: &HashSet<ScalarValue>,
+    ) -> Result<()> {
+        if key.is_null() {
+            return Err(DbError::ConstraintViolation(format!(
+                "Primary key {column_name} of Table {} cannot be NULL",
+                self.name
+            )));
+        }
+
+        if keys.contains(key) {
+            return Err(DbError::ConstraintViolation(format!(
+                "Duplicate primary key {key} for column {column_name} of Table {}",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Read the row of a table with a primary key, without SQL.
+    ///
+    /// Fails if the table has no primary key.
+    pub fn get_row(&self, table_name: &str, key: &ScalarValue) -> Result<Option<RowView>> {
+        let table = get_table!(self, table_name)?;
+        let column_name = &table
+            .primary_key
+            .as_ref()
+            .ok_or_else(|| DbError::PrimaryKeyNotFound(table_name.into()))?
+            .column_name;
+
+        let Some(row) = table.index_lookup(column_name, key)?.first().copied() else {
+            return Ok(None);
+        };
+
+        Ok(Some(RowView {
+            schema: table.schema(),
+            values: table.row_values(row)?,
+        }))
+    }
+
+    /// Add a CHECK constraint to a table from a SQL expression, e.g.
+    /// `price > 0 AND price < 1000`
+    pub fn add_check_constraint(&self, table_name: &str, name: &str, sql: &str) -> Result<()> {
+        let mut table = get_mut_table!(self, table_name)?;
+        let schema = DFSchema::try_from(table.schema().as_ref().to_owned())
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+        let expr = self
+            .ctx
+            .state()
+            .create_logical_expr(sql, &schema)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        table.add_check_constraint(name, expr)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_primary_key() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        let error = database
+            .query("insert into users values (1, 'Alice2')")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DbError::ConstraintViolation(_)));
+        assert!(error.to_string().contains("Duplicate primary key 1"));
+
+        assert!(database
+            .query("insert into users values (null, 'Nobody')")
+            .await
+            .is_err());
+
+        // deleted and updated keys can be reused
+        database
+            .query("delete from users where id = 1")
+            .await
+            .unwrap();
+        database
+            .query("update users set id = 10 where id = 2")
+            .await
+            .unwrap();
+        database
+            .query("insert into users values (1, 'Alice'), (2, 'Bob')")
+            .await
+            .unwrap();
+
+        assert!(database
+            .query("update users set id = 3 where id = 4")
+            .await
+            .is_err());
+
+        let table = get_table!(database, "users").unwrap();
+        let primary_key = table.primary_key.as_ref().unwrap();
+        assert_eq!(primary_key.len(), 5);
+        assert!(primary_key.contains(&ScalarValue::Int32(Some(10))));
+        assert_eq!(table.num_rows(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_check_and_not_null_constraints() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_not_null("name")
+            .unwrap();
+        database
+            .add_check_constraint("users", "positive_id", "id > 0 and id < 100")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        for sql in [
+            "insert into users values (5, null)",
+            "insert into users values (-1, 'Eve')",
+            "update users set id = 100 where id = 1",
+            "update users set name = null where id = 1",
+        ] {
+            let error = database.query(sql).await.unwrap_err();
+            assert!(matches!(error, DbError::ConstraintViolation(_)), "{sql}");
+        }
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database
+            .query("update users set id = 99 where id = 5")
+            .await
+            .unwrap();
+
+        // rejected writes leave the table untouched
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(table.num_rows(), 5);
+        assert_eq!(table.cell(0, 0).unwrap(), ScalarValue::Int32(Some(1)));
+        drop(table);
+
+        // existing rows must satisfy new constraints
+        assert!(database
+            .add_check_constraint("users", "small_id", "id < 10")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_row() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let key = ScalarValue::Int64(Some(2));
+        assert!(matches!(
+            database.get_row("users", &key),
+            Err(DbError::PrimaryKeyNotFound(_))
+        ));
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+
+        // keys are cast to the type of the primary key column
+        let row = database.get_row("users", &key).unwrap().unwrap();
+        assert_eq!(row.get("id"), Some(&ScalarValue::Int32(Some(2))));
+        assert_eq!(
+            row.get("name"),
+            Some(&ScalarValue::Utf8(Some("Bob".into())))
+        );
+        assert_eq!(row.get("missing"), None);
+
+        // writes move the row
+        database
+            .query("delete from users where id = 1")
+            .await
+            .unwrap();
+        let row = database.get_row("users", &key).unwrap().unwrap();
+        assert_eq!(row.values[1], ScalarValue::Utf8(Some("Bob".into())));
+        assert_eq!(
+            database
+                .get_row("users", &ScalarValue::Int32(Some(1)))
+                .unwrap(),
+            None
+        );
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .drop_primary_key();
+        assert!(database.get_row("users", &key).is_err());
+        assert!(get_table!(database, "users").unwrap().indexes.is_empty());
+    }
+
+    #[test]
+    fn test_primary_key_rejects_existing_duplicates() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        let mut table = get_mut_table!(database, "user_role").unwrap();
+
+        // the seeded roles are not unique
+        let role = table.set_primary_key("role");
+        assert!(matches!(role, Err(DbError::ConstraintViolation(_))));
+        assert!(table.primary_key.is_none());
+    }
+}
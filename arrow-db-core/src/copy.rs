@@ -0,0 +1,216 @@
+//! COPY statements.
+//!
+//! `COPY table TO 'file'` exports a table to a file, and `COPY table FROM
+//! 'file'` appends the rows of a file to a table.  The format of the file is
+//! chosen by its extension: `parquet`, `csv`, `json` or `ndjson` (imported from
+//! newline-delimited objects or an array, exported as newline-delimited
+//! objects) or `arrow` (IPC file format).
+//!
+//! COPY statements are intercepted before planning, so any other form, like
+//! `COPY (query) TO 'file' STORED AS ...`, is left to DataFusion.
+
+use datafusion::sql::sqlparser::{
+    ast::{CopySource, CopyTarget, Statement},
+    dialect::GenericDialect,
+    parser::Parser,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    bulk::BulkLoadOptions,
+    database::Database,
+    error::{DbError, Result},
+    get_table,
+    table::Table,
+};
+
+/// A COPY statement between a table and a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyStatement {
+    /// `COPY table TO 'file'`
+    To {
+        table_name: String,
+        file_name: String,
+    },
+    /// `COPY table FROM 'file'`
+    From {
+        table_name: String,
+        file_name: String,
+    },
+}
+
+/// Get a `COPY table TO 'file'` or `COPY table FROM 'file'` statement
+pub fn copy_statement(sql: &str) -> Option<CopyStatement> {
+    let is_copy = sql
+        .trim_start()
+        .get(..4)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("copy"));
+
+    if !is_copy {
+        return None;
+    }
+
+    let mut statements = Parser::parse_sql(&GenericDialect {}, sql).ok()?;
+
+    if statements.len() != 1 {
+        return None;
+    }
+
+    match statements.remove(0) {
+        Statement::Copy {
+            source:
+                CopySource::Table {
+                    table_name,
+                    columns,
+                },
+            to,
+            target: CopyTarget::File { filename },
+            options,
+            legacy_options,
+            ..
+        } if columns.is_empty() && options.is_empty() && legacy_options.is_empty() => {
+            let table_name = table_name.0.last()?.value.to_owned();
+
+            Some(match to {
+                true => CopyStatement::To {
+                    table_name,
+                    file_name: filename,
+                },
+                false => CopyStatement::From {
+                    table_name,
+                    file_name: filename,
+                },
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database {
+    /// Execute a COPY statement, returning the number of rows copied
+    pub async fn copy(&self, statement: CopyStatement) -> Result<usize> {
+        match statement {
+            CopyStatement::To {
+                table_name,
+                file_name,
+            } => self.copy_to(&table_name, &file_name).await,
+            CopyStatement::From {
+                table_name,
+                file_name,
+            } => self.copy_from(&table_name, &file_name).await,
+        }
+    }
+
+    /// Export a table to a file, returning the number of rows exported
+    pub async fn copy_to(&self, table_name: &str, file_name: &str) -> Result<usize> {
+        // the table is copied so it isn't locked while the file is written
+        let mut table = get_table!(self, table_name)?.to_owned();
+        table.export_file(file_name).await?;
+
+        Ok(table.num_rows())
+    }
+
+    /// Append the rows of a file to a table, returning the number of rows
+    /// appended.
+    ///
+    /// The rows are loaded with `bulk_load`, so nothing is appended if any
+    /// row can't be cast to the table or violates its constraints.
+    pub async fn copy_from(&self, table_name: &str, file_name: &str) -> Result<usize> {
+        let mut file = Table::new(table_name);
+        file.import_file(file_name, self.options().import_batch_size)
+            .await?;
+
+        let report = self.bulk_load(table_name, &file.chunks, BulkLoadOptions::default())?;
+
+        if !report.is_valid() {
+            return Err(DbError::TableImportError(
+                table_name.into(),
+                report.violations_message(),
+            ));
+        }
+
+        Ok(report.rows_loaded)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array};
+    use arrow::datatypes::{Int32Type, UInt64Type};
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[test]
+    fn test_copy_statement() {
+        assert_eq!(
+            copy_statement("COPY users TO 'users.csv'"),
+            Some(CopyStatement::To {
+                table_name: "users".into(),
+                file_name: "users.csv".into()
+            })
+        );
+        assert_eq!(
+            copy_statement("copy users from '/tmp/users.parquet';"),
+            Some(CopyStatement::From {
+                table_name: "users".into(),
+                file_name: "/tmp/users.parquet".into()
+            })
+        );
+        assert_eq!(copy_statement("select * from users"), None);
+        assert_eq!(
+            copy_statement("COPY (select * from users) TO 'users.csv'"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let file_names = ["parquet", "csv", "json", "arrow"].map(|extension| {
+            std::env::temp_dir()
+                .join(format!("arrow_db_copy_users.{extension}"))
+                .display()
+                .to_string()
+        });
+
+        for file_name in file_names.iter() {
+            let results = database
+                .query(&format!("COPY users TO '{file_name}'"))
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                results[0].column(0).as_primitive::<UInt64Type>().value(0),
+                4
+            );
+        }
+
+        // every format is read back and appended
+        for file_name in file_names.iter() {
+            database
+                .query(&format!("COPY users FROM '{file_name}'"))
+                .await
+                .unwrap();
+        }
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from([1, 2, 3, 4].repeat(5))
+        );
+        drop(table);
+
+        assert!(database
+            .query("COPY users FROM 'missing.csv'")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Keyset pagination.
+//!
+//! Paging with OFFSET re-executes the query and skips every row before the
+//! page, so each page costs more than the last.  A keyset page instead starts
+//! after the key of the last row of the previous page, which the filter skips
+//! without reading, so every page costs the same.
+//!
+//! The order key must be unique within the results of the query, e.g. a
+//! primary key, or rows with the same key as the last row of a page are
+//! skipped.  Rows with a NULL key are never returned.
+
+use std::sync::Arc;
+
+use arrow::{array::RecordBatch, compute::concat_batches};
+use datafusion::{
+    prelude::{ident, lit},
+    scalar::ScalarValue,
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    row::get_column_value,
+};
+
+/// A page of the results of a query, ordered by a key
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPage {
+    pub record_batch: RecordBatch,
+    /// The key to read the next page after, or `None` if this is the last
+    /// page
+    pub cursor: Option<ScalarValue>,
+}
+
+impl Database {
+    /// Read a page of up to `page_size` rows of the results of a query,
+    /// ordered by a column, starting after the cursor of the previous page
+    pub async fn query_cursor(
+        &self,
+        sql: &str,
+        order_key: &str,
+        after: Option<ScalarValue>,
+        page_size: usize,
+    ) -> Result<QueryPage> {
+        let query_error = |e: &dyn ToString| DbError::Query(sql.into(), e.to_string());
+
+        let mut data_frame = self
+            .query(sql)
+            .await?
+            .filter(ident(order_key).is_not_null())
+            .map_err(|e| query_error(&e))?;
+
+        if let Some(after) = after {
+            data_frame = data_frame
+                .filter(ident(order_key).gt(lit(after)))
+                .map_err(|e| query_error(&e))?;
+        }
+
+        let data_frame = data_frame
+            .sort(vec![ident(order_key).sort(true, false)])
+            .and_then(|data_frame| data_frame.limit(0, Some(page_size)))
+            .map_err(|e| query_error(&e))?;
+        let schema = Arc::new(data_frame.schema().as_arrow().to_owned());
+        let record_batches = data_frame.collect().await.map_err(|e| query_error(&e))?;
+        let record_batch = concat_batches(&schema, &record_batches).map_err(|e| query_error(&e))?;
+
+        // a full page may be followed by more rows
+        let num_rows = record_batch.num_rows();
+        let cursor = match num_rows > 0 && num_rows == page_size {
+            true => {
+                let column_index = record_batch
+                    .schema()
+                    .index_of(order_key)
+                    .map_err(|e| query_error(&e))?;
+
+                Some(get_column_value(
+                    record_batch.column(column_index),
+                    num_rows - 1,
+                )?)
+            }
+            false => None,
+        };
+
+        Ok(QueryPage {
+            record_batch,
+            cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::{array::AsArray, datatypes::Int32Type};
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_cursor() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let ids = |page: &QueryPage| {
+            page.record_batch
+                .column(0)
+                .as_primitive::<Int32Type>()
+                .values()
+                .to_vec()
+        };
+        let sql = "select id, name from users";
+
+        let first = database.query_cursor(sql, "id", None, 3).await.unwrap();
+        assert_eq!(ids(&first), vec![1, 2, 3]);
+        assert_eq!(first.cursor, Some(ScalarValue::Int32(Some(3))));
+
+        // the next page starts after the cursor, and is the last page
+        let second = database
+            .query_cursor(sql, "id", first.cursor, 3)
+            .await
+            .unwrap();
+        assert_eq!(ids(&second), vec![4]);
+        assert_eq!(second.cursor, None);
+
+        assert!(matches!(
+            database.query_cursor(sql, "missing", None, 3).await,
+            Err(DbError::Query(_, _))
+        ));
+    }
+}
@@ -3,76 +3,333 @@
 //! A database is a collection of tables.  Each table is a collection of equal
 //! length columns, known as a `RecordBatch` in Arrow.
 
-use std::fmt::Debug;
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
 
+use arrow::array::RecordBatch;
+use arrow_schema::SchemaRef;
 use bytes::Bytes;
 use dashmap::{
-    mapref::one::{Ref, RefMut},
+    mapref::{
+        entry::Entry,
+        one::{Ref, RefMut},
+    },
     DashMap,
 };
 use datafusion::prelude::SessionContext;
+use futures::channel::mpsc::UnboundedSender;
 
 use crate::{
+    change::ChangeEvent,
+    config::DatabaseConfig,
     error::{DbError, Result},
+    json::register_json_functions,
+    linked::LinkedTable,
+    options::QueryOptions,
+    provider::LiveTable,
+    query_log::QueryLog,
+    sketch::{register_sketch_functions, Sketches},
     table::Table,
+    trigger::Trigger,
+    view::MaterializedView,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    export::ExportOptions,
+    persist::{remove_table_file, WriteThrough},
 };
 
 #[cfg(not(target_arch = "wasm32"))]
-const DISK_PATH: &'static str = "./../data/";
+pub(crate) const DISK_PATH: &str = "./../data/";
 
-#[derive(Clone)]
-pub struct Database<'a> {
-    pub name: &'a str,
-    pub tables: DashMap<&'a str, Table<'a>>,
+pub struct Database {
+    pub name: String,
+    pub tables: DashMap<String, Table>,
     pub ctx: SessionContext,
+    pub(crate) options: RwLock<QueryOptions>,
+    pub(crate) linked_tables: DashMap<String, LinkedTable>,
+    pub(crate) views: DashMap<String, MaterializedView>,
+    pub(crate) subscribers: DashMap<String, Vec<UnboundedSender<ChangeEvent>>>,
+    pub(crate) triggers: DashMap<String, Vec<Trigger>>,
+    /// The providers the tables are registered with, keyed by table name
+    pub(crate) providers: DashMap<String, Arc<LiveTable>>,
+    pub(crate) query_log: Arc<QueryLog>,
+    /// The sketches of tables, shared with the SQL functions that read them
+    pub(crate) sketches: Sketches,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) write_through: RwLock<Option<WriteThrough>>,
+    /// The old names of renamed tables and the names of dropped tables, whose
+    /// files are removed by the next export
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) renamed_tables: RwLock<HashSet<String>>,
+    /// The data directory holding the directory of the database on disk
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) data_dir: String,
 }
 
-impl Debug for Database<'_> {
+impl Debug for Database {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Database")
+        let mut debug = f.debug_struct("Database");
+        debug
             .field("name", &self.name)
             .field("tables", &self.tables)
-            .finish()
+            .field("options", &self.options())
+            .field("linked_tables", &self.linked_tables)
+            .field("views", &self.views)
+            .field("subscribers", &self.subscribers)
+            .field("triggers", &self.triggers);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        debug.field("write_through", &self.write_through);
+
+        debug.finish()
     }
 }
 
-impl<'a> Database<'a> {
-    pub fn new(name: &'a str) -> Result<Database<'a>> {
+/// Cloning a database copies its tables into a fresh `SessionContext` with the
+/// same configuration and runtime, so it shares the memory pool of the
+/// original, and registers every table with it.
+///
+/// User defined, JSON and sketch functions are registered with the new context
+/// too, and triggers and sketches are copied.  The clone starts with an empty
+/// query log.
+///
+/// The clone is fully independent: tables registered, altered or removed on
+/// the clone are not visible to the original, and vice versa.  Subscribers
+/// only receive the changes of the original.  Write-through
+/// persistence is not enabled on the clone, so it never overwrites the files
+/// of the original.
+impl Clone for Database {
+    fn clone(&self) -> Self {
+        let database = Database {
+            name: self.name.to_owned(),
+            tables: self.tables.clone(),
+            ctx: SessionContext::new_with_config_rt(
+                self.ctx.copied_config(),
+                self.ctx.runtime_env(),
+            ),
+            options: RwLock::new(self.options()),
+            linked_tables: self.linked_tables.clone(),
+            views: self.views.clone(),
+            subscribers: DashMap::new(),
+            triggers: self.triggers.clone(),
+            providers: DashMap::new(),
+            query_log: Arc::new(QueryLog::default()),
+            sketches: Arc::new(self.sketches.as_ref().clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            write_through: RwLock::new(None),
+            #[cfg(not(target_arch = "wasm32"))]
+            renamed_tables: RwLock::new(self.renamed_tables.read().unwrap().to_owned()),
+            #[cfg(not(target_arch = "wasm32"))]
+            data_dir: self.data_dir.to_owned(),
+        };
+
+        register_json_functions(&database.ctx);
+        register_sketch_functions(&database.ctx, &database.sketches);
+        self.copy_functions(&database.ctx);
+        database
+            .register_query_log()
+            .expect("The query log of an existing database is always registrable");
+        database
+            .add_all_table_contexts()
+            .expect("Tables of an existing database are always registrable");
+
+        database
+    }
+}
+
+impl Database {
+    pub fn new(name: impl Into<String>) -> Result<Database> {
+        Database::new_with_config(name, DatabaseConfig::default())
+    }
+
+    /// Create a database whose `SessionContext` has the settings of a config
+    pub fn new_with_config(name: impl Into<String>, config: DatabaseConfig) -> Result<Database> {
+        let name = name.into();
+
         if name.contains(" ") {
             return Err(DbError::CreateDatabase(
                 "Database name cannot contain spaces".into(),
             ));
         }
 
-        Ok(Database {
+        let database = Database {
             name,
             tables: DashMap::new(),
-            ctx: SessionContext::new(),
-        })
+            ctx: config.session_context()?,
+            options: RwLock::new(config.query_options()),
+            linked_tables: DashMap::new(),
+            views: DashMap::new(),
+            subscribers: DashMap::new(),
+            triggers: DashMap::new(),
+            providers: DashMap::new(),
+            query_log: Arc::new(QueryLog::default()),
+            sketches: Arc::new(DashMap::new()),
+            #[cfg(not(target_arch = "wasm32"))]
+            write_through: RwLock::new(None),
+            #[cfg(not(target_arch = "wasm32"))]
+            renamed_tables: RwLock::new(HashSet::new()),
+            #[cfg(not(target_arch = "wasm32"))]
+            data_dir: DISK_PATH.into(),
+        };
+
+        register_sketch_functions(&database.ctx, &database.sketches);
+        database.register_query_log()?;
+
+        Ok(database)
+    }
+
+    /// Fork the database under a new name, e.g. for what-if analysis.
+    ///
+    /// Record batches are immutable, so the fork shares the data of the
+    /// original instead of copying it: a write to either database replaces the
+    /// chunks it changes, and the other keeps reading the old ones.  Like a
+    /// clone, the fork registers every table with its own context, and has no
+    /// subscribers or write-through persistence.
+    pub fn fork(&self, name: impl Into<String>) -> Result<Database> {
+        let name = name.into();
+
+        if name.contains(" ") {
+            return Err(DbError::CreateDatabase(
+                "Database name cannot contain spaces".into(),
+            ));
+        }
+
+        let mut database = self.clone();
+        database.name = name;
+
+        // renamed files belong to the directory of the original
+        #[cfg(not(target_arch = "wasm32"))]
+        database.renamed_tables.write().unwrap().clear();
+
+        Ok(database)
     }
 
     /// Add a table to the database
-    pub fn add_table(&mut self, table: Table<'a>) -> Result<()> {
-        let table_name = table.name;
+    pub fn add_table(&mut self, table: Table) -> Result<()> {
+        if self.tables.contains_key(&table.name) {
+            return Err(DbError::TableAlreadyExists(table.name));
+        }
+
+        self.tables.insert(table.name.to_owned(), table);
+
+        Ok(())
+    }
 
-        if self.tables.contains_key(table_name) {
-            return Err(DbError::TableAlreadyExists(table_name.into()));
+    /// Create an empty table with a schema, registering it with the
+    /// DataFusion context
+    pub fn create_table(&self, table_name: &str, schema: SchemaRef) -> Result<()> {
+        let mut table = Table::new(table_name);
+        table.set_record_batch(RecordBatch::new_empty(schema));
+
+        match self.tables.entry(table_name.into()) {
+            Entry::Occupied(_) => return Err(DbError::TableAlreadyExists(table_name.into())),
+            Entry::Vacant(entry) => {
+                entry.insert(table);
+            }
         }
 
-        self.tables.insert(table_name, table);
+        self.add_table_context(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
 
         Ok(())
     }
 
-    /// Get a table from the database
-    pub fn get_table(&self, name: &str) -> Result<Ref<'a, &str, Table>> {
+    /// Drop a table, removing it from the DataFusion context and returning
+    /// it.
+    ///
+    /// The linked source, view definition, triggers and sketch of the table
+    /// are dropped with it, and its subscribers stop receiving changes.  The
+    /// file of the table on disk is removed by the next export.
+    pub fn drop_table(&self, table_name: &str) -> Result<Table> {
+        let (_, table) = self
+            .tables
+            .remove(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+
+        self.linked_tables.remove(table_name);
+        self.views.remove(table_name);
+        self.subscribers.remove(table_name);
+        self.triggers.remove(table_name);
+        self.sketches.remove(table_name);
+
+        if self.providers.remove(table_name).is_some() {
+            let _ = self.ctx.deregister_table(table_name);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.renamed_tables
+                .write()
+                .unwrap()
+                .insert(table_name.into());
+            self.schedule_remove(table_name)?;
+        }
+
+        Ok(table)
+    }
+
+    /// Rename a table, registering it with the DataFusion context under the
+    /// new name.
+    ///
+    /// The linked source, view definition, triggers, sketch and subscribers of
+    /// the table move to the new name, but SQL that refers to the old name,
+    /// like the query of a view over the table, isn't rewritten.  The file of
+    /// the table on disk is renamed by the next export.
+    pub fn rename_table(&self, table_name: &str, new_name: &str) -> Result<()> {
+        if self.tables.contains_key(new_name) {
+            return Err(DbError::TableAlreadyExists(new_name.into()));
+        }
+
+        let (_, mut table) = self
+            .tables
+            .remove(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+        table.name = new_name.into();
+        self.tables.insert(new_name.into(), table);
+
+        rename_key(&self.linked_tables, table_name, new_name);
+        rename_key(&self.views, table_name, new_name);
+        rename_key(&self.subscribers, table_name, new_name);
+        rename_key(&self.triggers, table_name, new_name);
+        rename_key(&self.sketches, table_name, new_name);
+
+        if self.providers.remove(table_name).is_some() {
+            let _ = self.ctx.deregister_table(table_name);
+            self.add_table_context(new_name)?;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.renamed_tables
+                .write()
+                .unwrap()
+                .insert(table_name.into());
+            self.schedule_rename(table_name, new_name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get a table from the database, loading it back into memory if it's
+    /// spilled
+    pub fn get_table(&self, name: &str) -> Result<Ref<'_, String, Table>> {
+        self.load_spilled_table(name)?;
         self.tables
             .get(name)
             .ok_or_else(|| DbError::TableNotFound(name.into()))
     }
 
-    /// Get a mutable table from the database
-    pub fn get_mut_table(&self, name: &str) -> Result<RefMut<'a, &str, Table>> {
+    /// Get a mutable table from the database, loading it back into memory if
+    /// it's spilled
+    pub fn get_mut_table(&self, name: &str) -> Result<RefMut<'_, String, Table>> {
+        self.load_spilled_table(name)?;
         self.tables
             .get_mut(name)
             .ok_or_else(|| DbError::TableNotFound(name.into()))
@@ -80,15 +337,35 @@ impl<'a> Database<'a> {
 
     /// Create a new database from a directory on disk
     ///
-    /// The directory name is the database name, and each file
-    /// within the directory is a parquet file representing a table
+    /// The directory name is the database name, and each file within the
+    /// directory represents a table.  Parquet, CSV, JSON (`.json` or
+    /// `.ndjson`) and Arrow IPC (`.arrow`) files are imported, other files are
+    /// skipped.  Files are imported concurrently, up to one per available
+    /// thread at a time.
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn new_from_disk(name: &str) -> Result<Database> {
+        Database::new_from_disk_in(DISK_PATH, name).await
+    }
+
+    /// Create a new database from its directory in a data directory, as with
+    /// `new_from_disk`.  The database is exported and checkpointed to the same
+    /// directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_from_disk_in(data_dir: &str, name: &str) -> Result<Database> {
+        use tokio::{sync::Semaphore, task::JoinSet};
+
+        use crate::import::{import_concurrency, IMPORT_EXTENSIONS};
+
         let mut database = Database::new(name)?;
-        let path = format!("{DISK_PATH}{}", database.name);
-        let mut entries = tokio::fs::read_dir(path.to_owned()).await.map_err(|e| {
-            DbError::CreateDatabase(format!("Error reading file: {}", e.to_string()))
-        })?;
+        database.data_dir = data_dir.into();
+        let path = database.disk_path();
+        let mut entries = tokio::fs::read_dir(path.to_owned())
+            .await
+            .map_err(|e| DbError::CreateDatabase(format!("Error reading file: {e}")))?;
+
+        let batch_size = database.options().import_batch_size;
+        let semaphore = Arc::new(Semaphore::new(import_concurrency()));
+        let mut imports = JoinSet::new();
 
         while let Ok(Some(entry)) = entries.next_entry().await {
             if let Ok(file_type) = entry.file_type().await {
@@ -97,49 +374,74 @@ impl<'a> Database<'a> {
                     let file_str = file_name.to_string_lossy();
 
                     if let Some((table_name, extension)) = file_str.split_once('.') {
-                        if extension != "parquet" {
+                        if !IMPORT_EXTENSIONS.contains(&extension) {
                             continue;
                         }
 
-                        let table_name = Box::new(table_name.to_string());
-                        let mut table = Table::new(Box::leak(table_name.clone()));
+                        let (semaphore, path) = (semaphore.to_owned(), path.to_owned());
+                        let mut table = Table::new(table_name);
+                        let extension = extension.to_owned();
+
+                        imports.spawn(async move {
+                            let _permit = semaphore.acquire_owned().await;
 
-                        table.import_parquet_from_disk(&path).await?;
-                        database.add_table(table)?;
+                            table
+                                .import_file_from_disk(&path, &extension, batch_size)
+                                .await
+                                .map(|_| table)
+                        });
                     }
                 }
             }
         }
 
+        while let Some(table) = imports.join_next().await {
+            let table = table
+                .map_err(|e| DbError::CreateDatabase(format!("Error importing table: {e}")))??;
+            database.add_table(table)?;
+        }
+
         Ok(database)
     }
 
     pub fn load_table_bytes(&mut self, table_name: String, bytes: Bytes) -> Result<()> {
-        let table_name = Box::leak(table_name.into_boxed_str());
         let mut table = Table::new(table_name);
 
-        table.import_parquet_from_bytes(bytes)?;
+        table.import_parquet_from_bytes(bytes, self.options().import_batch_size)?;
         self.add_table(table)?;
 
         Ok(())
     }
 
-    /// Export the database to a directory on disk
+    /// The directory of the database on disk, in its data directory
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn disk_path(&self) -> String {
+        std::path::Path::new(&self.data_dir)
+            .join(&self.name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Export the database to a directory on disk, writing parquet or Arrow
+    /// IPC files with export options
     #[cfg(not(target_arch = "wasm32"))]
-    pub async fn export_to_disk(&self) -> Result<()> {
-        let path = format!("{DISK_PATH}{}", self.name);
+    pub async fn export_to_disk(&self, options: &ExportOptions) -> Result<()> {
+        let path = self.disk_path();
         tokio::fs::create_dir_all(path.to_owned())
             .await
-            .map_err(|e| {
-                DbError::CreateDatabase(format!("Error creating directory: {}", e.to_string()))
-            })?;
+            .map_err(|e| DbError::CreateDatabase(format!("Error creating directory: {e}")))?;
 
         for table in self.tables.iter() {
-            table
-                .value()
-                .to_owned()
-                .export_parquet_to_disk(&path)
-                .await?;
+            table.unspilled()?.export_to_disk(&path, options).await?;
+        }
+
+        // remove the files of renamed tables, unless the name was reused
+        let renamed_tables = std::mem::take(&mut *self.renamed_tables.write().unwrap());
+
+        for table_name in renamed_tables {
+            if !self.tables.contains_key(table_name.as_str()) {
+                remove_table_file(&path, &table_name).await?;
+            }
         }
 
         Ok(())
@@ -157,33 +459,44 @@ impl<'a> Database<'a> {
 #[macro_export]
 macro_rules! get_table {
     ( $self:ident, $name:tt ) => {
-        $self
-            .tables
-            .get($name)
-            .ok_or($crate::error::DbError::TableNotFound($name.into()))
+        $self.load_spilled_table($name).and_then(|_| {
+            $self
+                .tables
+                .get($name)
+                .ok_or($crate::error::DbError::TableNotFound($name.into()))
+        })
     };
 }
 
 #[macro_export]
 macro_rules! get_mut_table {
     ( $self:ident, $name:tt ) => {
-        $self
-            .tables
-            .get_mut($name)
-            .ok_or($crate::error::DbError::TableNotFound($name.into()))
+        $self.load_spilled_table($name).and_then(|_| {
+            $self
+                .tables
+                .get_mut($name)
+                .ok_or($crate::error::DbError::TableNotFound($name.into()))
+        })
     };
 }
 
+/// Move the entry of a table in a map keyed by table name to a new name
+fn rename_key<V>(map: &DashMap<String, V>, table_name: &str, new_name: &str) {
+    if let Some((_, value)) = map.remove(table_name) {
+        map.insert(new_name.into(), value);
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
 
     use arrow::array::{Int32Array, StringArray};
-    use arrow_schema::DataType;
+    use arrow_schema::{DataType, Field, Schema};
     use std::time::Instant;
 
     use super::*;
 
-    pub fn create_database<'a>() -> (Database<'a>, Table<'a>) {
+    pub fn create_database() -> (Database, Table) {
         let mut database = Database::new("MyDB").unwrap();
 
         let table_users = Table::new("users");
@@ -195,7 +508,7 @@ pub mod tests {
         (database, table_users)
     }
 
-    pub fn seed_database<'a>(database: &mut Database) {
+    pub fn seed_database(database: &mut Database) {
         get_mut_table!(database, "users")
             .unwrap()
             .add_column::<Int32Array>(
@@ -256,13 +569,154 @@ pub mod tests {
         database.print();
     }
 
+    #[tokio::test]
+    async fn test_rename_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        assert_eq!(
+            database.rename_table("users", "user_role"),
+            Err(DbError::TableAlreadyExists("user_role".into()))
+        );
+        database.rename_table("users", "people").unwrap();
+
+        assert_eq!(get_table!(database, "people").unwrap().name, "people");
+        assert!(!database.tables.contains_key("users"));
+        assert!(database.query("select * from users").await.is_err());
+
+        database
+            .query("insert into people values (5, 'Eve')")
+            .await
+            .unwrap();
+        let results = database
+            .query("select * from people")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_drop_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("sku", DataType::Utf8, true)]));
+        database.create_table("items", schema.to_owned()).unwrap();
+        assert_eq!(
+            database.create_table("users", schema.to_owned()),
+            Err(DbError::TableAlreadyExists("users".into()))
+        );
+
+        database
+            .query("insert into items values ('A1')")
+            .await
+            .unwrap();
+        assert_eq!(database.tables.get("items").unwrap().num_rows(), 1);
+
+        let table = database.drop_table("items").unwrap();
+        assert_eq!(table.num_rows(), 1);
+        assert!(database.query("select * from items").await.is_err());
+        assert_eq!(
+            database.drop_table("items").unwrap_err(),
+            DbError::TableNotFound("items".into())
+        );
+
+        // the name of a dropped table can be reused
+        database.create_table("items", schema).unwrap();
+        assert_eq!(database.tables.get("items").unwrap().num_rows(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fork() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let fork = database.fork("WhatIf").unwrap();
+        assert_eq!(fork.name, "WhatIf");
+        assert!(database.fork("What If").is_err());
+
+        // the fork shares the record batches of the original
+        let column = |database: &Database| {
+            database.tables.get("users").unwrap().chunks[0]
+                .column(0)
+                .to_owned()
+        };
+        assert!(Arc::ptr_eq(&column(&database), &column(&fork)));
+
+        // and writes to either are invisible to the other
+        fork.query("delete from users where id > 1").await.unwrap();
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+
+        let count = |database: &Database| get_table!(database, "users").unwrap().num_rows();
+        assert_eq!(count(&fork), 1);
+        assert_eq!(count(&database), 5);
+
+        let results = fork
+            .query("select name from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 1);
+    }
+
     #[tokio::test]
     async fn test_database_new_from_disk() {
+        // a scratch directory keeps the test out of the data directory
+        let data_dir = std::env::temp_dir()
+            .join(format!("arrow-db-new-from-disk-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
         let (mut database, _) = create_database();
         seed_database(&mut database);
-        database.export_to_disk().await.unwrap();
+        database.data_dir = data_dir.to_owned();
+        database
+            .export_to_disk(&ExportOptions::default())
+            .await
+            .unwrap();
 
-        let _database = Database::new_from_disk(database.name).await.unwrap();
+        let _database = Database::new_from_disk_in(&data_dir, &database.name)
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_database_new_from_disk_in() {
+        let data_dir = std::env::temp_dir()
+            .join(format!("arrow-db-data-dir-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.data_dir = data_dir.to_owned();
+        database
+            .export_to_disk(&ExportOptions::default())
+            .await
+            .unwrap();
+
+        let loaded = Database::new_from_disk_in(&data_dir, &database.name)
+            .await
+            .unwrap();
+        assert_eq!(loaded.disk_path(), database.disk_path());
+        assert!(loaded.disk_path().starts_with(&data_dir));
+        assert_eq!(get_table!(loaded, "users").unwrap().num_rows(), 4);
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
     }
 
     #[tokio::test]
@@ -271,19 +725,16 @@ pub mod tests {
         let database = Database::new_from_disk("LargeDB").await.unwrap();
         let elapsed = now.elapsed();
 
-        let rows = get_table!(database, "flights_1m")
-            .unwrap()
-            .record_batch
-            .num_rows();
-        let cols = get_table!(database, "flights_1m")
-            .unwrap()
-            .record_batch
-            .num_columns();
+        let rows = get_table!(database, "flights_1m").unwrap().num_rows();
+        let cols = get_table!(database, "flights_1m").unwrap().num_columns();
 
         println!("Loaded {} rows and {} cols in {:.2?}", rows, cols, elapsed);
 
         let now = Instant::now();
-        database.export_to_disk().await.unwrap();
+        database
+            .export_to_disk(&ExportOptions::default())
+            .await
+            .unwrap();
         let elapsed = now.elapsed();
 
         println!(
@@ -0,0 +1,113 @@
+//! Column defaults.
+//!
+//! INSERTs with a column list fill the columns they omit with the default
+//! value of the column, or with NULL if it has none.  Auto-increment and
+//! timestamp system columns are then filled in as usual.
+
+use std::collections::HashMap;
+
+use datafusion::{logical_expr::Expr, scalar::ScalarValue};
+
+use crate::{error::Result, row::cast_scalar, table::Table};
+
+impl Table {
+    /// Set the default value of a column, cast to the type of the column
+    pub fn set_default(&mut self, column_name: &str, value: ScalarValue) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+        let schema = self.schema();
+        let value = cast_scalar(&value, schema.field(column_index).data_type())?;
+
+        self.defaults.insert(column_name.into(), value);
+
+        Ok(())
+    }
+
+    /// Remove the default value of a column, so omitted values are NULL
+    pub fn drop_default(&mut self, column_name: &str) {
+        self.defaults.remove(column_name);
+    }
+
+    /// The defaults as expressions for DataFusion to plan INSERTs with
+    pub(crate) fn column_defaults(&self) -> HashMap<String, Expr> {
+        self.defaults
+            .iter()
+            .map(|(column_name, value)| (column_name.to_owned(), Expr::Literal(value.to_owned())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_defaults() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_default("name", ScalarValue::Utf8(Some("Anonymous".into())))
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        // omitted columns are filled with their default, or NULL
+        database
+            .query("insert into users (id) values (5)")
+            .await
+            .unwrap();
+        database
+            .query("insert into users (name) values ('Frank')")
+            .await
+            .unwrap();
+
+        // explicit NULLs are kept
+        database
+            .query("insert into users (id, name) values (7, NULL)")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(5),
+                None,
+                Some(7)
+            ])
+        );
+        assert_eq!(
+            table.column(1).unwrap().as_string::<i32>(),
+            &StringArray::from(vec![
+                Some("Alice"),
+                Some("Bob"),
+                Some("Charlie"),
+                Some("David"),
+                Some("Anonymous"),
+                Some("Frank"),
+                None
+            ])
+        );
+        drop(table);
+
+        // defaults must fit the column
+        let mut table = get_mut_table!(database, "users").unwrap();
+        assert!(table
+            .set_default("id", ScalarValue::Utf8(Some("one".into())))
+            .is_err());
+        assert!(table
+            .set_default("missing", ScalarValue::Int32(Some(1)))
+            .is_err());
+    }
+}
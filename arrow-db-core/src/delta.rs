@@ -0,0 +1,163 @@
+//! Delta Lake tables.
+//!
+//! With the `delta` feature, a Delta Lake table directory or URL can either be
+//! linked as a table, loading its current version into memory, or registered
+//! with the context, scanning its parquet files for every query.
+//!
+//! A linked Delta table is refreshed like any other linked table, picking up
+//! the versions committed since it was loaded.  A registered Delta table
+//! always reads the version it was registered at, and can only be queried,
+//! though queries can join it against in-memory tables.
+
+use std::{sync::Arc, time::Duration};
+
+use arrow::compute::concat_batches;
+use datafusion::prelude::SessionContext;
+use deltalake::DeltaTable;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    linked::TableLoader,
+};
+
+/// Open the current version of a Delta table
+async fn open_delta_table(table_name: &str, location: &str) -> Result<DeltaTable> {
+    deltalake::open_table(location)
+        .await
+        .map_err(|e| delta_error(table_name, e))
+}
+
+impl Database {
+    /// Link a table to a Delta table, loading its current version into memory
+    pub async fn link_delta_table(
+        &mut self,
+        table_name: &str,
+        location: &str,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let (name, location) = (table_name.to_owned(), location.to_owned());
+
+        let loader: TableLoader = Arc::new(move || {
+            let (name, location) = (name.to_owned(), location.to_owned());
+
+            Box::pin(async move {
+                let delta_table = open_delta_table(&name, &location).await?;
+                let df = SessionContext::new()
+                    .read_table(Arc::new(delta_table))
+                    .map_err(|e| delta_error(&name, e))?;
+                let schema = Arc::new(df.schema().as_arrow().to_owned());
+                let record_batches = df.collect().await.map_err(|e| delta_error(&name, e))?;
+
+                concat_batches(&schema, &record_batches).map_err(|e| delta_error(&name, e))
+            })
+        });
+
+        self.link_table(table_name, loader, ttl).await
+    }
+
+    /// Register a Delta table with the context under a table name, so queries
+    /// scan its files instead of loading it into memory
+    pub async fn register_delta_table(&self, table_name: &str, location: &str) -> Result<()> {
+        if self.tables.contains_key(table_name) {
+            return Err(DbError::TableAlreadyExists(table_name.into()));
+        }
+
+        let delta_table = open_delta_table(table_name, location).await?;
+
+        self.ctx
+            .register_table(table_name, Arc::new(delta_table))
+            .map_err(|e| delta_error(table_name, e))?;
+
+        Ok(())
+    }
+}
+
+fn delta_error(table_name: &str, error: impl ToString) -> DbError {
+    DbError::TableImportError(table_name.into(), error.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::RecordBatch;
+    use deltalake::DeltaOps;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    async fn count_rows(database: &Database, sql: &str) -> usize {
+        database
+            .query(sql)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_delta_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let path = std::env::temp_dir().join(format!("arrow-db-delta-{}", std::process::id()));
+        let location = path.to_string_lossy().to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        let users = get_table!(database, "users")
+            .unwrap()
+            .record_batch()
+            .unwrap();
+        let write = |location: String, users: RecordBatch| async move {
+            DeltaOps::try_from_uri(location)
+                .await
+                .unwrap()
+                .write(vec![users])
+                .await
+                .unwrap();
+        };
+        write(location.to_owned(), users.to_owned()).await;
+
+        database
+            .link_delta_table("delta_users", &location, None)
+            .await
+            .unwrap();
+        database
+            .register_delta_table("scanned_users", &location)
+            .await
+            .unwrap();
+        assert!(database
+            .register_delta_table("users", &location)
+            .await
+            .is_err());
+
+        assert_eq!(get_table!(database, "delta_users").unwrap().num_rows(), 4);
+        assert_eq!(
+            count_rows(
+                &database,
+                "select * from users u join scanned_users s on u.id = s.id"
+            )
+            .await,
+            4
+        );
+
+        // refreshing a linked table reads the latest version
+        write(location.to_owned(), users).await;
+        assert_eq!(database.refresh_table("delta_users").await.unwrap(), 8);
+        assert_eq!(
+            count_rows(&database, "select * from scanned_users").await,
+            4
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
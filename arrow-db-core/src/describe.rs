@@ -0,0 +1,191 @@
+//! DESCRIBE statements.
+//!
+//! `DESCRIBE table` returns a row for each column of a table, with its name,
+//! data type, nullability and constraints, so clients that only speak SQL can
+//! inspect a table.  System columns are included.
+
+use std::sync::Arc;
+
+use arrow::array::{BooleanArray, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use datafusion::prelude::DataFrame;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+impl Database {
+    /// Describe the columns of a table, returning a `DataFrame` with the
+    /// `column_name`, `data_type`, `is_nullable` and `constraints` of each
+    /// column
+    pub fn describe(&self, table_name: &str) -> Result<DataFrame> {
+        // describing a spilled table doesn't need its rows
+        let table = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+        let batch = table.describe()?;
+        drop(table);
+
+        self.ctx
+            .read_batch(batch)
+            .map_err(|e| DbError::Query(format!("DESCRIBE {table_name}"), e.to_string()))
+    }
+}
+
+impl Table {
+    /// Describe the columns of the table, one row per column
+    pub(crate) fn describe(&self) -> Result<RecordBatch> {
+        let schema = self.schema();
+        let primary_key = self.primary_key.as_ref().map(|key| &key.column_name);
+        let mut names = vec![];
+        let mut data_types = vec![];
+        let mut nullable = vec![];
+        let mut constraints = vec![];
+
+        for field in schema.fields().iter() {
+            let name = field.name();
+            let is_primary_key = primary_key == Some(name);
+            let is_not_null = self.not_null.contains(name);
+            let mut column_constraints = vec![];
+
+            if is_primary_key {
+                column_constraints.push("PRIMARY KEY".to_string());
+            }
+
+            if is_not_null {
+                column_constraints.push("NOT NULL".into());
+            }
+
+            if let Some(value) = self.defaults.get(name) {
+                column_constraints.push(format!("DEFAULT {value}"));
+            }
+
+            for check in self.checks.iter() {
+                if check.expr.column_refs().iter().any(|c| &c.name == name) {
+                    column_constraints.push(format!("CHECK {} ({})", check.name, check.expr));
+                }
+            }
+
+            names.push(name.to_owned());
+            data_types.push(field.data_type().to_string());
+            nullable.push(field.is_nullable() && !is_primary_key && !is_not_null);
+            constraints.push(match column_constraints.is_empty() {
+                true => None,
+                false => Some(column_constraints.join(", ")),
+            });
+        }
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("column_name", DataType::Utf8, false),
+            Field::new("data_type", DataType::Utf8, false),
+            Field::new("is_nullable", DataType::Boolean, false),
+            Field::new("constraints", DataType::Utf8, true),
+        ]));
+
+        Table::new_record_batch(
+            schema,
+            vec![
+                Arc::new(StringArray::from(names)),
+                Arc::new(StringArray::from(data_types)),
+                Arc::new(BooleanArray::from(nullable)),
+                Arc::new(StringArray::from(constraints)),
+            ],
+        )
+    }
+}
+
+/// Get the table name of a `DESCRIBE name`, `DESCRIBE TABLE name` or `DESC
+/// name` statement
+pub fn describe_table_name(sql: &str) -> Option<&str> {
+    let mut words = sql.trim().trim_end_matches(';').split_whitespace();
+    let is_describe =
+        |word: &str| word.eq_ignore_ascii_case("describe") || word.eq_ignore_ascii_case("desc");
+
+    let table_name = match (words.next(), words.next(), words.next(), words.next()) {
+        (Some(describe), Some(table_name), None, None) if is_describe(describe) => table_name,
+        (Some(describe), Some(table), Some(table_name), None)
+            if is_describe(describe) && table.eq_ignore_ascii_case("table") =>
+        {
+            table_name
+        }
+        _ => return None,
+    };
+
+    Some(table_name.trim_matches('"'))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::AsArray;
+    use datafusion::scalar::ScalarValue;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_describe_table_name() {
+        assert_eq!(describe_table_name("DESCRIBE users;"), Some("users"));
+        assert_eq!(describe_table_name("describe table users"), Some("users"));
+        assert_eq!(describe_table_name("desc \"users\""), Some("users"));
+        assert_eq!(describe_table_name("describe select 1"), None);
+        assert_eq!(describe_table_name("select * from users"), None);
+    }
+
+    #[tokio::test]
+    async fn test_describe() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        {
+            let mut table = get_mut_table!(database, "users").unwrap();
+            table.set_primary_key("id").unwrap();
+            table
+                .set_default("name", ScalarValue::Utf8(Some("Anonymous".into())))
+                .unwrap();
+        }
+        database
+            .add_check_constraint("users", "positive_id", "id > 0")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        let results = database
+            .query("DESCRIBE users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let batch = &results[0];
+
+        assert_eq!(
+            batch.column(0).as_string::<i32>(),
+            &StringArray::from(vec!["id", "name"])
+        );
+        assert_eq!(
+            batch.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Int32", "Utf8"])
+        );
+        assert_eq!(
+            batch.column(2).as_boolean(),
+            &BooleanArray::from(vec![false, true])
+        );
+
+        let constraints = batch.column(3).as_string::<i32>();
+        assert!(constraints
+            .value(0)
+            .starts_with("PRIMARY KEY, CHECK positive_id"));
+        assert_eq!(constraints.value(1), "DEFAULT Anonymous");
+
+        assert!(matches!(
+            database.query("DESCRIBE missing").await.unwrap_err(),
+            DbError::TableNotFound(_)
+        ));
+    }
+}
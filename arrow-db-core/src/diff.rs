@@ -0,0 +1,233 @@
+//! Table diffs.
+//!
+//! A diff compares the rows of two tables with the same columns by primary
+//! key, e.g. a table and a fresh import of it.  Rows are matched by the
+//! primary key of the first table, and compared on every column, so the diff
+//! holds the rows that were inserted, deleted or changed between them.
+
+use std::collections::HashMap;
+
+use arrow::{
+    array::{RecordBatch, UInt64Array},
+    compute::take_record_batch,
+    row::{RowConverter, SortField},
+};
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    row::get_column_value,
+    table::Table,
+};
+
+/// The rows that differ between two tables
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDiff {
+    /// Rows of the second table whose keys aren't in the first
+    pub inserted: RecordBatch,
+    /// Rows of the first table whose keys aren't in the second
+    pub deleted: RecordBatch,
+    /// Rows of the second table whose keys are in the first, but with
+    /// different values
+    pub changed: RecordBatch,
+}
+
+impl TableDiff {
+    /// Check if the tables have the same rows
+    pub fn is_empty(&self) -> bool {
+        self.inserted.num_rows() == 0
+            && self.deleted.num_rows() == 0
+            && self.changed.num_rows() == 0
+    }
+}
+
+impl Table {
+    /// Diff the rows of the table against another table with the same
+    /// columns, matching rows by the primary key of the table
+    pub fn diff(&self, other: &Table) -> Result<TableDiff> {
+        let key_column = self
+            .primary_key
+            .as_ref()
+            .map(|primary_key| primary_key.column_name.to_owned())
+            .ok_or_else(|| DbError::PrimaryKeyNotFound(self.name.to_owned()))?;
+
+        let fields = |table: &Table| {
+            table
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| (field.name().to_owned(), field.data_type().to_owned()))
+                .collect::<Vec<_>>()
+        };
+
+        if fields(self) != fields(other) {
+            return Err(DbError::DataType(format!(
+                "Tables {} and {} have different columns",
+                self.name, other.name
+            )));
+        }
+
+        let before = self.record_batch()?;
+        let after = other.record_batch()?;
+        let key_index = self.column_index(&key_column)?;
+
+        let before_keys = (0..before.num_rows())
+            .map(|row| Ok((get_column_value(before.column(key_index), row)?, row)))
+            .collect::<Result<HashMap<ScalarValue, usize>>>()?;
+
+        // rows are compared in the row format, which compares every column
+        // at once
+        let converter = RowConverter::new(
+            before
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| SortField::new(field.data_type().to_owned()))
+                .collect(),
+        )
+        .map_err(|e| DbError::ArrayData(format!("Error diffing Table {}: {e}", self.name)))?;
+        let convert = |record_batch: &RecordBatch| {
+            converter
+                .convert_columns(record_batch.columns())
+                .map_err(|e| DbError::ArrayData(format!("Error diffing Table {}: {e}", self.name)))
+        };
+        let before_rows = convert(&before)?;
+        let after_rows = convert(&after)?;
+
+        let mut matched = vec![false; before.num_rows()];
+        let mut inserted = vec![];
+        let mut changed = vec![];
+
+        for row in 0..after.num_rows() {
+            let key = get_column_value(after.column(key_index), row)?;
+
+            match before_keys.get(&key) {
+                Some(&before_row) => {
+                    matched[before_row] = true;
+
+                    if before_rows.row(before_row) != after_rows.row(row) {
+                        changed.push(row as u64);
+                    }
+                }
+                None => inserted.push(row as u64),
+            }
+        }
+
+        let deleted = matched
+            .iter()
+            .enumerate()
+            .filter(|(_, matched)| !**matched)
+            .map(|(row, _)| row as u64)
+            .collect();
+
+        Ok(TableDiff {
+            inserted: take_rows(&after, inserted)?,
+            deleted: take_rows(&before, deleted)?,
+            changed: take_rows(&after, changed)?,
+        })
+    }
+}
+
+impl Database {
+    /// Diff the rows of two tables, matching rows by the primary key of the
+    /// first
+    pub fn diff_tables(&self, table_name: &str, other_name: &str) -> Result<TableDiff> {
+        // load both tables before reading either, so neither is held while
+        // the other is loaded
+        self.load_spilled_table(table_name)?;
+        self.load_spilled_table(other_name)?;
+
+        let table = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+        let other = self
+            .tables
+            .get(other_name)
+            .ok_or_else(|| DbError::TableNotFound(other_name.into()))?;
+
+        table.diff(&other)
+    }
+}
+
+fn take_rows(record_batch: &RecordBatch, rows: Vec<u64>) -> Result<RecordBatch> {
+    take_record_batch(record_batch, &UInt64Array::from(rows))
+        .map_err(|e| DbError::ArrayData(format!("Error taking rows: {e}")))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use arrow_schema::DataType;
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diff_tables() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // rows are matched by primary key
+        assert_eq!(
+            database.diff_tables("users", "users"),
+            Err(DbError::PrimaryKeyNotFound("users".into()))
+        );
+        database
+            .tables
+            .get_mut("users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        assert!(database.diff_tables("users", "users").unwrap().is_empty());
+
+        let mut imported = database.tables.get("users").unwrap().to_owned();
+        imported.name = "imported".into();
+        database.add_table(imported).unwrap();
+        database.add_table_context("imported").unwrap();
+
+        database
+            .query("delete from imported where id = 1")
+            .await
+            .unwrap();
+        database
+            .query("update imported set name = 'Robert' where id = 2")
+            .await
+            .unwrap();
+        database
+            .query("insert into imported values (5, 'Eve')")
+            .await
+            .unwrap();
+
+        let diff = database.diff_tables("users", "imported").unwrap();
+        let ids = |record_batch: &RecordBatch| {
+            record_batch
+                .column(0)
+                .as_primitive::<Int32Type>()
+                .values()
+                .to_vec()
+        };
+        assert_eq!(ids(&diff.inserted), vec![5]);
+        assert_eq!(ids(&diff.deleted), vec![1]);
+        assert_eq!(ids(&diff.changed), vec![2]);
+        assert_eq!(
+            diff.changed.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Robert"])
+        );
+
+        // tables with different columns can't be diffed
+        let mut other = Table::new("other");
+        other
+            .add_column::<Int32Array>(0, "id", DataType::Int32, Int32Array::from(vec![1]).into())
+            .unwrap();
+        database.add_table(other).unwrap();
+        assert!(matches!(
+            database.diff_tables("users", "other"),
+            Err(DbError::DataType(_))
+        ));
+    }
+}
@@ -0,0 +1,1213 @@
+//! DML operations.
+//!
+//! DataFusion plans INSERT, UPDATE and DELETE statements, but the providers
+//! registered with the context only read the committed chunks of the database
+//! tables.  Instead of executing the plans in DataFusion, DML statements are
+//! applied directly to the tables, which are then committed and published to
+//! their providers.
+
+use std::{cmp::Ordering, collections::HashSet, sync::Arc};
+
+use arrow::array::{Array, AsArray, BooleanArray, RecordBatch};
+use arrow_schema::{Field, Schema};
+use datafusion::{
+    common::{
+        tree_node::{Transformed, TreeNode, TreeNodeRecursion},
+        Column, DFSchema,
+    },
+    error::DataFusionError,
+    logical_expr::{
+        dml::InsertOp, execution_props::ExecutionProps, type_coercion::binary::comparison_coercion,
+        Between, BinaryExpr, Cast, ColumnarValue, DmlStatement, Expr, Like, LogicalPlan, Operator,
+        TryCast, WriteOp,
+    },
+    optimizer::simplify_expressions::{ExprSimplifier, SimplifyContext},
+    physical_expr::{create_physical_expr, PhysicalExpr},
+    prelude::DataFrame,
+    scalar::ScalarValue,
+    sql::{
+        parser::Statement,
+        sqlparser::ast::{Ident, SetExpr, Statement as SqlStatement},
+    },
+};
+
+use crate::{
+    change::{ChangeKind, TableChange},
+    chunk::take_rows,
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    row::get_column_value,
+    system::{hide_system_columns, now, UPDATED_AT_COLUMN},
+    table::Table,
+};
+
+impl Database {
+    /// Execute a DML statement, returning the number of affected rows
+    pub async fn execute_dml(&self, dml: &DmlStatement) -> Result<usize> {
+        Ok(self.apply_dml(dml).await?.rows.len())
+    }
+
+    /// Execute a DML statement, returning the change it committed
+    pub(crate) async fn apply_dml(&self, dml: &DmlStatement) -> Result<TableChange> {
+        let table_name = dml.table_name.table();
+
+        let change = match dml.op {
+            WriteOp::Insert(InsertOp::Append) => {
+                self.execute_insert(table_name, &dml.input).await?
+            }
+            WriteOp::Update => self.execute_update(table_name, &dml.input)?,
+            WriteOp::Delete => self.execute_delete(table_name, &dml.input)?,
+            ref op => return Err(DbError::Dml(format!("Unsupported operation {op:?}"))),
+        };
+
+        let version = self.commit_table(table_name)?;
+        self.publish_change(table_name, version, &change)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        self.run_after_triggers(table_name, version, &change)?;
+
+        Ok(change)
+    }
+
+    /// Insert rows from a VALUES list into a table.
+    ///
+    /// DataFusion wraps the VALUES list in a projection that casts each value
+    /// to the type of its target column.
+    async fn execute_insert(&self, table_name: &str, input: &LogicalPlan) -> Result<TableChange> {
+        let LogicalPlan::Projection(projection) = input else {
+            return self.execute_insert_from_select(table_name, input).await;
+        };
+
+        let LogicalPlan::Values(values) = projection.input.as_ref() else {
+            return self.execute_insert_from_select(table_name, input).await;
+        };
+
+        let rows = values
+            .values
+            .iter()
+            .map(|row| {
+                projection
+                    .expr
+                    .iter()
+                    .map(|expr| evaluate_scalar(expr, row, &values.schema))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut table = get_mut_table!(self, table_name)?;
+        let before = table.chunks.to_owned();
+
+        for row in rows {
+            table.append_row(row)?;
+        }
+
+        let change = TableChange::inserted(before, &table.chunks);
+        self.run_before_triggers(&mut table, change)
+    }
+
+    /// Insert the results of a query into a table.
+    async fn execute_insert_from_select(
+        &self,
+        table_name: &str,
+        input: &LogicalPlan,
+    ) -> Result<TableChange> {
+        let record_batches = DataFrame::new(self.ctx.state(), input.to_owned())
+            .collect()
+            .await
+            .map_err(|e| DbError::Dml(e.to_string()))?;
+
+        let mut table = get_mut_table!(self, table_name)?;
+        let before = table.chunks.to_owned();
+
+        for batch in record_batches {
+            for row in 0..batch.num_rows() {
+                let values = batch
+                    .columns()
+                    .iter()
+                    .map(|column| get_column_value(column, row))
+                    .collect::<Result<Vec<_>>>()?;
+
+                table.append_row(values)?;
+            }
+        }
+
+        let change = TableChange::inserted(before, &table.chunks);
+        self.run_before_triggers(&mut table, change)
+    }
+
+    /// Give an `INSERT INTO table SELECT ...` without a column list the
+    /// columns of the query, so its columns are matched to the table by name
+    /// rather than by position.
+    ///
+    /// Queries whose columns aren't all columns of the table are matched by
+    /// position, and fail if the number of columns differs.
+    pub(crate) async fn insert_columns_by_name(
+        &self,
+        mut statement: Statement,
+    ) -> Result<Statement> {
+        let Statement::Statement(sql_statement) = &mut statement else {
+            return Ok(statement);
+        };
+
+        let SqlStatement::Insert(insert) = sql_statement.as_mut() else {
+            return Ok(statement);
+        };
+
+        let Some(source) = insert.source.as_ref() else {
+            return Ok(statement);
+        };
+
+        if !insert.columns.is_empty() || matches!(source.body.as_ref(), SetExpr::Values(_)) {
+            return Ok(statement);
+        }
+
+        let Some(table_name) = insert.table_name.0.last() else {
+            return Ok(statement);
+        };
+
+        let column_names = {
+            let table = self
+                .tables
+                .get(table_name.value.as_str())
+                .or_else(|| self.tables.get(table_name.value.to_lowercase().as_str()));
+
+            match table {
+                Some(table) => table.visible_column_names(),
+                None => return Ok(statement),
+            }
+        };
+
+        // plan the query on its own to find the names of its columns, leaving
+        // any errors for planning the whole statement
+        let query = Statement::Statement(Box::new(SqlStatement::Query(source.to_owned())));
+        let Ok(plan) = self.ctx.state().statement_to_plan(query).await else {
+            return Ok(statement);
+        };
+
+        let source_names = hide_system_columns(plan)?
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>();
+
+        if source_names == column_names {
+            return Ok(statement);
+        }
+
+        let unique = source_names.iter().collect::<HashSet<_>>().len() == source_names.len();
+        let by_name = unique && source_names.iter().all(|name| column_names.contains(name));
+
+        if by_name {
+            insert.columns = source_names
+                .into_iter()
+                .map(|name| Ident::with_quote('"', name))
+                .collect();
+        } else if source_names.len() != column_names.len() {
+            return Err(DbError::Dml(format!(
+                "Cannot insert columns ({}) into Table {} with columns ({}): \
+                 the names and the number of columns don't match",
+                source_names.join(", "),
+                table_name.value,
+                column_names.join(", ")
+            )));
+        }
+
+        Ok(statement)
+    }
+
+    /// Update the rows of a table that match the WHERE clause.
+    ///
+    /// DataFusion plans an UPDATE as a projection of every column in the
+    /// table over an optional filter.  Columns that are not assigned are
+    /// projected as-is.
+    fn execute_update(&self, table_name: &str, input: &LogicalPlan) -> Result<TableChange> {
+        let LogicalPlan::Projection(projection) = input else {
+            return Err(DbError::Dml(format!("Unexpected UPDATE plan {input}")));
+        };
+
+        let predicate = match projection.input.as_ref() {
+            LogicalPlan::Filter(filter) => Some(&filter.predicate),
+            _ => None,
+        };
+
+        let mut table = get_mut_table!(self, table_name)?;
+        let record_batch = table.record_batch()?;
+        let schema = record_batch.schema();
+        let rows = matching_rows(&table, predicate)?;
+
+        let mut mask = vec![false; record_batch.num_rows()];
+
+        for row in rows.iter() {
+            mask[*row] = true;
+        }
+
+        let mask = BooleanArray::from(mask);
+
+        let mut assignments = projection
+            .expr
+            .iter()
+            .enumerate()
+            .filter_map(|(column_index, expr)| {
+                let expr = unalias(expr);
+
+                match expr {
+                    Expr::Column(column) if &column.name == schema.field(column_index).name() => {
+                        None
+                    }
+                    // expressions over the row, like CASE, are evaluated for
+                    // the matching rows only
+                    _ if !expr.column_refs().is_empty() => Some(
+                        physical_expr(&record_batch, expr)
+                            .and_then(|value| {
+                                value
+                                    .evaluate_selection(&record_batch, &mask)
+                                    .map_err(|e| DbError::Dml(format!("{expr}: {e}")))
+                            })
+                            .map(|value| (column_index, value)),
+                    ),
+                    _ => Some(
+                        evaluate_scalar(expr, &[], &DFSchema::empty())
+                            .map(|value| (column_index, ColumnarValue::Scalar(value))),
+                    ),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // system columns are maintained unless assigned explicitly
+        if let Ok(updated_at) = table.column_index(UPDATED_AT_COLUMN) {
+            if !assignments
+                .iter()
+                .any(|(column_index, _)| *column_index == updated_at)
+            {
+                assignments.push((updated_at, ColumnarValue::Scalar(now())));
+            }
+        }
+
+        let before = table.chunks.to_owned();
+        table.update_rows_columnar(&rows, &assignments)?;
+
+        let change = TableChange {
+            kind: ChangeKind::Update,
+            rows,
+            before,
+            after: table.chunks.to_owned(),
+        };
+        self.run_before_triggers(&mut table, change)
+    }
+
+    /// Delete the rows of a table that match the WHERE clause.
+    fn execute_delete(&self, table_name: &str, input: &LogicalPlan) -> Result<TableChange> {
+        let predicate = match input {
+            LogicalPlan::Filter(filter) => Some(&filter.predicate),
+            _ => None,
+        };
+
+        let mut table = get_mut_table!(self, table_name)?;
+        let rows = matching_rows(&table, predicate)?;
+        let before = table.chunks.to_owned();
+        table.delete_rows(&rows)?;
+
+        let change = TableChange {
+            kind: ChangeKind::Delete,
+            rows,
+            before,
+            after: table.chunks.to_owned(),
+        };
+        self.run_before_triggers(&mut table, change)
+    }
+}
+
+/// Strip any aliases from an expression
+fn unalias(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Alias(alias) => unalias(&alias.expr),
+        expr => expr,
+    }
+}
+
+/// Get the indexes of the rows of a table that match an optional predicate.
+///
+/// If an index narrows the predicate down to a few rows, only those rows are
+/// checked.
+fn matching_rows(table: &Table, predicate: Option<&Expr>) -> Result<Vec<usize>> {
+    let Some(predicate) = predicate else {
+        return Ok((0..table.num_rows()).collect());
+    };
+
+    let (batch, rows) = match table.indexed_rows(Some(predicate)) {
+        Some(rows) => (take_rows(&table.chunks, &rows)?, rows),
+        None => (table.record_batch()?, (0..table.num_rows()).collect()),
+    };
+    let (batch, predicate) = evaluate_functions(&batch, predicate)?;
+
+    rows.into_iter()
+        .enumerate()
+        .filter_map(|(batch_row, row)| {
+            match evaluate_where_condition(&batch, batch_row, &predicate) {
+                Ok(true) => Some(Ok(row)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+        .collect()
+}
+
+/// Evaluate an expression that doesn't reference table columns.
+///
+/// Columns are resolved against a row of a VALUES list, if provided.
+pub fn evaluate_scalar(expr: &Expr, row: &[Expr], schema: &DFSchema) -> Result<ScalarValue> {
+    match expr {
+        Expr::Alias(alias) => evaluate_scalar(&alias.expr, row, schema),
+        Expr::Literal(value) => Ok(value.to_owned()),
+        Expr::Cast(Cast { expr, data_type }) | Expr::TryCast(TryCast { expr, data_type }) => {
+            evaluate_scalar(expr, row, schema)?
+                .cast_to(data_type)
+                .map_err(|e| DbError::Dml(e.to_string()))
+        }
+        Expr::Negative(expr) => evaluate_scalar(expr, row, schema)?
+            .arithmetic_negate()
+            .map_err(|e| DbError::Dml(e.to_string())),
+        Expr::Column(column) => {
+            let index = schema
+                .fields()
+                .iter()
+                .position(|field| field.name() == &column.name)
+                .ok_or_else(|| DbError::Dml(format!("Unsupported value {expr}")))?;
+
+            evaluate_scalar(&row[index], &[], &DFSchema::empty())
+        }
+        Expr::Placeholder(placeholder) => Err(DbError::Dml(format!(
+            "No value bound to placeholder {}",
+            placeholder.id
+        ))),
+        expr => Err(DbError::Dml(format!("Unsupported value {expr}"))),
+    }
+}
+
+/// Evaluate the scalar functions of a condition, e.g. `lower(name)`, over a
+/// whole `RecordBatch`.
+///
+/// Each function is planned and evaluated once, and its results are added to
+/// the batch as a column, which the returned condition reads in place of the
+/// function.  Conditions are then evaluated row by row against the batch.
+pub fn evaluate_functions(batch: &RecordBatch, expr: &Expr) -> Result<(RecordBatch, Expr)> {
+    let mut functions = vec![];
+
+    // nested functions are evaluated with the function that contains them
+    expr.apply(|expr| match expr {
+        Expr::ScalarFunction(_) => {
+            if !functions.contains(expr) {
+                functions.push(expr.to_owned());
+            }
+
+            Ok(TreeNodeRecursion::Jump)
+        }
+        _ => Ok(TreeNodeRecursion::Continue),
+    })
+    .map_err(|e| DbError::Dml(e.to_string()))?;
+
+    if functions.is_empty() {
+        return Ok((batch.to_owned(), expr.to_owned()));
+    }
+
+    let mut fields = batch.schema().fields().to_vec();
+    let mut columns = batch.columns().to_vec();
+    let mut names = vec![];
+
+    for function in functions.iter() {
+        let values = physical_expr(batch, function)?
+            .evaluate(batch)
+            .and_then(|values| values.into_array(batch.num_rows()))
+            .map_err(|e| DbError::Dml(format!("{function}: {e}")))?;
+        let name = format!("__function_{}", columns.len());
+
+        fields.push(Arc::new(Field::new(
+            &name,
+            values.data_type().to_owned(),
+            true,
+        )));
+        columns.push(values);
+        names.push(name);
+    }
+
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+        .map_err(|e| DbError::Dml(e.to_string()))?;
+
+    let expr = expr
+        .to_owned()
+        .transform_down(|expr| match functions.iter().position(|f| f == &expr) {
+            Some(index) => Ok(Transformed::yes(Expr::Column(Column::new_unqualified(
+                &names[index],
+            )))),
+            None => Ok(Transformed::no(expr)),
+        })
+        .map_err(|e| DbError::Dml(e.to_string()))?
+        .data;
+
+    Ok((batch, expr))
+}
+
+/// Evaluate a WHERE clause against a single row of a `RecordBatch`.
+///
+/// Only rows where the condition is true match, so a condition that is NULL
+/// (unknown) doesn't match, even under NOT.  Scalar functions are evaluated
+/// beforehand with `evaluate_functions`.
+pub fn evaluate_where_condition(batch: &RecordBatch, row: usize, expr: &Expr) -> Result<bool> {
+    Ok(evaluate_condition(batch, row, expr)? == Some(true))
+}
+
+/// Evaluate a condition against a single row of a `RecordBatch` with SQL's
+/// three-valued logic, where `None` is unknown.
+pub fn evaluate_condition(batch: &RecordBatch, row: usize, expr: &Expr) -> Result<Option<bool>> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+            // false AND unknown is false, true OR unknown is true
+            Operator::And => match evaluate_condition(batch, row, left)? {
+                Some(false) => Ok(Some(false)),
+                left => Ok(match (left, evaluate_condition(batch, row, right)?) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }),
+            },
+            Operator::Or => match evaluate_condition(batch, row, left)? {
+                Some(true) => Ok(Some(true)),
+                left => Ok(match (left, evaluate_condition(batch, row, right)?) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }),
+            },
+            _ => check_column_comparison(batch, row, left, op, right),
+        },
+        Expr::Not(expr) => Ok(evaluate_condition(batch, row, expr)?.map(|value| !value)),
+        Expr::Like(Like {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+            case_insensitive,
+        }) => {
+            let value = evaluate_row_value(batch, row, expr)?;
+            let pattern = evaluate_row_value(batch, row, pattern)?;
+
+            let (ScalarValue::Utf8(Some(value)), ScalarValue::Utf8(Some(pattern))) =
+                (value, pattern)
+            else {
+                return Ok(None);
+            };
+
+            let matches = if *case_insensitive {
+                matches_like_pattern(
+                    &value.to_lowercase(),
+                    &pattern.to_lowercase(),
+                    escape_char.map(|escape_char| escape_char.to_ascii_lowercase()),
+                )
+            } else {
+                matches_like_pattern(&value, &pattern, *escape_char)
+            };
+
+            Ok(Some(matches != *negated))
+        }
+        Expr::Between(Between {
+            expr,
+            negated,
+            low,
+            high,
+        }) => {
+            let value = evaluate_row_value(batch, row, expr)?;
+            let low = compare_values(&value, &evaluate_row_value(batch, row, low)?)?;
+            let high = compare_values(&value, &evaluate_row_value(batch, row, high)?)?;
+
+            // comparisons with NULL are unknown, even when negated
+            let (Some(low), Some(high)) = (low, high) else {
+                return Ok(None);
+            };
+
+            let between = low != Ordering::Less && high != Ordering::Greater;
+
+            Ok(Some(between != *negated))
+        }
+        Expr::IsNull(expr) => Ok(Some(evaluate_row_value(batch, row, expr)?.is_null())),
+        Expr::IsNotNull(expr) => Ok(Some(!evaluate_row_value(batch, row, expr)?.is_null())),
+        Expr::IsTrue(expr) => Ok(Some(evaluate_condition(batch, row, expr)? == Some(true))),
+        Expr::IsFalse(expr) => Ok(Some(evaluate_condition(batch, row, expr)? == Some(false))),
+        Expr::IsUnknown(expr) => Ok(Some(evaluate_condition(batch, row, expr)?.is_none())),
+        Expr::IsNotTrue(expr) => Ok(Some(evaluate_condition(batch, row, expr)? != Some(true))),
+        Expr::IsNotFalse(expr) => Ok(Some(evaluate_condition(batch, row, expr)? != Some(false))),
+        Expr::IsNotUnknown(expr) => Ok(Some(evaluate_condition(batch, row, expr)?.is_some())),
+        Expr::Literal(ScalarValue::Boolean(value)) => Ok(*value),
+        // a boolean column, or the results of a function
+        Expr::Column(column) => {
+            let column_index = batch
+                .schema()
+                .index_of(&column.name)
+                .map_err(|e| DbError::Dml(e.to_string()))?;
+
+            match batch.column(column_index).as_boolean_opt() {
+                Some(values) => Ok(values.is_valid(row).then(|| values.value(row))),
+                None => Err(DbError::Dml(format!("Unsupported condition {expr}"))),
+            }
+        }
+        expr => Err(DbError::Dml(format!("Unsupported condition {expr}"))),
+    }
+}
+
+/// Evaluate an expression in a WHERE clause to the value of a column at a row
+/// or to a literal value.
+fn evaluate_row_value(batch: &RecordBatch, row: usize, expr: &Expr) -> Result<ScalarValue> {
+    match expr {
+        Expr::Column(column) => {
+            let column_index = batch
+                .schema()
+                .index_of(&column.name)
+                .map_err(|e| DbError::Dml(e.to_string()))?;
+
+            get_column_value(batch.column(column_index), row)
+        }
+        expr => evaluate_scalar(expr, &[], &DFSchema::empty()),
+    }
+}
+
+/// Plan an expression over the columns of a `RecordBatch` with DataFusion's
+/// physical expressions.
+///
+/// The types of the expression are coerced like a planned query, so e.g.
+/// `id - 2` subtracts an `Int64` literal from an `Int32` column.
+fn physical_expr(batch: &RecordBatch, expr: &Expr) -> Result<Arc<dyn PhysicalExpr>> {
+    let to_dml_error = |e: DataFusionError| DbError::Dml(format!("{expr}: {e}"));
+
+    // the schema of the table is unqualified, so columns are resolved by name
+    let unqualified = expr
+        .to_owned()
+        .transform(|expr| match expr {
+            Expr::Column(column) => Ok(Transformed::yes(Expr::Column(Column::new_unqualified(
+                column.name,
+            )))),
+            expr => Ok(Transformed::no(expr)),
+        })
+        .map_err(to_dml_error)?
+        .data;
+
+    let schema =
+        Arc::new(DFSchema::try_from(batch.schema().as_ref().to_owned()).map_err(to_dml_error)?);
+    let props = ExecutionProps::new();
+    let simplifier =
+        ExprSimplifier::new(SimplifyContext::new(&props).with_schema(Arc::clone(&schema)));
+    let coerced = simplifier
+        .coerce(unqualified, &schema)
+        .map_err(to_dml_error)?;
+
+    create_physical_expr(&coerced, &schema, &props).map_err(to_dml_error)
+}
+
+/// Compare a column to a literal value with a comparison operator.
+///
+/// The literal is cast to the type of the column.  Comparisons with NULL are
+/// unknown.
+pub fn check_column_comparison(
+    batch: &RecordBatch,
+    row: usize,
+    left: &Expr,
+    op: &Operator,
+    right: &Expr,
+) -> Result<Option<bool>> {
+    let left = evaluate_row_value(batch, row, left)?;
+    let right = evaluate_row_value(batch, row, right)?;
+
+    let Some(ordering) = compare_values(&left, &right)? else {
+        return Ok(None);
+    };
+    let ordering = Some(ordering);
+
+    let matches = match op {
+        Operator::Eq => ordering == Some(Ordering::Equal),
+        Operator::NotEq => ordering.is_some_and(|ordering| ordering != Ordering::Equal),
+        Operator::Lt => ordering == Some(Ordering::Less),
+        Operator::LtEq => ordering.is_some_and(|ordering| ordering != Ordering::Greater),
+        Operator::Gt => ordering == Some(Ordering::Greater),
+        Operator::GtEq => ordering.is_some_and(|ordering| ordering != Ordering::Less),
+        op => return Err(DbError::Dml(format!("Unsupported operator {op}"))),
+    };
+
+    Ok(Some(matches))
+}
+
+/// Order two values, or `None` if either is NULL or they can't be compared.
+///
+/// Integers of any width and signedness are compared by value.  Otherwise both
+/// values are cast to the type DataFusion compares them as, so a fraction isn't
+/// truncated when compared to an integer column, and strings are parsed when
+/// compared to dates and times.
+pub(crate) fn compare_values(left: &ScalarValue, right: &ScalarValue) -> Result<Option<Ordering>> {
+    if left.is_null() || right.is_null() {
+        return Ok(None);
+    }
+
+    // literals are Int64, which may not fit the type of a column
+    if let (Some(left), Some(right)) = (integer_value(left), integer_value(right)) {
+        return Ok(Some(left.cmp(&right)));
+    }
+
+    let (left_type, right_type) = (left.data_type(), right.data_type());
+
+    if left_type == right_type {
+        return Ok(left.partial_cmp(right));
+    }
+
+    let data_type = comparison_coercion(&left_type, &right_type)
+        .ok_or_else(|| DbError::Dml(format!("Cannot compare {left_type} with {right_type}")))?;
+    let cast = |value: &ScalarValue| {
+        value
+            .cast_to(&data_type)
+            .map_err(|e| DbError::Dml(e.to_string()))
+    };
+
+    Ok(cast(left)?.partial_cmp(&cast(right)?))
+}
+
+/// Widen a non-null integer value of any type, so it can be compared with
+/// integers of other types
+fn integer_value(value: &ScalarValue) -> Option<i128> {
+    match *value {
+        ScalarValue::Int8(Some(value)) => Some(value.into()),
+        ScalarValue::Int16(Some(value)) => Some(value.into()),
+        ScalarValue::Int32(Some(value)) => Some(value.into()),
+        ScalarValue::Int64(Some(value)) => Some(value.into()),
+        ScalarValue::UInt8(Some(value)) => Some(value.into()),
+        ScalarValue::UInt16(Some(value)) => Some(value.into()),
+        ScalarValue::UInt32(Some(value)) => Some(value.into()),
+        ScalarValue::UInt64(Some(value)) => Some(value.into()),
+        _ => None,
+    }
+}
+
+/// A token of a LIKE pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LikeToken {
+    /// `%`, any sequence of characters
+    Any,
+    /// `_`, any single character
+    One,
+    Literal(char),
+}
+
+/// Match a value against a SQL LIKE pattern, where `%` matches any sequence
+/// of characters and `_` matches any single character.
+///
+/// A character after the escape character, if any, is matched literally, so
+/// `100\%` with an escape character of `\` only matches `100%`.
+pub fn matches_like_pattern(value: &str, pattern: &str, escape_char: Option<char>) -> bool {
+    let value = value.chars().collect::<Vec<_>>();
+    let mut characters = pattern.chars();
+    let mut pattern = vec![];
+
+    while let Some(character) = characters.next() {
+        pattern.push(match character {
+            // a trailing escape character matches itself
+            character if Some(character) == escape_char => {
+                LikeToken::Literal(characters.next().unwrap_or(character))
+            }
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            character => LikeToken::Literal(character),
+        });
+    }
+
+    // matches[j] is true if the value consumed so far matches pattern[..j]
+    let mut matches = vec![false; pattern.len() + 1];
+    matches[0] = true;
+
+    for (j, token) in pattern.iter().enumerate() {
+        matches[j + 1] = matches[j] && *token == LikeToken::Any;
+    }
+
+    for character in value {
+        let mut next = vec![false; pattern.len() + 1];
+
+        for (j, token) in pattern.iter().enumerate() {
+            next[j + 1] = match token {
+                LikeToken::Any => next[j] || matches[j + 1],
+                LikeToken::One => matches[j],
+                LikeToken::Literal(token) => matches[j] && *token == character,
+            };
+        }
+
+        matches = next;
+    }
+
+    matches[pattern.len()]
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{
+        AsArray, Int32Array, Int64Array, Int8Array, StringArray, Time64MicrosecondArray,
+        TimestampNanosecondArray, UInt32Array,
+    };
+    use arrow::datatypes::{
+        DataType, Field, Int32Type, Int64Type, Int8Type, Schema, Time64MicrosecondType, TimeUnit,
+        TimestampNanosecondType, UInt32Type,
+    };
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+        table::Table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dml() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, 'Eve'), (6, 'Frank')")
+            .await
+            .unwrap();
+        database
+            .query("update users set name = 'Eve2' where id = 5")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id < 3 or name like 'Fr%'")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let ids = table.column(0).unwrap();
+        let ids = ids.as_primitive::<Int32Type>();
+        let names = table.column(1).unwrap();
+        let names = names.as_string::<i32>();
+        assert_eq!(ids, &Int32Array::from(vec![3, 4, 5]));
+        assert_eq!(names, &StringArray::from(vec!["Charlie", "David", "Eve2"]));
+
+        // the context sees the changes
+        let results = database
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_dml_integer_types() {
+        let mut database = Database::new("Integers").unwrap();
+        let mut table = Table::new("counters");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("small", DataType::Int8, true),
+            Field::new("big", DataType::Int64, true),
+            Field::new("unsigned", DataType::UInt32, true),
+        ]));
+        let record_batch = Table::new_record_batch(
+            schema,
+            vec![
+                Arc::new(Int8Array::from(vec![1, 2, 3])),
+                Arc::new(Int64Array::from(vec![10_000_000_000, 20, 30])),
+                Arc::new(UInt32Array::from(vec![100, 200, 300])),
+            ],
+        )
+        .unwrap();
+        table.set_record_batch(record_batch);
+        database.add_table(table).unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        // literals that don't fit the column type still compare by value
+        database
+            .query("update counters set small = 4, unsigned = 400 where big > 5000000000")
+            .await
+            .unwrap();
+        database
+            .query("delete from counters where small < 300 and unsigned between 150 and 250")
+            .await
+            .unwrap();
+        database
+            .query("insert into counters values (5, 50, 500)")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "counters").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int8Type>(),
+            &Int8Array::from(vec![4, 3, 5])
+        );
+        assert_eq!(
+            table.column(1).unwrap().as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![10_000_000_000, 30, 50])
+        );
+        assert_eq!(
+            table.column(2).unwrap().as_primitive::<UInt32Type>(),
+            &UInt32Array::from(vec![400, 300, 500])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_temporal_types() {
+        let mut database = Database::new("Temporal").unwrap();
+        let mut table = Table::new("events");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("at", DataType::Timestamp(TimeUnit::Nanosecond, None), true),
+            Field::new("time", DataType::Time64(TimeUnit::Microsecond), true),
+        ]));
+        let at = |timestamp: &str| {
+            timestamp
+                .parse::<chrono::NaiveDateTime>()
+                .unwrap()
+                .and_utc()
+                .timestamp_nanos_opt()
+                .unwrap()
+        };
+        let hours = |hours: i64| hours * 3_600_000_000;
+        let record_batch = Table::new_record_batch(
+            schema,
+            vec![
+                Arc::new(TimestampNanosecondArray::from(vec![
+                    at("2024-01-01T00:00:00"),
+                    at("2024-02-01T00:00:00"),
+                    at("2024-03-01T00:00:00"),
+                ])),
+                Arc::new(Time64MicrosecondArray::from(vec![
+                    hours(9),
+                    hours(12),
+                    hours(18),
+                ])),
+            ],
+        )
+        .unwrap();
+        table.set_record_batch(record_batch);
+        database.add_table(table).unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("update events set time = '08:00:00' where at < '2024-01-15T00:00:00'")
+            .await
+            .unwrap();
+        database
+            .query("delete from events where time between '10:00:00' and '13:00:00'")
+            .await
+            .unwrap();
+        database
+            .query("delete from events where '2024-02-15T00:00:00' < at")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "events").unwrap();
+        assert_eq!(
+            table
+                .column(0)
+                .unwrap()
+                .as_primitive::<TimestampNanosecondType>(),
+            &TimestampNanosecondArray::from(vec![at("2024-01-01T00:00:00")])
+        );
+        assert_eq!(
+            table
+                .column(1)
+                .unwrap()
+                .as_primitive::<Time64MicrosecondType>(),
+            &Time64MicrosecondArray::from(vec![hours(8)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_between() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("update users set name = 'Middle' where id between 2 and 3")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id not between 2 and 3")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let ids = table.column(0).unwrap();
+        let ids = ids.as_primitive::<Int32Type>();
+        let names = table.column(1).unwrap();
+        let names = names.as_string::<i32>();
+        assert_eq!(ids, &Int32Array::from(vec![2, 3]));
+        assert_eq!(names, &StringArray::from(vec!["Middle", "Middle"]));
+    }
+
+    #[tokio::test]
+    async fn test_dml_fractional_literals() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // fractions aren't truncated to the type of an integer column
+        database
+            .query("update users set name = 'Truncated' where id = 2.5")
+            .await
+            .unwrap();
+        database
+            .query("update users set name = 'Middle' where id between 2.5 and 3.5")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id < 2.5")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let ids = table.column(0).unwrap();
+        let ids = ids.as_primitive::<Int32Type>();
+        let names = table.column(1).unwrap();
+        let names = names.as_string::<i32>();
+        assert_eq!(ids, &Int32Array::from(vec![3, 4]));
+        assert_eq!(names, &StringArray::from(vec!["Middle", "David"]));
+
+        assert_eq!(
+            compare_values(
+                &ScalarValue::Int32(Some(2)),
+                &ScalarValue::Float64(Some(2.5))
+            )
+            .unwrap(),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_values(
+                &ScalarValue::Float64(Some(2.0)),
+                &ScalarValue::Int32(Some(2))
+            )
+            .unwrap(),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_not() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, NULL)")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where not (name like 'A%' or id > 3)")
+            .await
+            .unwrap();
+
+        // NOT of an unknown condition is still unknown, so NULL names are kept
+        database
+            .query("delete from users where not (name = 'David')")
+            .await
+            .unwrap();
+
+        let ids = |database: &Database| {
+            get_table!(database, "users")
+                .unwrap()
+                .column(0)
+                .unwrap()
+                .as_primitive::<Int32Type>()
+                .to_owned()
+        };
+        assert_eq!(ids(&database), Int32Array::from(vec![4, 5]));
+
+        database
+            .query("delete from users where (name = 'Nobody') is unknown")
+            .await
+            .unwrap();
+        assert_eq!(ids(&database), Int32Array::from(vec![4]));
+    }
+
+    #[tokio::test]
+    async fn test_dml_like_escape() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, '100%'), (6, '1000')")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where name like '100!%' escape '!'")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let names = table.column(1).unwrap();
+        let names = names.as_string::<i32>();
+        assert_eq!(
+            names,
+            &StringArray::from(vec!["Alice", "Bob", "Charlie", "David", "1000"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_scalar_functions() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("update users set name = 'Alicia' where lower(name) = 'alice'")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where abs(id - 2) > 1 or starts_with(name, 'B')")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let names = table.column(1).unwrap();
+        let names = names.as_string::<i32>();
+        assert_eq!(names, &StringArray::from(vec!["Alicia", "Charlie"]));
+    }
+
+    #[tokio::test]
+    async fn test_dml_insert_select_by_name() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // matched by name when every column is a column of the table
+        database
+            .query("insert into users select name, id + 10 as id from users where id < 3")
+            .await
+            .unwrap();
+
+        // and by position otherwise
+        database
+            .query("insert into users select id + 20 as key, upper(name) as label from users where id = 1")
+            .await
+            .unwrap();
+
+        let error = database
+            .query("insert into users select id, name, id as extra from users")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("don't match"));
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 2, 3, 4, 11, 12, 21])
+        );
+        assert_eq!(
+            table.column(1).unwrap().as_string::<i32>(),
+            &StringArray::from(vec![
+                "Alice", "Bob", "Charlie", "David", "Alice", "Bob", "ALICE"
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_case() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query(
+                "update users set name = case when id > 2 then upper(name) else 'low' end, \
+                 id = id * 10 where id > 1",
+            )
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 20, 30, 40])
+        );
+        assert_eq!(
+            table.column(1).unwrap().as_string::<i32>(),
+            &StringArray::from(vec!["Alice", "low", "CHARLIE", "DAVID"])
+        );
+        drop(table);
+
+        // the Int64 literal is coerced to the type of the column
+        database
+            .query("update users set id = abs(id - 100) where id = 1")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![99, 20, 30, 40])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dml_null_literals() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, NULL), (NULL, 'Frank')")
+            .await
+            .unwrap();
+        database
+            .query("update users set name = NULL where id = 1")
+            .await
+            .unwrap();
+
+        {
+            let table = get_table!(database, "users").unwrap();
+            let ids = table.column(0).unwrap();
+            let ids = ids.as_primitive::<Int32Type>();
+            let names = table.column(1).unwrap();
+            let names = names.as_string::<i32>();
+            assert_eq!(
+                ids,
+                &Int32Array::from(vec![Some(1), Some(2), Some(3), Some(4), Some(5), None])
+            );
+            assert_eq!(
+                names,
+                &StringArray::from(vec![
+                    None,
+                    Some("Bob"),
+                    Some("Charlie"),
+                    Some("David"),
+                    None,
+                    Some("Frank")
+                ])
+            );
+        }
+
+        database
+            .query("delete from users where name is null")
+            .await
+            .unwrap();
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 4);
+    }
+
+    #[test]
+    fn test_matches_like_pattern() {
+        assert!(matches_like_pattern("Alice", "A%", None));
+        assert!(matches_like_pattern("Alice", "%ic%", None));
+        assert!(matches_like_pattern("Alice", "_lice", None));
+        assert!(matches_like_pattern("", "%", None));
+        assert!(!matches_like_pattern("Alice", "B%", None));
+        assert!(!matches_like_pattern("Alice", "_ice", None));
+
+        // escaped wildcards match literally
+        assert!(matches_like_pattern("100%", "100\\%", Some('\\')));
+        assert!(!matches_like_pattern("1000", "100\\%", Some('\\')));
+        assert!(matches_like_pattern("a_b", "a!_b", Some('!')));
+        assert!(!matches_like_pattern("axb", "a!_b", Some('!')));
+        assert!(matches_like_pattern("a!!", "a!!!", Some('!')));
+        assert!(matches_like_pattern("50%", "%\\%", Some('\\')));
+    }
+}
@@ -9,24 +9,78 @@ pub type Result<T> = std::result::Result<T, DbError>;
 
 #[derive(Error, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum DbError {
+    #[error("Error reading archive: {0}")]
+    Archive(String),
+
     #[error("{0}")]
     ArrayData(String),
 
+    #[error("Error in checkpoint {0}")]
+    Checkpoint(String),
+
     #[error("Error creating Database: {0}")]
     CreateDatabase(String),
 
     #[error("Error creating RecordBatch: {0}")]
     CreateRecordBatch(String),
 
+    #[error("Column {0} already exists in Table {1}")]
+    ColumnAlreadyExists(String, String),
+
     #[error("Column index {0} is out of bounds in Table {1}")]
     ColumnIndexOutOfBounds(usize, String),
 
+    #[error("Column {0} not found in Table {1}")]
+    ColumnNotFound(String, String),
+
+    #[error("Column {0} has {1} rows, but Table {2} has {3} rows")]
+    ColumnLengthMismatch(String, usize, String, usize),
+
+    #[error("Constraint violation: {0}")]
+    ConstraintViolation(String),
+
+    #[error("Database {0} already exists")]
+    DatabaseAlreadyExists(String),
+
+    #[error("Database {0} not found")]
+    DatabaseNotFound(String),
+
     #[error("{0}")]
     DataType(String),
 
+    #[error("Error deserializing rows: {0}")]
+    Deserialization(String),
+
+    #[error("Error executing DML: {0}")]
+    Dml(String),
+
+    #[error("Index on column {0} not found in Table {1}")]
+    IndexNotFound(String, String),
+
+    #[error("Table {0} has no primary key")]
+    PrimaryKeyNotFound(String),
+
     #[error("Error executing query ({0}) {1}")]
     Query(String, String),
 
+    #[error("Schema {0} already exists")]
+    SchemaAlreadyExists(String),
+
+    #[error("Schema {0} not found")]
+    SchemaNotFound(String),
+
+    #[error("Error serializing rows: {0}")]
+    Serialization(String),
+
+    #[error("Sketch of Table {0} not found")]
+    SketchNotFound(String),
+
+    #[error("Error spilling to {0}: {1}")]
+    Spill(String, String),
+
+    #[error("Error accessing object store {0}: {1}")]
+    Store(String, String),
+
     #[error("Table {0} already exists")]
     TableAlreadyExists(String),
 
@@ -38,4 +92,10 @@ pub enum DbError {
 
     #[error("Table {0} not found")]
     TableNotFound(String),
+
+    #[error("Error in trigger {0}")]
+    Trigger(String),
+
+    #[error("Write conflict on Table {0}: expected version {1}, but found version {2}")]
+    WriteConflict(String, u64, u64),
 }
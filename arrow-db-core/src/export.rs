@@ -1,34 +1,117 @@
 //! Export operations.
 //!
-//! Tables can be exported to parquet files on disk.
+//! Tables can be exported to parquet, CSV, newline-delimited JSON and Arrow
+//! IPC files, either on disk or to bytes.
+//!
+//! Parquet exports are written with `ExportOptions`, which default to the
+//! defaults of the parquet writer: no compression, page statistics and
+//! dictionary encoding.
+//!
+//! Databases are exported to disk in the format of the options, either
+//! parquet or Arrow IPC.  IPC files are the in-memory layout of the record
+//! batches, optionally compressed with LZ4 or ZSTD, so they're much faster to
+//! write and read than parquet at the cost of larger files.
 
-use parquet::arrow::AsyncArrowWriter;
-// use parquet::basic::{Compression, ZstdLevel};
+use arrow::csv::Writer as CsvWriter;
+use arrow::ipc::writer::{FileWriter, IpcWriteOptions};
+use arrow::ipc::CompressionType;
+use arrow::json::LineDelimitedWriter;
 use parquet::arrow::async_writer::AsyncFileWriter;
-use parquet::file::properties::WriterProperties;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::{
+    EnabledStatistics, WriterProperties, DEFAULT_DICTIONARY_ENABLED, DEFAULT_MAX_ROW_GROUP_SIZE,
+    DEFAULT_STATISTICS_ENABLED,
+};
 
 use crate::error::{DbError, Result};
 use crate::table::Table;
 
-impl<'a> Table<'a> {
+/// Settings of the parquet writer for exports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    /// The codec, and level where the codec has one, that pages are
+    /// compressed with, e.g. `Compression::ZSTD(ZstdLevel::try_new(10)?)`
+    pub compression: Compression,
+    /// The maximum number of rows in a row group
+    pub max_row_group_size: usize,
+    /// Whether min/max statistics are written for each column chunk or page
+    pub statistics: EnabledStatistics,
+    /// Whether columns are dictionary encoded
+    pub dictionary: bool,
+    /// The format of the files of database exports to disk
+    pub format: ExportFormat,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            compression: Compression::UNCOMPRESSED,
+            max_row_group_size: DEFAULT_MAX_ROW_GROUP_SIZE,
+            statistics: DEFAULT_STATISTICS_ENABLED,
+            dictionary: DEFAULT_DICTIONARY_ENABLED,
+            format: ExportFormat::Parquet,
+        }
+    }
+}
+
+/// The format of the files of database exports to disk
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExportFormat {
+    /// Parquet files, written with the parquet settings of the options
+    #[default]
+    Parquet,
+    /// Arrow IPC files, with buffers compressed with a codec, e.g.
+    /// `CompressionType::LZ4_FRAME` or `CompressionType::ZSTD`
+    Ipc(Option<CompressionType>),
+}
+
+impl ExportFormat {
+    /// The extension of files in the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Ipc(_) => "arrow",
+        }
+    }
+}
+
+impl ExportOptions {
+    /// The properties of a parquet writer with these settings
+    pub fn writer_properties(&self) -> WriterProperties {
+        WriterProperties::builder()
+            .set_compression(self.compression)
+            // the writer rejects empty row groups
+            .set_max_row_group_size(self.max_row_group_size.max(1))
+            .set_statistics_enabled(self.statistics)
+            .set_dictionary_enabled(self.dictionary)
+            .build()
+    }
+}
+
+impl Table {
     /// Helper function to create a `DbError` for table export errors
     fn export_error(&self, error: impl ToString) -> DbError {
-        DbError::TableExportError(self.name.into(), error.to_string())
+        DbError::TableExportError(self.name.to_owned(), error.to_string())
     }
 
     /// Generic export the table to a parquet file
-    pub async fn export_parquet_to_bytes(&mut self, buffer: impl AsyncFileWriter) -> Result<()> {
-        let record_batch = &self.record_batch;
-        let props = WriterProperties::builder()
-            // .set_compression(Compression::ZSTD(ZstdLevel::try_new(10).unwrap()))
-            .build();
-        let mut writer = AsyncArrowWriter::try_new(buffer, record_batch.schema(), Some(props))
+    pub async fn export_parquet_to_bytes(
+        &mut self,
+        buffer: impl AsyncFileWriter,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        let props = options.writer_properties();
+        let mut writer = AsyncArrowWriter::try_new(buffer, self.schema(), Some(props))
             .map_err(|e| self.export_error(e))?;
 
-        writer
-            .write(&record_batch)
-            .await
-            .map_err(|e| self.export_error(e))?;
+        for chunk in self.chunks.iter() {
+            writer
+                .write(chunk)
+                .await
+                .map_err(|e| self.export_error(e))?;
+        }
+
         writer.close().await.map_err(|e| self.export_error(e))?;
 
         Ok(())
@@ -36,23 +119,143 @@ impl<'a> Table<'a> {
 
     /// Export the table to a parquet file on disk
     #[cfg(not(target_arch = "wasm32"))]
-    pub async fn export_parquet_to_disk(&mut self, path: &str) -> Result<()> {
+    pub async fn export_parquet_to_disk(
+        &mut self,
+        path: &str,
+        options: &ExportOptions,
+    ) -> Result<()> {
         let file_name = format!("{path}/{}.parquet", self.name);
         let file = tokio::fs::File::create(&file_name)
             .await
             .map_err(|e| self.export_error(e))?;
 
-        self.export_parquet_to_bytes(file).await
+        self.export_parquet_to_bytes(file, options).await
+    }
+
+    /// Export the table to a file on disk in the format of the options, and
+    /// remove its file in the other format, so a database directory holds one
+    /// file per table
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn export_to_disk(&mut self, path: &str, options: &ExportOptions) -> Result<()> {
+        let stale = match options.format {
+            ExportFormat::Parquet => {
+                self.export_parquet_to_disk(path, options).await?;
+                ExportFormat::Ipc(None)
+            }
+            ExportFormat::Ipc(compression) => {
+                let bytes = self.export_ipc_compressed_to_bytes(compression)?;
+                tokio::fs::write(format!("{path}/{}.arrow", self.name), bytes)
+                    .await
+                    .map_err(|e| self.export_error(e))?;
+                ExportFormat::Parquet
+            }
+        };
+
+        let file_name = format!("{path}/{}.{}", self.name, stale.extension());
+
+        match tokio::fs::remove_file(file_name).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(self.export_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Export the table to CSV bytes with a header row
+    pub fn export_csv_to_bytes(&self) -> Result<Vec<u8>> {
+        let mut writer = CsvWriter::new(vec![]);
+
+        for chunk in self.chunks.iter() {
+            writer.write(chunk).map_err(|e| self.export_error(e))?;
+        }
+
+        Ok(writer.into_inner())
+    }
+
+    /// Export the table to newline-delimited JSON bytes
+    pub fn export_ndjson_to_bytes(&self) -> Result<Vec<u8>> {
+        let mut writer = LineDelimitedWriter::new(vec![]);
+
+        for chunk in self.chunks.iter() {
+            writer.write(chunk).map_err(|e| self.export_error(e))?;
+        }
+
+        writer.finish().map_err(|e| self.export_error(e))?;
+
+        Ok(writer.into_inner())
+    }
+
+    /// Export the table to Arrow IPC file bytes
+    pub fn export_ipc_to_bytes(&self) -> Result<Vec<u8>> {
+        self.export_ipc_compressed_to_bytes(None)
+    }
+
+    /// Export the table to Arrow IPC file bytes, with buffers compressed with
+    /// a codec
+    pub fn export_ipc_compressed_to_bytes(
+        &self,
+        compression: Option<CompressionType>,
+    ) -> Result<Vec<u8>> {
+        let options = IpcWriteOptions::default()
+            .try_with_compression(compression)
+            .map_err(|e| self.export_error(e))?;
+        let mut writer = FileWriter::try_new_with_options(vec![], &self.schema(), options)
+            .map_err(|e| self.export_error(e))?;
+
+        for chunk in self.chunks.iter() {
+            writer.write(chunk).map_err(|e| self.export_error(e))?;
+        }
+
+        writer.finish().map_err(|e| self.export_error(e))?;
+
+        writer.into_inner().map_err(|e| self.export_error(e))
+    }
+
+    /// Export the table to a file at a path on disk, choosing the format by
+    /// the extension of the file.
+    ///
+    /// Supported extensions are `parquet`, `csv`, `json` and `ndjson` (both
+    /// newline-delimited) and `arrow` (IPC file format).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn export_file(&mut self, file_name: &str) -> Result<()> {
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        let bytes = match extension {
+            "parquet" => {
+                let file = tokio::fs::File::create(file_name)
+                    .await
+                    .map_err(|e| self.export_error(e))?;
+
+                return self
+                    .export_parquet_to_bytes(file, &ExportOptions::default())
+                    .await;
+            }
+            "csv" => self.export_csv_to_bytes()?,
+            "json" | "ndjson" => self.export_ndjson_to_bytes()?,
+            "arrow" => self.export_ipc_to_bytes()?,
+            _ => return Err(self.export_error(format!("Unsupported file extension {extension}"))),
+        };
+
+        tokio::fs::write(file_name, bytes)
+            .await
+            .map_err(|e| self.export_error(e))
     }
 }
 
 #[cfg(test)]
 pub mod tests {
+    use std::fs::File;
+
+    use parquet::{arrow::arrow_reader::ParquetRecordBatchReaderBuilder, basic::ZstdLevel};
+
     use crate::{
         database::tests::{create_database, seed_database},
         get_mut_table,
     };
 
+    use super::*;
+
     #[tokio::test]
     async fn test_export_parquet_to_disk() {
         let (mut database, _) = create_database();
@@ -60,8 +263,80 @@ pub mod tests {
 
         get_mut_table!(database, "users")
             .unwrap()
-            .export_parquet_to_disk(database.name)
+            .export_parquet_to_disk(&database.name, &ExportOptions::default())
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn test_export_options() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        let path = std::env::temp_dir().join(format!("arrow-db-export-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+
+        let options = ExportOptions {
+            compression: Compression::ZSTD(ZstdLevel::try_new(10).unwrap()),
+            max_row_group_size: 3,
+            statistics: EnabledStatistics::None,
+            dictionary: false,
+            format: ExportFormat::Parquet,
+        };
+        get_mut_table!(database, "users")
+            .unwrap()
+            .export_parquet_to_disk(&path.to_string_lossy(), &options)
+            .await
+            .unwrap();
+
+        let file = File::open(path.join("users.parquet")).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let metadata = builder.metadata();
+        assert_eq!(metadata.num_row_groups(), 2);
+
+        let column = metadata.row_group(0).column(1);
+        assert!(matches!(column.compression(), Compression::ZSTD(_)));
+        assert!(column.statistics().is_none());
+        assert!(column.dictionary_page_offset().is_none());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_ipc_to_disk() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        let path = std::env::temp_dir().join(format!("arrow-db-export-ipc-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let mut users = get_mut_table!(database, "users").unwrap();
+
+        users
+            .export_to_disk(&path.to_string_lossy(), &ExportOptions::default())
+            .await
+            .unwrap();
+        assert!(path.join("users.parquet").exists());
+
+        // exporting in the other format replaces the parquet file
+        for compression in [CompressionType::LZ4_FRAME, CompressionType::ZSTD] {
+            let options = ExportOptions {
+                format: ExportFormat::Ipc(Some(compression)),
+                ..Default::default()
+            };
+            users
+                .export_to_disk(&path.to_string_lossy(), &options)
+                .await
+                .unwrap();
+            assert!(!path.join("users.parquet").exists());
+
+            let mut imported = Table::new("users");
+            imported
+                .import_file(&path.join("users.arrow").to_string_lossy(), 1024)
+                .await
+                .unwrap();
+            assert_eq!(imported.chunks, users.chunks);
+        }
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
 }
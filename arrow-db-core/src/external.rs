@@ -0,0 +1,152 @@
+//! External tables.
+//!
+//! An external table is registered with the context as a listing of files,
+//! instead of being imported into memory.  Queries scan the files, so files
+//! larger than memory can be queried lazily and joined with in-memory tables,
+//! with filters and projections pushed down to the scan where the format
+//! supports them.
+//!
+//! External tables can only be queried.  They aren't part of `tables`, so
+//! they aren't exported with the database or reachable through the table API.
+
+use std::sync::Arc;
+
+use datafusion::datasource::{
+    file_format::{
+        arrow::ArrowFormat, csv::CsvFormat, json::JsonFormat, parquet::ParquetFormat, FileFormat,
+    },
+    listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+};
+
+impl Database {
+    /// Register the files at a path as a table, without loading them.
+    ///
+    /// The path is a file, a directory ending with `/` or a glob, and every
+    /// file with the extension of the format is scanned.  Supported formats are `parquet`,
+    /// `csv` (with a header row), `json` and `ndjson` (newline-delimited) and
+    /// `arrow` (IPC file format).  The schema is inferred from the files.
+    pub async fn register_external_table(
+        &self,
+        table_name: &str,
+        path: &str,
+        format: &str,
+    ) -> Result<()> {
+        if self.tables.contains_key(table_name) {
+            return Err(DbError::TableAlreadyExists(table_name.into()));
+        }
+
+        let external_error =
+            |e: &dyn ToString| DbError::TableImportError(table_name.into(), e.to_string());
+
+        let file_format: Arc<dyn FileFormat> = match format {
+            "parquet" => Arc::new(ParquetFormat::default()),
+            "csv" => Arc::new(CsvFormat::default().with_has_header(true)),
+            "json" | "ndjson" => Arc::new(JsonFormat::default()),
+            "arrow" => Arc::new(ArrowFormat),
+            _ => return Err(external_error(&format!("Unsupported format {format}"))),
+        };
+        let options = ListingOptions::new(file_format).with_file_extension(format!(".{format}"));
+
+        let url = ListingTableUrl::parse(path).map_err(|e| external_error(&e))?;
+        let config = ListingTableConfig::new(url)
+            .with_listing_options(options)
+            .infer_schema(&self.ctx.state())
+            .await
+            .map_err(|e| external_error(&e))?;
+        let listing_table = ListingTable::try_new(config).map_err(|e| external_error(&e))?;
+
+        self.ctx
+            .register_table(table_name, Arc::new(listing_table))
+            .map_err(|e| external_error(&e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table,
+    };
+
+    use super::*;
+
+    async fn count_rows(database: &Database, sql: &str) -> usize {
+        database
+            .query(sql)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_register_external_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("arrow-db-external-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for extension in ["parquet", "csv"] {
+            let file_name = dir.join(format!("users.{extension}"));
+            get_mut_table!(database, "users")
+                .unwrap()
+                .export_file(&file_name.to_string_lossy())
+                .await
+                .unwrap();
+        }
+
+        let path = format!("{}/", dir.to_string_lossy());
+        database
+            .register_external_table("external_users", &path, "parquet")
+            .await
+            .unwrap();
+        database
+            .register_external_table("external_csv", &format!("{path}users.csv"), "csv")
+            .await
+            .unwrap();
+
+        // external tables are scanned from their files, and join with
+        // in-memory tables
+        assert!(!database.tables.contains_key("external_users"));
+        assert_eq!(
+            count_rows(
+                &database,
+                "select * from users u join external_users e on u.id = e.id where e.id > 2"
+            )
+            .await,
+            2
+        );
+        assert_eq!(
+            count_rows(&database, "select name from external_csv").await,
+            4
+        );
+
+        assert_eq!(
+            database
+                .register_external_table("users", &path, "parquet")
+                .await,
+            Err(DbError::TableAlreadyExists("users".into()))
+        );
+        assert!(matches!(
+            database
+                .register_external_table("external_xml", &path, "xml")
+                .await,
+            Err(DbError::TableImportError(_, _))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
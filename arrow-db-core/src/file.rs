@@ -8,10 +8,10 @@ use parquet::arrow::ParquetRecordBatchStreamBuilder;
 use crate::error::{DbError, Result};
 use crate::table::Table;
 
-impl<'a> Table<'a> {
+impl Table {
     /// Helper function to create a `DbError` for table import errors
     fn import_error(&self, error: impl ToString) -> DbError {
-        DbError::TableImportError(self.name.into(), error.to_string())
+        DbError::TableImportError(self.name.to_owned(), error.to_string())
     }
 
     /// Import the table from a parquet file on disk
@@ -57,7 +57,7 @@ pub mod tests {
 
         get_mut_table!(database, "users")
             .unwrap()
-            .import_parquet_from_disk(database.name)
+            .import_parquet_from_disk(&database.name)
             .await
             .unwrap();
 
@@ -0,0 +1,247 @@
+//! User defined functions.
+//!
+//! Functions registered with a database can be used in any query run on it,
+//! including queries served by the Flight server, so domain-specific logic
+//! doesn't require forking the crate.  Registered functions are kept by
+//! clones of the database.
+//!
+//! Table functions are called in the FROM clause, e.g. `select * from
+//! generate_series(1, 10)`, and return a table provider, so they can generate
+//! or load data lazily.
+
+use std::sync::Arc;
+
+use datafusion::{
+    datasource::function::TableFunctionImpl, logical_expr::AggregateUDF, prelude::SessionContext,
+};
+
+use crate::database::Database;
+
+impl Database {
+    /// Register a user defined aggregate function, e.g. a weighted average,
+    /// replacing any function with the same name
+    pub fn register_udaf(&self, udaf: AggregateUDF) {
+        self.ctx.register_udaf(udaf);
+    }
+
+    /// Register a user defined table function, replacing any function with
+    /// the same name
+    pub fn register_udtf(&self, name: &str, udtf: Arc<dyn TableFunctionImpl>) {
+        self.ctx.register_udtf(name, udtf);
+    }
+
+    /// Register the functions of this database with another context
+    pub(crate) fn copy_functions(&self, ctx: &SessionContext) {
+        let state = self.ctx.state();
+
+        for udaf in state.aggregate_functions().values() {
+            ctx.register_udaf(udaf.as_ref().to_owned());
+        }
+
+        for (name, udtf) in state.table_functions().iter() {
+            ctx.register_udtf(name, udtf.function().to_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{ArrayRef, AsArray, Float64Array, Int64Array, RecordBatch};
+    use arrow::datatypes::{Float64Type, Int64Type};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::{
+        catalog::TableProvider,
+        datasource::MemTable,
+        error::{DataFusionError, Result as DataFusionResult},
+        logical_expr::{create_udaf, Accumulator, Expr, Volatility},
+        scalar::ScalarValue,
+    };
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table,
+    };
+
+    use super::*;
+
+    /// An average of values weighted by a second column
+    #[derive(Debug, Default)]
+    struct WeightedAvg {
+        sum: f64,
+        weight: f64,
+    }
+
+    impl Accumulator for WeightedAvg {
+        fn update_batch(&mut self, values: &[ArrayRef]) -> DataFusionResult<()> {
+            let weights = values[1].as_primitive::<Float64Type>();
+            let values = values[0].as_primitive::<Float64Type>();
+
+            for (value, weight) in values.iter().zip(weights.iter()) {
+                if let (Some(value), Some(weight)) = (value, weight) {
+                    self.sum += value * weight;
+                    self.weight += weight;
+                }
+            }
+
+            Ok(())
+        }
+
+        fn merge_batch(&mut self, states: &[ArrayRef]) -> DataFusionResult<()> {
+            let sums = states[0].as_primitive::<Float64Type>();
+            let weights = states[1].as_primitive::<Float64Type>();
+
+            self.sum += sums.iter().flatten().sum::<f64>();
+            self.weight += weights.iter().flatten().sum::<f64>();
+
+            Ok(())
+        }
+
+        fn state(&mut self) -> DataFusionResult<Vec<ScalarValue>> {
+            Ok(vec![
+                ScalarValue::Float64(Some(self.sum)),
+                ScalarValue::Float64(Some(self.weight)),
+            ])
+        }
+
+        fn evaluate(&mut self) -> DataFusionResult<ScalarValue> {
+            Ok(ScalarValue::Float64(
+                (self.weight != 0.0).then(|| self.sum / self.weight),
+            ))
+        }
+
+        fn size(&self) -> usize {
+            std::mem::size_of_val(self)
+        }
+    }
+
+    fn weighted_avg() -> AggregateUDF {
+        create_udaf(
+            "weighted_avg",
+            vec![DataType::Float64, DataType::Float64],
+            Arc::new(DataType::Float64),
+            Volatility::Immutable,
+            Arc::new(|_| Ok(Box::<WeightedAvg>::default())),
+            Arc::new(vec![DataType::Float64, DataType::Float64]),
+        )
+    }
+
+    /// A table of the integers from `start` to `stop`, inclusive
+    #[derive(Debug)]
+    struct GenerateSeries;
+
+    impl TableFunctionImpl for GenerateSeries {
+        fn call(&self, args: &[Expr]) -> DataFusionResult<Arc<dyn TableProvider>> {
+            let bounds = args
+                .iter()
+                .map(|arg| match arg {
+                    Expr::Literal(ScalarValue::Int64(Some(value))) => Ok(*value),
+                    _ => Err(DataFusionError::Plan(format!(
+                        "generate_series expects integers, got {arg}"
+                    ))),
+                })
+                .collect::<DataFusionResult<Vec<_>>>()?;
+
+            let [start, stop] = bounds[..] else {
+                return Err(DataFusionError::Plan(
+                    "generate_series expects a start and a stop".into(),
+                ));
+            };
+
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "value",
+                DataType::Int64,
+                false,
+            )]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int64Array::from_iter_values(start..=stop))],
+            )?;
+
+            Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_udaf() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .add_column::<Float64Array>(
+                2,
+                "score",
+                DataType::Float64,
+                Float64Array::from(vec![1.0, 2.0, 3.0, 4.0]).into(),
+            )
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        assert!(database
+            .query("select weighted_avg(score, id) from users")
+            .await
+            .is_err());
+
+        database.register_udaf(weighted_avg());
+
+        // registered functions are kept by clones
+        for database in [&database, &database.clone()] {
+            let results = database
+                .query("select weighted_avg(score, id) from users")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+
+            // (1 + 4 + 9 + 16) / (1 + 2 + 3 + 4)
+            assert_eq!(
+                results[0].column(0).as_primitive::<Float64Type>().value(0),
+                3.0
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_udtf() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database.register_udtf("generate_series", Arc::new(GenerateSeries));
+
+        // registered functions are kept by clones
+        for database in [&database, &database.clone()] {
+            let results = database
+                .query(
+                    "select users.name from generate_series(2, 3) as series
+                    inner join users on users.id = series.value",
+                )
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                results.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+                2
+            );
+
+            let results = database
+                .query("select sum(value) from generate_series(1, 100)")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                results[0].column(0).as_primitive::<Int64Type>().value(0),
+                5050
+            );
+        }
+
+        assert!(database
+            .query("select * from generate_series('a', 'b')")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,448 @@
+//! Property-based fuzz tests for DML.
+//!
+//! Random tables and DML statements are executed against the database and
+//! against an oracle.  The oracle applies each statement by computing the
+//! expected table contents with a SELECT in DataFusion, then rebuilding its
+//! `MemTable` from the result.  Any difference in the final contents, or a
+//! statement that fails on only one side, fails the test.
+
+use std::sync::Arc;
+
+use arrow::array::RecordBatch;
+use arrow_schema::{DataType, Field, Schema};
+use datafusion::{
+    datasource::MemTable,
+    prelude::{SessionConfig, SessionContext},
+    scalar::ScalarValue,
+};
+use proptest::prelude::*;
+
+use crate::{database::Database, table::Table};
+
+const TABLE: &str = "fuzz";
+
+#[derive(Debug, Clone, Copy)]
+enum ColumnType {
+    Int32,
+    Int64,
+    UInt8,
+    Float64,
+    Utf8,
+}
+
+impl ColumnType {
+    fn data_type(&self) -> DataType {
+        match self {
+            ColumnType::Int32 => DataType::Int32,
+            ColumnType::Int64 => DataType::Int64,
+            ColumnType::UInt8 => DataType::UInt8,
+            ColumnType::Float64 => DataType::Float64,
+            ColumnType::Utf8 => DataType::Utf8,
+        }
+    }
+
+    fn sql_type(&self) -> &'static str {
+        match self {
+            ColumnType::Int32 => "INT",
+            ColumnType::Int64 => "BIGINT",
+            ColumnType::UInt8 => "TINYINT UNSIGNED",
+            ColumnType::Float64 => "DOUBLE",
+            ColumnType::Utf8 => "VARCHAR",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Compare(usize, &'static str, ScalarValue),
+    IsNull(usize, bool),
+    Like(usize, &'static str),
+    Between(usize, ScalarValue, ScalarValue, bool),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// The predicate executed by the database
+    fn to_sql(&self) -> String {
+        self.sql(false)
+    }
+
+    /// The predicate executed by the oracle.
+    ///
+    /// DataFusion simplifies `c LIKE '%'` to `true`, even when `c` is NULL,
+    /// so the oracle only matches patterns against values.
+    fn to_oracle_sql(&self) -> String {
+        self.sql(true)
+    }
+
+    fn sql(&self, oracle: bool) -> String {
+        match self {
+            Predicate::Compare(column, op, value) => {
+                format!("c{column} {op} {}", sql_literal(value))
+            }
+            Predicate::IsNull(column, false) => format!("c{column} IS NULL"),
+            Predicate::IsNull(column, true) => format!("c{column} IS NOT NULL"),
+            Predicate::Like(column, pattern) => {
+                let like = format!("c{column} LIKE '{}'", pattern.replace('\'', "''"));
+
+                if oracle {
+                    format!("CASE WHEN c{column} IS NULL THEN NULL ELSE {like} END")
+                } else {
+                    like
+                }
+            }
+            Predicate::Between(column, low, high, negated) => format!(
+                "c{column} {}BETWEEN {} AND {}",
+                if *negated { "NOT " } else { "" },
+                sql_literal(low),
+                sql_literal(high)
+            ),
+            Predicate::And(left, right) => {
+                format!("({} AND {})", left.sql(oracle), right.sql(oracle))
+            }
+            Predicate::Or(left, right) => {
+                format!("({} OR {})", left.sql(oracle), right.sql(oracle))
+            }
+            Predicate::Not(predicate) => format!("NOT ({})", predicate.sql(oracle)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Insert(Vec<ScalarValue>),
+    Update(usize, ScalarValue, Option<Predicate>),
+    Delete(Option<Predicate>),
+}
+
+impl Statement {
+    /// The statement executed by the database
+    fn to_sql(&self) -> String {
+        match self {
+            Statement::Insert(values) => {
+                let values = values.iter().map(sql_literal).collect::<Vec<_>>();
+                format!("INSERT INTO {TABLE} VALUES ({})", values.join(", "))
+            }
+            Statement::Update(column, value, predicate) => format!(
+                "UPDATE {TABLE} SET c{column} = {}{}",
+                sql_literal(value),
+                where_clause(predicate)
+            ),
+            Statement::Delete(predicate) => {
+                format!("DELETE FROM {TABLE}{}", where_clause(predicate))
+            }
+        }
+    }
+
+    /// A SELECT computing the contents of the table after the statement
+    fn to_oracle_sql(&self, types: &[ColumnType]) -> String {
+        let cast = |value: &ScalarValue, column: usize| {
+            format!(
+                "CAST({} AS {}) AS c{column}",
+                sql_literal(value),
+                types[column].sql_type()
+            )
+        };
+
+        match self {
+            Statement::Insert(values) => {
+                let values = values
+                    .iter()
+                    .enumerate()
+                    .map(|(column, value)| cast(value, column))
+                    .collect::<Vec<_>>();
+                format!(
+                    "SELECT * FROM {TABLE} UNION ALL SELECT {}",
+                    values.join(", ")
+                )
+            }
+            Statement::Update(assigned, value, predicate) => {
+                let columns = (0..types.len())
+                    .map(|column| match (column == *assigned, predicate) {
+                        (false, _) => format!("c{column}"),
+                        (true, None) => cast(value, column),
+                        (true, Some(predicate)) => format!(
+                            "CASE WHEN {} THEN CAST({} AS {}) ELSE c{column} END AS c{column}",
+                            predicate.to_oracle_sql(),
+                            sql_literal(value),
+                            types[column].sql_type()
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                format!("SELECT {} FROM {TABLE}", columns.join(", "))
+            }
+            Statement::Delete(None) => format!("SELECT * FROM {TABLE} WHERE false"),
+            Statement::Delete(Some(predicate)) => format!(
+                "SELECT * FROM {TABLE} WHERE ({}) IS NOT TRUE",
+                predicate.to_oracle_sql()
+            ),
+        }
+    }
+}
+
+fn where_clause(predicate: &Option<Predicate>) -> String {
+    predicate
+        .as_ref()
+        .map(|predicate| format!(" WHERE {}", predicate.to_sql()))
+        .unwrap_or_default()
+}
+
+fn sql_literal(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Int32(Some(value)) => value.to_string(),
+        ScalarValue::Int64(Some(value)) => value.to_string(),
+        ScalarValue::UInt8(Some(value)) => value.to_string(),
+        ScalarValue::Float64(Some(value)) => format!("{value:?}"),
+        ScalarValue::Utf8(Some(value)) => format!("'{}'", value.replace('\'', "''")),
+        _ => "NULL".into(),
+    }
+}
+
+fn non_null_value(column_type: ColumnType) -> BoxedStrategy<ScalarValue> {
+    match column_type {
+        ColumnType::Int32 => (-5..5i32)
+            .prop_map(|value| ScalarValue::Int32(Some(value)))
+            .boxed(),
+        ColumnType::Int64 => (-5..5i64)
+            .prop_map(|value| ScalarValue::Int64(Some(value)))
+            .boxed(),
+        ColumnType::UInt8 => (0..10u8)
+            .prop_map(|value| ScalarValue::UInt8(Some(value)))
+            .boxed(),
+        ColumnType::Float64 => (-10..10i32)
+            .prop_map(|value| ScalarValue::Float64(Some(value as f64 / 2.0)))
+            .boxed(),
+        ColumnType::Utf8 => prop::sample::select(vec!["", "a", "b", "ab", "ba", "it's"])
+            .prop_map(|value| ScalarValue::Utf8(Some(value.into())))
+            .boxed(),
+    }
+}
+
+fn value(column_type: ColumnType) -> BoxedStrategy<ScalarValue> {
+    let null = ScalarValue::try_from(&column_type.data_type()).unwrap();
+
+    prop_oneof![
+        1 => Just(null),
+        4 => non_null_value(column_type),
+    ]
+    .boxed()
+}
+
+fn row(types: &[ColumnType]) -> BoxedStrategy<Vec<ScalarValue>> {
+    types
+        .iter()
+        .map(|column_type| value(*column_type))
+        .collect::<Vec<_>>()
+        .boxed()
+}
+
+fn predicate(types: Vec<ColumnType>) -> BoxedStrategy<Predicate> {
+    let leaf = (0..types.len())
+        .prop_flat_map(move |column| {
+            let column_type = types[column];
+            let compare = (
+                prop::sample::select(vec!["=", "<>", "<", "<=", ">", ">="]),
+                non_null_value(column_type),
+            )
+                .prop_map(move |(op, value)| Predicate::Compare(column, op, value));
+            let is_null = any::<bool>().prop_map(move |negated| Predicate::IsNull(column, negated));
+            let between = (
+                non_null_value(column_type),
+                non_null_value(column_type),
+                any::<bool>(),
+            )
+                .prop_map(move |(low, high, negated)| {
+                    Predicate::Between(column, low, high, negated)
+                });
+
+            match column_type {
+                ColumnType::Utf8 => prop_oneof![
+                    compare,
+                    is_null,
+                    between,
+                    prop::sample::select(vec!["a%", "%b", "_", "%", "a_", "%'%"])
+                        .prop_map(move |pattern| Predicate::Like(column, pattern)),
+                ]
+                .boxed(),
+                _ => prop_oneof![compare, is_null, between].boxed(),
+            }
+        })
+        .boxed();
+
+    leaf.prop_recursive(2, 8, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone())
+                .prop_map(|(left, right)| Predicate::And(Box::new(left), Box::new(right))),
+            (inner.clone(), inner.clone())
+                .prop_map(|(left, right)| Predicate::Or(Box::new(left), Box::new(right))),
+            inner.prop_map(|predicate| Predicate::Not(Box::new(predicate))),
+        ]
+    })
+    .boxed()
+}
+
+fn statement(types: Vec<ColumnType>) -> BoxedStrategy<Statement> {
+    let update_types = types.clone();
+    let update = (0..types.len())
+        .prop_flat_map(move |column| {
+            (
+                Just(column),
+                value(update_types[column]),
+                prop::option::of(predicate(update_types.clone())),
+            )
+        })
+        .prop_map(|(column, value, predicate)| Statement::Update(column, value, predicate));
+
+    prop_oneof![
+        row(&types).prop_map(Statement::Insert),
+        update,
+        prop::option::of(predicate(types.clone())).prop_map(Statement::Delete),
+    ]
+    .boxed()
+}
+
+fn case() -> impl Strategy<Value = (Vec<ColumnType>, Vec<Vec<ScalarValue>>, Vec<Statement>)> {
+    prop::collection::vec(
+        prop_oneof![
+            Just(ColumnType::Int32),
+            Just(ColumnType::Int64),
+            Just(ColumnType::UInt8),
+            Just(ColumnType::Float64),
+            Just(ColumnType::Utf8)
+        ],
+        1..4,
+    )
+    .prop_flat_map(|types| {
+        (
+            Just(types.clone()),
+            prop::collection::vec(row(&types), 0..8),
+            prop::collection::vec(statement(types), 1..6),
+        )
+    })
+}
+
+/// Build the initial contents of the table
+fn record_batch(types: &[ColumnType], rows: &[Vec<ScalarValue>]) -> RecordBatch {
+    let fields = types
+        .iter()
+        .enumerate()
+        .map(|(column, column_type)| {
+            Field::new(format!("c{column}"), column_type.data_type(), true)
+        })
+        .collect::<Vec<_>>();
+
+    let columns = types
+        .iter()
+        .enumerate()
+        .map(|(column, column_type)| {
+            let values = rows.iter().map(|row| row[column].to_owned());
+            ScalarValue::iter_to_array(values)
+                .unwrap_or_else(|_| arrow::array::new_empty_array(&column_type.data_type()))
+        })
+        .collect();
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+}
+
+/// Render the rows of the batches as sorted strings, so tables can be compared
+/// regardless of row order
+fn sorted_rows(batches: &[RecordBatch]) -> Vec<String> {
+    let mut rows = batches
+        .iter()
+        .flat_map(|batch| {
+            (0..batch.num_rows()).map(move |row| {
+                batch
+                    .columns()
+                    .iter()
+                    .map(|column| {
+                        ScalarValue::try_from_array(column, row)
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("|")
+            })
+        })
+        .collect::<Vec<_>>();
+    rows.sort();
+
+    rows
+}
+
+fn register_oracle(ctx: &SessionContext, schema: Arc<Schema>, batches: Vec<RecordBatch>) {
+    let provider = MemTable::try_new(schema, vec![batches]).unwrap();
+    ctx.deregister_table(TABLE).unwrap();
+    ctx.register_table(TABLE, Arc::new(provider)).unwrap();
+}
+
+async fn run_case(
+    types: Vec<ColumnType>,
+    rows: Vec<Vec<ScalarValue>>,
+    statements: Vec<Statement>,
+) -> std::result::Result<(), TestCaseError> {
+    let batch = record_batch(&types, &rows);
+    let schema = batch.schema();
+
+    let mut database = Database::new("FuzzDB").unwrap();
+    let mut table = Table::new(TABLE);
+    table.set_record_batch(batch.clone());
+    database.add_table(table).unwrap();
+    database.add_all_table_contexts().unwrap();
+
+    let oracle = SessionContext::new_with_config(SessionConfig::new().with_target_partitions(1));
+    register_oracle(&oracle, schema.clone(), vec![batch]);
+
+    for statement in statements {
+        let sql = statement.to_sql();
+        let oracle_sql = statement.to_oracle_sql(&types);
+
+        let actual = database.query(&sql).await;
+        let expected = match oracle.sql(&oracle_sql).await {
+            Ok(df) => df.collect().await,
+            Err(e) => Err(e),
+        };
+
+        match (actual, expected) {
+            (Ok(_), Ok(batches)) => register_oracle(&oracle, schema.clone(), batches),
+            (Err(_), Err(_)) => {}
+            (actual, expected) => prop_assert!(
+                false,
+                "{sql} returned {actual:?}, but the oracle ({oracle_sql}) returned {expected:?}"
+            ),
+        }
+    }
+
+    let actual = database
+        .query(&format!("SELECT * FROM {TABLE}"))
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let expected = oracle
+        .sql(&format!("SELECT * FROM {TABLE}"))
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+
+    prop_assert_eq!(sorted_rows(&actual), sorted_rows(&expected));
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_dml_matches_oracle((types, rows, statements) in case()) {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run_case(types, rows, statements))?;
+    }
+}
@@ -1,77 +1,371 @@
 //! Import operations.
 //!
-//! Tables can be imported from parquet files on disk.
+//! Tables can be imported from parquet, CSV, JSON and Arrow IPC files, either
+//! on disk or from bytes.  JSON files hold either newline-delimited objects or
+//! a single array of objects.
 
-use arrow::compute::concat_batches;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arrow::csv::{reader::Format, ReaderBuilder as CsvReaderBuilder};
+use arrow::datatypes::SchemaRef;
+use arrow::ipc::reader::FileReader;
+use arrow::json::{reader::infer_json_schema, ReaderBuilder as JsonReaderBuilder};
 use bytes::Bytes;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 
 #[cfg(not(target_arch = "wasm32"))]
-use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use arrow::array::RecordBatch;
+#[cfg(not(target_arch = "wasm32"))]
+use parquet::arrow::{arrow_reader::ArrowReaderMetadata, ParquetRecordBatchStreamBuilder};
 
 use crate::error::{DbError, Result};
+use crate::merge::{align_batch, merge_schemas};
 use crate::table::Table;
 
-impl<'a> Table<'a> {
+impl Table {
     /// Helper function to create a `DbError` for table import errors
     fn import_error(&self, error: impl ToString) -> DbError {
-        DbError::TableImportError(self.name.into(), error.to_string())
+        DbError::TableImportError(self.name.to_owned(), error.to_string())
     }
 
-    /// Import the table from a parquet file on disk
+    /// Import the table from a parquet file on disk, reading `batch_size` rows
+    /// at a time
     #[cfg(not(target_arch = "wasm32"))]
-    pub async fn import_parquet_from_disk(&mut self, path: &str) -> Result<()> {
-        use futures::TryStreamExt;
-
+    pub async fn import_parquet_from_disk(&mut self, path: &str, batch_size: usize) -> Result<()> {
         let file_name = format!("{path}/{}.parquet", self.name);
-        let file = tokio::fs::File::open(&file_name)
-            .await
-            .map_err(|e| self.import_error(e))?;
 
-        // self.import_parquet(file).await
+        self.import_parquet_file(&file_name, batch_size).await
+    }
+
+    /// Import the table from a parquet file at a path on disk, reading
+    /// `batch_size` rows at a time.
+    ///
+    /// Row groups are read in parallel, each from its own handle of the file,
+    /// and kept in the order of the file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn import_parquet_file(&mut self, file_name: &str, batch_size: usize) -> Result<()> {
+        use futures::{StreamExt, TryStreamExt};
 
-        let builder = ParquetRecordBatchStreamBuilder::new(file)
+        let mut file = tokio::fs::File::open(file_name)
             .await
-            .map_err(|e| self.import_error(e))?
-            .with_batch_size(8192);
+            .map_err(|e| self.import_error(e))?;
+        let metadata = ArrowReaderMetadata::load_async(&mut file, Default::default())
+            .await
+            .map_err(|e| self.import_error(e))?;
+        let schema = metadata.schema().to_owned();
 
-        let stream = builder.build().map_err(|e| self.import_error(e))?;
-        let record_batches = stream
+        let readers = (0..metadata.metadata().num_row_groups()).map(|row_group| {
+            tokio::spawn(read_row_group(
+                self.name.to_owned(),
+                file_name.to_owned(),
+                metadata.to_owned(),
+                row_group,
+                batch_size,
+            ))
+        });
+        let row_groups = futures::stream::iter(readers)
+            .buffered(import_concurrency())
             .try_collect::<Vec<_>>()
             .await
             .map_err(|e| self.import_error(e))?;
+        let record_batches = row_groups.into_iter().collect::<Result<Vec<_>>>()?.concat();
 
-        if let Some(batch) = record_batches.first() {
-            let schema = batch.schema();
-            self.record_batch =
-                concat_batches(&schema, &record_batches).map_err(|e| self.import_error(e))?;
-        }
-
-        Ok(())
+        self.set_record_batches(schema, &record_batches)
     }
 
-    /// Import the table from a parquet file on disk
-    pub fn import_parquet_from_bytes(&mut self, bytes: Bytes) -> Result<()> {
+    /// Import the table from parquet bytes, reading `batch_size` rows at a time
+    pub fn import_parquet_from_bytes(&mut self, bytes: Bytes, batch_size: usize) -> Result<()> {
         let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)
             .map_err(|e| self.import_error(e))?
-            .with_batch_size(8192);
+            .with_batch_size(batch_size);
 
         let reader = builder.build().map_err(|e| self.import_error(e))?;
         let record_batches = reader.flatten().collect::<Vec<_>>();
 
         if let Some(batch) = record_batches.first() {
-            let schema = batch.schema();
-            self.record_batch =
-                concat_batches(&schema, &record_batches).map_err(|e| self.import_error(e))?;
+            self.set_record_batches(batch.schema(), &record_batches)?;
         }
 
         Ok(())
     }
+
+    /// Import the table from a CSV file with a header row, inferring the schema
+    pub fn import_csv_from_bytes(&mut self, bytes: Bytes, batch_size: usize) -> Result<()> {
+        let (schema, _) = Format::default()
+            .with_header(true)
+            .infer_schema(Cursor::new(&bytes), Some(IMPORT_INFER_RECORDS))
+            .map_err(|e| self.import_error(e))?;
+        let schema = Arc::new(schema);
+
+        let reader = CsvReaderBuilder::new(schema.clone())
+            .with_header(true)
+            .with_batch_size(batch_size)
+            .build(Cursor::new(bytes))
+            .map_err(|e| self.import_error(e))?;
+
+        let record_batches = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| self.import_error(e))?;
+
+        self.set_record_batches(schema, &record_batches)
+    }
+
+    /// Import the table from a newline-delimited JSON file, inferring the schema
+    pub fn import_ndjson_from_bytes(&mut self, bytes: Bytes, batch_size: usize) -> Result<()> {
+        let (schema, _) = infer_json_schema(Cursor::new(&bytes), Some(IMPORT_INFER_RECORDS))
+            .map_err(|e| self.import_error(e))?;
+        let schema = Arc::new(schema);
+
+        let reader = JsonReaderBuilder::new(schema.clone())
+            .with_batch_size(batch_size)
+            .build(Cursor::new(bytes))
+            .map_err(|e| self.import_error(e))?;
+
+        let record_batches = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| self.import_error(e))?;
+
+        self.set_record_batches(schema, &record_batches)
+    }
+
+    /// Import the table from a JSON file of newline-delimited objects or a
+    /// single array of objects, inferring the schema
+    pub fn import_json_from_bytes(&mut self, bytes: Bytes, batch_size: usize) -> Result<()> {
+        let is_array = bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'[');
+
+        if !is_array {
+            return self.import_ndjson_from_bytes(bytes, batch_size);
+        }
+
+        // the reader only reads newline-delimited objects, so the array is
+        // written out as such
+        let values = serde_json::from_slice::<Vec<serde_json::Value>>(&bytes)
+            .map_err(|e| self.import_error(e))?;
+        let mut ndjson = Vec::with_capacity(bytes.len());
+
+        for value in values.iter() {
+            serde_json::to_writer(&mut ndjson, value).map_err(|e| self.import_error(e))?;
+            ndjson.push(b'\n');
+        }
+
+        self.import_ndjson_from_bytes(Bytes::from(ndjson), batch_size)
+    }
+
+    /// Import the table from an Arrow IPC file
+    pub fn import_ipc_from_bytes(&mut self, bytes: Bytes) -> Result<()> {
+        let reader =
+            FileReader::try_new(Cursor::new(bytes), None).map_err(|e| self.import_error(e))?;
+        let schema = reader.schema();
+
+        let record_batches = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| self.import_error(e))?;
+
+        self.set_record_batches(schema, &record_batches)
+    }
+
+    /// Import the table from a file on disk, choosing the format by extension.
+    ///
+    /// Supported extensions are `parquet`, `csv`, `json` and `ndjson` (either
+    /// newline-delimited or an array) and `arrow` (IPC file format).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn import_file_from_disk(
+        &mut self,
+        path: &str,
+        extension: &str,
+        batch_size: usize,
+    ) -> Result<()> {
+        let file_name = format!("{path}/{}.{extension}", self.name);
+
+        self.import_file(&file_name, batch_size).await
+    }
+
+    /// Import the table from a file at a path on disk, choosing the format by
+    /// the extension of the file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn import_file(&mut self, file_name: &str, batch_size: usize) -> Result<()> {
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        if extension == "parquet" {
+            return self.import_parquet_file(file_name, batch_size).await;
+        }
+
+        let bytes = Bytes::from(
+            tokio::fs::read(file_name)
+                .await
+                .map_err(|e| self.import_error(e))?,
+        );
+
+        self.import_bytes(bytes, extension, batch_size)
+    }
+
+    /// Import the table from the bytes of a file, choosing the format by the
+    /// extension of the file, as with `import_file`
+    pub fn import_bytes(&mut self, bytes: Bytes, extension: &str, batch_size: usize) -> Result<()> {
+        match extension {
+            "parquet" => self.import_parquet_from_bytes(bytes, batch_size),
+            "csv" => self.import_csv_from_bytes(bytes, batch_size),
+            "json" | "ndjson" => self.import_json_from_bytes(bytes, batch_size),
+            "arrow" => self.import_ipc_from_bytes(bytes),
+            _ => Err(self.import_error(format!("Unsupported file extension {extension}"))),
+        }
+    }
+
+    /// Import the table from several parquet files at paths on disk, e.g. the
+    /// daily files of one table, merging their schemas.  See `merge_schemas`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn import_parquet_files(
+        &mut self,
+        file_names: &[&str],
+        batch_size: usize,
+    ) -> Result<()> {
+        let mut record_batches = vec![];
+
+        for file_name in file_names {
+            let mut imported = self.import_staging();
+            imported.import_parquet_file(file_name, batch_size).await?;
+            record_batches.extend(imported.chunks);
+        }
+
+        let schema = merge_schemas(record_batches.iter().map(RecordBatch::schema_ref))?;
+
+        self.set_record_batches(schema, &record_batches)
+    }
+
+    /// Append the rows of parquet bytes to the table instead of replacing
+    /// them, reading `batch_size` rows at a time, returning the number of rows
+    /// appended.
+    ///
+    /// Columns of the table missing from the file are appended as NULL, and
+    /// columns of the file with narrower types are cast to the types of the
+    /// table, but the file can't add columns or widen them.  Nothing is
+    /// appended if any row violates the constraints of the table.  See
+    /// `append_batch`.
+    pub fn import_parquet_append(&mut self, bytes: Bytes, batch_size: usize) -> Result<usize> {
+        let mut imported = self.import_staging();
+        imported.import_parquet_from_bytes(bytes, batch_size)?;
+
+        self.append_imported(imported)
+    }
+
+    /// Append the rows of a parquet file at a path on disk to the table, as
+    /// with `import_parquet_append`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn import_parquet_file_append(
+        &mut self,
+        file_name: &str,
+        batch_size: usize,
+    ) -> Result<usize> {
+        let mut imported = self.import_staging();
+        imported.import_parquet_file(file_name, batch_size).await?;
+
+        self.append_imported(imported)
+    }
+
+    /// An empty table to import rows into before appending them
+    fn import_staging(&self) -> Table {
+        let mut staging = Table::new(self.name.as_str());
+        staging.chunk_size = self.chunk_size;
+
+        staging
+    }
+
+    /// Append the rows of an imported table.  A table without columns takes
+    /// the columns of the import.
+    fn append_imported(&mut self, imported: Table) -> Result<usize> {
+        let num_rows = imported.num_rows();
+
+        match (self.num_columns(), imported.num_columns()) {
+            (_, 0) => return Ok(0),
+            (0, _) => self.chunks = imported.chunks,
+            _ => {
+                let schema = self.schema();
+                let record_batch = imported.record_batch()?;
+                let merged = merge_schemas([&schema, record_batch.schema_ref()])?;
+
+                // the table keeps its columns, so the merged schema must be
+                // the schema of the table
+                let columns = |schema: &SchemaRef| {
+                    schema
+                        .fields()
+                        .iter()
+                        .map(|field| (field.name().to_owned(), field.data_type().to_owned()))
+                        .collect::<Vec<_>>()
+                };
+
+                if columns(&merged) != columns(&schema) {
+                    return Err(DbError::DataType(format!(
+                        "The columns of the import don't fit Table {}",
+                        self.name
+                    )));
+                }
+
+                self.append_batch(align_batch(&record_batch, &schema)?)?;
+            }
+        }
+
+        Ok(num_rows)
+    }
+}
+
+/// Read the record batches of a row group of a parquet file
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_row_group(
+    table_name: String,
+    file_name: String,
+    metadata: ArrowReaderMetadata,
+    row_group: usize,
+    batch_size: usize,
+) -> Result<Vec<RecordBatch>> {
+    use futures::TryStreamExt;
+
+    let import_error =
+        |e: &dyn ToString| DbError::TableImportError(table_name.to_owned(), e.to_string());
+    let file = tokio::fs::File::open(&file_name)
+        .await
+        .map_err(|e| import_error(&e))?;
+
+    ParquetRecordBatchStreamBuilder::new_with_metadata(file, metadata)
+        .with_row_groups(vec![row_group])
+        .with_batch_size(batch_size)
+        .build()
+        .map_err(|e| import_error(&e))?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|e| import_error(&e))
+}
+
+/// The number of files, or row groups of a file, imported at once
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn import_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
 }
 
+/// File extensions that can be imported from disk
+pub const IMPORT_EXTENSIONS: [&str; 5] = ["parquet", "csv", "json", "ndjson", "arrow"];
+
+/// The default number of rows read at a time when importing
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// The number of records read to infer the schema of CSV and JSON files
+const IMPORT_INFER_RECORDS: usize = 1000;
+
 #[cfg(test)]
 pub mod tests {
-    use crate::{database::tests::create_database, get_mut_table, get_table};
+    use arrow::ipc::writer::FileWriter;
+    use arrow_schema::DataType;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        export::ExportOptions,
+        get_mut_table, get_table,
+    };
+
+    use super::*;
 
     #[tokio::test]
     async fn test_import_parquet_from_disk() {
@@ -79,10 +373,138 @@ pub mod tests {
 
         get_mut_table!(database, "users")
             .unwrap()
-            .import_parquet_from_disk(database.name)
+            .import_parquet_from_disk(&database.name, DEFAULT_BATCH_SIZE)
             .await
             .unwrap();
 
         get_table!(database, "users").unwrap().print();
     }
+
+    #[tokio::test]
+    async fn test_import_parquet_row_groups() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        let path = std::env::temp_dir().join(format!("arrow-db-row-groups-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let options = ExportOptions {
+            max_row_group_size: 1,
+            ..Default::default()
+        };
+        get_mut_table!(database, "users")
+            .unwrap()
+            .export_parquet_to_disk(&path.to_string_lossy(), &options)
+            .await
+            .unwrap();
+
+        // the row groups are read in parallel, but keep their order
+        let mut table = Table::new("users");
+        table
+            .import_parquet_from_disk(&path.to_string_lossy(), DEFAULT_BATCH_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(table.chunks.len(), 1);
+        assert_eq!(
+            table.record_batch().unwrap().columns(),
+            get_table!(database, "users")
+                .unwrap()
+                .record_batch()
+                .unwrap()
+                .columns()
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_parquet_append() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        let mut buffer = vec![];
+        get_mut_table!(database, "users")
+            .unwrap()
+            .export_parquet_to_bytes(&mut buffer, &ExportOptions::default())
+            .await
+            .unwrap();
+        let bytes = Bytes::from(buffer);
+
+        // rows are appended to the existing rows, not replacing them
+        let mut users = get_table!(database, "users").unwrap().to_owned();
+        assert_eq!(
+            users
+                .import_parquet_append(bytes.to_owned(), DEFAULT_BATCH_SIZE)
+                .unwrap(),
+            4
+        );
+        assert_eq!(users.num_rows(), 8);
+
+        let mut empty = Table::new("users");
+        empty
+            .import_parquet_append(bytes.to_owned(), DEFAULT_BATCH_SIZE)
+            .unwrap();
+        assert_eq!(empty.num_rows(), 4);
+
+        // the columns of the file must match the table
+        let mut user_role = get_table!(database, "user_role").unwrap().to_owned();
+        assert!(matches!(
+            user_role.import_parquet_append(bytes, DEFAULT_BATCH_SIZE),
+            Err(DbError::DataType(_))
+        ));
+        assert_eq!(user_role.num_rows(), 4);
+    }
+
+    #[test]
+    fn test_import_csv_ndjson_and_ipc() {
+        let mut csv = Table::new("users");
+        csv.import_csv_from_bytes(Bytes::from("id,name\n1,Alice\n2,Bob\n"), DEFAULT_BATCH_SIZE)
+            .unwrap();
+        assert_eq!(csv.num_rows(), 2);
+        assert_eq!(csv.schema().field(0).data_type(), &DataType::Int64);
+
+        let mut json = Table::new("users");
+        json.import_ndjson_from_bytes(
+            Bytes::from("{\"id\": 1, \"name\": \"Alice\"}\n{\"id\": 2, \"name\": \"Bob\"}\n"),
+            DEFAULT_BATCH_SIZE,
+        )
+        .unwrap();
+        assert_eq!(json.chunks, csv.chunks);
+
+        let mut buffer = vec![];
+        let mut writer = FileWriter::try_new(&mut buffer, &csv.schema()).unwrap();
+        writer.write(&csv.record_batch().unwrap()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut ipc = Table::new("users");
+        ipc.import_ipc_from_bytes(Bytes::from(buffer)).unwrap();
+        assert_eq!(ipc.chunks, csv.chunks);
+    }
+
+    #[test]
+    fn test_import_json_array() {
+        let mut ndjson = Table::new("users");
+        ndjson
+            .import_json_from_bytes(
+                Bytes::from("{\"id\": 1, \"name\": \"Alice\"}\n{\"id\": 2}\n"),
+                DEFAULT_BATCH_SIZE,
+            )
+            .unwrap();
+        assert_eq!(ndjson.num_rows(), 2);
+
+        let mut array = Table::new("users");
+        array
+            .import_json_from_bytes(
+                Bytes::from("  [{\"id\": 1, \"name\": \"Alice\"},\n {\"id\": 2}]"),
+                DEFAULT_BATCH_SIZE,
+            )
+            .unwrap();
+        assert_eq!(array.chunks, ndjson.chunks);
+
+        let mut invalid = Table::new("users");
+        assert!(matches!(
+            invalid.import_json_from_bytes(Bytes::from("[{\"id\": 1}"), DEFAULT_BATCH_SIZE),
+            Err(DbError::TableImportError(_, _))
+        ));
+    }
 }
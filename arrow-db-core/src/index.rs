@@ -0,0 +1,339 @@
+//! Secondary indexes.
+//!
+//! A hash index maps each value of a column to the rows that hold it, so
+//! UPDATE and DELETE statements with an equality predicate on the column, like
+//! `WHERE email = 'alice@example.com'`, only evaluate the predicate on the
+//! rows with the value instead of scanning the table.  `Database::lookup` reads
+//! the rows with a value directly.
+//!
+//! Indexes are maintained by the row operations used by DML statements and
+//! bulk loads.  Writes through the column API bypass them, so call
+//! `create_index` again after changing an indexed column that way.
+
+use std::collections::HashMap;
+
+use arrow::array::RecordBatch;
+use datafusion::{
+    common::DFSchema,
+    logical_expr::{BinaryExpr, ColumnarValue, Expr, Operator},
+    scalar::ScalarValue,
+};
+
+use crate::{
+    chunk::take_rows,
+    database::Database,
+    dml::evaluate_scalar,
+    error::{DbError, Result},
+    get_mut_table, get_table,
+    row::{cast_scalar, columnar_value, get_column_value},
+    table::Table,
+};
+
+/// A hash index of the rows of a table by the value of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashIndex {
+    pub column_name: String,
+    rows: HashMap<ScalarValue, Vec<usize>>,
+}
+
+impl HashIndex {
+    /// The number of distinct values in the index
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Check if the index has no values
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The rows with a value, in table order
+    pub fn get(&self, value: &ScalarValue) -> &[usize] {
+        self.rows.get(value).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    fn insert(&mut self, value: ScalarValue, row: usize) {
+        let rows = self.rows.entry(value).or_default();
+        let position = rows.partition_point(|indexed| *indexed < row);
+        rows.insert(position, row);
+    }
+
+    fn remove(&mut self, value: &ScalarValue, row: usize) {
+        if let Some(rows) = self.rows.get_mut(value) {
+            rows.retain(|indexed| *indexed != row);
+
+            if rows.is_empty() {
+                self.rows.remove(value);
+            }
+        }
+    }
+}
+
+impl Table {
+    /// Index the rows of the table by the value of a column, replacing any
+    /// existing index of the column
+    pub fn create_index(&mut self, column_name: &str) -> Result<()> {
+        let column = self.column(self.column_index(column_name)?)?;
+        let mut index = HashIndex {
+            column_name: column_name.into(),
+            rows: HashMap::new(),
+        };
+
+        for row in 0..column.len() {
+            index.insert(get_column_value(&column, row)?, row);
+        }
+
+        self.indexes.insert(column_name.into(), index);
+
+        Ok(())
+    }
+
+    /// Remove the index of a column
+    pub fn drop_index(&mut self, column_name: &str) -> Result<()> {
+        self.indexes
+            .remove(column_name)
+            .map(|_| ())
+            .ok_or_else(|| DbError::IndexNotFound(column_name.into(), self.name.to_owned()))
+    }
+
+    /// The rows with a value in an indexed column, in table order
+    pub fn index_lookup(&self, column_name: &str, value: &ScalarValue) -> Result<Vec<usize>> {
+        let index = self
+            .indexes
+            .get(column_name)
+            .ok_or_else(|| DbError::IndexNotFound(column_name.into(), self.name.to_owned()))?;
+        let column_index = self.column_index(column_name)?;
+        let value = cast_scalar(value, self.schema().field(column_index).data_type())?;
+
+        Ok(index.get(&value).to_vec())
+    }
+
+    /// Rebuild every index from the current rows of the table
+    pub(crate) fn rebuild_indexes(&mut self) -> Result<()> {
+        let column_names = self.indexes.keys().cloned().collect::<Vec<_>>();
+
+        column_names
+            .iter()
+            .try_for_each(|column_name| self.create_index(column_name))
+    }
+
+    /// Index the rows of a record batch appended to the table at a row
+    pub(crate) fn index_appended(
+        &mut self,
+        first_row: usize,
+        record_batch: &RecordBatch,
+    ) -> Result<()> {
+        for index in self.indexes.values_mut() {
+            let column_index = record_batch
+                .schema()
+                .index_of(&index.column_name)
+                .map_err(|e| DbError::DataType(e.to_string()))?;
+            let column = record_batch.column(column_index);
+
+            for row in 0..record_batch.num_rows() {
+                index.insert(get_column_value(column, row)?, first_row + row);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove deleted rows from the indexes, shifting the rows after them.
+    ///
+    /// The rows must be sorted.
+    pub(crate) fn index_deleted(&mut self, rows: &[usize]) {
+        for index in self.indexes.values_mut() {
+            index.rows.retain(|_, indexed| {
+                *indexed = indexed
+                    .iter()
+                    .filter_map(|row| match rows.binary_search(row) {
+                        Ok(_) => None,
+                        Err(deleted) => Some(row - deleted),
+                    })
+                    .collect();
+
+                !indexed.is_empty()
+            });
+        }
+    }
+
+    /// The current and new values of the indexed columns of updated rows, for
+    /// `index_updated` once the rows are written
+    pub(crate) fn index_changes(
+        &self,
+        rows: &[usize],
+        assignments: &[(usize, ColumnarValue)],
+    ) -> Result<Vec<IndexChange>> {
+        let schema = self.schema();
+        let mut changes = vec![];
+
+        for (column_index, value) in assignments.iter() {
+            let column_name = schema.field(*column_index).name();
+
+            if !self.indexes.contains_key(column_name) {
+                continue;
+            }
+
+            for row in rows {
+                let current = self.cell(*column_index, *row)?;
+                let new = columnar_value(value, *row)?;
+
+                if current != new {
+                    changes.push(IndexChange {
+                        column_name: column_name.to_owned(),
+                        row: *row,
+                        current,
+                        new,
+                    });
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Move updated rows to their new values in the indexes
+    pub(crate) fn index_updated(&mut self, changes: Vec<IndexChange>) {
+        for change in changes {
+            if let Some(index) = self.indexes.get_mut(&change.column_name) {
+                index.remove(&change.current, change.row);
+                index.insert(change.new, change.row);
+            }
+        }
+    }
+
+    /// The rows that can match a predicate, if it requires an indexed column
+    /// to equal a value.  The rows still need to be checked against the whole
+    /// predicate.
+    pub(crate) fn indexed_rows(&self, predicate: Option<&Expr>) -> Option<Vec<usize>> {
+        let Expr::BinaryExpr(BinaryExpr { left, op, right }) = predicate? else {
+            return None;
+        };
+
+        match op {
+            Operator::And => self
+                .indexed_rows(Some(left.as_ref()))
+                .or_else(|| self.indexed_rows(Some(right.as_ref()))),
+            Operator::Eq => match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), value) | (value, Expr::Column(column))
+                    if self.indexes.contains_key(&column.name) =>
+                {
+                    let value = evaluate_scalar(value, &[], &DFSchema::empty()).ok()?;
+                    self.index_lookup(&column.name, &value).ok()
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A change to the value of an indexed column of a row
+#[derive(Debug)]
+pub(crate) struct IndexChange {
+    column_name: String,
+    row: usize,
+    current: ScalarValue,
+    new: ScalarValue,
+}
+
+impl Database {
+    /// Index the rows of a table by the value of a column
+    pub fn create_index(&self, table_name: &str, column_name: &str) -> Result<()> {
+        get_mut_table!(self, table_name)?.create_index(column_name)
+    }
+
+    /// Remove the index of a column of a table
+    pub fn drop_index(&self, table_name: &str, column_name: &str) -> Result<()> {
+        get_mut_table!(self, table_name)?.drop_index(column_name)
+    }
+
+    /// The rows of a table with a value in an indexed column
+    pub fn lookup(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        value: &ScalarValue,
+    ) -> Result<RecordBatch> {
+        let table = get_table!(self, table_name)?;
+        let rows = table.index_lookup(column_name, value)?;
+
+        take_rows(&table.chunks, &rows)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use datafusion::prelude::{col, lit};
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    fn ids(record_batch: &RecordBatch) -> Int32Array {
+        record_batch
+            .column(0)
+            .as_primitive::<Int32Type>()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_hash_index() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database.create_index("user_role", "role").unwrap();
+        assert!(database.create_index("user_role", "missing").is_err());
+
+        let employee = ScalarValue::Utf8(Some("employee".into()));
+        let guest = ScalarValue::Utf8(Some("guest".into()));
+        assert_eq!(
+            ids(&database.lookup("user_role", "role", &employee).unwrap()),
+            Int32Array::from(vec![3, 4])
+        );
+
+        // DML statements maintain the index
+        database
+            .query("insert into user_role values (5, 'guest'), (6, 'employee')")
+            .await
+            .unwrap();
+        database
+            .query("update user_role set role = 'guest' where user_id = 3")
+            .await
+            .unwrap();
+        database
+            .query("delete from user_role where role = 'admin'")
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&database.lookup("user_role", "role", &employee).unwrap()),
+            Int32Array::from(vec![4, 6])
+        );
+        assert_eq!(
+            ids(&database.lookup("user_role", "role", &guest).unwrap()),
+            Int32Array::from(vec![3, 5])
+        );
+
+        // equality predicates only check the indexed rows
+        database
+            .query("delete from user_role where role = 'guest' and user_id > 3")
+            .await
+            .unwrap();
+        let user_role = get_table!(database, "user_role").unwrap();
+        assert_eq!(
+            user_role.indexed_rows(Some(&col("role").eq(lit("guest")))),
+            Some(vec![1])
+        );
+        assert_eq!(
+            user_role.column(1).unwrap().as_string::<i32>(),
+            &StringArray::from(vec!["manager", "guest", "employee", "employee"])
+        );
+        drop(user_role);
+
+        database.drop_index("user_role", "role").unwrap();
+        assert!(database.lookup("user_role", "role", &guest).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+//! JSON columns.
+//!
+//! A JSON column is a string column tagged with the canonical `arrow.json`
+//! extension type, whose values must be valid JSON documents.  Documents are
+//! validated when the column is declared and on every INSERT and UPDATE.
+//!
+//! Semi-structured payloads can be filtered and projected in SQL without
+//! flattening them first:
+//!
+//! * `json_get(json, key, ...)` returns the JSON of the value at a path of
+//!   object keys and array indexes, e.g. `json_get(payload, 'tags', 0)`
+//! * `json_extract(json, path)` returns the value at a JSONPath like
+//!   `$.user.tags[0]`, with strings unquoted, so it can be compared or cast
+//!
+//! Both return NULL if the document isn't valid JSON or the path doesn't
+//! exist, and work on any string column, not only JSON columns.
+
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, RecordBatch, StringArray},
+    compute::cast,
+    datatypes::{DataType, Field, Int64Type},
+};
+use datafusion::{
+    error::{DataFusionError, Result as DataFusionResult},
+    logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility},
+    prelude::SessionContext,
+    scalar::ScalarValue,
+};
+use serde_json::Value;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+/// The field metadata key of the extension type of a column
+pub const EXTENSION_NAME_KEY: &str = "ARROW:extension:name";
+
+/// The extension type of JSON columns
+pub const JSON_EXTENSION_NAME: &str = "arrow.json";
+
+/// A string field tagged as a JSON column
+pub fn json_field(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Utf8, nullable).with_metadata(HashMap::from([(
+        EXTENSION_NAME_KEY.into(),
+        JSON_EXTENSION_NAME.into(),
+    )]))
+}
+
+/// Whether a field is a JSON column
+pub fn is_json_field(field: &Field) -> bool {
+    field
+        .metadata()
+        .get(EXTENSION_NAME_KEY)
+        .is_some_and(|name| name == JSON_EXTENSION_NAME)
+}
+
+/// Register `json_get` and `json_extract` with a context
+pub fn register_json_functions(ctx: &SessionContext) {
+    ctx.register_udf(ScalarUDF::new_from_impl(JsonGet::new()));
+    ctx.register_udf(ScalarUDF::new_from_impl(JsonExtract::new()));
+}
+
+impl Table {
+    /// Declare a string column as a JSON column.
+    ///
+    /// Fails if the column contains a value that isn't valid JSON.
+    pub fn set_json_column(&mut self, column_name: &str) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+        let schema = self.schema();
+        let field = schema.field(column_index);
+
+        if !matches!(
+            field.data_type(),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        ) {
+            return Err(DbError::DataType(format!(
+                "Column {column_name} of Table {} must be a string to hold JSON, not {}",
+                self.name,
+                field.data_type()
+            )));
+        }
+
+        self.check_json_column(field, &self.column(column_index)?)?;
+
+        let mut metadata = field.metadata().to_owned();
+        metadata.insert(EXTENSION_NAME_KEY.into(), JSON_EXTENSION_NAME.into());
+
+        let mut fields = schema.fields().to_vec();
+        fields[column_index] = Arc::new(field.to_owned().with_metadata(metadata));
+        let order = (0..fields.len()).collect::<Vec<_>>();
+
+        self.reshape_columns(fields, &order)
+    }
+
+    /// Ensure the values of a row are valid JSON in the JSON columns
+    pub(crate) fn check_json_values(&self, values: &[ScalarValue]) -> Result<()> {
+        for (field, value) in self.schema().fields().iter().zip(values) {
+            if !is_json_field(field) {
+                continue;
+            }
+
+            if let ScalarValue::Utf8(Some(document))
+            | ScalarValue::LargeUtf8(Some(document))
+            | ScalarValue::Utf8View(Some(document)) = value
+            {
+                self.check_json_document(field, document)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the rows of a record batch are valid JSON in the JSON columns
+    pub(crate) fn check_json_batch(&self, batch: &RecordBatch) -> Result<()> {
+        for (field, column) in self.schema().fields().iter().zip(batch.columns()) {
+            if is_json_field(field) {
+                self.check_json_column(field, column)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_json_column(&self, field: &Field, column: &ArrayRef) -> Result<()> {
+        let documents = cast(column, &DataType::Utf8)
+            .map_err(|e| DbError::DataType(format!("Error casting {}: {e}", field.name())))?;
+
+        for document in documents.as_string::<i32>().iter().flatten() {
+            self.check_json_document(field, document)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_json_document(&self, field: &Field, document: &str) -> Result<()> {
+        serde_json::from_str::<Value>(document)
+            .map(|_| ())
+            .map_err(|e| {
+                DbError::ConstraintViolation(format!(
+                    "Column {} of Table {} must be valid JSON: {e}",
+                    field.name(),
+                    self.name
+                ))
+            })
+    }
+}
+
+impl Database {
+    /// Declare a string column of a table as a JSON column, registering the
+    /// new schema with the DataFusion context
+    pub fn set_json_column(&self, table_name: &str, column_name: &str) -> Result<()> {
+        get_mut_table!(self, table_name)?.set_json_column(column_name)?;
+        self.commit_table(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(())
+    }
+}
+
+/// A step of a path into a JSON document
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(i64),
+}
+
+/// The value at a path into a JSON document
+fn get_path<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        PathSegment::Key(key) => value.as_object()?.get(key),
+        PathSegment::Index(index) => value.as_array()?.get(usize::try_from(*index).ok()?),
+    })
+}
+
+/// Parse a JSONPath of keys and indexes, like `$.user.tags[0]` or
+/// `$['first name']`
+fn parse_json_path(path: &str) -> Option<Vec<PathSegment>> {
+    let mut rest = path.trim().strip_prefix('$')?;
+    let mut segments = vec![];
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+
+            if end == 0 {
+                return None;
+            }
+
+            segments.push(PathSegment::Key(after[..end].into()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let inner = after[..end].trim();
+            let quoted = inner.len() >= 2
+                && (inner.starts_with('\'') && inner.ends_with('\'')
+                    || inner.starts_with('"') && inner.ends_with('"'));
+
+            segments.push(match quoted {
+                true => PathSegment::Key(inner[1..inner.len() - 1].into()),
+                false => PathSegment::Index(inner.parse().ok()?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+
+    Some(segments)
+}
+
+/// Apply a function to the JSON documents of a column, row by row.  Rows
+/// that aren't valid JSON are NULL.
+fn map_documents(
+    documents: &ArrayRef,
+    scalar: bool,
+    f: impl Fn(&Value, usize) -> DataFusionResult<Option<String>>,
+) -> DataFusionResult<ColumnarValue> {
+    let documents = cast(documents, &DataType::Utf8)?;
+    let documents = documents.as_string::<i32>();
+    let results = (0..documents.len())
+        .map(|row| {
+            if documents.is_null(row) {
+                return Ok(None);
+            }
+
+            match serde_json::from_str::<Value>(documents.value(row)) {
+                Ok(document) => f(&document, row),
+                Err(_) => Ok(None),
+            }
+        })
+        .collect::<DataFusionResult<StringArray>>()?;
+
+    match scalar {
+        true => Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+            &results, 0,
+        )?)),
+        false => Ok(ColumnarValue::Array(Arc::new(results))),
+    }
+}
+
+fn is_scalar(args: &[ColumnarValue]) -> bool {
+    args.iter()
+        .all(|arg| matches!(arg, ColumnarValue::Scalar(_)))
+}
+
+/// `json_get(json, key, ...)`
+#[derive(Debug)]
+struct JsonGet {
+    signature: Signature,
+}
+
+impl JsonGet {
+    fn new() -> JsonGet {
+        JsonGet {
+            signature: Signature::variadic_any(Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for JsonGet {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "json_get"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        if arg_types.is_empty() {
+            return Err(DataFusionError::Plan(
+                "json_get requires a JSON document".into(),
+            ));
+        }
+
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> DataFusionResult<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+
+        // integer arguments are array indexes, the others are object keys
+        let path = arrays[1..]
+            .iter()
+            .map(|array| match array.data_type().is_integer() {
+                true => Ok(PathArg::Indexes(cast(array, &DataType::Int64)?)),
+                false => Ok(PathArg::Keys(cast(array, &DataType::Utf8)?)),
+            })
+            .collect::<DataFusionResult<Vec<_>>>()?;
+
+        map_documents(&arrays[0], is_scalar(args), |document, row| {
+            let segments = path
+                .iter()
+                .map(|arg| arg.segment(row))
+                .collect::<Option<Vec<_>>>();
+
+            Ok(segments.and_then(|segments| get_path(document, &segments).map(Value::to_string)))
+        })
+    }
+}
+
+/// An argument of `json_get`, cast to strings or integers
+enum PathArg {
+    Keys(ArrayRef),
+    Indexes(ArrayRef),
+}
+
+impl PathArg {
+    /// The path segment of a row, or `None` if it's NULL
+    fn segment(&self, row: usize) -> Option<PathSegment> {
+        match self {
+            PathArg::Keys(keys) => {
+                let keys = keys.as_string::<i32>();
+                (!keys.is_null(row)).then(|| PathSegment::Key(keys.value(row).into()))
+            }
+            PathArg::Indexes(indexes) => {
+                let indexes = indexes.as_primitive::<Int64Type>();
+                (!indexes.is_null(row)).then(|| PathSegment::Index(indexes.value(row)))
+            }
+        }
+    }
+}
+
+/// `json_extract(json, path)`
+#[derive(Debug)]
+struct JsonExtract {
+    signature: Signature,
+}
+
+impl JsonExtract {
+    fn new() -> JsonExtract {
+        JsonExtract {
+            signature: Signature::exact(
+                vec![DataType::Utf8, DataType::Utf8],
+                Volatility::Immutable,
+            ),
+        }
+    }
+}
+
+impl ScalarUDFImpl for JsonExtract {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "json_extract"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> DataFusionResult<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let paths = arrays[1].as_string::<i32>();
+
+        map_documents(&arrays[0], is_scalar(args), |document, row| {
+            if paths.is_null(row) {
+                return Ok(None);
+            }
+
+            let path = paths.value(row);
+            let segments = parse_json_path(path)
+                .ok_or_else(|| DataFusionError::Execution(format!("Invalid JSON path {path}")))?;
+
+            // strings are unquoted, and JSON null is NULL
+            Ok(match get_path(document, &segments) {
+                None | Some(Value::Null) => None,
+                Some(Value::String(value)) => Some(value.to_owned()),
+                Some(value) => Some(value.to_string()),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_json_path() {
+        assert_eq!(
+            parse_json_path("$.user.tags[0]['first name']"),
+            Some(vec![
+                PathSegment::Key("user".into()),
+                PathSegment::Key("tags".into()),
+                PathSegment::Index(0),
+                PathSegment::Key("first name".into()),
+            ])
+        );
+        assert_eq!(parse_json_path("$"), Some(vec![]));
+        assert_eq!(parse_json_path("user.tags"), None);
+        assert_eq!(parse_json_path("$.tags[x]"), None);
+    }
+
+    #[tokio::test]
+    async fn test_json_column() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .add_column::<StringArray>(
+                2,
+                "profile",
+                DataType::Utf8,
+                StringArray::from(vec![
+                    Some(r#"{"age": 30, "tags": ["admin", "dev"]}"#),
+                    Some(r#"{"age": 25, "tags": []}"#),
+                    Some(r#"{"age": 41, "address": {"city": "Paris"}}"#),
+                    None,
+                ])
+                .into(),
+            )
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+        database.set_json_column("users", "profile").unwrap();
+        assert!(is_json_field(
+            database
+                .tables
+                .get("users")
+                .unwrap()
+                .schema()
+                .field_with_name("profile")
+                .unwrap()
+        ));
+        assert!(database.set_json_column("users", "id").is_err());
+
+        let strings = |record_batches: Vec<RecordBatch>| {
+            record_batches
+                .iter()
+                .flat_map(|batch| {
+                    batch
+                        .column(0)
+                        .as_string::<i32>()
+                        .iter()
+                        .map(|value| value.map(String::from))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let record_batches = database
+            .query("select json_get(profile, 'tags', 0) from users order by id")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            strings(record_batches),
+            vec![Some("\"admin\"".into()), None, None, None]
+        );
+
+        let record_batches = database
+            .query(
+                "select name from users \
+                 where cast(json_extract(profile, '$.age') as int) > 28 order by id",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            strings(record_batches),
+            vec![Some("Alice".into()), Some("Charlie".into())]
+        );
+
+        let record_batches = database
+            .query("select json_extract(profile, '$.address.city') from users where id = 3")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(strings(record_batches), vec![Some("Paris".into())]);
+
+        // documents are validated on write
+        database
+            .query(r#"insert into users values (5, 'Eve', '{"age": 19}')"#)
+            .await
+            .unwrap();
+        assert!(matches!(
+            database
+                .query("insert into users values (6, 'Frank', 'not json')")
+                .await,
+            Err(DbError::ConstraintViolation(_))
+        ));
+    }
+}
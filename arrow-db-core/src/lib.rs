@@ -1,9 +1,69 @@
+pub mod archive;
+pub mod arrow_table;
+pub mod batch;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod builder;
+pub mod bulk;
+pub mod catalog;
+pub mod change;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod checkpoint;
+pub mod chunk;
 pub mod column;
+pub mod config;
+pub mod constraint;
+pub mod copy;
+pub mod cursor;
 pub mod database;
+pub mod default;
+#[cfg(feature = "delta")]
+pub mod delta;
+pub mod describe;
+pub mod diff;
+pub mod dml;
 pub mod error;
 pub mod export;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod external;
+pub mod function;
+#[cfg(test)]
+mod fuzz;
 pub mod import;
+pub mod index;
+pub mod json;
+pub mod limit;
+pub mod linked;
+pub mod manager;
+pub mod merge;
+pub mod metrics;
+pub mod options;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod partition;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod persist;
+pub mod prepared;
+pub mod protocol;
+pub mod provider;
+pub mod query_log;
+pub mod returning;
+pub mod row;
+pub mod sequence;
+pub mod sketch;
+pub mod snapshot;
+pub mod spill;
 pub mod sql;
+pub mod statistics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod store;
+pub mod system;
 pub mod table;
+pub mod trigger;
+pub mod ttl;
+pub mod typed;
+pub mod upsert;
+pub mod view;
+pub mod watch;
+pub mod zone;
 
 pub use database::Database;
@@ -0,0 +1,154 @@
+//! LIMIT fast path.
+//!
+//! Previews like `SELECT * FROM table LIMIT 100` are the most common query in
+//! interactive use.  Without ORDER BY, filters or aggregates, the result is
+//! just the first rows of the table, so it is sliced straight out of the table
+//! instead of planning and executing a scan over the whole table.
+
+use datafusion::{
+    logical_expr::{expr::WildcardOptions, Expr, FetchType, LogicalPlan, SkipType},
+    prelude::DataFrame,
+};
+
+use crate::{
+    chunk::slice_chunks,
+    database::Database,
+    error::{DbError, Result},
+    system::is_system_column,
+    table::Table,
+};
+
+impl Database {
+    /// Execute a `SELECT <columns> FROM table LIMIT n [OFFSET m]` plan by
+    /// slicing the table, or return `None` if the plan has any other shape.
+    pub(crate) fn limit_fast_path(&self, plan: &LogicalPlan) -> Result<Option<DataFrame>> {
+        let LogicalPlan::Limit(limit) = plan else {
+            return Ok(None);
+        };
+
+        // skips and fetches that aren't literals are planned as usual
+        let (Ok(SkipType::Literal(skip)), Ok(FetchType::Literal(Some(fetch)))) =
+            (limit.get_skip_type(), limit.get_fetch_type())
+        else {
+            return Ok(None);
+        };
+
+        let (exprs, scan) = match limit.input.as_ref() {
+            LogicalPlan::Projection(projection) => match projection.input.as_ref() {
+                LogicalPlan::TableScan(scan) => (projection.expr.as_slice(), scan),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        if !scan.filters.is_empty() || scan.fetch.is_some() || scan.projection.is_some() {
+            return Ok(None);
+        }
+
+        // spilled tables are streamed by their provider instead
+        let Some(table) = self
+            .tables
+            .get(scan.table_name.table())
+            .filter(|table| table.spill.is_none())
+        else {
+            return Ok(None);
+        };
+
+        let Some(columns) = projected_columns(&table, exprs)? else {
+            return Ok(None);
+        };
+
+        let num_rows = table.num_rows();
+        let offset = skip.min(num_rows);
+        let length = fetch.min(num_rows - offset);
+
+        // only the chunks with the rows in the window are read
+        let record_batches = slice_chunks(&table.chunks, offset, length)
+            .iter()
+            .map(|record_batch| record_batch.project(&columns))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))?;
+        drop(table);
+
+        self.ctx
+            .read_batches(record_batches)
+            .map(Some)
+            .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))
+    }
+}
+
+/// Get the indexes of the table columns a projection selects, or `None` if the
+/// projection computes anything
+fn projected_columns(table: &Table, exprs: &[Expr]) -> Result<Option<Vec<usize>>> {
+    let schema = table.schema();
+    let mut columns = vec![];
+
+    for expr in exprs {
+        match expr {
+            Expr::Column(column) => columns.push(table.column_index(&column.name)?),
+            Expr::Wildcard {
+                qualifier: None,
+                options,
+            } if options == &WildcardOptions::default() => columns.extend(
+                schema
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, field)| !is_system_column(field.name()))
+                    .map(|(index, _)| index),
+            ),
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(Some(columns))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array};
+    use arrow::datatypes::Int32Type;
+
+    use crate::database::tests::{create_database, seed_database};
+
+    #[tokio::test]
+    async fn test_limit_fast_path() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        for (sql, fast_path) in [
+            ("select * from users limit 2 offset 1", true),
+            ("select name, id from users limit 10", true),
+            ("select * from users where id > 1 limit 2", false),
+            ("select * from users order by id desc limit 2", false),
+            ("select id + 1 from users limit 2", false),
+        ] {
+            let plan = database.create_logical_plan(sql).await.unwrap();
+            let result = database.limit_fast_path(&plan).unwrap();
+            assert_eq!(result.is_some(), fast_path, "{sql}");
+        }
+
+        let results = database
+            .query("select * from users limit 2 offset 1")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_columns(), 2);
+        assert_eq!(
+            results[0].column(0).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![2, 3])
+        );
+
+        let results = database
+            .query("select name from users limit 10 offset 3")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 1);
+    }
+}
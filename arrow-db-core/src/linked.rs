@@ -0,0 +1,272 @@
+//! Linked tables.
+//!
+//! A linked table is imported from an external source, like a file or an
+//! object store, and can be refreshed from it without restarting.  Tables are
+//! refreshed manually with `REFRESH TABLE name`, or automatically by the next
+//! query once their TTL has expired.
+//!
+//! A refresh imports the whole source before swapping the table data, so
+//! queries never see a partially refreshed table.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow::array::RecordBatch;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+/// Loads the current data of a linked table from its source
+pub type TableLoader = Arc<dyn Fn() -> BoxFuture<'static, Result<RecordBatch>> + Send + Sync>;
+
+/// The source and refresh policy of a linked table
+#[derive(Clone)]
+pub struct LinkedTable {
+    pub loader: TableLoader,
+    /// How long the data is fresh for, or `None` to only refresh manually
+    pub ttl: Option<Duration>,
+    pub refreshed_at: DateTime<Utc>,
+}
+
+impl Debug for LinkedTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkedTable")
+            .field("ttl", &self.ttl)
+            .field("refreshed_at", &self.refreshed_at)
+            .finish()
+    }
+}
+
+impl LinkedTable {
+    /// Check if the TTL of the table has expired
+    pub fn is_expired(&self) -> bool {
+        self.ttl.is_some_and(|ttl| {
+            Utc::now()
+                .signed_duration_since(self.refreshed_at)
+                .to_std()
+                .is_ok_and(|age| age >= ttl)
+        })
+    }
+}
+
+impl Database {
+    /// Add a table that is loaded from an external source, and register it
+    /// with the context.
+    pub async fn link_table(
+        &mut self,
+        table_name: &str,
+        loader: TableLoader,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let record_batch = loader().await?;
+        let mut table = Table::new(table_name);
+        table.set_record_batch(record_batch);
+
+        self.add_table(table)?;
+        self.add_table_context(table_name)?;
+        self.linked_tables.insert(
+            table_name.into(),
+            LinkedTable {
+                loader,
+                ttl,
+                refreshed_at: Utc::now(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Link a table to a file on disk, in any format supported by
+    /// `import_file_from_disk`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn link_file(
+        &mut self,
+        table_name: &str,
+        path: &str,
+        extension: &str,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let (name, path, extension) =
+            (table_name.to_owned(), path.to_owned(), extension.to_owned());
+        let batch_size = self.options().import_batch_size;
+
+        let loader: TableLoader = Arc::new(move || {
+            let (name, path, extension) = (name.to_owned(), path.to_owned(), extension.to_owned());
+
+            Box::pin(async move {
+                let mut table = Table::new(&name);
+                table
+                    .import_file_from_disk(&path, &extension, batch_size)
+                    .await?;
+
+                table.record_batch()
+            })
+        });
+
+        self.link_table(table_name, loader, ttl).await
+    }
+
+    /// Re-import a linked table from its source and swap in the new data,
+    /// returning the number of rows loaded.
+    ///
+    /// Primary key and secondary indexes are rebuilt, and the refresh is rejected if the
+    /// new data violates the primary key.
+    pub async fn refresh_table(&self, table_name: &str) -> Result<usize> {
+        let loader = self
+            .linked_tables
+            .get(table_name)
+            .map(|linked| linked.loader.to_owned())
+            .ok_or_else(|| {
+                DbError::TableImportError(table_name.into(), "Table is not linked".into())
+            })?;
+
+        let record_batch = loader().await?;
+        let num_rows = record_batch.num_rows();
+
+        {
+            let mut table = get_mut_table!(self, table_name)?;
+            let previous = table.chunks.to_owned();
+            table.set_record_batch(record_batch);
+
+            let primary_key = table
+                .primary_key
+                .as_ref()
+                .map(|primary_key| primary_key.column_name.to_owned());
+
+            if let Some(column_name) = primary_key {
+                if let Err(e) = table.set_primary_key(&column_name) {
+                    table.chunks = previous;
+                    return Err(e);
+                }
+            }
+
+            if let Err(e) = table.rebuild_indexes() {
+                table.chunks = previous;
+                return Err(e);
+            }
+        }
+
+        self.commit_table(table_name)?;
+
+        if let Some(mut linked) = self.linked_tables.get_mut(table_name) {
+            linked.refreshed_at = Utc::now();
+        }
+
+        Ok(num_rows)
+    }
+
+    /// Refresh every linked table whose TTL has expired
+    pub async fn refresh_expired_tables(&self) -> Result<()> {
+        let expired = self
+            .linked_tables
+            .iter()
+            .filter(|linked| linked.is_expired())
+            .map(|linked| linked.key().to_owned())
+            .collect::<Vec<_>>();
+
+        for table_name in expired {
+            self.refresh_table(&table_name).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the table name of a `REFRESH TABLE name` statement
+pub fn refresh_table_name(sql: &str) -> Option<&str> {
+    let mut words = sql.trim().trim_end_matches(';').split_whitespace();
+
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (Some(refresh), Some(table), Some(table_name), None)
+            if refresh.eq_ignore_ascii_case("refresh") && table.eq_ignore_ascii_case("table") =>
+        {
+            Some(table_name.trim_matches('"'))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use arrow::array::Int32Array;
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::*;
+
+    /// A loader that returns one more row each time it is called
+    fn counting_loader() -> TableLoader {
+        let calls = Arc::new(AtomicI32::new(0));
+
+        Arc::new(move || {
+            let calls = calls.fetch_add(1, Ordering::SeqCst) + 1;
+
+            Box::pin(async move {
+                let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+                let ids = Int32Array::from_iter_values(0..calls);
+
+                Table::new_record_batch(schema, vec![Arc::new(ids)])
+            })
+        })
+    }
+
+    async fn count_rows(database: &Database) -> usize {
+        database
+            .query("select * from events")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[test]
+    fn test_refresh_table_name() {
+        assert_eq!(refresh_table_name("REFRESH TABLE events;"), Some("events"));
+        assert_eq!(
+            refresh_table_name("refresh table \"events\""),
+            Some("events")
+        );
+        assert_eq!(refresh_table_name("select * from events"), None);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_linked_table() {
+        let mut database = Database::new("Linked").unwrap();
+        database
+            .link_table("events", counting_loader(), None)
+            .await
+            .unwrap();
+        assert_eq!(count_rows(&database).await, 1);
+
+        database.query("REFRESH TABLE events").await.unwrap();
+        assert_eq!(count_rows(&database).await, 2);
+
+        // only linked tables can be refreshed
+        assert!(database.refresh_table("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_expired_table() {
+        let mut database = Database::new("Linked").unwrap();
+        database
+            .link_table("events", counting_loader(), Some(Duration::ZERO))
+            .await
+            .unwrap();
+
+        // every query sees a fresh copy of the source
+        assert_eq!(count_rows(&database).await, 2);
+        assert_eq!(count_rows(&database).await, 3);
+    }
+}
@@ -0,0 +1,149 @@
+//! Managing many databases in one process.
+//!
+//! A `DatabaseManager` holds named databases, so a server or a browser tab can
+//! serve more than one.  Databases are shared as `Arc<Database>`, so queries
+//! against a database can run while others are created or dropped.  Tables
+//! are added to a database before it's handed to the manager, or through SQL
+//! and the methods that take `&self` afterwards.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+};
+
+#[derive(Default)]
+pub struct DatabaseManager {
+    databases: DashMap<String, Arc<Database>>,
+}
+
+impl DatabaseManager {
+    pub fn new() -> DatabaseManager {
+        DatabaseManager::default()
+    }
+
+    /// Create an empty database
+    pub fn create_database(&self, name: &str) -> Result<Arc<Database>> {
+        self.add_database(Database::new(name)?)
+    }
+
+    /// Add an existing database, keyed by its name
+    pub fn add_database(&self, database: Database) -> Result<Arc<Database>> {
+        if self.databases.contains_key(&database.name) {
+            return Err(DbError::DatabaseAlreadyExists(database.name));
+        }
+
+        database.add_all_table_contexts()?;
+
+        let database = Arc::new(database);
+        self.databases
+            .insert(database.name.to_owned(), database.to_owned());
+
+        Ok(database)
+    }
+
+    /// Load a database from its directory on disk, as with
+    /// `Database::new_from_disk`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn open_database(&self, name: &str) -> Result<Arc<Database>> {
+        if self.databases.contains_key(name) {
+            return Err(DbError::DatabaseAlreadyExists(name.into()));
+        }
+
+        self.add_database(Database::new_from_disk(name).await?)
+    }
+
+    /// Load a database from its directory in a data directory, as with
+    /// `Database::new_from_disk_in`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn open_database_in(&self, data_dir: &str, name: &str) -> Result<Arc<Database>> {
+        if self.databases.contains_key(name) {
+            return Err(DbError::DatabaseAlreadyExists(name.into()));
+        }
+
+        self.add_database(Database::new_from_disk_in(data_dir, name).await?)
+    }
+
+    /// Get a database by name
+    pub fn database(&self, name: &str) -> Result<Arc<Database>> {
+        self.databases
+            .get(name)
+            .map(|database| database.to_owned())
+            .ok_or_else(|| DbError::DatabaseNotFound(name.into()))
+    }
+
+    /// Remove a database from the manager, returning it.  Queries that
+    /// already hold the database keep it alive, and its files on disk are left
+    /// as they are.
+    pub fn drop_database(&self, name: &str) -> Result<Arc<Database>> {
+        self.databases
+            .remove(name)
+            .map(|(_, database)| database)
+            .ok_or_else(|| DbError::DatabaseNotFound(name.into()))
+    }
+
+    /// The names of the databases, sorted
+    pub fn database_names(&self) -> Vec<String> {
+        let mut names = self
+            .databases
+            .iter()
+            .map(|database| database.key().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+
+        names
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_database_manager() {
+        let manager = DatabaseManager::new();
+
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        manager.add_database(database).unwrap();
+        manager.create_database("Empty").unwrap();
+
+        assert_eq!(manager.database_names(), vec!["Empty", "MyDB"]);
+        assert!(matches!(
+            manager.create_database("MyDB"),
+            Err(DbError::DatabaseAlreadyExists(_))
+        ));
+
+        // databases are queried independently
+        let batches = manager
+            .database("MyDB")
+            .unwrap()
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 4);
+        assert!(manager
+            .database("Empty")
+            .unwrap()
+            .query("select * from users")
+            .await
+            .is_err());
+
+        // a dropped database lives on while it's held
+        let dropped = manager.drop_database("MyDB").unwrap();
+        assert_eq!(manager.database_names(), vec!["Empty"]);
+        assert!(matches!(
+            manager.database("MyDB"),
+            Err(DbError::DatabaseNotFound(_))
+        ));
+        assert!(dropped.tables.contains_key("users"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Schema merging.
+//!
+//! Record batches imported into one table, e.g. from several files, don't
+//! always have the same schema.  Compatible schemas are merged instead of
+//! failing to concatenate: columns are matched by name, a column missing from
+//! some batches is filled with NULLs, and a column with different numeric
+//! types takes the wider type.  Columns are ordered by their first
+//! appearance.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{new_null_array, RecordBatch, RecordBatchOptions},
+    compute::cast,
+    datatypes::{DataType, Field, Schema, SchemaRef},
+};
+
+use crate::error::{DbError, Result};
+
+/// Merge schemas into a schema with the columns of all of them
+pub fn merge_schemas<'a>(schemas: impl IntoIterator<Item = &'a SchemaRef>) -> Result<SchemaRef> {
+    let schemas = schemas.into_iter().collect::<Vec<_>>();
+
+    let Some(first) = schemas.first() else {
+        return Ok(Arc::new(Schema::empty()));
+    };
+
+    if schemas.iter().all(|schema| schema == first) {
+        return Ok((*first).to_owned());
+    }
+
+    let mut fields: Vec<Field> = vec![];
+
+    for field in schemas.iter().flat_map(|schema| schema.fields()) {
+        match fields
+            .iter_mut()
+            .find(|merged| merged.name() == field.name())
+        {
+            Some(merged) => {
+                let data_type =
+                    widen_type(merged.data_type(), field.data_type()).ok_or_else(|| {
+                        DbError::DataType(format!(
+                            "Column {} has incompatible types {} and {}",
+                            field.name(),
+                            merged.data_type(),
+                            field.data_type()
+                        ))
+                    })?;
+                let nullable = merged.is_nullable() || field.is_nullable();
+
+                *merged = merged
+                    .to_owned()
+                    .with_data_type(data_type)
+                    .with_nullable(nullable);
+            }
+            None => fields.push(field.as_ref().to_owned()),
+        }
+    }
+
+    // columns missing from any schema are filled with NULLs
+    let fields = fields
+        .into_iter()
+        .map(|field| {
+            let missing = schemas
+                .iter()
+                .any(|schema| schema.field_with_name(field.name()).is_err());
+            let nullable = field.is_nullable() || missing;
+
+            field.with_nullable(nullable)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        first.metadata().to_owned(),
+    )))
+}
+
+/// The type that holds the values of two types, or `None` if they're
+/// incompatible.
+///
+/// Integers of the same signedness and floats take the wider type, mixed
+/// integers take a signed type wide enough for both, and integers with floats
+/// take `Float64`.
+pub fn widen_type(left: &DataType, right: &DataType) -> Option<DataType> {
+    let width = |data_type: &DataType| data_type.primitive_width().unwrap_or_default();
+
+    match (left, right) {
+        (left, right) if left == right => Some(left.to_owned()),
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.to_owned()),
+        (left, right)
+            if (left.is_signed_integer() && right.is_signed_integer())
+                || (left.is_unsigned_integer() && right.is_unsigned_integer())
+                || (left.is_floating() && right.is_floating()) =>
+        {
+            match width(left) >= width(right) {
+                true => Some(left.to_owned()),
+                false => Some(right.to_owned()),
+            }
+        }
+        (signed, unsigned) | (unsigned, signed)
+            if signed.is_signed_integer() && unsigned.is_unsigned_integer() =>
+        {
+            match width(signed).max(width(unsigned) * 2) {
+                2 => Some(DataType::Int16),
+                4 => Some(DataType::Int32),
+                8 => Some(DataType::Int64),
+                _ => None,
+            }
+        }
+        (integer, float) | (float, integer) if integer.is_integer() && float.is_floating() => {
+            Some(DataType::Float64)
+        }
+        (DataType::Utf8 | DataType::LargeUtf8, DataType::Utf8 | DataType::LargeUtf8) => {
+            Some(DataType::LargeUtf8)
+        }
+        (DataType::Binary | DataType::LargeBinary, DataType::Binary | DataType::LargeBinary) => {
+            Some(DataType::LargeBinary)
+        }
+        _ => None,
+    }
+}
+
+/// Align a record batch with a merged schema, casting its columns to the
+/// merged types and filling missing columns with NULLs
+pub fn align_batch(record_batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    if record_batch.schema_ref() == schema {
+        return Ok(record_batch.to_owned());
+    }
+
+    let num_rows = record_batch.num_rows();
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match record_batch.column_by_name(field.name()) {
+            Some(column) => cast(column, field.data_type()).map_err(|e| {
+                DbError::DataType(format!("Error casting column {}: {e}", field.name()))
+            }),
+            None => Ok(new_null_array(field.data_type(), num_rows)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // the row count is kept for schemas without columns
+    let options = RecordBatchOptions::new().with_row_count(Some(num_rows));
+
+    RecordBatch::try_new_with_options(schema.to_owned(), columns, &options)
+        .map_err(|e| DbError::ArrayData(format!("Error aligning record batch: {e}")))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{
+        Array, ArrayRef, AsArray, Float64Array, Int16Array, Int32Array, Int64Array, StringArray,
+    };
+    use arrow::datatypes::Int64Type;
+
+    use crate::table::Table;
+
+    use super::*;
+
+    fn batch(fields: Vec<Field>, columns: Vec<ArrayRef>) -> RecordBatch {
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+
+    #[test]
+    fn test_widen_type() {
+        assert_eq!(
+            widen_type(&DataType::Int16, &DataType::Int64),
+            Some(DataType::Int64)
+        );
+        assert_eq!(
+            widen_type(&DataType::UInt32, &DataType::Int8),
+            Some(DataType::Int64)
+        );
+        assert_eq!(
+            widen_type(&DataType::Int32, &DataType::Float32),
+            Some(DataType::Float64)
+        );
+        assert_eq!(
+            widen_type(&DataType::Utf8, &DataType::LargeUtf8),
+            Some(DataType::LargeUtf8)
+        );
+        assert_eq!(widen_type(&DataType::UInt64, &DataType::Int64), None);
+        assert_eq!(widen_type(&DataType::Utf8, &DataType::Int32), None);
+    }
+
+    #[test]
+    fn test_merge_batches() {
+        let first = batch(
+            vec![
+                Field::new("id", DataType::Int16, false),
+                Field::new("name", DataType::Utf8, false),
+            ],
+            vec![
+                Arc::new(Int16Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["Alice", "Bob"])),
+            ],
+        );
+        let second = batch(
+            vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("score", DataType::Float64, true),
+            ],
+            vec![
+                Arc::new(Int64Array::from(vec![3])),
+                Arc::new(Float64Array::from(vec![0.5])),
+            ],
+        );
+
+        let schema = merge_schemas([first.schema_ref(), second.schema_ref()]).unwrap();
+        assert_eq!(
+            schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>(),
+            vec!["id", "name", "score"]
+        );
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert!(!schema.field(0).is_nullable());
+        assert!(schema.field(1).is_nullable());
+
+        // importing batches with different schemas merges them
+        let mut table = Table::new("users");
+        table
+            .set_record_batches(first.schema(), &[first, second])
+            .unwrap();
+        let record_batch = table.record_batch().unwrap();
+        assert_eq!(
+            record_batch.column(0).as_primitive::<Int64Type>().values(),
+            &[1, 2, 3]
+        );
+        assert_eq!(record_batch.column(1).null_count(), 1);
+        assert_eq!(record_batch.column(2).null_count(), 2);
+
+        let incompatible = batch(
+            vec![Field::new("id", DataType::Utf8, false)],
+            vec![Arc::new(StringArray::from(vec!["4"]))],
+        );
+        let int = batch(
+            vec![Field::new("id", DataType::Int32, false)],
+            vec![Arc::new(Int32Array::from(vec![4]))],
+        );
+        assert!(matches!(
+            merge_schemas([incompatible.schema_ref(), int.schema_ref()]),
+            Err(DbError::DataType(_))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+//! Query metrics.
+//!
+//! `query_with_metrics` runs a query to completion and returns its results
+//! with metrics of how it ran: the time spent planning and executing, the
+//! rows the scans read, the rows and batches returned and the memory the
+//! operators used.
+//!
+//! Times are measured with the system clock, so they work in the browser.
+//! DML statements are applied while they're planned, so their planning time
+//! includes the write.
+
+use std::{sync::Arc, time::Duration};
+
+use arrow::array::RecordBatch;
+use arrow_schema::SchemaRef;
+use chrono::Utc;
+use datafusion::physical_plan::{collect, ExecutionPlan};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+};
+
+/// The name of the metric operators report their peak memory use with
+const PEAK_MEMORY_METRIC: &str = "peak_mem_used";
+
+/// Metrics of a query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryMetrics {
+    /// The time spent parsing, planning and optimizing the query
+    pub planning_time: Duration,
+    /// The time spent executing the plan and collecting its results
+    pub execution_time: Duration,
+    /// The number of rows read by the scans of the plan
+    pub rows_scanned: usize,
+    /// The number of rows returned
+    pub output_rows: usize,
+    /// The number of record batches returned
+    pub batches: usize,
+    /// The bytes of memory used by the operators of the plan at their peaks,
+    /// or by the results if they're larger
+    pub peak_memory: usize,
+}
+
+/// The results of a query with its metrics
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub schema: SchemaRef,
+    pub record_batches: Vec<RecordBatch>,
+    pub metrics: QueryMetrics,
+}
+
+impl QueryResult {
+    /// The number of rows returned
+    pub fn num_rows(&self) -> usize {
+        self.metrics.output_rows
+    }
+}
+
+impl Database {
+    /// Run a query to completion, returning its results with metrics
+    pub async fn query_with_metrics(&self, sql: &str) -> Result<QueryResult> {
+        let query_error = |e: &dyn ToString| DbError::Query(sql.into(), e.to_string());
+
+        let started = Utc::now();
+        let data_frame = self.query(sql).await?;
+        let task_ctx = Arc::new(data_frame.task_ctx());
+        let plan = data_frame
+            .create_physical_plan()
+            .await
+            .map_err(|e| query_error(&e))?;
+
+        let planned = Utc::now();
+        let record_batches = collect(plan.to_owned(), task_ctx)
+            .await
+            .map_err(|e| query_error(&e))?;
+        let executed = Utc::now();
+
+        let result_memory = record_batches
+            .iter()
+            .map(|record_batch| record_batch.get_array_memory_size())
+            .sum::<usize>();
+
+        let metrics = QueryMetrics {
+            planning_time: (planned - started).to_std().unwrap_or_default(),
+            execution_time: (executed - planned).to_std().unwrap_or_default(),
+            rows_scanned: rows_scanned(&plan),
+            output_rows: record_batches.iter().map(RecordBatch::num_rows).sum(),
+            batches: record_batches.len(),
+            peak_memory: peak_memory(&plan).max(result_memory),
+        };
+
+        Ok(QueryResult {
+            schema: plan.schema(),
+            record_batches,
+            metrics,
+        })
+    }
+}
+
+/// The rows read by the leaves of a plan.  Leaves without an output row
+/// metric, like in-memory scans, report the rows they hold.
+fn rows_scanned(plan: &Arc<dyn ExecutionPlan>) -> usize {
+    let children = plan.children();
+
+    if !children.is_empty() {
+        return children.into_iter().map(rows_scanned).sum();
+    }
+
+    plan.metrics()
+        .and_then(|metrics| metrics.output_rows())
+        .or_else(|| {
+            plan.statistics()
+                .ok()
+                .and_then(|statistics| statistics.num_rows.get_value().copied())
+        })
+        .unwrap_or_default()
+}
+
+/// The peak memory reported by the operators of a plan
+fn peak_memory(plan: &Arc<dyn ExecutionPlan>) -> usize {
+    let memory = plan
+        .metrics()
+        .and_then(|metrics| metrics.sum_by_name(PEAK_MEMORY_METRIC))
+        .map_or(0, |value| value.as_usize());
+
+    memory + plan.children().into_iter().map(peak_memory).sum::<usize>()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_with_metrics() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let result = database
+            .query_with_metrics("select name from users where id > 2 order by id")
+            .await
+            .unwrap();
+        assert_eq!(result.num_rows(), 2);
+        assert_eq!(result.schema.fields().len(), 1);
+        assert_eq!(result.metrics.rows_scanned, 4);
+        assert_eq!(result.metrics.batches, result.record_batches.len());
+        assert!(result.metrics.peak_memory > 0);
+
+        assert!(matches!(
+            database.query_with_metrics("select * from missing").await,
+            Err(DbError::Query(_, _))
+        ));
+    }
+}
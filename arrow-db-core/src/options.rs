@@ -0,0 +1,282 @@
+//! Query options.
+//!
+//! Options are set per database with `SET name = value` statements, and apply
+//! to every statement that follows.  Options that DataFusion knows about (e.g.
+//! `timezone`) are also passed through to the `SessionContext`.
+
+use datafusion::{
+    common::tree_node::{Transformed, TreeNode},
+    logical_expr::{Expr, LogicalPlan},
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    import::DEFAULT_BATCH_SIZE,
+};
+
+/// Session-scoped query options
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOptions {
+    /// The maximum number of rows returned by a query
+    pub max_rows: Option<usize>,
+
+    /// Whether `LIKE` patterns match case sensitively
+    pub case_sensitive: bool,
+
+    /// The time zone used for timestamps
+    pub timezone: Option<String>,
+
+    /// The number of rows per batch when registering tables and returning
+    /// results.  Smaller batches use less memory, larger batches have more
+    /// throughput.
+    pub batch_size: usize,
+
+    /// The number of rows read at a time when importing tables
+    pub import_batch_size: usize,
+
+    /// The number of bytes of memory a table can take before its committed
+    /// writes spill it to disk
+    pub spill_threshold: Option<usize>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            max_rows: None,
+            case_sensitive: true,
+            timezone: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            import_batch_size: DEFAULT_BATCH_SIZE,
+            spill_threshold: None,
+        }
+    }
+}
+
+impl QueryOptions {
+    /// Set an option from its SQL name and value.
+    ///
+    /// Returns `false` if the option is unknown, or if DataFusion should also
+    /// receive it.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<bool> {
+        let invalid =
+            |expected: &str| DbError::Query(format!("SET {name} = {value}"), expected.into());
+
+        match name.to_lowercase().as_str() {
+            "max_rows" => {
+                self.max_rows = match value.to_lowercase().as_str() {
+                    "none" | "null" | "0" => None,
+                    value => Some(value.parse().map_err(|_| invalid("expected a row count"))?),
+                };
+                Ok(true)
+            }
+            "spill_threshold" => {
+                self.spill_threshold = match value.to_lowercase().as_str() {
+                    "none" | "null" | "0" => None,
+                    value => Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid("expected a byte count"))?,
+                    ),
+                };
+                Ok(true)
+            }
+            "case_sensitive" => {
+                self.case_sensitive = value
+                    .to_lowercase()
+                    .parse()
+                    .map_err(|_| invalid("expected true or false"))?;
+                Ok(true)
+            }
+            "batch_size" => {
+                self.batch_size = parse_batch_size(value)
+                    .ok_or_else(|| invalid("expected a positive row count"))?;
+                Ok(true)
+            }
+            "import_batch_size" => {
+                self.import_batch_size = parse_batch_size(value)
+                    .ok_or_else(|| invalid("expected a positive row count"))?;
+                Ok(true)
+            }
+            "timezone" | "time.zone" | "datafusion.execution.time_zone" => {
+                self.timezone = Some(value.into());
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Rewrite a plan to respect the options that change how expressions
+    /// evaluate, so the query and DML layers behave the same
+    pub fn apply_to_plan(&self, plan: LogicalPlan) -> Result<LogicalPlan> {
+        if self.case_sensitive {
+            return Ok(plan);
+        }
+
+        plan.transform_up_with_subqueries(|plan| {
+            plan.map_expressions(|expr| {
+                expr.transform_up(|expr| match expr {
+                    Expr::Like(mut like) if !like.case_insensitive => {
+                        like.case_insensitive = true;
+                        Ok(Transformed::yes(Expr::Like(like)))
+                    }
+                    expr => Ok(Transformed::no(expr)),
+                })
+            })
+        })
+        .map(|transformed| transformed.data)
+        .map_err(|e| DbError::Query("apply query options".into(), e.to_string()))
+    }
+}
+
+/// Parse a batch size, which must be at least one row
+fn parse_batch_size(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|batch_size| *batch_size > 0)
+}
+
+impl Database {
+    /// The current query options
+    pub fn options(&self) -> QueryOptions {
+        self.options.read().expect("query options lock").to_owned()
+    }
+
+    /// Set a query option, as with `SET name = value`.
+    ///
+    /// Returns `false` if the option should also be set in DataFusion.
+    pub fn set_option(&self, name: &str, value: &str) -> Result<bool> {
+        let mut options = self.options.write().expect("query options lock");
+        let handled = options.set(name, value)?;
+
+        // results are batched by DataFusion
+        self.ctx
+            .state_ref()
+            .write()
+            .config_mut()
+            .options_mut()
+            .execution
+            .batch_size = options.batch_size;
+
+        Ok(handled)
+    }
+
+    /// Set the number of rows per batch when registering tables and returning
+    /// results.  Tables registered before the change keep their batches until
+    /// they are registered again.
+    pub fn set_batch_size(&self, batch_size: usize) -> Result<()> {
+        self.set_option("batch_size", &batch_size.to_string())
+            .map(|_| ())
+    }
+
+    /// Set the number of rows read at a time when importing tables
+    pub fn set_import_batch_size(&self, batch_size: usize) -> Result<()> {
+        self.set_option("import_batch_size", &batch_size.to_string())
+            .map(|_| ())
+    }
+
+    /// Set the number of bytes of memory a table can take before it's
+    /// spilled to disk, or `None` to keep every table in memory
+    pub fn set_spill_threshold(&self, spill_threshold: Option<usize>) -> Result<()> {
+        let value = spill_threshold.map_or("none".into(), |bytes| bytes.to_string());
+
+        self.set_option("spill_threshold", &value).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[test]
+    fn test_set_options() {
+        let mut options = QueryOptions::default();
+
+        assert!(options.set("MAX_ROWS", "10").unwrap());
+        assert_eq!(options.max_rows, Some(10));
+        assert!(options.set("max_rows", "none").unwrap());
+        assert_eq!(options.max_rows, None);
+        assert!(options.set("max_rows", "lots").is_err());
+
+        assert!(options.set("case_sensitive", "FALSE").unwrap());
+        assert!(!options.case_sensitive);
+
+        assert!(options.set("batch_size", "1024").unwrap());
+        assert_eq!(options.batch_size, 1024);
+        assert!(options.set("import_batch_size", "0").is_err());
+
+        assert!(options.set("spill_threshold", "1048576").unwrap());
+        assert_eq!(options.spill_threshold, Some(1_048_576));
+        assert!(options.set("spill_threshold", "lots").is_err());
+
+        assert!(!options
+            .set("datafusion.execution.time_zone", "UTC")
+            .unwrap());
+        assert_eq!(options.timezone, Some("UTC".into()));
+    }
+
+    #[tokio::test]
+    async fn test_set_statements() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let count_rows = |sql: &'static str| {
+            let database = &database;
+            async move {
+                let results = database.query(sql).await.unwrap().collect().await.unwrap();
+                results.iter().map(|batch| batch.num_rows()).sum::<usize>()
+            }
+        };
+
+        database.query("SET max_rows = 2").await.unwrap();
+        assert_eq!(count_rows("select * from users").await, 2);
+
+        database.query("SET max_rows = 0").await.unwrap();
+        assert_eq!(count_rows("select * from users").await, 4);
+
+        assert_eq!(
+            count_rows("select * from users where name like 'alice'").await,
+            0
+        );
+        database.query("SET case_sensitive = false").await.unwrap();
+        assert_eq!(
+            count_rows("select * from users where name like 'alice'").await,
+            1
+        );
+
+        // the DML layer sees the same options
+        database
+            .query("delete from users where name like 'BOB'")
+            .await
+            .unwrap();
+        assert_eq!(count_rows("select * from users").await, 3);
+
+        // tables are registered and results returned in batches
+        database.query("SET batch_size = 2").await.unwrap();
+        database.add_table_context("users").unwrap();
+        let results = database
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|batch| batch.num_rows() <= 2));
+        assert_eq!(database.ctx.copied_config().batch_size(), 2);
+
+        database.query("SET timezone = 'UTC'").await.unwrap();
+        assert_eq!(database.options().timezone, Some("UTC".into()));
+        assert_eq!(
+            database
+                .ctx
+                .copied_config()
+                .options()
+                .execution
+                .time_zone
+                .as_deref(),
+            Some("UTC")
+        );
+    }
+}
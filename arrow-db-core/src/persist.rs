@@ -0,0 +1,328 @@
+//! Write-through persistence.
+//!
+//! When enabled, every successful INSERT, UPDATE and DELETE schedules the
+//! affected table to be exported to its parquet file in the background.  The
+//! export is debounced, so a burst of writes results in a single export of
+//! each table once writes have been quiet for the debounce interval.
+//!
+//! `flush_writes` exports any pending tables immediately, and should be awaited
+//! before the process exits so the last writes aren't lost.
+//!
+//! Renaming a table exports it under the new name, and the file with the old
+//! name is removed once the export succeeds.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use arrow::array::RecordBatch;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    export::{ExportFormat, ExportOptions},
+    table::Table,
+};
+
+/// Messages sent to the background writer
+enum Message {
+    /// The latest chunks of a table that was written to
+    Write(String, Vec<RecordBatch>),
+    /// The old name of a renamed table, whose file is removed after the next
+    /// export
+    Rename(String),
+    /// Export pending tables now, and report the result
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+/// The sender of a running background writer
+#[derive(Debug, Clone)]
+pub struct WriteThrough {
+    /// The directory tables are exported to
+    pub path: String,
+    /// How long a table must be quiet before it is exported
+    pub debounce: Duration,
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+impl Database {
+    /// Export tables to the directory of the database after every DML
+    /// statement, so databases loaded with `new_from_disk` keep their writes.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn enable_write_through(&self, debounce: Duration) -> Result<()> {
+        let path = self.disk_path();
+        self.enable_write_through_to(&path, debounce).await
+    }
+
+    /// Export tables to a directory after every DML statement, replacing any
+    /// previous write-through directory
+    pub async fn enable_write_through_to(&self, path: &str, debounce: Duration) -> Result<()> {
+        tokio::fs::create_dir_all(path)
+            .await
+            .map_err(|e| DbError::CreateDatabase(format!("Error creating directory: {e}")))?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_tables(path.to_owned(), debounce, receiver));
+
+        *self.write_through.write().unwrap() = Some(WriteThrough {
+            path: path.into(),
+            debounce,
+            sender,
+        });
+
+        Ok(())
+    }
+
+    /// Stop exporting tables after DML statements.  Pending tables are still
+    /// exported by the background writer.
+    pub fn disable_write_through(&self) {
+        self.write_through.write().unwrap().take();
+    }
+
+    /// Export any tables with pending writes now, returning the error of the
+    /// last failed export, if any
+    pub async fn flush_writes(&self) -> Result<()> {
+        let Some(sender) = self.write_through_sender() else {
+            return Ok(());
+        };
+
+        let (respond, response) = oneshot::channel();
+        sender
+            .send(Message::Flush(respond))
+            .map_err(|_| write_error(&self.name, "Background writer stopped"))?;
+
+        response
+            .await
+            .map_err(|_| write_error(&self.name, "Background writer stopped"))?
+    }
+
+    /// Schedule a table to be exported, if write-through is enabled
+    pub(crate) fn schedule_write(&self, table_name: &str) -> Result<()> {
+        let Some(sender) = self.write_through_sender() else {
+            return Ok(());
+        };
+
+        // the chunks are cheap to clone, and are a snapshot of the table
+        let chunks = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?
+            .unspilled()?
+            .chunks;
+
+        sender
+            .send(Message::Write(table_name.into(), chunks))
+            .map_err(|_| write_error(table_name, "Background writer stopped"))
+    }
+
+    /// Export a renamed table under its new name and remove the file with
+    /// its old name, if write-through is enabled
+    pub(crate) fn schedule_rename(&self, table_name: &str, new_name: &str) -> Result<()> {
+        self.schedule_remove(table_name)?;
+        self.schedule_write(new_name)
+    }
+
+    /// Remove the file of a renamed or dropped table, if write-through is
+    /// enabled
+    pub(crate) fn schedule_remove(&self, table_name: &str) -> Result<()> {
+        let Some(sender) = self.write_through_sender() else {
+            return Ok(());
+        };
+
+        sender
+            .send(Message::Rename(table_name.into()))
+            .map_err(|_| write_error(table_name, "Background writer stopped"))
+    }
+
+    fn write_through_sender(&self) -> Option<mpsc::UnboundedSender<Message>> {
+        self.write_through
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|write_through| write_through.sender.to_owned())
+    }
+}
+
+/// Receive table writes, exporting the pending tables once writes have been
+/// quiet for the debounce interval.  Runs until every sender is dropped, then exports any
+/// pending tables.
+async fn write_tables(
+    path: String,
+    debounce: Duration,
+    mut receiver: mpsc::UnboundedReceiver<Message>,
+) {
+    let mut pending = HashMap::new();
+    let mut renamed = HashSet::new();
+    let mut last_error = None;
+
+    loop {
+        let message = match pending.is_empty() {
+            true => receiver.recv().await,
+            false => match tokio::time::timeout(debounce, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    if let Err(e) = export_tables(&path, &mut pending, &mut renamed).await {
+                        last_error = Some(e);
+                    }
+                    continue;
+                }
+            },
+        };
+
+        match message {
+            Some(Message::Write(table_name, chunks)) => {
+                // the name of a renamed table was reused
+                renamed.remove(&table_name);
+                pending.insert(table_name, chunks);
+            }
+            Some(Message::Rename(table_name)) => {
+                pending.remove(&table_name);
+                renamed.insert(table_name);
+            }
+            Some(Message::Flush(respond)) => {
+                let result = export_tables(&path, &mut pending, &mut renamed).await;
+                let _ = respond.send(result.and(last_error.take().map_or(Ok(()), Err)));
+            }
+            None => {
+                let _ = export_tables(&path, &mut pending, &mut renamed).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Export pending tables to parquet files, then remove the files of renamed
+/// tables.  Tables that fail to export stay pending, so they are retried.
+async fn export_tables(
+    path: &str,
+    pending: &mut HashMap<String, Vec<RecordBatch>>,
+    renamed: &mut HashSet<String>,
+) -> Result<()> {
+    let mut result = Ok(());
+
+    for (table_name, chunks) in std::mem::take(pending) {
+        let mut table = Table::new(&table_name);
+        table.chunks = chunks;
+
+        if let Err(e) = table.export_to_disk(path, &ExportOptions::default()).await {
+            result = Err(e);
+            pending.insert(table_name.to_owned(), table.chunks);
+        }
+    }
+
+    // keep the old files until the renamed tables are exported
+    if result.is_ok() {
+        for table_name in std::mem::take(renamed) {
+            if let Err(e) = remove_table_file(path, &table_name).await {
+                result = Err(e);
+                renamed.insert(table_name);
+            }
+        }
+    }
+
+    result
+}
+
+/// Remove the parquet or Arrow IPC file of a table, if it exists
+pub(crate) async fn remove_table_file(path: &str, table_name: &str) -> Result<()> {
+    for format in [ExportFormat::Parquet, ExportFormat::Ipc(None)] {
+        let file_name = format!("{path}/{table_name}.{}", format.extension());
+
+        match tokio::fs::remove_file(file_name).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(write_error(table_name, &e.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn write_error(table_name: &str, error: &str) -> DbError {
+    DbError::TableExportError(table_name.into(), error.into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    async fn count_rows(path: &str) -> usize {
+        let mut table = Table::new("users");
+        table.import_parquet_from_disk(path, 1024).await.unwrap();
+        table.num_rows()
+    }
+
+    #[tokio::test]
+    async fn test_write_through() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let path = std::env::temp_dir()
+            .join("arrow_db_write_through")
+            .display()
+            .to_string();
+        database
+            .enable_write_through_to(&path, Duration::from_millis(10))
+            .await
+            .unwrap();
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database.flush_writes().await.unwrap();
+        assert_eq!(count_rows(&path).await, 5);
+
+        // writes are exported in the background once the table is quiet
+        database
+            .query("delete from users where id > 2")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(count_rows(&path).await, 2);
+
+        // clones don't write to the files of the original
+        let cloned = database.clone();
+        cloned.query("delete from users").await.unwrap();
+        cloned.flush_writes().await.unwrap();
+        assert_eq!(count_rows(&path).await, 2);
+
+        database.disable_write_through();
+        database.query("delete from users").await.unwrap();
+        assert_eq!(count_rows(&path).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_write_through_rename() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let path = std::env::temp_dir()
+            .join("arrow_db_write_through_rename")
+            .display()
+            .to_string();
+        database
+            .enable_write_through_to(&path, Duration::from_millis(10))
+            .await
+            .unwrap();
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database.flush_writes().await.unwrap();
+
+        database.rename_table("users", "people").unwrap();
+        database.flush_writes().await.unwrap();
+        assert!(!std::path::Path::new(&format!("{path}/users.parquet")).exists());
+
+        let mut people = Table::new("people");
+        people.import_parquet_from_disk(&path, 1024).await.unwrap();
+        assert_eq!(people.num_rows(), 5);
+    }
+}
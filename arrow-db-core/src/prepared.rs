@@ -0,0 +1,379 @@
+//! Prepared statements.
+//!
+//! A prepared statement is planned once and can then be executed any number
+//! of times with different parameters, without interpolating values into the
+//! SQL string.  Parameters are bound to numbered `$1`, `$2`, ... placeholders,
+//! or to `?` placeholders, which are numbered in the order they appear.
+//!
+//! `query_params` binds parameters to a statement that is run once.  Its
+//! placeholders whose type DataFusion can't infer, like the pattern of a
+//! LIKE, take the types of their parameters.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_schema::{DataType, SchemaRef};
+use datafusion::{
+    common::tree_node::{Transformed, TreeNodeRecursion},
+    datasource::provider_as_source,
+    logical_expr::LogicalPlan,
+    prelude::{DataFrame, SessionContext},
+    scalar::ScalarValue,
+    sql::{
+        parser::Statement as DFStatement,
+        sqlparser::ast::{DataType as SqlDataType, Ident, Statement as SqlStatement},
+    },
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    sql::statement_error,
+};
+
+/// A SQL statement planned against a database
+#[derive(Debug)]
+pub struct PreparedStatement<'a> {
+    database: &'a Database,
+    sql: String,
+    plan: LogicalPlan,
+}
+
+impl Database {
+    /// Plan a SQL statement for repeated execution
+    pub async fn prepare(&self, sql: &str) -> Result<PreparedStatement<'_>> {
+        let plan = self
+            .create_logical_plan(&number_placeholders(sql))
+            .await
+            .map_err(|e| statement_error(sql, e))?;
+
+        Ok(PreparedStatement {
+            database: self,
+            sql: sql.into(),
+            plan,
+        })
+    }
+
+    /// Run a SQL query with parameters bound to its placeholders, like
+    /// `query`, without interpolating values into the SQL string
+    pub async fn query_params(&self, sql: &str, params: &[ScalarValue]) -> Result<DataFrame> {
+        self.refresh_expired_tables().await?;
+
+        let numbered = number_placeholders(sql);
+        let plan = match self.create_logical_plan(&numbered).await {
+            Ok(plan) => plan,
+            Err(e) => self
+                .plan_with_param_types(&numbered, params)
+                .await
+                .map_err(|_| e)?,
+        };
+        let params = cast_params(&plan, params)?;
+        let plan = plan
+            .with_param_values(params)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        self.execute_plan(plan)
+            .await
+            .map_err(|e| statement_error(sql, e))
+    }
+
+    /// Plan a statement as if prepared with the types of its parameters, for
+    /// placeholders DataFusion can't infer a type for
+    async fn plan_with_param_types(
+        &self,
+        sql: &str,
+        params: &[ScalarValue],
+    ) -> Result<LogicalPlan> {
+        let data_types = params
+            .iter()
+            .map(|param| {
+                sql_data_type(&param.data_type()).ok_or_else(|| {
+                    DbError::DataType(format!("Cannot infer the type of parameter {param}"))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let DFStatement::Statement(statement) = self.parse_statement(sql)? else {
+            return Err(DbError::Query(
+                sql.into(),
+                "Expected a SQL statement".into(),
+            ));
+        };
+
+        let prepare = SqlStatement::Prepare {
+            name: Ident::new("query_params"),
+            data_types,
+            statement,
+        };
+
+        self.plan_statement(sql, DFStatement::Statement(Box::new(prepare)))
+            .await
+    }
+}
+
+impl PreparedStatement<'_> {
+    /// The SQL the statement was prepared from
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The schema of the results of the statement
+    pub fn schema(&self) -> SchemaRef {
+        self.plan.schema().inner().to_owned()
+    }
+
+    /// Bind parameters to the placeholders and execute the statement
+    pub async fn execute(&self, params: Vec<ScalarValue>) -> Result<DataFrame> {
+        let params = cast_params(&self.plan, &params)?;
+        let plan = self
+            .plan
+            .to_owned()
+            .with_param_values(params)
+            .map_err(|e| self.query_error(e))?;
+
+        let plan = refresh_table_sources(&self.database.ctx, plan).await?;
+
+        self.database
+            .execute_plan(plan)
+            .await
+            .map_err(|e| statement_error(&self.sql, e))
+    }
+
+    /// Helper function to create a `DbError` for query errors
+    fn query_error(&self, error: impl ToString) -> DbError {
+        DbError::Query(self.sql.to_owned(), error.to_string())
+    }
+}
+
+/// Point the table scans of a plan at the currently registered tables.
+///
+/// Tables are re-registered when their schema or column defaults change,
+/// so a plan created before the change would otherwise read stale data.
+pub(crate) async fn refresh_table_sources(
+    ctx: &SessionContext,
+    plan: LogicalPlan,
+) -> Result<LogicalPlan> {
+    let mut table_names = vec![];
+
+    plan.apply_with_subqueries(|plan| {
+        if let LogicalPlan::TableScan(scan) = plan {
+            table_names.push(scan.table_name.to_owned());
+        }
+
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))?;
+
+    let mut sources = HashMap::new();
+
+    for table_name in table_names {
+        let provider = ctx
+            .table_provider(table_name.to_owned())
+            .await
+            .map_err(|e| DbError::TableNotFound(format!("{table_name}: {e}")))?;
+
+        sources.insert(table_name, provider_as_source(provider));
+    }
+
+    plan.transform_up_with_subqueries(|plan| match plan {
+        LogicalPlan::TableScan(mut scan) => {
+            if let Some(source) = sources.get(&scan.table_name) {
+                scan.source = Arc::clone(source);
+            }
+
+            Ok(Transformed::yes(LogicalPlan::TableScan(scan)))
+        }
+        plan => Ok(Transformed::no(plan)),
+    })
+    .map(|transformed| transformed.data)
+    .map_err(|e| DbError::Query("refresh table sources".into(), e.to_string()))
+}
+
+/// Cast parameters to the types DataFusion inferred for their placeholders,
+/// so an `Int64` can be bound to a placeholder compared to an `Int32` column
+fn cast_params(plan: &LogicalPlan, params: &[ScalarValue]) -> Result<Vec<ScalarValue>> {
+    let types = plan
+        .get_parameter_types()
+        .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))?;
+
+    params
+        .iter()
+        .enumerate()
+        .map(
+            |(index, param)| match types.get(&format!("${}", index + 1)) {
+                Some(Some(data_type)) if &param.data_type() != data_type => {
+                    param.cast_to(data_type).map_err(|e| {
+                        DbError::DataType(format!("Cannot bind {param} to ${}: {e}", index + 1))
+                    })
+                }
+                _ => Ok(param.to_owned()),
+            },
+        )
+        .collect()
+}
+
+/// The SQL type a placeholder is declared with to take the type of a
+/// parameter, if the type can be declared
+fn sql_data_type(data_type: &DataType) -> Option<SqlDataType> {
+    let sql_data_type = match data_type {
+        DataType::Boolean => SqlDataType::Boolean,
+        DataType::Int8 => SqlDataType::TinyInt(None),
+        DataType::Int16 => SqlDataType::SmallInt(None),
+        DataType::Int32 => SqlDataType::Int(None),
+        DataType::Int64 => SqlDataType::BigInt(None),
+        DataType::UInt8 => SqlDataType::UnsignedTinyInt(None),
+        DataType::UInt16 => SqlDataType::UnsignedSmallInt(None),
+        DataType::UInt32 => SqlDataType::UnsignedInt(None),
+        DataType::UInt64 => SqlDataType::UnsignedBigInt(None),
+        DataType::Float32 => SqlDataType::Real,
+        DataType::Float64 => SqlDataType::Double,
+        DataType::Utf8 => SqlDataType::Varchar(None),
+        DataType::Date32 => SqlDataType::Date,
+        _ => return None,
+    };
+
+    Some(sql_data_type)
+}
+
+/// Rewrite `?` placeholders to `$1`, `$2`, ... in order of appearance,
+/// leaving quoted strings and identifiers untouched.
+pub fn number_placeholders(sql: &str) -> String {
+    let mut numbered = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut count = 0;
+
+    for character in sql.chars() {
+        match (quote, character) {
+            (None, '\'' | '"') => quote = Some(character),
+            (Some(open), close) if open == close => quote = None,
+            (None, '?') => {
+                count += 1;
+                numbered.push_str(&format!("${count}"));
+                continue;
+            }
+            _ => {}
+        }
+
+        numbered.push(character);
+    }
+
+    numbered
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, StringArray};
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[test]
+    fn test_number_placeholders() {
+        assert_eq!(
+            number_placeholders("select * from users where id = ? and name = ?"),
+            "select * from users where id = $1 and name = $2"
+        );
+        assert_eq!(
+            number_placeholders("select '?' from users where id = ?"),
+            "select '?' from users where id = $1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prepared_statements() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let select = database
+            .prepare("select name from users where id = $1")
+            .await
+            .unwrap();
+        let insert = database
+            .prepare("insert into users values (?, ?)")
+            .await
+            .unwrap();
+        let update = database
+            .prepare("update users set name = ? where id = ?")
+            .await
+            .unwrap();
+        assert_eq!(select.schema().fields().len(), 1);
+        assert_eq!(select.schema().field(0).name(), "name");
+
+        insert
+            .execute(vec![
+                ScalarValue::Int32(Some(5)),
+                ScalarValue::Utf8(Some("Eve".into())),
+            ])
+            .await
+            .unwrap();
+
+        update
+            .execute(vec![
+                ScalarValue::Utf8(Some("Eve2".into())),
+                ScalarValue::Int32(Some(5)),
+            ])
+            .await
+            .unwrap();
+
+        // the select was planned before the writes, but sees them
+        let results = select
+            .execute(vec![ScalarValue::Int32(Some(5))])
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results[0].column(0).as_string::<i32>(),
+            &StringArray::from(vec!["Eve2"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_params() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // values are bound, never parsed as SQL
+        database
+            .query_params(
+                "insert into users values (?, ?)",
+                &[
+                    ScalarValue::Int32(Some(5)),
+                    ScalarValue::Utf8(Some("Eve'); drop table users; --".into())),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let results = database
+            .query_params(
+                "select name from users where id > $1 and name like $2",
+                &[
+                    // cast to the type of the column
+                    ScalarValue::Int64(Some(4)),
+                    ScalarValue::Utf8(Some("Eve%".into())),
+                ],
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results[0].column(0).as_string::<i32>(),
+            &StringArray::from(vec!["Eve'); drop table users; --"])
+        );
+
+        // every placeholder needs a value
+        assert!(database
+            .query_params("select * from users where id = ?", &[])
+            .await
+            .is_err());
+    }
+}
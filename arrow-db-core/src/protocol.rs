@@ -0,0 +1,7 @@
+//! Names shared by the Arrow Flight server and its clients.
+
+/// Response header carrying the current schema version of the database
+pub const SCHEMA_VERSION_HEADER: &str = "x-arrow-db-schema-version";
+
+/// Request header naming the database of a server with several databases
+pub const DATABASE_HEADER: &str = "x-arrow-db-database";
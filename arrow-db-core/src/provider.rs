@@ -0,0 +1,286 @@
+//! Live table providers.
+//!
+//! Each table is registered with the DataFusion context once, as a `LiveTable`
+//! holding the committed chunks of the table.  Committing a write publishes
+//! the new chunks to the registered provider, which only swaps reference
+//! counted record batches, so writes never copy or re-register the table.
+//!
+//! Scans read the chunks published when they start, skipping chunks whose zone
+//! maps rule out the filters of the query.  The chunks of a spilled table are
+//! streamed from disk.  A table is only
+//! re-registered when its schema or column defaults change, since DataFusion
+//! plans statements with both.
+
+use std::{any::Any, collections::HashMap, sync::Arc, sync::RwLock};
+
+use arrow::array::RecordBatch;
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use datafusion::{
+    catalog::{Session, TableProvider},
+    common::Statistics,
+    error::Result as DataFusionResult,
+    logical_expr::{Expr, TableProviderFilterPushDown, TableType},
+    physical_plan::{memory::MemoryExec, union::UnionExec, ExecutionPlan},
+};
+
+use crate::{
+    spill::SpilledChunks,
+    table::Table,
+    zone::{zone_chunks, ZonedChunk},
+};
+
+/// A table provider that reads the committed chunks of a table
+#[derive(Debug)]
+pub struct LiveTable {
+    schema: SchemaRef,
+    column_defaults: HashMap<String, Expr>,
+    chunks: RwLock<Vec<ZonedChunk>>,
+    spill: RwLock<Option<Arc<SpilledChunks>>>,
+    statistics: RwLock<Option<Statistics>>,
+}
+
+impl LiveTable {
+    /// A provider with the current chunks of a table
+    pub fn new(table: &Table) -> LiveTable {
+        LiveTable {
+            schema: table.schema(),
+            column_defaults: table.column_defaults(),
+            chunks: RwLock::new(zone_chunks(&[], &table.chunks)),
+            spill: RwLock::new(table.spill.to_owned()),
+            statistics: RwLock::new(table_statistics(table)),
+        }
+    }
+
+    /// The chunks published to the provider, not counting spilled chunks
+    pub fn chunks(&self) -> Vec<RecordBatch> {
+        self.chunks
+            .read()
+            .unwrap()
+            .iter()
+            .map(|zoned| zoned.chunk.to_owned())
+            .collect()
+    }
+
+    /// Whether a table can be published to this provider, without planning
+    /// with a different schema or column defaults
+    pub(crate) fn can_publish(&self, table: &Table) -> bool {
+        self.schema == table.schema() && self.column_defaults == table.column_defaults()
+    }
+
+    /// Publish the current chunks of a table to queries
+    pub(crate) fn publish(&self, table: &Table) {
+        let mut chunks = self.chunks.write().unwrap();
+        *chunks = zone_chunks(&chunks, &table.chunks);
+        *self.spill.write().unwrap() = table.spill.to_owned();
+        *self.statistics.write().unwrap() = table_statistics(table);
+    }
+
+    /// The published chunks that can match every filter
+    fn matching_chunks(&self, filters: &[Expr]) -> Vec<RecordBatch> {
+        self.chunks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|zoned| filters.iter().all(|filter| zoned.may_match(filter)))
+            .map(|zoned| zoned.chunk.to_owned())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl TableProvider for LiveTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.to_owned()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn get_column_default(&self, column: &str) -> Option<&Expr> {
+        self.column_defaults.get(column)
+    }
+
+    fn statistics(&self) -> Option<Statistics> {
+        self.statistics.read().unwrap().to_owned()
+    }
+
+    /// Filters only skip chunks, so DataFusion still applies them to the rows
+    /// of the remaining chunks
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(vec![TableProviderFilterPushDown::Inexact; filters.len()])
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let batch_size = state.config_options().execution.batch_size;
+        let batches = split_batches(&self.matching_chunks(filters), batch_size);
+        let exec = Arc::new(MemoryExec::try_new(
+            &[batches],
+            self.schema(),
+            projection.cloned(),
+        )?);
+        let spill = self.spill.read().unwrap().to_owned();
+
+        match spill {
+            Some(spill) => {
+                let spilled = spill
+                    .scan(state, self.schema(), projection, filters)
+                    .await?;
+
+                Ok(Arc::new(UnionExec::new(vec![spilled, exec])))
+            }
+            None => Ok(exec),
+        }
+    }
+}
+
+/// The statistics of a table for the planner, if they were computed
+fn table_statistics(table: &Table) -> Option<Statistics> {
+    table
+        .statistics
+        .as_ref()
+        .map(|statistics| statistics.to_datafusion(table))
+}
+
+/// Split chunks into batches of up to `batch_size` rows, without copying
+fn split_batches(chunks: &[RecordBatch], batch_size: usize) -> Vec<RecordBatch> {
+    let batch_size = batch_size.max(1);
+
+    chunks
+        .iter()
+        .flat_map(|chunk| {
+            let num_rows = chunk.num_rows();
+
+            (0..num_rows)
+                .step_by(batch_size)
+                .map(move |offset| chunk.slice(offset, batch_size.min(num_rows - offset)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::Int32Array;
+    use arrow_schema::DataType;
+    use datafusion::{
+        prelude::{col, lit},
+        scalar::ScalarValue,
+    };
+
+    use crate::{
+        database::{
+            tests::{create_database, seed_database},
+            Database,
+        },
+        get_mut_table,
+    };
+
+    use super::*;
+
+    async fn count_rows(database: &Database) -> usize {
+        database
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_live_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let provider = database.providers.get("users").unwrap().to_owned();
+
+        // writes are published to the registered provider
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        get_mut_table!(database, "users")
+            .unwrap()
+            .append_row(vec![
+                ScalarValue::Int32(Some(6)),
+                ScalarValue::Utf8(Some("Frank".into())),
+            ])
+            .unwrap();
+        assert_eq!(count_rows(&database).await, 5);
+
+        database.commit_table("users").unwrap();
+        assert_eq!(count_rows(&database).await, 6);
+        assert!(Arc::ptr_eq(
+            &provider,
+            &database.providers.get("users").unwrap()
+        ));
+
+        // a new schema registers a new provider
+        get_mut_table!(database, "users")
+            .unwrap()
+            .add_column::<Int32Array>(
+                2,
+                "age",
+                DataType::Int32,
+                Int32Array::from(vec![30; 6]).into(),
+            )
+            .unwrap();
+        database.commit_table("users").unwrap();
+        assert!(!Arc::ptr_eq(
+            &provider,
+            &database.providers.get("users").unwrap()
+        ));
+        assert_eq!(count_rows(&database).await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_zone_map_pruning() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_chunk_size(2)
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        let provider = database.providers.get("users").unwrap().to_owned();
+        assert_eq!(provider.matching_chunks(&[col("id").gt(lit(2))]).len(), 1);
+        assert_eq!(
+            provider
+                .matching_chunks(&[col("id").gt_eq(lit(2)).and(col("id").lt(lit(4)))])
+                .len(),
+            2
+        );
+
+        // the rows of the remaining chunks are still filtered
+        let results = database
+            .query("select id from users where id > 1 and id < 4")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            2
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Query log.
+//!
+//! Every statement run with `query` is recorded in a ring buffer of the most
+//! recent statements, with when it started, how long it took, the rows it
+//! wrote and its error, if it failed.  The log is queryable as the
+//! `system.query_log` table, e.g. to debug a production server:
+//!
+//! ```sql
+//! SELECT sql, duration_ms FROM system.query_log WHERE error IS NOT NULL
+//! ```
+//!
+//! The log can also be appended to a file of newline-delimited JSON, which
+//! keeps every statement after they're dropped from the ring buffer.
+
+use std::{any::Any, collections::VecDeque, sync::Arc, sync::Mutex, time::Duration};
+
+use arrow::array::{
+    Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::{
+    catalog::{Session, TableProvider},
+    catalog_common::MemorySchemaProvider,
+    error::{DataFusionError, Result as DataFusionResult},
+    logical_expr::{Expr, LogicalPlan, TableType},
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+    prelude::DataFrame,
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+/// The default number of statements kept in the query log
+pub const DEFAULT_QUERY_LOG_CAPACITY: usize = 1000;
+
+/// The schema the query log is registered under
+pub const QUERY_LOG_SCHEMA: &str = "system";
+
+/// The name the query log is registered as
+pub const QUERY_LOG_TABLE: &str = "query_log";
+
+/// A statement in the query log
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryLogEntry {
+    pub started_at: DateTime<Utc>,
+    pub sql: String,
+    pub duration: Duration,
+    /// The number of rows written by INSERT, UPDATE, DELETE, COPY and REFRESH
+    /// statements, or `None` for other statements
+    pub rows: Option<u64>,
+    /// The error of a failed statement
+    pub error: Option<String>,
+}
+
+/// A ring buffer of the most recent statements of a database
+#[derive(Debug)]
+pub struct QueryLog {
+    entries: Mutex<VecDeque<QueryLogEntry>>,
+    capacity: Mutex<usize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    file: Mutex<Option<std::fs::File>>,
+}
+
+impl Default for QueryLog {
+    fn default() -> Self {
+        QueryLog::new(DEFAULT_QUERY_LOG_CAPACITY)
+    }
+}
+
+impl QueryLog {
+    /// Create a query log keeping up to `capacity` statements
+    pub fn new(capacity: usize) -> QueryLog {
+        QueryLog {
+            entries: Mutex::new(VecDeque::new()),
+            capacity: Mutex::new(capacity),
+            #[cfg(not(target_arch = "wasm32"))]
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Add a statement to the log, dropping the oldest statement if it's full
+    pub fn record(&self, entry: QueryLogEntry) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.append_to_file(&entry);
+
+        let capacity = *self.capacity.lock().unwrap();
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(entry);
+
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// The statements in the log, oldest first
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Change the number of statements kept, dropping the oldest statements
+    /// past it.  A capacity of zero disables the log.
+    pub fn set_capacity(&self, capacity: usize) {
+        *self.capacity.lock().unwrap() = capacity;
+
+        let mut entries = self.entries.lock().unwrap();
+
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// Remove every statement from the log
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The statements in the log as a record batch with the schema of
+    /// `system.query_log`
+    pub fn record_batch(&self) -> Result<RecordBatch> {
+        let entries = self.entries();
+
+        Table::new_record_batch(
+            query_log_schema(),
+            vec![
+                Arc::new(
+                    TimestampMicrosecondArray::from_iter_values(
+                        entries
+                            .iter()
+                            .map(|entry| entry.started_at.timestamp_micros()),
+                    )
+                    .with_timezone("UTC"),
+                ),
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|entry| entry.sql.as_str()),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    entries
+                        .iter()
+                        .map(|entry| entry.duration.as_secs_f64() * 1000.0),
+                )),
+                Arc::new(
+                    entries
+                        .iter()
+                        .map(|entry| entry.rows)
+                        .collect::<UInt64Array>(),
+                ),
+                Arc::new(
+                    entries
+                        .iter()
+                        .map(|entry| entry.error.as_deref())
+                        .collect::<StringArray>(),
+                ),
+            ],
+        )
+    }
+
+    /// Append every statement recorded from now on to a file of
+    /// newline-delimited JSON
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn persist_to_file(&self, path: &str) -> Result<()> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| DbError::Query("open query log".into(), e.to_string()))?;
+
+        *self.file.lock().unwrap() = Some(file);
+
+        Ok(())
+    }
+
+    /// Append a statement to the file of the log, if it's persisted.  Errors
+    /// are ignored, so the log never fails a statement.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn append_to_file(&self, entry: &QueryLogEntry) {
+        use std::io::Write;
+
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let line = serde_json::json!({
+                "started_at": entry.started_at.to_rfc3339(),
+                "sql": entry.sql,
+                "duration_ms": entry.duration.as_secs_f64() * 1000.0,
+                "rows": entry.rows,
+                "error": entry.error,
+            });
+
+            let _ = writeln!(file, "{line}");
+        }
+    }
+}
+
+/// The schema of `system.query_log`
+pub fn query_log_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "started_at",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("sql", DataType::Utf8, false),
+        Field::new("duration_ms", DataType::Float64, false),
+        Field::new("rows", DataType::UInt64, true),
+        Field::new("error", DataType::Utf8, true),
+    ]))
+}
+
+/// A table provider that reads the query log when a scan starts
+#[derive(Debug)]
+struct QueryLogTable {
+    log: Arc<QueryLog>,
+}
+
+#[async_trait]
+impl TableProvider for QueryLogTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        query_log_schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let record_batch = self
+            .log
+            .record_batch()
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![record_batch]],
+            self.schema(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+impl Database {
+    /// The log of the most recent statements of the database
+    pub fn query_log(&self) -> &QueryLog {
+        &self.query_log
+    }
+
+    /// Register the query log with the context as `system.query_log`
+    pub(crate) fn register_query_log(&self) -> Result<()> {
+        let log_error =
+            |e: DataFusionError| DbError::Query("register query log".into(), e.to_string());
+        let catalog_name = self
+            .ctx
+            .state()
+            .config_options()
+            .catalog
+            .default_catalog
+            .to_owned();
+        let catalog = self.ctx.catalog(&catalog_name).ok_or_else(|| {
+            DbError::Query(
+                "register query log".into(),
+                format!("No catalog {catalog_name}"),
+            )
+        })?;
+
+        if catalog.schema(QUERY_LOG_SCHEMA).is_none() {
+            catalog
+                .register_schema(QUERY_LOG_SCHEMA, Arc::new(MemorySchemaProvider::new()))
+                .map_err(log_error)?;
+        }
+
+        self.ctx
+            .register_table(
+                format!("{QUERY_LOG_SCHEMA}.{QUERY_LOG_TABLE}").as_str(),
+                Arc::new(QueryLogTable {
+                    log: self.query_log.to_owned(),
+                }),
+            )
+            .map_err(log_error)?;
+
+        Ok(())
+    }
+
+    /// Record a statement run with `query` in the query log
+    pub(crate) async fn log_query(
+        &self,
+        sql: &str,
+        started_at: DateTime<Utc>,
+        result: &Result<DataFrame>,
+    ) {
+        let duration = (Utc::now() - started_at).to_std().unwrap_or_default();
+        let rows = match result {
+            Ok(data_frame) => affected_rows(data_frame).await,
+            Err(_) => None,
+        };
+
+        self.query_log.record(QueryLogEntry {
+            started_at,
+            sql: sql.into(),
+            duration,
+            rows,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+    }
+}
+
+/// The number of rows a statement wrote, if its result is the count of a
+/// write, which is a single in-memory batch, so it's cheap to collect
+async fn affected_rows(data_frame: &DataFrame) -> Option<u64> {
+    let fields = data_frame.schema().fields();
+    let is_count = fields.len() == 1
+        && fields[0].name() == "count"
+        && fields[0].data_type() == &DataType::UInt64
+        && matches!(
+            data_frame.logical_plan(),
+            LogicalPlan::TableScan(scan)
+                if scan.table_name.table() == "?table?"
+        );
+
+    if !is_count {
+        return None;
+    }
+
+    let record_batches = data_frame.to_owned().collect().await.ok()?;
+    let counts = record_batches
+        .first()?
+        .column(0)
+        .as_any()
+        .downcast_ref::<UInt64Array>()?;
+
+    counts.iter().next().flatten()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    #[tokio::test]
+    async fn test_query_log() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database.query("select * from users").await.unwrap();
+        assert!(database.query("select * from missing").await.is_err());
+
+        let entries = database.query_log().entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].rows, Some(1));
+        assert_eq!(entries[1].rows, None);
+        assert!(entries[1].error.is_none());
+        assert!(entries[2].error.is_some());
+
+        let record_batches = database
+            .query("select sql from system.query_log where error is not null")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(record_batches[0].num_rows(), 1);
+
+        // the ring buffer keeps the most recent statements
+        database.query_log().set_capacity(2);
+        let entries = database.query_log().entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[1].sql,
+            "select sql from system.query_log where error is not null"
+        );
+
+        let path = std::env::temp_dir().join(format!("arrow-db-query-log-{}", std::process::id()));
+        database
+            .query_log()
+            .persist_to_file(&path.to_string_lossy())
+            .unwrap();
+        database.query("select 1").await.unwrap();
+        let persisted = std::fs::read_to_string(&path).unwrap();
+        assert!(persisted.contains("\"sql\":\"select 1\""));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
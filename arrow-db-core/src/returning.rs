@@ -0,0 +1,158 @@
+//! RETURNING clauses.
+//!
+//! INSERT, UPDATE and DELETE statements with a `RETURNING` clause return the
+//! rows they changed instead of a count: inserted and updated rows as they are
+//! after the statement, and deleted rows as they were before it.  The clause
+//! is evaluated like the projection of a query over the changed rows, so it
+//! can return system columns like `_rowid` or any expression of the columns.
+//!
+//! `RETURNING *` returns every column except the system columns.
+
+use datafusion::{
+    prelude::DataFrame,
+    sql::{
+        parser::Statement,
+        sqlparser::ast::{Delete, Ident, Insert, SelectItem, Statement as SqlStatement},
+    },
+};
+
+use crate::{
+    change::{ChangeKind, TableChange},
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+/// Remove the RETURNING clause of a DML statement, returning its items
+pub(crate) fn take_returning(statement: &mut Statement) -> Option<Vec<SelectItem>> {
+    let Statement::Statement(sql_statement) = statement else {
+        return None;
+    };
+
+    match sql_statement.as_mut() {
+        SqlStatement::Insert(Insert { returning, .. })
+        | SqlStatement::Update { returning, .. }
+        | SqlStatement::Delete(Delete { returning, .. }) => returning.take(),
+        _ => None,
+    }
+}
+
+impl Database {
+    /// Evaluate the items of a RETURNING clause over the rows a DML statement
+    /// changed
+    pub(crate) async fn returning(
+        &self,
+        table_name: &str,
+        change: &TableChange,
+        items: &[SelectItem],
+    ) -> Result<DataFrame> {
+        let event = change.event(table_name, 0)?;
+        let rows = match change.kind {
+            ChangeKind::Insert | ChangeKind::Update => event.after,
+            ChangeKind::Delete => event.before,
+        }
+        .ok_or_else(|| DbError::Dml(format!("No changed rows of Table {table_name}")))?;
+
+        // the rows are queried under the name of the table, so the items can
+        // refer to its columns by qualified name
+        let database = self.read_only_database()?;
+        let mut table = Table::new(table_name);
+        table.set_record_batch(rows);
+        database.tables.insert(table_name.into(), table);
+        database.add_table_context(table_name)?;
+
+        let items = items
+            .iter()
+            .map(|item| item.to_string())
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "SELECT {} FROM {}",
+            items.join(", "),
+            Ident::with_quote('"', table_name)
+        );
+
+        // boxed, since the query is planned by this same function
+        Box::pin(database.query(&sql)).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Int64Array, RecordBatch, StringArray};
+    use arrow::compute::concat_batches;
+    use arrow::datatypes::{Int32Type, Int64Type};
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    async fn collect(database: &Database, sql: &str) -> RecordBatch {
+        let df = database.query(sql).await.unwrap();
+        let schema = Arc::new(df.schema().as_arrow().to_owned());
+
+        concat_batches(&schema, &df.collect().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_returning() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+        database.add_rowid_column("users").unwrap();
+
+        // inserted rows are returned with their new row ids
+        let inserted = collect(
+            &database,
+            "insert into users values (5, 'Eve'), (6, 'Frank') returning _rowid, upper(name)",
+        )
+        .await;
+        assert_eq!(
+            inserted.column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![5, 6])
+        );
+        assert_eq!(
+            inserted.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["EVE", "FRANK"])
+        );
+
+        // rows can be addressed by row id, and RETURNING * hides system columns
+        let updated = collect(
+            &database,
+            "update users set name = 'Eve2' where _rowid = 5 returning *",
+        )
+        .await;
+        assert_eq!(updated.num_columns(), 2);
+        assert_eq!(
+            updated.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Eve2"])
+        );
+
+        // deleted rows are returned as they were, and row ids aren't reused
+        let deleted = collect(
+            &database,
+            "delete from users where id > 4 returning users.id",
+        )
+        .await;
+        assert_eq!(
+            deleted.column(0).as_primitive::<Int32Type>().values(),
+            &[5, 6]
+        );
+
+        database
+            .query("insert into users values (7, 'Grace')")
+            .await
+            .unwrap();
+        let rowids = collect(&database, "select _rowid from users where id = 7").await;
+        assert_eq!(rowids.column(0).as_primitive::<Int64Type>().value(0), 7);
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 5);
+
+        // an empty change returns no rows
+        let none = collect(&database, "delete from users where id > 100 returning id").await;
+        assert_eq!(none.num_rows(), 0);
+    }
+}
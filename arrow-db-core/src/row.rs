@@ -0,0 +1,650 @@
+//! Row operations.
+//!
+//! Arrow is columnar, so operating on a single row touches every column in
+//! the table.  Row values are represented as DataFusion `ScalarValue`s.
+
+use std::{collections::HashSet, sync::Arc};
+
+use arrow::{
+    array::{
+        Array, ArrayRef, AsArray, BinaryArray, BooleanArray, Float64Array, Int16Array, Int32Array,
+        Int64Array, Int8Array, LargeBinaryArray, LargeStringArray, RecordBatch, Scalar,
+        StringArray, Time32MillisecondArray, Time32SecondArray, Time64MicrosecondArray,
+        Time64NanosecondArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+        TimestampNanosecondArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+        UInt8Array,
+    },
+    compute::{cast, concat, filter_record_batch, kernels::zip::zip},
+    datatypes::{
+        DataType, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, Time32MillisecondType,
+        Time32SecondType, Time64MicrosecondType, Time64NanosecondType, TimeUnit,
+        TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+        TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+    },
+};
+use datafusion::{logical_expr::ColumnarValue, scalar::ScalarValue};
+
+use crate::{
+    error::{DbError, Result},
+    table::Table,
+};
+
+impl Table {
+    /// Append a row of values to the table, one value per column.
+    ///
+    /// NULL values in auto-increment columns are replaced with the next
+    /// value of the column's sequence, and NULL timestamp system columns with
+    /// the current time.  Rows that violate the primary key, NOT NULL or CHECK
+    /// constraints are rejected.
+    pub fn append_row(&mut self, mut values: Vec<ScalarValue>) -> Result<()> {
+        let num_columns = self.num_columns();
+
+        if values.len() != num_columns {
+            return Err(DbError::Dml(format!(
+                "Table {} has {num_columns} columns, but {} values were provided",
+                self.name,
+                values.len()
+            )));
+        }
+
+        self.fill_auto_increment(&mut values)?;
+        self.fill_system_columns(&mut values)?;
+
+        let schema = self.schema();
+        let values = schema
+            .fields()
+            .iter()
+            .zip(values.iter())
+            .map(|(field, value)| cast_scalar(value, field.data_type()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let primary_key_index = self.primary_key_index()?;
+
+        if let Some(column_index) = primary_key_index {
+            self.check_primary_key(&values[column_index])?;
+        }
+
+        self.check_constraints(&values)?;
+
+        let columns = values
+            .iter()
+            .map(scalar_to_array_ref)
+            .collect::<Result<Vec<_>>>()?;
+
+        // only the last chunk is rewritten
+        self.append_record_batch(Self::new_record_batch(schema, columns)?)?;
+
+        if let Some(column_index) = primary_key_index {
+            self.insert_primary_key(values[column_index].to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Delete a row from the table
+    pub fn delete_row(&mut self, row: usize) -> Result<()> {
+        self.delete_rows(&[row])
+    }
+
+    /// Delete rows from the table in a single pass, filtering the chunks with
+    /// deleted rows once with a mask of the rows to keep.
+    pub fn delete_rows(&mut self, rows: &[usize]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut keep = vec![true; self.num_rows()];
+
+        for row in rows {
+            *keep.get_mut(*row).ok_or_else(|| {
+                DbError::Dml(format!(
+                    "Row {row} is out of bounds for Table {}",
+                    self.name
+                ))
+            })? = false;
+        }
+
+        let keys = match self.primary_key_index()? {
+            Some(column_index) => rows
+                .iter()
+                .map(|row| self.cell(column_index, *row))
+                .collect::<Result<Vec<_>>>()?,
+            None => vec![],
+        };
+
+        let keep = BooleanArray::from(keep);
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        let mut offset = 0;
+
+        for chunk in self.chunks.iter() {
+            let mask = keep.slice(offset, chunk.num_rows());
+            offset += chunk.num_rows();
+
+            let chunk = match mask.true_count() == chunk.num_rows() {
+                true => chunk.to_owned(),
+                false => filter_record_batch(chunk, &mask)
+                    .map_err(|e| DbError::ArrayData(format!("Error deleting rows: {e}")))?,
+            };
+
+            if chunk.num_rows() > 0 {
+                chunks.push(chunk);
+            }
+        }
+
+        if chunks.is_empty() {
+            chunks.push(RecordBatch::new_empty(self.schema()));
+        }
+
+        self.chunks = chunks;
+
+        for key in keys.iter() {
+            self.remove_primary_key(key);
+        }
+
+        let mut rows = rows.to_vec();
+        rows.sort_unstable();
+        rows.dedup();
+        self.index_deleted(&rows);
+
+        Ok(())
+    }
+
+    /// Update a row with a new value per assigned column
+    pub fn update_row(&mut self, row: usize, assignments: &[(usize, ScalarValue)]) -> Result<()> {
+        self.update_rows(&[row], assignments)
+    }
+
+    /// Update rows with a new value per assigned column
+    pub fn update_rows(
+        &mut self,
+        rows: &[usize],
+        assignments: &[(usize, ScalarValue)],
+    ) -> Result<()> {
+        let assignments = assignments
+            .iter()
+            .map(|(column_index, value)| (*column_index, ColumnarValue::Scalar(value.to_owned())))
+            .collect::<Vec<_>>();
+
+        self.update_rows_columnar(rows, &assignments)
+    }
+
+    /// Update rows with new values per assigned column.
+    ///
+    /// A value is either a scalar written to every updated row, or an array
+    /// with a value for every row of the table, of which only the values of
+    /// the updated rows are written.  The updated rows are checked against the
+    /// constraints of the table before anything is written.  Each assigned
+    /// column is then rewritten once, zipping the new values into the updated
+    /// rows with a mask.
+    pub fn update_rows_columnar(
+        &mut self,
+        rows: &[usize],
+        assignments: &[(usize, ColumnarValue)],
+    ) -> Result<()> {
+        if rows.is_empty() || assignments.is_empty() {
+            return Ok(());
+        }
+
+        let num_rows = self.num_rows();
+        let schema = self.schema();
+        let mut mask = vec![false; num_rows];
+
+        for row in rows {
+            *mask.get_mut(*row).ok_or_else(|| {
+                DbError::Dml(format!(
+                    "Row {row} is out of bounds for Table {}",
+                    self.name
+                ))
+            })? = true;
+        }
+
+        let assignments = assignments
+            .iter()
+            .map(|(column_index, value)| {
+                let field = schema.fields().get(*column_index).ok_or_else(|| {
+                    DbError::Dml(format!(
+                        "Column {column_index} is out of bounds for Table {}",
+                        self.name
+                    ))
+                })?;
+
+                let value = match value {
+                    ColumnarValue::Scalar(value) => {
+                        ColumnarValue::Scalar(cast_scalar(value, field.data_type())?)
+                    }
+                    ColumnarValue::Array(values) if values.len() != num_rows => {
+                        return Err(DbError::Dml(format!(
+                            "Table {} has {num_rows} rows, but {} values were provided",
+                            self.name,
+                            values.len()
+                        )))
+                    }
+                    ColumnarValue::Array(values) => ColumnarValue::Array(
+                        cast(values, field.data_type())
+                            .map_err(|e| DbError::DataType(e.to_string()))?,
+                    ),
+                };
+
+                Ok((*column_index, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let primary_key = match self.primary_key_index()? {
+            Some(column_index) => assignments
+                .iter()
+                .find(|(assigned, _)| *assigned == column_index)
+                .map(|(_, keys)| (column_index, keys)),
+            None => None,
+        };
+
+        // the current and new keys of the updated rows whose key changes
+        let mut changed_keys = vec![];
+
+        if let Some((column_index, keys)) = primary_key {
+            let mut new_keys = HashSet::with_capacity(rows.len());
+
+            for row in rows {
+                let current = self.cell(column_index, *row)?;
+                let key = columnar_value(keys, *row)?;
+
+                if key.is_null() {
+                    self.check_primary_key(&key)?;
+                }
+
+                if !new_keys.insert(key.to_owned()) {
+                    return Err(DbError::ConstraintViolation(format!(
+                        "Duplicate primary key {key} for column {} of Table {}",
+                        schema.field(column_index).name(),
+                        self.name
+                    )));
+                }
+
+                if current != key {
+                    changed_keys.push((current, key));
+                }
+            }
+
+            // keys released by other updated rows can be reused
+            let released = changed_keys
+                .iter()
+                .map(|(current, _)| current)
+                .collect::<HashSet<_>>();
+
+            for (_, key) in changed_keys.iter() {
+                if !released.contains(key) {
+                    self.check_primary_key(key)?;
+                }
+            }
+        }
+
+        if !self.not_null.is_empty() || !self.checks.is_empty() {
+            for row in rows {
+                let mut values = self.row_values(*row)?;
+
+                for (column_index, value) in assignments.iter() {
+                    values[*column_index] = columnar_value(value, *row)?;
+                }
+
+                self.check_constraints(&values)?;
+            }
+        }
+
+        let index_changes = self.index_changes(rows, &assignments)?;
+        let mask = BooleanArray::from(mask);
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        let mut offset = 0;
+
+        for chunk in self.chunks.iter() {
+            let length = chunk.num_rows();
+            let chunk_mask = mask.slice(offset, length);
+
+            // chunks without updated rows are kept as they are
+            if chunk_mask.true_count() == 0 {
+                chunks.push(chunk.to_owned());
+                offset += length;
+                continue;
+            }
+
+            let mut columns = chunk.columns().to_vec();
+
+            for (column_index, value) in assignments.iter() {
+                let updated = match value {
+                    ColumnarValue::Scalar(value) => {
+                        let value = Scalar::new(scalar_to_array_ref(value)?);
+                        zip(&chunk_mask, &value, &columns[*column_index])
+                    }
+                    ColumnarValue::Array(values) => zip(
+                        &chunk_mask,
+                        &values.slice(offset, length),
+                        &columns[*column_index],
+                    ),
+                };
+
+                columns[*column_index] =
+                    updated.map_err(|e| DbError::ArrayData(format!("Error updating rows: {e}")))?;
+            }
+
+            chunks.push(Self::new_record_batch(schema.to_owned(), columns)?);
+            offset += length;
+        }
+
+        self.chunks = chunks;
+
+        for (current, _) in changed_keys.iter() {
+            self.remove_primary_key(current);
+        }
+
+        for (_, key) in changed_keys {
+            self.insert_primary_key(key);
+        }
+
+        self.index_updated(index_changes);
+
+        Ok(())
+    }
+
+    /// Set the value of a single cell in the table.
+    ///
+    /// Non-null primitive values are written in place, while other values
+    /// are spliced into a rebuilt column to keep the validity buffer intact.
+    pub fn apply_set_assignment(
+        &mut self,
+        row: usize,
+        column_index: usize,
+        value: &ScalarValue,
+    ) -> Result<()> {
+        let column = self.column(column_index)?;
+        let value = cast_scalar(value, column.data_type())?;
+        let index_changes = self.index_changes(
+            &[row],
+            &[(column_index, ColumnarValue::Scalar(value.to_owned()))],
+        )?;
+
+        if self.primary_key_index()? == Some(column_index) {
+            let key = get_column_value(&column, row)?;
+
+            if key != value {
+                self.check_primary_key(&value)?;
+                self.set_column_value(row, column_index, &column, &value)?;
+                self.remove_primary_key(&key);
+                self.insert_primary_key(value);
+                self.index_updated(index_changes);
+            }
+
+            return Ok(());
+        }
+
+        self.set_column_value(row, column_index, &column, &value)?;
+        self.index_updated(index_changes);
+
+        Ok(())
+    }
+
+    /// Write an already cast value to a cell
+    fn set_column_value(
+        &mut self,
+        row: usize,
+        column_index: usize,
+        column: &ArrayRef,
+        value: &ScalarValue,
+    ) -> Result<()> {
+        if value.is_null() {
+            return self.replace_column_data(column_index, splice_value(column, row, value)?);
+        }
+
+        let data = scalar_to_array_ref(value)?.to_data();
+
+        match column.data_type() {
+            DataType::Int8 => self.update_column_data::<Int8Array>(column_index, row, data),
+            DataType::Int16 => self.update_column_data::<Int16Array>(column_index, row, data),
+            DataType::Int32 => self.update_column_data::<Int32Array>(column_index, row, data),
+            DataType::Int64 => self.update_column_data::<Int64Array>(column_index, row, data),
+            DataType::UInt8 => self.update_column_data::<UInt8Array>(column_index, row, data),
+            DataType::UInt16 => self.update_column_data::<UInt16Array>(column_index, row, data),
+            DataType::UInt32 => self.update_column_data::<UInt32Array>(column_index, row, data),
+            DataType::UInt64 => self.update_column_data::<UInt64Array>(column_index, row, data),
+            DataType::Float64 => self.update_column_data::<Float64Array>(column_index, row, data),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                self.update_column_data::<TimestampSecondArray>(column_index, row, data)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                self.update_column_data::<TimestampMillisecondArray>(column_index, row, data)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                self.update_column_data::<TimestampMicrosecondArray>(column_index, row, data)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                self.update_column_data::<TimestampNanosecondArray>(column_index, row, data)
+            }
+            DataType::Time32(TimeUnit::Second) => {
+                self.update_column_data::<Time32SecondArray>(column_index, row, data)
+            }
+            DataType::Time32(TimeUnit::Millisecond) => {
+                self.update_column_data::<Time32MillisecondArray>(column_index, row, data)
+            }
+            DataType::Time64(TimeUnit::Microsecond) => {
+                self.update_column_data::<Time64MicrosecondArray>(column_index, row, data)
+            }
+            DataType::Time64(TimeUnit::Nanosecond) => {
+                self.update_column_data::<Time64NanosecondArray>(column_index, row, data)
+            }
+            DataType::Utf8 => self.update_column_data::<StringArray>(column_index, row, data),
+            DataType::LargeUtf8 => {
+                self.update_column_data::<LargeStringArray>(column_index, row, data)
+            }
+            DataType::Binary => self.update_column_data::<BinaryArray>(column_index, row, data),
+            DataType::LargeBinary => {
+                self.update_column_data::<LargeBinaryArray>(column_index, row, data)
+            }
+            data_type => Err(DbError::DataType(format!(
+                "Updating {data_type} columns is not supported"
+            ))),
+        }
+    }
+}
+
+/// Rebuild a column with the value at a row replaced
+fn splice_value(column: &ArrayRef, row: usize, value: &ScalarValue) -> Result<ArrayRef> {
+    let value = scalar_to_array_ref(value)?;
+    let before = column.slice(0, row);
+    let after = column.slice(row + 1, column.len() - row - 1);
+
+    concat(&[before.as_ref(), value.as_ref(), after.as_ref()])
+        .map_err(|e| DbError::ArrayData(format!("Error updating row: {e}")))
+}
+
+/// Get the value of a scalar or array at a row
+pub(crate) fn columnar_value(value: &ColumnarValue, row: usize) -> Result<ScalarValue> {
+    match value {
+        ColumnarValue::Scalar(value) => Ok(value.to_owned()),
+        ColumnarValue::Array(values) => get_column_value(values, row),
+    }
+}
+
+/// Cast a value to the type of the column it is written to, so untyped NULLs
+/// become NULLs of the column type.
+pub(crate) fn cast_scalar(value: &ScalarValue, data_type: &DataType) -> Result<ScalarValue> {
+    if &value.data_type() == data_type {
+        return Ok(value.to_owned());
+    }
+
+    value
+        .cast_to(data_type)
+        .map_err(|e| DbError::DataType(format!("Cannot write {value:?} to {data_type}: {e}")))
+}
+
+/// Get the value of a column at a given row
+pub fn get_column_value(array: &ArrayRef, row: usize) -> Result<ScalarValue> {
+    let is_valid = array.is_valid(row);
+
+    let value = match array.data_type() {
+        DataType::Int8 => {
+            ScalarValue::Int8(is_valid.then(|| array.as_primitive::<Int8Type>().value(row)))
+        }
+        DataType::Int16 => {
+            ScalarValue::Int16(is_valid.then(|| array.as_primitive::<Int16Type>().value(row)))
+        }
+        DataType::Int32 => {
+            ScalarValue::Int32(is_valid.then(|| array.as_primitive::<Int32Type>().value(row)))
+        }
+        DataType::Int64 => {
+            ScalarValue::Int64(is_valid.then(|| array.as_primitive::<Int64Type>().value(row)))
+        }
+        DataType::UInt8 => {
+            ScalarValue::UInt8(is_valid.then(|| array.as_primitive::<UInt8Type>().value(row)))
+        }
+        DataType::UInt16 => {
+            ScalarValue::UInt16(is_valid.then(|| array.as_primitive::<UInt16Type>().value(row)))
+        }
+        DataType::UInt32 => {
+            ScalarValue::UInt32(is_valid.then(|| array.as_primitive::<UInt32Type>().value(row)))
+        }
+        DataType::UInt64 => {
+            ScalarValue::UInt64(is_valid.then(|| array.as_primitive::<UInt64Type>().value(row)))
+        }
+        DataType::Float64 => {
+            ScalarValue::Float64(is_valid.then(|| array.as_primitive::<Float64Type>().value(row)))
+        }
+        DataType::Timestamp(TimeUnit::Second, timezone) => ScalarValue::TimestampSecond(
+            is_valid.then(|| array.as_primitive::<TimestampSecondType>().value(row)),
+            timezone.to_owned(),
+        ),
+        DataType::Timestamp(TimeUnit::Millisecond, timezone) => ScalarValue::TimestampMillisecond(
+            is_valid.then(|| array.as_primitive::<TimestampMillisecondType>().value(row)),
+            timezone.to_owned(),
+        ),
+        DataType::Timestamp(TimeUnit::Microsecond, timezone) => ScalarValue::TimestampMicrosecond(
+            is_valid.then(|| array.as_primitive::<TimestampMicrosecondType>().value(row)),
+            timezone.to_owned(),
+        ),
+        DataType::Timestamp(TimeUnit::Nanosecond, timezone) => ScalarValue::TimestampNanosecond(
+            is_valid.then(|| array.as_primitive::<TimestampNanosecondType>().value(row)),
+            timezone.to_owned(),
+        ),
+        DataType::Time32(TimeUnit::Second) => ScalarValue::Time32Second(
+            is_valid.then(|| array.as_primitive::<Time32SecondType>().value(row)),
+        ),
+        DataType::Time32(TimeUnit::Millisecond) => ScalarValue::Time32Millisecond(
+            is_valid.then(|| array.as_primitive::<Time32MillisecondType>().value(row)),
+        ),
+        DataType::Time64(TimeUnit::Microsecond) => ScalarValue::Time64Microsecond(
+            is_valid.then(|| array.as_primitive::<Time64MicrosecondType>().value(row)),
+        ),
+        DataType::Time64(TimeUnit::Nanosecond) => ScalarValue::Time64Nanosecond(
+            is_valid.then(|| array.as_primitive::<Time64NanosecondType>().value(row)),
+        ),
+        DataType::Utf8 => {
+            ScalarValue::Utf8(is_valid.then(|| array.as_string::<i32>().value(row).to_string()))
+        }
+        data_type => {
+            return Err(DbError::DataType(format!(
+                "Reading {data_type} values is not supported"
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+/// Convert a scalar value, which may be NULL, into a single element array
+pub fn scalar_to_array_ref(value: &ScalarValue) -> Result<ArrayRef> {
+    let array: ArrayRef = match value {
+        ScalarValue::Int8(value) => Arc::new(Int8Array::from(vec![*value])),
+        ScalarValue::Int16(value) => Arc::new(Int16Array::from(vec![*value])),
+        ScalarValue::Int32(value) => Arc::new(Int32Array::from(vec![*value])),
+        ScalarValue::Int64(value) => Arc::new(Int64Array::from(vec![*value])),
+        ScalarValue::UInt8(value) => Arc::new(UInt8Array::from(vec![*value])),
+        ScalarValue::UInt16(value) => Arc::new(UInt16Array::from(vec![*value])),
+        ScalarValue::UInt32(value) => Arc::new(UInt32Array::from(vec![*value])),
+        ScalarValue::UInt64(value) => Arc::new(UInt64Array::from(vec![*value])),
+        ScalarValue::Float64(value) => Arc::new(Float64Array::from(vec![*value])),
+        ScalarValue::TimestampSecond(value, timezone) => Arc::new(
+            TimestampSecondArray::from(vec![*value]).with_timezone_opt(timezone.to_owned()),
+        ),
+        ScalarValue::TimestampMillisecond(value, timezone) => Arc::new(
+            TimestampMillisecondArray::from(vec![*value]).with_timezone_opt(timezone.to_owned()),
+        ),
+        ScalarValue::TimestampMicrosecond(value, timezone) => Arc::new(
+            TimestampMicrosecondArray::from(vec![*value]).with_timezone_opt(timezone.to_owned()),
+        ),
+        ScalarValue::TimestampNanosecond(value, timezone) => Arc::new(
+            TimestampNanosecondArray::from(vec![*value]).with_timezone_opt(timezone.to_owned()),
+        ),
+        ScalarValue::Time32Second(value) => Arc::new(Time32SecondArray::from(vec![*value])),
+        ScalarValue::Time32Millisecond(value) => {
+            Arc::new(Time32MillisecondArray::from(vec![*value]))
+        }
+        ScalarValue::Time64Microsecond(value) => {
+            Arc::new(Time64MicrosecondArray::from(vec![*value]))
+        }
+        ScalarValue::Time64Nanosecond(value) => Arc::new(Time64NanosecondArray::from(vec![*value])),
+        ScalarValue::Utf8(value) => Arc::new(StringArray::from(vec![value.as_deref()])),
+        value => return Err(DbError::DataType(format!("Unsupported value {value:?}"))),
+    };
+
+    Ok(array)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+    use crate::get_mut_table;
+
+    use super::*;
+
+    #[test]
+    fn test_row_operations() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        let mut table = get_mut_table!(database, "users").unwrap();
+
+        table
+            .append_row(vec![
+                ScalarValue::Int32(Some(5)),
+                ScalarValue::Utf8(Some("Eve".into())),
+            ])
+            .unwrap();
+        assert_eq!(table.num_rows(), 5);
+
+        table
+            .apply_set_assignment(4, 1, &ScalarValue::Utf8(Some("Eve2".into())))
+            .unwrap();
+        assert_eq!(
+            table.cell(1, 4).unwrap(),
+            ScalarValue::Utf8(Some("Eve2".into()))
+        );
+
+        table
+            .update_rows(&[1, 3], &[(1, ScalarValue::Utf8(None))])
+            .unwrap();
+        assert_eq!(table.column(1).unwrap().null_count(), 2);
+        assert!(table.column(1).unwrap().is_null(3));
+        assert!(table.column(1).unwrap().is_valid(2));
+
+        // updated rows can't share a primary key
+        table.set_primary_key("id").unwrap();
+        assert!(table
+            .update_rows(&[1, 3], &[(0, ScalarValue::Int32(Some(9)))])
+            .is_err());
+        table
+            .update_rows(&[1], &[(0, ScalarValue::Int64(Some(9)))])
+            .unwrap();
+        assert_eq!(table.cell(0, 1).unwrap(), ScalarValue::Int32(Some(9)));
+        assert!(table
+            .update_rows(&[3], &[(0, ScalarValue::Int32(Some(9)))])
+            .is_err());
+
+        // the row updated to 9 moves up to the first row
+        table.delete_row(0).unwrap();
+        assert_eq!(table.num_rows(), 4);
+        assert_eq!(table.cell(0, 0).unwrap(), ScalarValue::Int32(Some(9)));
+
+        table.delete_rows(&[0, 2]).unwrap();
+        assert_eq!(table.num_rows(), 2);
+        assert_eq!(table.cell(0, 1).unwrap(), ScalarValue::Int32(Some(5)));
+        assert!(table.delete_rows(&[2]).is_err());
+
+        // every column needs a value
+        assert!(table.append_row(vec![ScalarValue::Int32(Some(6))]).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Sequence operations.
+//!
+//! A sequence allocates monotonically increasing values for an auto-increment
+//! column, so the column can be omitted from INSERTs.  Allocation is atomic,
+//! so concurrent inserts never receive the same value.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use arrow::{
+    array::AsArray,
+    compute::{cast, max},
+    datatypes::{DataType, Int64Type},
+};
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    error::{DbError, Result},
+    table::Table,
+};
+
+#[derive(Debug)]
+pub struct Sequence {
+    next: AtomicI64,
+}
+
+impl Sequence {
+    /// Create a sequence that starts at `start`
+    pub fn new(start: i64) -> Sequence {
+        Sequence {
+            next: AtomicI64::new(start),
+        }
+    }
+
+    /// Allocate the next value of the sequence
+    pub fn next_value(&self) -> i64 {
+        self.next.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// The value that will be allocated next
+    pub fn peek(&self) -> i64 {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// Ensure the sequence never allocates `value` or anything below it
+    pub fn advance_past(&self, value: i64) {
+        self.next
+            .fetch_max(value.saturating_add(1), Ordering::SeqCst);
+    }
+}
+
+/// Cloned sequences continue from the same value, but allocate independently
+impl Clone for Sequence {
+    fn clone(&self) -> Self {
+        Sequence::new(self.peek())
+    }
+}
+
+impl PartialEq for Sequence {
+    fn eq(&self, other: &Self) -> bool {
+        self.peek() == other.peek()
+    }
+}
+
+impl Table {
+    /// Declare an integer column as auto-increment.
+    ///
+    /// The sequence starts after the largest value already in the column.
+    pub fn set_auto_increment(&mut self, column_name: &str) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+        let column = self.column(column_index)?;
+
+        if !column.data_type().is_integer() {
+            return Err(DbError::DataType(format!(
+                "Auto-increment column {column_name} must be an integer, not {}",
+                column.data_type()
+            )));
+        }
+
+        let values = cast(&column, &DataType::Int64)
+            .map_err(|e| DbError::DataType(format!("Error reading {column_name}: {e}")))?;
+        let start = max(values.as_primitive::<Int64Type>()).map_or(1, |max| max + 1);
+
+        self.sequences
+            .insert(column_name.to_string(), Sequence::new(start));
+
+        Ok(())
+    }
+
+    /// Get the index of a column by name
+    pub fn column_index(&self, column_name: &str) -> Result<usize> {
+        self.schema()
+            .index_of(column_name)
+            .map_err(|_| DbError::ColumnNotFound(column_name.into(), self.name.to_owned()))
+    }
+
+    /// Fill NULL values of auto-increment columns in a row with the next
+    /// value of their sequence.
+    ///
+    /// Explicit values advance the sequence past them, so later allocations
+    /// never collide with them.
+    pub fn fill_auto_increment(&self, values: &mut [ScalarValue]) -> Result<()> {
+        for (column_name, sequence) in self.sequences.iter() {
+            let column_index = self.column_index(column_name)?;
+            let value = &mut values[column_index];
+
+            if value.is_null() {
+                *value = ScalarValue::Int64(Some(sequence.next_value()));
+            } else {
+                let explicit = value
+                    .cast_to(&DataType::Int64)
+                    .map_err(|e| DbError::DataType(e.to_string()))?;
+
+                if let ScalarValue::Int64(Some(explicit)) = explicit {
+                    sequence.advance_past(explicit);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::Int32Array;
+    use arrow::datatypes::Int32Type;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_concurrent_sequence() {
+        let sequence = Arc::new(Sequence::new(1));
+
+        let mut values = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| {
+                    let sequence = sequence.clone();
+                    scope.spawn(move || (0..100).map(|_| sequence.next_value()).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        values.sort();
+        assert_eq!(values, (1..=400).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_auto_increment_insert() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_auto_increment("id")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users (name) values ('Eve'), ('Frank')")
+            .await
+            .unwrap();
+
+        // explicit values advance the sequence
+        database
+            .query("insert into users values (10, 'Grace')")
+            .await
+            .unwrap();
+        database
+            .query("insert into users (name) values ('Heidi')")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        assert_eq!(
+            table.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 2, 3, 4, 5, 6, 10, 11])
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Approximate aggregate sketches.
+//!
+//! DataFusion's `approx_distinct` and `approx_percentile_cont` scan every row
+//! of a table on each query.  A sketch of a table instead keeps a
+//! HyperLogLog of the distinct values and a uniform sample of the numeric
+//! values of some of its columns, so dashboards over huge tables get
+//! cardinality and percentile answers without a scan:
+//!
+//! ```sql
+//! SELECT sketch_distinct('events', 'user_id'), sketch_percentile('events', 'latency', 0.99)
+//! ```
+//!
+//! Sketches are maintained by committed statements and bulk loads: inserted
+//! rows are added to them, and they're rebuilt after updates and deletes,
+//! which they can't subtract.  Writes through the column or row API are
+//! picked up the next time the sketch is read with `Database::sketch`.
+
+use std::{
+    any::Any,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, RecordBatch},
+    compute::cast,
+    datatypes::{DataType, Float64Type},
+};
+use dashmap::DashMap;
+use datafusion::{
+    error::{DataFusionError, Result as DataFusionResult},
+    logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility},
+    prelude::SessionContext,
+    scalar::ScalarValue,
+};
+
+use crate::{
+    change::{ChangeKind, TableChange},
+    chunk::take_rows,
+    database::Database,
+    error::{DbError, Result},
+    get_table,
+    row::get_column_value,
+    table::Table,
+};
+
+/// The bits of the hash that pick a HyperLogLog register, for 4096
+/// registers and a standard error of about 1.6%
+const HLL_PRECISION: u32 = 12;
+
+/// The number of values sampled to estimate percentiles
+const PERCENTILE_SAMPLE_SIZE: usize = 4096;
+
+/// The sketches of tables, keyed by table name, shared with the SQL
+/// functions that read them
+pub(crate) type Sketches = Arc<DashMap<String, TableSketch>>;
+
+/// A HyperLogLog estimate of the number of distinct values
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+}
+
+impl HyperLogLog {
+    /// Add a value to the estimate
+    pub fn add(&mut self, value: &impl Hash) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let register = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION).leading_zeros() + 1).min(64 - HLL_PRECISION + 1);
+
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+
+    /// The estimated number of distinct values added
+    pub fn estimate(&self) -> u64 {
+        let registers = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let sum = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-(*rank as i32)))
+            .sum::<f64>();
+        let estimate = alpha * registers * registers / sum;
+
+        // small cardinalities are counted from the empty registers
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+
+        match estimate <= 2.5 * registers && empty > 0 {
+            true => (registers * (registers / empty as f64).ln()).round() as u64,
+            false => estimate.round() as u64,
+        }
+    }
+}
+
+/// A uniform sample of numeric values for estimating percentiles.
+///
+/// Each value is given a random priority from a hash of its position, and
+/// the values with the smallest priorities are kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PercentileSample {
+    seen: u64,
+    sample: BTreeMap<(u64, u64), f64>,
+}
+
+impl PercentileSample {
+    /// Add a value to the sample
+    pub fn add(&mut self, value: f64) {
+        let mut hasher = DefaultHasher::new();
+        self.seen.hash(&mut hasher);
+        self.sample.insert((hasher.finish(), self.seen), value);
+        self.seen += 1;
+
+        if self.sample.len() > PERCENTILE_SAMPLE_SIZE {
+            self.sample.pop_last();
+        }
+    }
+
+    /// The estimated value at a percentile between 0 and 1, interpolated
+    /// between the closest sampled values, or `None` if nothing was sampled
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        let mut values = self.sample.values().copied().collect::<Vec<_>>();
+        values.sort_by(f64::total_cmp);
+
+        let last = values.len().checked_sub(1)?;
+        let rank = percentile.clamp(0.0, 1.0) * last as f64;
+        let (lower, upper) = (values[rank.floor() as usize], values[rank.ceil() as usize]);
+
+        Some(lower + (upper - lower) * rank.fract())
+    }
+}
+
+/// The sketch of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSketch {
+    pub column_name: String,
+    pub distinct: HyperLogLog,
+    /// The sample of the values of numeric columns
+    pub percentiles: Option<PercentileSample>,
+}
+
+impl ColumnSketch {
+    fn new(column_name: &str, data_type: &DataType) -> ColumnSketch {
+        ColumnSketch {
+            column_name: column_name.into(),
+            distinct: HyperLogLog::default(),
+            percentiles: data_type.is_numeric().then(PercentileSample::default),
+        }
+    }
+
+    /// Add the non-null values of a column to the sketch
+    fn add_column(&mut self, column: &ArrayRef) -> Result<()> {
+        for row in 0..column.len() {
+            if !column.is_null(row) {
+                self.distinct.add(&get_column_value(column, row)?);
+            }
+        }
+
+        if let Some(percentiles) = self.percentiles.as_mut() {
+            let values = cast(column, &DataType::Float64)
+                .map_err(|e| DbError::DataType(format!("Error sketching column: {e}")))?;
+
+            for value in values.as_primitive::<Float64Type>().iter().flatten() {
+                percentiles.add(value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The sketches of columns of a table at a version
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSketch {
+    /// The version of the table the sketches are current for
+    pub version: u64,
+    pub columns: Vec<ColumnSketch>,
+}
+
+impl TableSketch {
+    /// Sketch columns of a table.  Columns the table doesn't have, e.g.
+    /// because they were dropped, are left out.
+    fn build(table: &Table, column_names: &[String]) -> Result<TableSketch> {
+        let schema = table.schema();
+        let mut sketch = TableSketch {
+            version: table.version,
+            columns: column_names
+                .iter()
+                .filter_map(|column_name| {
+                    let field = schema.field_with_name(column_name).ok()?;
+                    Some(ColumnSketch::new(column_name, field.data_type()))
+                })
+                .collect(),
+        };
+
+        sketch.add_chunks(&table.chunks)?;
+
+        Ok(sketch)
+    }
+
+    /// Add the rows of chunks to the sketches
+    fn add_chunks(&mut self, chunks: &[RecordBatch]) -> Result<()> {
+        for chunk in chunks {
+            for column in self.columns.iter_mut() {
+                if let Some(values) = chunk.column_by_name(&column.column_name) {
+                    column.add_column(values)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The sketch of a column
+    pub fn column(&self, column_name: &str) -> Option<&ColumnSketch> {
+        self.columns
+            .iter()
+            .find(|column| column.column_name == column_name)
+    }
+
+    fn column_names(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| column.column_name.to_owned())
+            .collect()
+    }
+}
+
+impl Database {
+    /// Sketch columns of a table, replacing any sketch of the table
+    pub fn create_sketch(&self, table_name: &str, column_names: &[&str]) -> Result<TableSketch> {
+        let sketch = {
+            let table = get_table!(self, table_name)?;
+
+            for column_name in column_names {
+                table.column_index(column_name)?;
+            }
+
+            let column_names = column_names
+                .iter()
+                .map(|column_name| column_name.to_string())
+                .collect::<Vec<_>>();
+
+            TableSketch::build(&table, &column_names)?
+        };
+
+        self.sketches.insert(table_name.into(), sketch.to_owned());
+
+        Ok(sketch)
+    }
+
+    /// Stop maintaining the sketch of a table
+    pub fn drop_sketch(&self, table_name: &str) -> Result<()> {
+        self.sketches
+            .remove(table_name)
+            .map(|_| ())
+            .ok_or_else(|| DbError::SketchNotFound(table_name.into()))
+    }
+
+    /// The sketch of a table, rebuilt if the table was written to since it
+    /// was last maintained
+    pub fn sketch(&self, table_name: &str) -> Result<Option<TableSketch>> {
+        let Some(sketch) = self
+            .sketches
+            .get(table_name)
+            .map(|sketch| sketch.to_owned())
+        else {
+            return Ok(None);
+        };
+
+        let table = get_table!(self, table_name)?;
+
+        if sketch.version == table.version {
+            return Ok(Some(sketch));
+        }
+
+        let sketch = TableSketch::build(&table, &sketch.column_names())?;
+        drop(table);
+        self.sketches.insert(table_name.into(), sketch.to_owned());
+
+        Ok(Some(sketch))
+    }
+
+    /// Apply a committed change to the sketch of a table, adding inserted
+    /// rows and rebuilding it after other changes
+    pub(crate) fn update_sketch(
+        &self,
+        table_name: &str,
+        version: u64,
+        change: &TableChange,
+    ) -> Result<()> {
+        let column_names = {
+            let Some(mut sketch) = self.sketches.get_mut(table_name) else {
+                return Ok(());
+            };
+
+            if change.kind == ChangeKind::Insert && sketch.version + 1 == version {
+                if !change.rows.is_empty() {
+                    sketch.add_chunks(&[take_rows(&change.after, &change.rows)?])?;
+                }
+
+                sketch.version = version;
+                return Ok(());
+            }
+
+            sketch.column_names()
+        };
+
+        let sketch = TableSketch::build(&*get_table!(self, table_name)?, &column_names)?;
+        self.sketches.insert(table_name.into(), sketch);
+
+        Ok(())
+    }
+}
+
+/// Register `sketch_distinct` and `sketch_percentile` with a context, reading
+/// the sketches of a database
+pub(crate) fn register_sketch_functions(ctx: &SessionContext, sketches: &Sketches) {
+    ctx.register_udf(ScalarUDF::new_from_impl(SketchFunction::new(
+        "sketch_distinct",
+        vec![DataType::Utf8, DataType::Utf8],
+        sketches,
+    )));
+    ctx.register_udf(ScalarUDF::new_from_impl(SketchFunction::new(
+        "sketch_percentile",
+        vec![DataType::Utf8, DataType::Utf8, DataType::Float64],
+        sketches,
+    )));
+}
+
+/// A SQL function answered from the sketch of a column, whose table and
+/// column are given as string literals
+#[derive(Debug)]
+struct SketchFunction {
+    name: &'static str,
+    signature: Signature,
+    sketches: Sketches,
+}
+
+impl SketchFunction {
+    fn new(name: &'static str, arg_types: Vec<DataType>, sketches: &Sketches) -> SketchFunction {
+        SketchFunction {
+            name,
+            // the answer changes as the table is written to
+            signature: Signature::exact(arg_types, Volatility::Volatile),
+            sketches: sketches.to_owned(),
+        }
+    }
+}
+
+impl ScalarUDFImpl for SketchFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        match arg_types.len() {
+            2 => Ok(DataType::UInt64),
+            _ => Ok(DataType::Float64),
+        }
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> DataFusionResult<ColumnarValue> {
+        let sketch_error = |message: String| DataFusionError::Execution(message);
+        let literal = |index: usize| match &args[index] {
+            ColumnarValue::Scalar(ScalarValue::Utf8(Some(value))) => Ok(value.to_owned()),
+            _ => Err(sketch_error(format!(
+                "{} requires a table and column name",
+                self.name
+            ))),
+        };
+        let (table_name, column_name) = (literal(0)?, literal(1)?);
+
+        let sketch = self
+            .sketches
+            .get(&table_name)
+            .ok_or_else(|| sketch_error(format!("Table {table_name} has no sketch")))?;
+        let column = sketch.column(&column_name).ok_or_else(|| {
+            sketch_error(format!(
+                "Column {column_name} of {table_name} isn't sketched"
+            ))
+        })?;
+
+        let value = match args.get(2) {
+            None => ScalarValue::UInt64(Some(column.distinct.estimate())),
+            Some(ColumnarValue::Scalar(ScalarValue::Float64(percentile))) => {
+                let percentiles = column.percentiles.as_ref().ok_or_else(|| {
+                    sketch_error(format!(
+                        "Column {column_name} of {table_name} isn't numeric"
+                    ))
+                })?;
+
+                ScalarValue::Float64(
+                    percentile.and_then(|percentile| percentiles.percentile(percentile)),
+                )
+            }
+            Some(_) => {
+                return Err(sketch_error(format!(
+                    "{} requires a literal percentile",
+                    self.name
+                )))
+            }
+        };
+
+        Ok(ColumnarValue::Scalar(value))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[test]
+    fn test_sketch_estimates() {
+        let mut distinct = HyperLogLog::default();
+        let mut percentiles = PercentileSample::default();
+
+        for value in 0..100_000 {
+            distinct.add(&(value % 20_000));
+            percentiles.add(value as f64);
+        }
+
+        let estimate = distinct.estimate() as f64;
+        assert!((estimate - 20_000.0).abs() / 20_000.0 < 0.05);
+
+        let median = percentiles.percentile(0.5).unwrap();
+        assert!((median - 50_000.0).abs() < 5_000.0);
+    }
+
+    async fn answer(database: &Database, sql: &str) -> DataFusionResult<ScalarValue> {
+        let record_batches = database.query(sql).await.unwrap().collect().await?;
+
+        Ok(get_column_value(record_batches[0].column(0), 0).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_sketch() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let sketch = database.create_sketch("users", &["id", "name"]).unwrap();
+        assert_eq!(sketch.column("id").unwrap().distinct.estimate(), 4);
+        assert!(sketch.column("name").unwrap().percentiles.is_none());
+        assert!(database.create_sketch("users", &["missing"]).is_err());
+
+        // inserted rows are added to the sketch
+        database
+            .query("insert into users values (5, 'Eve'), (5, 'Eve')")
+            .await
+            .unwrap();
+        assert_eq!(
+            database.sketch("users").unwrap().unwrap().version,
+            database.table_version("users").unwrap()
+        );
+        let sql = "select sketch_distinct('users', 'name')";
+        assert_eq!(
+            answer(&database, sql).await.unwrap(),
+            ScalarValue::UInt64(Some(5))
+        );
+
+        // deleted rows are removed by rebuilding the sketch
+        database
+            .query("delete from users where id > 2")
+            .await
+            .unwrap();
+        let sql = "select sketch_percentile('users', 'id', 0.5)";
+        assert_eq!(
+            answer(&database, sql).await.unwrap(),
+            ScalarValue::Float64(Some(1.5))
+        );
+
+        assert!(
+            answer(&database, "select sketch_distinct('user_role', 'role')")
+                .await
+                .is_err()
+        );
+    }
+}
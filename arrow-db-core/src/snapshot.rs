@@ -0,0 +1,303 @@
+//! Table versions and snapshots.
+//!
+//! Every committed write to a table, like a DML statement, a bulk load or a
+//! refresh, publishes a new version of the table.  Record batches are
+//! immutable, so a version is a cheap reference to the data of a table when it
+//! was committed, and later writes never change a version that is being read.
+//!
+//! A query reads the versions that are published when it's executed.  A
+//! `Snapshot` pins the current version of every table, so a series of queries
+//! see the same data while writes continue.
+//!
+//! Versions also detect lost updates.  `update_if_version` only writes to a
+//! table if it's still at the version the caller read, and a SQL statement
+//! fails with `DbError::WriteConflict` if its table was committed to while the
+//! statement was planned.
+
+use std::collections::HashMap;
+
+use datafusion::{
+    prelude::DataFrame,
+    sql::{parser::Statement, sqlparser::ast::Statement as SqlStatement},
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+/// The tables of a database at a point in time, for read-only queries
+#[derive(Debug)]
+pub struct Snapshot {
+    /// The version of each table in the snapshot, keyed by table name
+    pub versions: HashMap<String, u64>,
+    database: Database,
+}
+
+impl Database {
+    /// Publish the current data of a table to queries as a new version,
+    /// returning the version.
+    ///
+    /// Statements and bulk loads commit the tables they write to.  Call this
+    /// after writing to a table through the column or row API.
+    pub fn commit_table(&self, table_name: &str) -> Result<u64> {
+        let version = {
+            // committing doesn't need the rows of a spilled table
+            let mut table = self
+                .tables
+                .get_mut(table_name)
+                .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+            table.version += 1;
+            table.version
+        };
+
+        self.add_table_context(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.spill_if_oversized(table_name)?;
+
+        Ok(version)
+    }
+
+    /// Write to a table with a function, only if the table is still at the
+    /// expected version, and commit the write as a new version.
+    ///
+    /// Fails with `DbError::WriteConflict` if another write was committed
+    /// since the expected version was read.  Nothing is committed if the
+    /// function fails.
+    pub fn update_if_version<T>(
+        &self,
+        table_name: &str,
+        expected_version: u64,
+        f: impl FnOnce(&mut Table) -> Result<T>,
+    ) -> Result<T> {
+        let result = {
+            let mut table = get_mut_table!(self, table_name)?;
+
+            if table.version != expected_version {
+                return Err(DbError::WriteConflict(
+                    table_name.into(),
+                    expected_version,
+                    table.version,
+                ));
+            }
+
+            let result = f(&mut table)?;
+            table.version += 1;
+            result
+        };
+
+        self.add_table_context(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.spill_if_oversized(table_name)?;
+
+        Ok(result)
+    }
+
+    /// The current version of a table
+    pub fn table_version(&self, table_name: &str) -> Result<u64> {
+        self.tables
+            .get(table_name)
+            .map(|table| table.version)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))
+    }
+
+    /// The current version of every table, keyed by table name
+    pub(crate) fn table_versions(&self) -> HashMap<String, u64> {
+        self.tables
+            .iter()
+            .map(|table| (table.name.to_string(), table.version))
+            .collect()
+    }
+
+    /// Check that a table is still at a version
+    pub(crate) fn check_version(&self, table_name: &str, expected_version: u64) -> Result<()> {
+        let version = self.table_version(table_name)?;
+
+        match version == expected_version {
+            true => Ok(()),
+            false => Err(DbError::WriteConflict(
+                table_name.into(),
+                expected_version,
+                version,
+            )),
+        }
+    }
+
+    /// Pin the current version of every table.
+    ///
+    /// Each table is copied at a committed version, so a snapshot never sees a
+    /// partially applied statement.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let database = self.read_only_database()?;
+        let mut versions = HashMap::new();
+
+        for table in self.tables.iter() {
+            let mut snapshot = Table::new(&table.name);
+            snapshot.chunks = table.chunks.to_owned();
+            snapshot.spill = table.spill.to_owned();
+            snapshot.version = table.version;
+
+            versions.insert(table.name.to_string(), table.version);
+            database.tables.insert(table.name.to_owned(), snapshot);
+        }
+
+        database.add_all_table_contexts()?;
+
+        Ok(Snapshot { versions, database })
+    }
+
+    /// An empty database with the options and functions of this database, for
+    /// reading copies of its tables
+    pub(crate) fn read_only_database(&self) -> Result<Database> {
+        let database = Database::new(&self.name)?;
+        *database.options.write().unwrap() = self.options();
+        self.copy_functions(&database.ctx);
+
+        Ok(database)
+    }
+
+    /// Parse a SQL statement, rejecting anything but a query
+    pub(crate) fn read_only_statement(&self, sql: &str) -> Result<Statement> {
+        let state = self.ctx.state();
+        let dialect = state.config().options().sql_parser.dialect.to_owned();
+        let statement = state
+            .sql_to_statement(sql, &dialect)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        match &statement {
+            Statement::Statement(sql_statement)
+                if matches!(sql_statement.as_ref(), SqlStatement::Query(_)) =>
+            {
+                Ok(statement)
+            }
+            _ => Err(DbError::Query(
+                sql.into(),
+                "Only queries can read a snapshot".into(),
+            )),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Run a query against the snapshot, returning a `DataFrame`
+    pub async fn query(&self, sql: &str) -> Result<DataFrame> {
+        self.database.read_only_statement(sql)?;
+        self.database.query(sql).await
+    }
+
+    /// The version of a table in the snapshot
+    pub fn version(&self, table_name: &str) -> Option<u64> {
+        self.versions.get(table_name).copied()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::util::pretty::pretty_format_batches;
+    use datafusion::scalar::ScalarValue;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    async fn count_rows(results: Result<DataFrame>) -> usize {
+        results
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let snapshot = database.snapshot().unwrap();
+        assert_eq!(snapshot.version("users"), Some(0));
+
+        // every statement publishes a new version
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id < 3")
+            .await
+            .unwrap();
+        assert_eq!(get_table!(database, "users").unwrap().version, 2);
+        assert_eq!(get_table!(database, "user_role").unwrap().version, 0);
+
+        // the snapshot still reads the versions it pinned
+        assert_eq!(
+            count_rows(snapshot.query("select * from users").await).await,
+            4
+        );
+        assert_eq!(
+            count_rows(database.query("select * from users").await).await,
+            3
+        );
+        assert_eq!(database.snapshot().unwrap().version("users"), Some(2));
+
+        // snapshots are read-only
+        assert!(snapshot.query("delete from users").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_if_version() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // two clients read the same version
+        let version = database.table_version("users").unwrap();
+
+        database
+            .update_if_version("users", version, |table| {
+                table.update_row(0, &[(1, ScalarValue::Utf8(Some("Alicia".into())))])
+            })
+            .unwrap();
+        assert_eq!(database.table_version("users").unwrap(), version + 1);
+
+        // the second write would lose the first
+        let error = database
+            .update_if_version("users", version, |table| {
+                table.update_row(0, &[(1, ScalarValue::Utf8(Some("Ally".into())))])
+            })
+            .unwrap_err();
+        assert_eq!(
+            error,
+            DbError::WriteConflict("users".into(), version, version + 1)
+        );
+
+        // the committed write is visible to queries
+        let results = database
+            .query("select name from users where id = 1")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert!(pretty_format_batches(&results)
+            .unwrap()
+            .to_string()
+            .contains("Alicia"));
+
+        // a failed write isn't committed
+        assert!(database
+            .update_if_version("users", version + 1, |table| table.delete_row(100))
+            .is_err());
+        assert_eq!(database.table_version("users").unwrap(), version + 1);
+    }
+}
@@ -0,0 +1,342 @@
+//! Spilling tables to disk.
+//!
+//! When the `spill_threshold` option is set, a table that takes more than that
+//! many bytes of memory is spilled as its writes are committed: its chunks are
+//! written to parquet files in a temporary directory and dropped from memory,
+//! leaving only the schema.  Queries stream the spilled chunks from disk
+//! through the table's provider, so scanning a spilled table never loads all
+//! of it.
+//!
+//! Reading or writing a spilled table through the table API (`get_table!`,
+//! `get_mut_table!` and DML statements) loads its chunks back into memory, and
+//! the table is spilled again at its next commit if it's still too large.
+//! Spilled files are removed once no table, snapshot or provider refers to
+//! them.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{fs::File, path::PathBuf, sync::Arc};
+
+use arrow::array::{Array, RecordBatch};
+use arrow_schema::SchemaRef;
+use datafusion::{
+    catalog::{Session, TableProvider},
+    datasource::{
+        file_format::parquet::ParquetFormat,
+        listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
+    },
+    error::Result as DataFusionResult,
+    logical_expr::Expr,
+    physical_plan::ExecutionPlan,
+};
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+/// Tells apart the spill directories of the tables of a process
+#[cfg(not(target_arch = "wasm32"))]
+static SPILL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The chunks of a table spilled to parquet files, which are removed from
+/// disk when dropped
+#[derive(Debug, PartialEq)]
+pub struct SpilledChunks {
+    /// The directory holding the files
+    dir: PathBuf,
+    /// One parquet file per chunk, in table order
+    pub files: Vec<PathBuf>,
+    pub num_rows: usize,
+}
+
+impl SpilledChunks {
+    /// Read the spilled chunks back into record batches with a schema
+    pub fn read(&self, schema: &SchemaRef) -> Result<Vec<RecordBatch>> {
+        let mut record_batches = vec![];
+
+        for path in self.files.iter() {
+            let file = File::open(path).map_err(|e| spill_error(path, e))?;
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+                .and_then(|builder| builder.build())
+                .map_err(|e| spill_error(path, e))?;
+
+            for record_batch in reader {
+                let record_batch = record_batch.map_err(|e| spill_error(path, e))?;
+                record_batches.push(Table::new_record_batch(
+                    schema.to_owned(),
+                    record_batch.columns().to_vec(),
+                )?);
+            }
+        }
+
+        Ok(record_batches)
+    }
+
+    /// Plan a scan of the spilled chunks, streaming them from disk
+    pub(crate) async fn scan(
+        &self,
+        state: &dyn Session,
+        schema: SchemaRef,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let urls = self
+            .files
+            .iter()
+            .map(|path| ListingTableUrl::parse(path.to_string_lossy()))
+            .collect::<DataFusionResult<Vec<_>>>()?;
+        let options =
+            ListingOptions::new(Arc::new(ParquetFormat::default())).with_file_extension(".parquet");
+        let config = ListingTableConfig::new_with_multi_paths(urls)
+            .with_listing_options(options)
+            .with_schema(schema);
+
+        ListingTable::try_new(config)?
+            .scan(state, projection, filters, None)
+            .await
+    }
+}
+
+impl Drop for SpilledChunks {
+    fn drop(&mut self) {
+        // the files are temporary, so failing to remove them isn't an error
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl Table {
+    /// The number of bytes of memory the rows of the table take
+    pub fn memory_size(&self) -> usize {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.columns())
+            .map(|column| column.to_data().get_slice_memory_size().unwrap_or(0))
+            .sum()
+    }
+
+    /// The number of rows spilled to disk
+    pub fn num_spilled_rows(&self) -> usize {
+        self.spill.as_ref().map_or(0, |spill| spill.num_rows)
+    }
+
+    /// Write the chunks of the table to parquet files in a directory and drop
+    /// them from memory.  Tables without rows and tables that are already
+    /// spilled are left as they are.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spill(&mut self, dir: &std::path::Path) -> Result<()> {
+        if self.spill.is_some() || self.num_rows() == 0 {
+            return Ok(());
+        }
+
+        let id = SPILL_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = dir.join(format!("{}-{}-{id}", self.name, std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(|e| spill_error(&dir, e))?;
+
+        // files written before a failure are removed with the directory
+        let mut spill = SpilledChunks {
+            dir,
+            files: vec![],
+            num_rows: self.num_rows(),
+        };
+
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let path = spill.dir.join(format!("{index}.parquet"));
+            let file = File::create(&path).map_err(|e| spill_error(&path, e))?;
+            let mut writer = ArrowWriter::try_new(file, chunk.schema(), None)
+                .map_err(|e| spill_error(&path, e))?;
+            writer.write(chunk).map_err(|e| spill_error(&path, e))?;
+            writer.close().map_err(|e| spill_error(&path, e))?;
+
+            spill.files.push(path);
+        }
+
+        self.chunks = vec![RecordBatch::new_empty(self.schema())];
+        self.spill = Some(Arc::new(spill));
+
+        Ok(())
+    }
+
+    /// Read the spilled chunks of the table back into memory
+    pub fn load_spilled(&mut self) -> Result<()> {
+        let Some(spill) = self.spill.as_ref() else {
+            return Ok(());
+        };
+
+        let schema = self.schema();
+        let record_batches = spill.read(&schema)?;
+        self.set_record_batches(schema, &record_batches)?;
+        self.spill = None;
+
+        Ok(())
+    }
+
+    /// A copy of the table with its spilled chunks read back, leaving the
+    /// table spilled
+    pub fn unspilled(&self) -> Result<Table> {
+        let mut table = self.to_owned();
+        table.load_spilled()?;
+
+        Ok(table)
+    }
+}
+
+impl Database {
+    /// Read the spilled chunks of a table back into memory, publishing them
+    /// to queries.  Tables that aren't spilled, or don't exist, are left as
+    /// they are.
+    pub fn load_spilled_table(&self, table_name: &str) -> Result<()> {
+        let spilled = self
+            .tables
+            .get(table_name)
+            .is_some_and(|table| table.spill.is_some());
+
+        if !spilled {
+            return Ok(());
+        }
+
+        let Some(mut table) = self.tables.get_mut(table_name) else {
+            return Ok(());
+        };
+        table.load_spilled()?;
+        drop(table);
+
+        self.add_table_context(table_name)
+    }
+
+    /// Spill a table to disk, streaming it from disk in queries until it's
+    /// read or written through the table API
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spill_table(&self, table_name: &str) -> Result<()> {
+        let dir = self.spill_dir();
+
+        self.tables
+            .get_mut(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?
+            .spill(&dir)?;
+
+        self.add_table_context(table_name)
+    }
+
+    /// Spill a table if it takes more memory than the `spill_threshold`
+    /// option allows
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn spill_if_oversized(&self, table_name: &str) -> Result<()> {
+        let Some(spill_threshold) = self.options().spill_threshold else {
+            return Ok(());
+        };
+
+        let oversized = self
+            .tables
+            .get(table_name)
+            .is_some_and(|table| table.memory_size() > spill_threshold);
+
+        match oversized {
+            true => self.spill_table(table_name),
+            false => Ok(()),
+        }
+    }
+
+    /// The directory the tables of the database are spilled to
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spill_dir(&self) -> PathBuf {
+        std::env::temp_dir()
+            .join("arrow-db-spill")
+            .join(self.name.as_str())
+    }
+}
+
+fn spill_error(path: &std::path::Path, error: impl ToString) -> DbError {
+    DbError::Spill(path.display().to_string(), error.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    async fn count_rows(database: &Database, sql: &str) -> usize {
+        database
+            .query(sql)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_spill_table() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database.spill_table("users").unwrap();
+        let spill = database
+            .tables
+            .get("users")
+            .unwrap()
+            .spill
+            .to_owned()
+            .unwrap();
+        assert_eq!(spill.num_rows, 4);
+        assert!(spill.files.iter().all(|path| path.exists()));
+        assert_eq!(database.tables.get("users").unwrap().num_rows(), 0);
+
+        // queries stream the spilled chunks
+        assert_eq!(count_rows(&database, "select * from users").await, 4);
+        assert_eq!(
+            count_rows(&database, "select name from users where id > 2").await,
+            2
+        );
+
+        // the table API loads the chunks back
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 4);
+        assert!(database.tables.get("users").unwrap().spill.is_none());
+
+        // the files are removed once nothing refers to them
+        let files = spill.files.to_owned();
+        drop(spill);
+        assert!(files.iter().all(|path| !path.exists()));
+    }
+
+    #[tokio::test]
+    async fn test_spill_threshold() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database.query("SET spill_threshold = 1").await.unwrap();
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        assert!(database.tables.get("users").unwrap().spill.is_some());
+        assert_eq!(count_rows(&database, "select * from users").await, 5);
+
+        // writes load the table before spilling it again
+        database
+            .query("delete from users where id = 1")
+            .await
+            .unwrap();
+        assert!(database.tables.get("users").unwrap().spill.is_some());
+        assert_eq!(count_rows(&database, "select * from users").await, 4);
+
+        database.query("SET spill_threshold = none").await.unwrap();
+        database
+            .query("insert into users values (6, 'Frank')")
+            .await
+            .unwrap();
+        assert!(database.tables.get("users").unwrap().spill.is_none());
+        assert_eq!(count_rows(&database, "select * from users").await, 5);
+    }
+}
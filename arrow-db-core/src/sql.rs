@@ -1,36 +1,63 @@
 //! SQL operations in DataFusion.
 //!
 //! Before SQL queries can be executed on the database, the tables must be
-//! registered with the DataFusion context, which is a cheap operation.
+//! registered with the DataFusion context, which is a cheap operation.  Tables
+//! are registered once, and committed writes are published to their
+//! providers.
 
 use std::sync::Arc;
 
+use arrow::array::UInt64Array;
+use arrow_schema::{DataType, Field, Schema};
+use chrono::Utc;
 use datafusion::{
     catalog::TableProvider,
-    common::DFSchema,
-    datasource::MemTable,
-    logical_expr::{DmlStatement, LogicalPlan, WriteOp},
+    logical_expr::{LogicalPlan, LogicalPlanBuilder, Statement},
     prelude::DataFrame,
+    sql::parser::Statement as DFStatement,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::copy::copy_statement;
 use crate::{
     database::Database,
+    describe::describe_table_name,
     error::{DbError, Result},
-    get_table,
+    linked::refresh_table_name,
+    provider::LiveTable,
+    returning::take_returning,
+    system::hide_system_columns,
     table::Table,
 };
 
-impl<'a> Database<'a> {
-    /// Register a table with the DataFusion context
+impl Database {
+    /// Register a table with the DataFusion context.
+    ///
+    /// A table that's already registered has its current chunks published to
+    /// its provider instead, unless its schema or column defaults changed.
     pub fn add_table_context(&self, table_name: &str) -> Result<()> {
-        let table = get_table!(self, table_name)?;
-        let schema = table.record_batch.schema();
-        let provider =
-            MemTable::try_new(schema, vec![vec![table.record_batch.to_owned()]]).unwrap();
+        // spilled tables are registered with their spilled chunks
+        let table = self
+            .tables
+            .get(table_name)
+            .ok_or_else(|| DbError::TableNotFound(table_name.into()))?;
+        let registered = self.ctx.table_exist(table_name).unwrap_or(false);
+
+        if let Some(provider) = self.providers.get(table_name) {
+            if registered && provider.can_publish(&table) {
+                provider.publish(&table);
+                return Ok(());
+            }
+        }
+
+        let provider = Arc::new(LiveTable::new(&table));
 
+        // replace any previously registered provider of the table
+        self.ctx.deregister_table(table_name).unwrap();
         self.ctx
-            .register_table(table_name, Arc::new(provider))
+            .register_table(table_name, provider.to_owned())
             .unwrap();
+        self.providers.insert(table_name.into(), provider);
 
         Ok(())
     }
@@ -45,60 +72,180 @@ impl<'a> Database<'a> {
     }
 
     /// Remove a table from the DataFusion context
-    pub fn remove_table_context(&mut self, table: Table<'a>) -> Result<Arc<dyn TableProvider>> {
-        let table_name = table.name;
+    pub fn remove_table_context(&mut self, table: Table) -> Result<Arc<dyn TableProvider>> {
+        let table_name = table.name.as_str();
         let provider = self.ctx.deregister_table(table_name).unwrap().unwrap();
+        self.providers.remove(table_name);
 
         Ok(provider)
     }
 
     /// Run a SQL query, returning a `DataFrame`
+    ///
+    /// DML statements are executed immediately against the tables, and return
+    /// a `DataFrame` containing the number of affected rows.  `SET` statements
+    /// change the query options of the database, `REFRESH TABLE` re-imports a
+    /// linked table or recomputes a materialized view, `DESCRIBE table` lists
+    /// the columns of a table, and `COPY table TO 'file'` and `COPY table FROM
+    /// 'file'` export and append the rows of a table.
+    ///
+    /// Linked tables whose TTL has expired are refreshed first.  Every
+    /// statement is recorded in the query log.
     pub async fn query(&self, sql: &str) -> Result<DataFrame> {
-        let df = self
-            .ctx
-            .sql(sql)
+        let started_at = Utc::now();
+        let result = self.run_query(sql).await;
+        self.log_query(sql, started_at, &result).await;
+
+        result
+    }
+
+    /// Run a SQL query, as with `query`, without recording it
+    async fn run_query(&self, sql: &str) -> Result<DataFrame> {
+        if let Some(table_name) = refresh_table_name(sql) {
+            let count = match self.is_view(table_name) {
+                true => self.refresh_view(table_name).await?,
+                false => self.refresh_table(table_name).await?,
+            };
+            return self.count_data_frame(count);
+        }
+
+        self.refresh_expired_tables().await?;
+
+        if let Some(table_name) = describe_table_name(sql) {
+            return self.describe(table_name);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(statement) = copy_statement(sql) {
+            let count = self.copy(statement).await?;
+            return self.count_data_frame(count);
+        }
+
+        // a statement that writes to a table committed to while it was
+        // planned could be based on data that no longer exists
+        let versions = self.table_versions();
+        let mut statement = self.parse_statement(sql)?;
+        let returning = take_returning(&mut statement);
+        let plan = self.plan_statement(sql, statement).await?;
+
+        if let LogicalPlan::Dml(dml) = &plan {
+            let table_name = dml.table_name.table();
+
+            if let Some(version) = versions.get(table_name) {
+                self.check_version(table_name, *version)?;
+            }
+        }
+
+        if let (Some(items), LogicalPlan::Dml(dml)) = (returning, &plan) {
+            let dml = self
+                .options()
+                .apply_to_plan(LogicalPlan::Dml(dml.to_owned()))?;
+            let LogicalPlan::Dml(dml) = &dml else {
+                return Err(DbError::Dml(format!("Unexpected DML plan {dml}")));
+            };
+            let change = self
+                .apply_dml(dml)
+                .await
+                .map_err(|e| statement_error(sql, e))?;
+
+            return self
+                .returning(dml.table_name.table(), &change, &items)
+                .await;
+        }
+
+        self.execute_plan(plan)
+            .await
+            .map_err(|e| statement_error(sql, e))
+    }
+
+    /// Plan a SQL statement, hiding system columns from `*` and from INSERTs
+    /// without a column list, and matching the columns of `INSERT ... SELECT`
+    /// by name
+    pub(crate) async fn create_logical_plan(&self, sql: &str) -> Result<LogicalPlan> {
+        let statement = self.parse_statement(sql)?;
+
+        self.plan_statement(sql, statement).await
+    }
+
+    /// Parse a SQL statement with the dialect of the context
+    pub(crate) fn parse_statement(&self, sql: &str) -> Result<DFStatement> {
+        let state = self.ctx.state();
+        let dialect = state.config().options().sql_parser.dialect.to_owned();
+
+        state
+            .sql_to_statement(sql, &dialect)
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))
+    }
+
+    /// Plan a parsed SQL statement, as with `create_logical_plan`
+    pub(crate) async fn plan_statement(
+        &self,
+        sql: &str,
+        statement: DFStatement,
+    ) -> Result<LogicalPlan> {
+        let state = self.ctx.state();
+        let statement = self.insert_columns_by_name(statement).await?;
+        let plan = state
+            .statement_to_plan(self.insert_visible_columns(statement))
             .await
             .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
 
-        let logical_plan = df.logical_plan().to_owned();
-
-        let logical_plan = match logical_plan {
-            LogicalPlan::Dml(DmlStatement {
-                table_name,
-                op: WriteOp::Update,
-                input,
-                ..
-            }) => {
-                let name = table_name.table();
-                let _session_state = self.ctx.state();
-                let table_provider = self.ctx.table_provider(table_name.clone()).await.unwrap();
-                let schema_ref = table_provider.schema();
-                let df_schema =
-                    DFSchema::try_from_qualified_schema(table_name.table(), schema_ref.as_ref())
-                        .unwrap();
-                println!("{:?} {:?}", name, input);
-
-                LogicalPlan::Dml(DmlStatement::new(
-                    table_name,
-                    Arc::new(df_schema),
-                    WriteOp::Update,
-                    input,
-                    // Arc::new(project(rename_logical_plan(input, &fields)?, exprs)?),
-                ))
+        hide_system_columns(plan)
+    }
+
+    /// Execute a logical plan with the current query options, running DML
+    /// statements against the tables
+    pub(crate) async fn execute_plan(&self, plan: LogicalPlan) -> Result<DataFrame> {
+        if let LogicalPlan::Statement(Statement::SetVariable(set)) = &plan {
+            if self.set_option(&set.variable, &set.value)? {
+                let empty = LogicalPlanBuilder::empty(false)
+                    .build()
+                    .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))?;
+
+                return Ok(DataFrame::new(self.ctx.state(), empty));
             }
-            _ => logical_plan,
+        }
+
+        let options = self.options();
+        let plan = options.apply_to_plan(plan)?;
+        let df = match self.limit_fast_path(&plan)? {
+            Some(df) => df,
+            None => self
+                .ctx
+                .execute_logical_plan(plan)
+                .await
+                .map_err(|e| DbError::Query("execute plan".into(), e.to_string()))?,
         };
 
-        let physical_plan = self
-            .ctx
-            .state()
-            .create_physical_plan(&logical_plan)
-            .await
-            .unwrap();
+        match df.logical_plan() {
+            LogicalPlan::Dml(dml) => {
+                let count = self.execute_dml(dml).await?;
+                self.count_data_frame(count)
+            }
+            _ => match options.max_rows {
+                Some(max_rows) => df
+                    .limit(0, Some(max_rows))
+                    .map_err(|e| DbError::Query("limit rows".into(), e.to_string())),
+                None => Ok(df),
+            },
+        }
+    }
 
-        println!("{:?}", physical_plan);
+    /// Create a `DataFrame` with the number of rows affected by a statement
+    pub(crate) fn count_data_frame(&self, count: usize) -> Result<DataFrame> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "count",
+            DataType::UInt64,
+            false,
+        )]));
+        let batch = Table::new_record_batch(
+            schema,
+            vec![Arc::new(UInt64Array::from(vec![count as u64]))],
+        )?;
 
-        Ok(df)
+        self.ctx
+            .read_batch(batch)
+            .map_err(|e| DbError::Dml(e.to_string()))
     }
 
     #[cfg(test)]
@@ -108,10 +255,26 @@ impl<'a> Database<'a> {
     }
 }
 
+/// Attach the SQL of a statement to an error.
+///
+/// Constraint violations and write conflicts are passed through as-is, so
+/// callers can tell them apart from malformed queries.
+pub(crate) fn statement_error(sql: &str, error: DbError) -> DbError {
+    match error {
+        DbError::ConstraintViolation(_) | DbError::WriteConflict(..) => error,
+        error => DbError::Query(sql.into(), error.to_string()),
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use std::time::Instant;
 
+    use arrow::{
+        array::{AsArray, StringArray},
+        util::pretty::pretty_format_batches,
+    };
+
     use crate::{
         database::{
             tests::{create_database, seed_database},
@@ -158,20 +321,87 @@ pub mod tests {
         // let batch = database.remove_table_context(table).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_cloned_database_query() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_table_context("users").unwrap();
+
+        let cloned = database.clone();
+
+        // every table is queryable on the clone, even if never registered
+        let results = cloned
+            .query("select * from user_role")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 4);
+
+        // the clone does not share the context of the original
+        cloned.ctx.deregister_table("users").unwrap().unwrap();
+        assert!(database.query("select * from users").await.is_ok());
+        assert!(cloned.query("select * from users").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_information_schema() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let show_tables = database
+            .query("show tables")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let table_names = pretty_format_batches(&show_tables).unwrap().to_string();
+        assert!(table_names.contains("users"));
+        assert!(table_names.contains("user_role"));
+
+        let show_columns = database
+            .query("show columns from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(show_columns[0].num_rows(), 2);
+
+        let columns = database
+            .query(
+                "select column_name, data_type from information_schema.columns
+                where table_name = 'users' order by ordinal_position",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            columns[0].column(0).as_string::<i32>(),
+            &StringArray::from(vec!["id", "name"])
+        );
+        assert_eq!(
+            columns[0].column(1).as_string::<i32>(),
+            &StringArray::from(vec!["Int32", "Utf8"])
+        );
+
+        // clones keep the information schema
+        assert!(database.clone().query("show tables").await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_benchmark_sql_on_large_db() {
         let now = Instant::now();
         let database = Database::new_from_disk("LargeDB").await.unwrap();
         let elapsed = now.elapsed();
 
-        let rows = get_table!(database, "flights_1m")
-            .unwrap()
-            .record_batch
-            .num_rows();
-        let cols = get_table!(database, "flights_1m")
-            .unwrap()
-            .record_batch
-            .num_columns();
+        let rows = get_table!(database, "flights_1m").unwrap().num_rows();
+        let cols = get_table!(database, "flights_1m").unwrap().num_columns();
 
         println!("Loaded {} rows and {} cols in {:.2?}", rows, cols, elapsed);
 
@@ -0,0 +1,252 @@
+//! Table statistics.
+//!
+//! `compute_statistics` scans a table once for its row count and the null
+//! count, min, max and an estimate of the distinct values of each column.  The
+//! statistics are stored on the table, and the table provider hands them to
+//! DataFusion's planner, e.g. to put the smaller side of a join first.
+//!
+//! Statistics are a snapshot of the version of the table they were computed
+//! at.  Once the table is written to, they are reported to the planner as
+//! estimates until they are computed again.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet},
+    hash::{Hash, Hasher},
+};
+
+use arrow::array::{Array, ArrayRef};
+use datafusion::{
+    common::{
+        stats::Precision, ColumnStatistics as DataFusionColumnStatistics,
+        Statistics as DataFusionStatistics,
+    },
+    scalar::ScalarValue,
+};
+
+use crate::{
+    database::Database, error::Result, get_mut_table, row::get_column_value, table::Table,
+    zone::ZoneMap,
+};
+
+/// The number of smallest hashes kept to estimate the distinct values of a
+/// column.  Columns with fewer distinct values are counted exactly.
+const DISTINCT_SKETCH_SIZE: usize = 1024;
+
+/// The statistics of a table at a version
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    /// The version of the table the statistics were computed at
+    pub version: u64,
+    pub num_rows: usize,
+    pub columns: Vec<ColumnStatistics>,
+}
+
+/// The statistics of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub column_name: String,
+    pub null_count: usize,
+    /// The smallest non-null value, if the values can be compared
+    pub min: Option<ScalarValue>,
+    /// The largest non-null value, if the values can be compared
+    pub max: Option<ScalarValue>,
+    /// An estimate of the number of distinct non-null values
+    pub distinct_count: Option<usize>,
+}
+
+impl TableStatistics {
+    /// The statistics in DataFusion's terms, which are exact only if the table
+    /// hasn't been written to since they were computed
+    pub(crate) fn to_datafusion(&self, table: &Table) -> DataFusionStatistics {
+        let exact = self.version == table.version;
+        let precision = |value: Option<usize>| match (value, exact) {
+            (Some(value), true) => Precision::Exact(value),
+            (Some(value), false) => Precision::Inexact(value),
+            (None, _) => Precision::Absent,
+        };
+        let scalar_precision = |value: &Option<ScalarValue>| match (value, exact) {
+            (Some(value), true) => Precision::Exact(value.to_owned()),
+            (Some(value), false) => Precision::Inexact(value.to_owned()),
+            (None, _) => Precision::Absent,
+        };
+
+        let column_statistics = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut statistics = DataFusionColumnStatistics::new_unknown();
+                statistics.null_count = precision(Some(column.null_count));
+                statistics.min_value = scalar_precision(&column.min);
+                statistics.max_value = scalar_precision(&column.max);
+                statistics.distinct_count = match column.distinct_count {
+                    Some(distinct_count) => Precision::Inexact(distinct_count),
+                    None => Precision::Absent,
+                };
+                statistics
+            })
+            .collect();
+
+        DataFusionStatistics {
+            // the row count is always current
+            num_rows: Precision::Exact(table.num_rows() + table.num_spilled_rows()),
+            total_byte_size: Precision::Absent,
+            column_statistics,
+        }
+    }
+}
+
+impl Table {
+    /// Compute the statistics of the table and store them on the table
+    pub fn compute_statistics(&mut self) -> Result<TableStatistics> {
+        let schema = self.schema();
+        let mut columns = Vec::with_capacity(schema.fields().len());
+
+        for (column_index, field) in schema.fields().iter().enumerate() {
+            let column = self.column(column_index)?;
+            let zone_map = ZoneMap::new(&column).ok();
+            let non_null = |value: ScalarValue| (!value.is_null()).then_some(value);
+
+            columns.push(ColumnStatistics {
+                column_name: field.name().to_owned(),
+                null_count: column.null_count(),
+                min: zone_map
+                    .as_ref()
+                    .and_then(|zone_map| non_null(zone_map.min.to_owned())),
+                max: zone_map.and_then(|zone_map| non_null(zone_map.max)),
+                distinct_count: estimate_distinct(&column),
+            });
+        }
+
+        let statistics = TableStatistics {
+            version: self.version,
+            num_rows: self.num_rows(),
+            columns,
+        };
+        self.statistics = Some(statistics.to_owned());
+
+        Ok(statistics)
+    }
+}
+
+impl Database {
+    /// The statistics of a table, computed again if the table was written to
+    /// since they were last computed
+    pub fn statistics(&self, table_name: &str) -> Result<TableStatistics> {
+        let statistics = {
+            let mut table = get_mut_table!(self, table_name)?;
+
+            match &table.statistics {
+                Some(statistics) if statistics.version == table.version => statistics.to_owned(),
+                _ => table.compute_statistics()?,
+            }
+        };
+
+        // hand the new statistics to the planner
+        self.add_table_context(table_name)?;
+
+        Ok(statistics)
+    }
+}
+
+/// Estimate the number of distinct non-null values of a column from the
+/// smallest hashes of its values, or `None` if its values can't be read
+fn estimate_distinct(column: &ArrayRef) -> Option<usize> {
+    let mut hashes = BTreeSet::new();
+
+    for row in 0..column.len() {
+        if column.is_null(row) {
+            continue;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        get_column_value(column, row).ok()?.hash(&mut hasher);
+        hashes.insert(hasher.finish());
+
+        if hashes.len() > DISTINCT_SKETCH_SIZE {
+            hashes.pop_last();
+        }
+    }
+
+    // with fewer hashes than the sketch holds, every value was counted
+    if hashes.len() < DISTINCT_SKETCH_SIZE {
+        return Some(hashes.len());
+    }
+
+    let largest = *hashes.last()? as f64 / u64::MAX as f64;
+
+    Some(((DISTINCT_SKETCH_SIZE - 1) as f64 / largest).round() as usize)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::Int64Array;
+    use datafusion::catalog::TableProvider;
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_statistics() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, NULL), (6, 'Alice')")
+            .await
+            .unwrap();
+
+        let statistics = database.statistics("users").unwrap();
+        assert_eq!(statistics.version, 1);
+        assert_eq!(statistics.num_rows, 6);
+        assert_eq!(
+            statistics.columns[0],
+            ColumnStatistics {
+                column_name: "id".into(),
+                null_count: 0,
+                min: Some(ScalarValue::Int32(Some(1))),
+                max: Some(ScalarValue::Int32(Some(6))),
+                distinct_count: Some(6),
+            }
+        );
+        assert_eq!(statistics.columns[1].null_count, 1);
+        assert_eq!(statistics.columns[1].distinct_count, Some(4));
+
+        // the planner sees the statistics, as estimates once they are stale
+        let provider = database.providers.get("users").unwrap().to_owned();
+        let planned = provider.statistics().unwrap();
+        assert_eq!(planned.num_rows, Precision::Exact(6));
+        assert_eq!(
+            planned.column_statistics[0].max_value,
+            Precision::Exact(ScalarValue::Int32(Some(6)))
+        );
+
+        database
+            .query("delete from users where id = 6")
+            .await
+            .unwrap();
+        let planned = provider.statistics().unwrap();
+        assert_eq!(planned.num_rows, Precision::Exact(5));
+        assert_eq!(
+            planned.column_statistics[0].max_value,
+            Precision::Inexact(ScalarValue::Int32(Some(6)))
+        );
+        assert_eq!(
+            database.statistics("users").unwrap().columns[0].max,
+            Some(ScalarValue::Int32(Some(5)))
+        );
+    }
+
+    #[test]
+    fn test_estimate_distinct() {
+        let column: ArrayRef = Arc::new(Int64Array::from_iter_values(
+            (0..100_000).map(|value| value % 20_000),
+        ));
+        let estimate = estimate_distinct(&column).unwrap() as f64;
+
+        assert!((estimate - 20_000.0).abs() / 20_000.0 < 0.1);
+    }
+}
@@ -0,0 +1,175 @@
+//! Object store persistence.
+//!
+//! Databases can be loaded from and exported to a URL of an object store,
+//! like `s3://bucket/path/MyDb`, as well as a directory on disk, so a server
+//! can run without local state.  The last segment of the URL is the database
+//! name, and each object under it is a table, as with `new_from_disk`.
+//!
+//! Local files (`file://`) and memory (`memory://`) are always supported, and
+//! the `aws`, `gcp` and `azure` features add S3, Google Cloud Storage and
+//! Azure Blob Storage.  Credentials and other settings are read from the
+//! environment, e.g. `AWS_ACCESS_KEY_ID` and `AWS_REGION`.
+
+use bytes::Bytes;
+use object_store::{path::Path, ObjectStore};
+use url::Url;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    export::ExportOptions,
+    import::IMPORT_EXTENSIONS,
+    table::Table,
+};
+
+/// Parse the URL of a database into its object store and the path of the
+/// database within it
+pub fn parse_store_url(url: &str) -> Result<(Box<dyn ObjectStore>, Path)> {
+    let parsed = Url::parse(url).map_err(|e| store_error(url, e))?;
+
+    // the builders read the same options as their environment variables
+    let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+
+    object_store::parse_url_opts(&parsed, options).map_err(|e| store_error(url, e))
+}
+
+impl Database {
+    /// Create a new database from the objects under a URL of an object store
+    ///
+    /// Parquet, CSV, JSON and Arrow IPC objects are imported, other objects
+    /// are skipped.
+    pub async fn new_from_store(url: &str) -> Result<Database> {
+        let (store, prefix) = parse_store_url(url)?;
+        let name = prefix
+            .filename()
+            .ok_or_else(|| store_error(url, "The URL has no database name"))?;
+
+        let mut database = Database::new(name)?;
+        let batch_size = database.options().import_batch_size;
+        let objects = store
+            .list_with_delimiter(Some(&prefix))
+            .await
+            .map_err(|e| store_error(url, e))?
+            .objects;
+
+        for object in objects {
+            let Some((table_name, extension)) = object
+                .location
+                .filename()
+                .and_then(|file_name| file_name.split_once('.'))
+            else {
+                continue;
+            };
+
+            if !IMPORT_EXTENSIONS.contains(&extension) {
+                continue;
+            }
+
+            let bytes = store
+                .get(&object.location)
+                .await
+                .map_err(|e| store_error(url, e))?
+                .bytes()
+                .await
+                .map_err(|e| store_error(url, e))?;
+
+            let mut table = Table::new(table_name);
+            table.import_bytes(bytes, extension, batch_size)?;
+            database.add_table(table)?;
+        }
+
+        Ok(database)
+    }
+
+    /// Export the tables of the database to parquet objects under a URL of an
+    /// object store
+    pub async fn export_to_store(&self, url: &str) -> Result<()> {
+        let (store, prefix) = parse_store_url(url)?;
+
+        // tables are copied before exporting, so no table is held across an
+        // upload
+        let table_names = self
+            .tables
+            .iter()
+            .map(|table| table.key().to_owned())
+            .collect::<Vec<_>>();
+
+        for table_name in table_names {
+            let table = self
+                .tables
+                .get(&table_name)
+                .map(|table| table.unspilled())
+                .transpose()?;
+            let Some(mut table) = table else {
+                continue;
+            };
+
+            let mut buffer = vec![];
+            table
+                .export_parquet_to_bytes(&mut buffer, &ExportOptions::default())
+                .await?;
+
+            store
+                .put(
+                    &prefix.child(format!("{table_name}.parquet")),
+                    Bytes::from(buffer).into(),
+                )
+                .await
+                .map_err(|e| store_error(url, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn store_error(url: &str, error: impl ToString) -> DbError {
+    DbError::Store(url.into(), error.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_to_store() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+
+        let dir = std::env::temp_dir()
+            .join(format!("arrow-db-store-{}", std::process::id()))
+            .join(&database.name);
+        let url = Url::from_directory_path(&dir).unwrap().to_string();
+
+        database.export_to_store(&url).await.unwrap();
+        assert!(dir.join("users.parquet").exists());
+
+        let loaded = Database::new_from_store(&url).await.unwrap();
+        assert_eq!(loaded.name, database.name);
+        assert_eq!(
+            loaded
+                .tables
+                .get("users")
+                .unwrap()
+                .record_batch()
+                .unwrap()
+                .columns(),
+            database
+                .tables
+                .get("users")
+                .unwrap()
+                .record_batch()
+                .unwrap()
+                .columns()
+        );
+        assert_eq!(loaded.tables.len(), 2);
+
+        assert!(matches!(
+            Database::new_from_store("not a url").await,
+            Err(DbError::Store(_, _))
+        ));
+
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
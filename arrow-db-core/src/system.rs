@@ -0,0 +1,334 @@
+//! System columns.
+//!
+//! Tables can opt in to reserved columns that the DML executor maintains:
+//!
+//! - `_rowid`: a unique, auto-incrementing row identifier
+//! - `_created_at`: when the row was inserted
+//! - `_updated_at`: when the row was last inserted or updated
+//!
+//! System columns are hidden from `SELECT *` and from INSERTs without a column
+//! list, but can be selected and filtered on by name.  Tables can also opt in
+//! to `_rowid` alone, so clients can address rows without a primary key.  Row
+//! ids are never reused, so a row keeps its id until it's deleted.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, Int64Array, TimestampMicrosecondArray},
+    datatypes::{DataType, TimeUnit},
+};
+use datafusion::{
+    common::{tree_node::Transformed, Column},
+    logical_expr::{expr::WildcardOptions, Expr, LogicalPlan, Projection},
+    scalar::ScalarValue,
+    sql::{
+        parser::Statement,
+        sqlparser::ast::{Ident, Statement as SqlStatement},
+    },
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+pub const ROWID_COLUMN: &str = "_rowid";
+pub const CREATED_AT_COLUMN: &str = "_created_at";
+pub const UPDATED_AT_COLUMN: &str = "_updated_at";
+pub const SYSTEM_COLUMNS: [&str; 3] = [ROWID_COLUMN, CREATED_AT_COLUMN, UPDATED_AT_COLUMN];
+
+/// Check if a column is a system column
+pub fn is_system_column(column_name: &str) -> bool {
+    SYSTEM_COLUMNS.contains(&column_name)
+}
+
+/// The data type of the timestamp system columns
+pub fn system_timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+/// The current time as a value of the timestamp system columns
+pub(crate) fn now() -> ScalarValue {
+    ScalarValue::TimestampMicrosecond(
+        Some(chrono::Utc::now().timestamp_micros()),
+        Some("UTC".into()),
+    )
+}
+
+impl Table {
+    /// Add the system columns to the end of the table.
+    ///
+    /// Existing rows are numbered in order, and stamped with the current time.
+    pub fn add_system_columns(&mut self) -> Result<()> {
+        self.add_rowid_column()?;
+
+        if self.column_index(CREATED_AT_COLUMN).is_ok() {
+            return Ok(());
+        }
+
+        let num_rows = self.num_rows();
+        let timestamp = chrono::Utc::now().timestamp_micros();
+        let timestamps = TimestampMicrosecondArray::from(vec![timestamp; num_rows])
+            .with_timezone_opt(Some(Arc::from("UTC")));
+        let num_columns = self.num_columns();
+
+        self.add_column::<TimestampMicrosecondArray>(
+            num_columns,
+            CREATED_AT_COLUMN,
+            system_timestamp_type(),
+            timestamps.to_data(),
+        )?;
+        self.add_column::<TimestampMicrosecondArray>(
+            num_columns + 1,
+            UPDATED_AT_COLUMN,
+            system_timestamp_type(),
+            timestamps.into_data(),
+        )
+    }
+
+    /// Add the `_rowid` system column to the end of the table, without the
+    /// timestamp system columns.
+    ///
+    /// Existing rows are numbered in order.
+    pub fn add_rowid_column(&mut self) -> Result<()> {
+        if self.has_system_columns() {
+            return Ok(());
+        }
+
+        let num_rows = self.num_rows();
+
+        self.add_column::<Int64Array>(
+            self.num_columns(),
+            ROWID_COLUMN,
+            DataType::Int64,
+            Int64Array::from_iter_values(1..=num_rows as i64).into_data(),
+        )?;
+
+        self.set_auto_increment(ROWID_COLUMN)
+    }
+
+    /// Check if the table has system columns
+    pub fn has_system_columns(&self) -> bool {
+        self.column_index(ROWID_COLUMN).is_ok()
+    }
+
+    /// The names of the columns that aren't system columns
+    pub fn visible_column_names(&self) -> Vec<String> {
+        self.schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_owned())
+            .filter(|name| !is_system_column(name))
+            .collect()
+    }
+
+    /// Stamp NULL timestamp system columns of a new row with the current time.
+    ///
+    /// `_rowid` is filled by its auto-increment sequence.
+    pub fn fill_system_columns(&self, values: &mut [ScalarValue]) -> Result<()> {
+        for column_name in [CREATED_AT_COLUMN, UPDATED_AT_COLUMN] {
+            if let Ok(column_index) = self.column_index(column_name) {
+                if values[column_index].is_null() {
+                    values[column_index] = now();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stamp `_updated_at` of an updated row with the current time
+    pub fn touch_row(&mut self, row: usize) -> Result<()> {
+        match self.column_index(UPDATED_AT_COLUMN) {
+            Ok(column_index) => self.apply_set_assignment(row, column_index, &now()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl Database {
+    /// Add the `_rowid` system column to a table, registering the new schema
+    /// with the DataFusion context
+    pub fn add_rowid_column(&self, table_name: &str) -> Result<()> {
+        get_mut_table!(self, table_name)?.add_rowid_column()?;
+        self.commit_table(table_name)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(())
+    }
+
+    /// Give INSERTs without a column list the visible columns of the target
+    /// table, so values don't have to be provided for system columns
+    pub(crate) fn insert_visible_columns(&self, mut statement: Statement) -> Statement {
+        let Statement::Statement(sql_statement) = &mut statement else {
+            return statement;
+        };
+
+        let SqlStatement::Insert(insert) = sql_statement.as_mut() else {
+            return statement;
+        };
+
+        let Some(table_name) = insert.table_name.0.last() else {
+            return statement;
+        };
+
+        let table = self
+            .tables
+            .get(table_name.value.as_str())
+            .or_else(|| self.tables.get(table_name.value.to_lowercase().as_str()));
+
+        if let Some(table) = table {
+            if insert.columns.is_empty() && table.has_system_columns() {
+                insert.columns = table
+                    .visible_column_names()
+                    .into_iter()
+                    .map(|name| Ident::with_quote('"', name))
+                    .collect();
+            }
+        }
+
+        statement
+    }
+}
+
+/// Expand `*` in projections to every column except the system columns
+pub(crate) fn hide_system_columns(plan: LogicalPlan) -> Result<LogicalPlan> {
+    let mut expanded = false;
+
+    plan.transform_up_with_subqueries(|plan| match plan {
+        LogicalPlan::Projection(projection) if has_hidden_columns(&projection) => {
+            expanded = true;
+
+            let input = projection.input;
+            let expr = projection
+                .expr
+                .into_iter()
+                .flat_map(|expr| match expr {
+                    Expr::Wildcard { qualifier, options }
+                        if options == WildcardOptions::default() =>
+                    {
+                        input
+                            .schema()
+                            .iter()
+                            .filter(|(field_qualifier, field)| {
+                                !is_system_column(field.name())
+                                    && (qualifier.is_none()
+                                        || qualifier.as_ref() == *field_qualifier)
+                            })
+                            .map(|(field_qualifier, field)| {
+                                Expr::Column(Column::new(field_qualifier.cloned(), field.name()))
+                            })
+                            .collect()
+                    }
+                    expr => vec![expr],
+                })
+                .collect();
+
+            Projection::try_new(expr, input)
+                .map(|projection| Transformed::yes(LogicalPlan::Projection(projection)))
+        }
+        // parents of an expanded projection need their schemas rebuilt
+        plan if expanded => plan.recompute_schema().map(Transformed::yes),
+        plan => Ok(Transformed::no(plan)),
+    })
+    .map(|transformed| transformed.data)
+    .map_err(|e| DbError::Query("hide system columns".into(), e.to_string()))
+}
+
+/// Check if a projection has a wildcard over an input with system columns
+fn has_hidden_columns(projection: &Projection) -> bool {
+    let has_wildcard = projection.expr.iter().any(|expr| {
+        matches!(expr, Expr::Wildcard { options, .. } if options == &WildcardOptions::default())
+    });
+
+    has_wildcard
+        && projection
+            .input
+            .schema()
+            .fields()
+            .iter()
+            .any(|field| is_system_column(field.name()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::AsArray;
+    use arrow::datatypes::{Int64Type, TimestampMicrosecondType};
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_system_columns() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .add_system_columns()
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        // hidden from SELECT * and from INSERTs without a column list
+        let results = database
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_columns(), 2);
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+
+        let created_at = {
+            let table = get_table!(database, "users").unwrap();
+            let rowids = table.column(2).unwrap();
+            assert_eq!(
+                rowids.as_primitive::<Int64Type>(),
+                &Int64Array::from(vec![1, 2, 3, 4, 5])
+            );
+
+            table
+                .column(3)
+                .unwrap()
+                .as_primitive::<TimestampMicrosecondType>()
+                .value(4)
+        };
+
+        database
+            .query("update users set name = 'Eve2' where _rowid = 5")
+            .await
+            .unwrap();
+
+        let table = get_table!(database, "users").unwrap();
+        let updated_at = table
+            .column(4)
+            .unwrap()
+            .as_primitive::<TimestampMicrosecondType>()
+            .value(4);
+        assert!(updated_at >= created_at);
+        drop(table);
+
+        // but can be selected explicitly
+        let results = database
+            .query("select _rowid, name from users where _rowid > 4")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 1);
+        assert_eq!(results[0].schema().field(0).name(), ROWID_COLUMN);
+    }
+}
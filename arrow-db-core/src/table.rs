@@ -1,45 +1,90 @@
 //! Table operations.
 //!
 //! A table is a collection of equal length columns, known as a `RecordBatch` in
-//! Arrow.
+//! Arrow.  The rows of a table are stored in chunks of record batches.
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use arrow::array::RecordBatch;
 use arrow_schema::{Schema, SchemaRef};
-use datafusion::logical_expr::TableSource;
+use datafusion::{logical_expr::TableSource, scalar::ScalarValue};
+
+use crate::{
+    chunk::DEFAULT_CHUNK_SIZE,
+    constraint::{CheckConstraint, PrimaryKey},
+    index::HashIndex,
+    sequence::Sequence,
+    spill::SpilledChunks,
+    statistics::TableStatistics,
+    ttl::Ttl,
+};
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Table<'a> {
-    pub name: &'a str,
-    pub record_batch: RecordBatch,
+pub struct Table {
+    pub name: String,
+    /// The rows of the table, in chunks with the schema of the table
+    pub chunks: Vec<RecordBatch>,
+    /// The target number of rows per chunk
+    pub chunk_size: usize,
+    /// Sequences of auto-increment columns, keyed by column name
+    pub sequences: HashMap<String, Sequence>,
+    /// The primary key of the table, with an index of its keys
+    pub primary_key: Option<PrimaryKey>,
+    /// Names of the columns that cannot contain NULL values
+    pub not_null: HashSet<String>,
+    /// CHECK constraints that every row must satisfy
+    pub checks: Vec<CheckConstraint>,
+    /// Values of columns omitted from INSERTs, keyed by column name
+    pub defaults: HashMap<String, ScalarValue>,
+    /// Hash indexes of the rows by column value, keyed by column name
+    pub indexes: HashMap<String, HashIndex>,
+    /// The timestamp column and TTL that rows expire by
+    pub ttl: Option<Ttl>,
+    /// The statistics of the table, as of the version they were computed at
+    pub statistics: Option<TableStatistics>,
+    /// The number of writes committed to the table
+    pub version: u64,
+    /// The chunks of the table spilled to disk, if it's spilled
+    pub spill: Option<Arc<SpilledChunks>>,
 }
 
-impl<'a> Table<'a> {
+impl Table {
     /// Create a new table
-    pub fn new(name: &'a str) -> Table<'a> {
+    pub fn new(name: impl Into<String>) -> Table {
         let schema = Arc::new(Schema::empty());
 
         Table {
-            name,
-            record_batch: RecordBatch::new_empty(schema),
+            name: name.into(),
+            chunks: vec![RecordBatch::new_empty(schema)],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            sequences: HashMap::new(),
+            primary_key: None,
+            not_null: HashSet::new(),
+            checks: vec![],
+            defaults: HashMap::new(),
+            indexes: HashMap::new(),
+            ttl: None,
+            statistics: None,
+            version: 0,
+            spill: None,
         }
     }
 
     #[cfg(test)]
     pub fn print(&self) {
         println!("\nTable: {}", self.name);
-        arrow::util::pretty::print_batches(&[self.record_batch.to_owned()]).unwrap();
+        arrow::util::pretty::print_batches(&self.chunks).unwrap();
     }
 }
 
-impl TableSource for Table<'static> {
+impl TableSource for Table {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
     fn schema(&self) -> SchemaRef {
-        self.record_batch.schema()
+        self.chunks[0].schema()
     }
 }
 
@@ -0,0 +1,398 @@
+//! Triggers.
+//!
+//! Triggers are functions run by INSERT, UPDATE and DELETE statements on the
+//! rows they change, e.g. to maintain audit columns or derived tables.
+//!
+//! BEFORE triggers run while the statement holds the table, before the change
+//! is committed.  They can modify the changed rows, and an error rolls the
+//! statement back.  AFTER triggers run once the change is committed and the
+//! table is released, so they can write to other tables of the database.  An
+//! error from an AFTER trigger fails the statement, but doesn't undo it.
+//!
+//! Triggers run once per statement, and not for statements that change no
+//! rows.  Bulk loads, batch inserts and writes through the column or row API
+//! don't run triggers.
+
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    change::{ChangeEvent, ChangeKind, TableChange},
+    database::Database,
+    error::{DbError, Result},
+    table::Table,
+};
+
+/// A trigger run before a change is committed, with the table after the change
+pub type BeforeTrigger = Arc<dyn Fn(&mut Table, &ChangeEvent) -> Result<()> + Send + Sync>;
+
+/// A trigger run after a change is committed
+pub type AfterTrigger = Arc<dyn Fn(&Database, &ChangeEvent) -> Result<()> + Send + Sync>;
+
+/// When a trigger runs, relative to the commit of a change
+#[derive(Clone)]
+pub enum TriggerTiming {
+    Before(BeforeTrigger),
+    After(AfterTrigger),
+}
+
+/// A named trigger on a kind of change to a table
+#[derive(Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub kind: ChangeKind,
+    pub timing: TriggerTiming,
+}
+
+impl Debug for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let timing = match self.timing {
+            TriggerTiming::Before(_) => "Before",
+            TriggerTiming::After(_) => "After",
+        };
+
+        f.debug_struct("Trigger")
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("timing", &timing)
+            .finish()
+    }
+}
+
+impl Database {
+    /// Run a function on the rows changed by a kind of statement on a table,
+    /// before the change is committed
+    pub fn create_before_trigger(
+        &self,
+        table_name: &str,
+        trigger_name: &str,
+        kind: ChangeKind,
+        f: impl Fn(&mut Table, &ChangeEvent) -> Result<()> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let timing = TriggerTiming::Before(Arc::new(f));
+        self.create_trigger(table_name, trigger_name, kind, timing)
+    }
+
+    /// Run a function on the rows changed by a kind of statement on a table,
+    /// after the change is committed
+    pub fn create_after_trigger(
+        &self,
+        table_name: &str,
+        trigger_name: &str,
+        kind: ChangeKind,
+        f: impl Fn(&Database, &ChangeEvent) -> Result<()> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let timing = TriggerTiming::After(Arc::new(f));
+        self.create_trigger(table_name, trigger_name, kind, timing)
+    }
+
+    /// Remove a trigger from a table
+    pub fn drop_trigger(&self, table_name: &str, trigger_name: &str) -> Result<()> {
+        let mut triggers = self.triggers.get_mut(table_name).ok_or_else(|| {
+            DbError::Trigger(format!("{trigger_name} not found on Table {table_name}"))
+        })?;
+        let count = triggers.len();
+
+        triggers.retain(|trigger| trigger.name != trigger_name);
+
+        match triggers.len() < count {
+            true => Ok(()),
+            false => Err(DbError::Trigger(format!(
+                "{trigger_name} not found on Table {table_name}"
+            ))),
+        }
+    }
+
+    fn create_trigger(
+        &self,
+        table_name: &str,
+        trigger_name: &str,
+        kind: ChangeKind,
+        timing: TriggerTiming,
+    ) -> Result<()> {
+        if !self.tables.contains_key(table_name) {
+            return Err(DbError::TableNotFound(table_name.into()));
+        }
+
+        let mut triggers = self.triggers.entry(table_name.into()).or_default();
+
+        if triggers.iter().any(|trigger| trigger.name == trigger_name) {
+            return Err(DbError::Trigger(format!(
+                "{trigger_name} already exists on Table {table_name}"
+            )));
+        }
+
+        triggers.push(Trigger {
+            name: trigger_name.into(),
+            kind,
+            timing,
+        });
+
+        Ok(())
+    }
+
+    /// The triggers of a table on a kind of change, in the order they were
+    /// created
+    fn triggers_on(&self, table_name: &str, kind: ChangeKind) -> Vec<TriggerTiming> {
+        self.triggers
+            .get(table_name)
+            .map(|triggers| {
+                triggers
+                    .iter()
+                    .filter(|trigger| trigger.kind == kind)
+                    .map(|trigger| trigger.timing.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Run the BEFORE triggers of a table on a change that was applied to it,
+    /// restoring the table if any trigger fails
+    pub(crate) fn run_before_triggers(
+        &self,
+        table: &mut Table,
+        mut change: TableChange,
+    ) -> Result<TableChange> {
+        let triggers = self
+            .triggers_on(&table.name, change.kind)
+            .into_iter()
+            .filter_map(|timing| match timing {
+                TriggerTiming::Before(trigger) => Some(trigger),
+                TriggerTiming::After(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if triggers.is_empty() || change.rows.is_empty() {
+            return Ok(change);
+        }
+
+        let event = change.event(&table.name, table.version + 1)?;
+
+        for trigger in triggers {
+            if let Err(error) = trigger(&mut *table, &event) {
+                table.chunks = change.before;
+
+                // the indexes have the keys and values of the changed rows
+                if let Some(primary_key) = table.primary_key.take() {
+                    table.set_primary_key(&primary_key.column_name)?;
+                }
+
+                table.rebuild_indexes()?;
+
+                return Err(error);
+            }
+        }
+
+        // triggers may have modified the changed rows
+        change.after = table.chunks.to_owned();
+
+        Ok(change)
+    }
+
+    /// Run the AFTER triggers of a table on a committed change
+    pub(crate) fn run_after_triggers(
+        &self,
+        table_name: &str,
+        version: u64,
+        change: &TableChange,
+    ) -> Result<()> {
+        let triggers = self
+            .triggers_on(table_name, change.kind)
+            .into_iter()
+            .filter_map(|timing| match timing {
+                TriggerTiming::After(trigger) => Some(trigger),
+                TriggerTiming::Before(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if triggers.is_empty() || change.rows.is_empty() {
+            return Ok(());
+        }
+
+        let event = change.event(table_name, version)?;
+
+        triggers
+            .iter()
+            .try_for_each(|trigger| trigger(self, &event))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int32Array, StringArray};
+    use arrow::datatypes::Int32Type;
+    use datafusion::scalar::ScalarValue;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_before_trigger() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        // reject reserved ids, and upper case the names of new users
+        database
+            .create_before_trigger("users", "check_id", ChangeKind::Insert, |_, event| {
+                let ids = event.after.as_ref().unwrap().column(0);
+
+                match ids
+                    .as_primitive::<Int32Type>()
+                    .iter()
+                    .flatten()
+                    .any(|id| id >= 100)
+                {
+                    true => Err(DbError::ConstraintViolation("Reserved id".into())),
+                    false => Ok(()),
+                }
+            })
+            .unwrap();
+        database
+            .create_before_trigger("users", "upper_name", ChangeKind::Insert, |table, event| {
+                for (row, name) in event
+                    .rows
+                    .iter()
+                    .zip(event.after.as_ref().unwrap().column(1).as_string::<i32>())
+                {
+                    let name = name.map(|name| name.to_uppercase());
+                    table.update_row(*row, &[(1, ScalarValue::Utf8(name))])?;
+                }
+
+                Ok(())
+            })
+            .unwrap();
+        assert!(database
+            .create_before_trigger("users", "check_id", ChangeKind::Update, |_, _| Ok(()))
+            .is_err());
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        assert_eq!(
+            get_table!(database, "users")
+                .unwrap()
+                .column(1)
+                .unwrap()
+                .as_string::<i32>(),
+            &StringArray::from(vec!["Alice", "Bob", "Charlie", "David", "EVE"])
+        );
+
+        // a failed trigger rolls back the statement, including its keys
+        assert_eq!(
+            database
+                .query("insert into users values (6, 'Frank'), (100, 'Root')")
+                .await
+                .unwrap_err(),
+            DbError::ConstraintViolation("Reserved id".into())
+        );
+        assert_eq!(database.table_version("users").unwrap(), 1);
+        database
+            .query("insert into users values (6, 'Frank')")
+            .await
+            .unwrap();
+
+        database.drop_trigger("users", "check_id").unwrap();
+        assert!(database.drop_trigger("users", "check_id").is_err());
+        database
+            .query("insert into users values (100, 'Root')")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_after_trigger() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // give every new user a role, and remove the roles of deleted users
+        database
+            .create_after_trigger(
+                "users",
+                "add_role",
+                ChangeKind::Insert,
+                |database, event| {
+                    {
+                        let mut user_role = get_mut_table!(database, "user_role")?;
+
+                        for id in event
+                            .after
+                            .as_ref()
+                            .unwrap()
+                            .column(0)
+                            .as_primitive::<Int32Type>()
+                        {
+                            user_role.append_row(vec![
+                                ScalarValue::Int32(id),
+                                ScalarValue::Utf8(Some("guest".into())),
+                            ])?;
+                        }
+                    }
+
+                    database.commit_table("user_role").map(|_| ())
+                },
+            )
+            .unwrap();
+        database
+            .create_after_trigger(
+                "users",
+                "remove_role",
+                ChangeKind::Delete,
+                |database, event| {
+                    {
+                        let ids = event
+                            .before
+                            .as_ref()
+                            .unwrap()
+                            .column(0)
+                            .as_primitive::<Int32Type>();
+                        let mut user_role = get_mut_table!(database, "user_role")?;
+                        let rows = user_role
+                            .column(0)?
+                            .as_primitive::<Int32Type>()
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, user_id)| ids.iter().any(|id| id == *user_id))
+                            .map(|(row, _)| row)
+                            .collect::<Vec<_>>();
+
+                        user_role.delete_rows(&rows)?;
+                    }
+
+                    database.commit_table("user_role").map(|_| ())
+                },
+            )
+            .unwrap();
+
+        // triggers are kept by clones
+        for database in [&database, &database.clone()] {
+            database
+                .query("insert into users values (5, 'Eve'), (6, 'Frank')")
+                .await
+                .unwrap();
+            database
+                .query("delete from users where id < 3")
+                .await
+                .unwrap();
+
+            let user_role = get_table!(database, "user_role").unwrap();
+            assert_eq!(
+                user_role.column(0).unwrap().as_primitive::<Int32Type>(),
+                &Int32Array::from(vec![3, 4, 5, 6])
+            );
+            assert_eq!(
+                user_role.column(1).unwrap().as_string::<i32>(),
+                &StringArray::from(vec!["employee", "employee", "guest", "guest"])
+            );
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Row expiration.
+//!
+//! A table with a TTL column expires each row once the timestamp in the
+//! column is older than the TTL, which suits session or cache-like tables.
+//! Rows with a NULL timestamp never expire.
+//!
+//! Expired rows are deleted by `Database::expire_rows`, either on demand or
+//! periodically by a background task started with `expire_rows_every`.  Each
+//! sweep is committed like a DELETE statement, so subscribers and
+//! write-through persistence see the deleted rows.
+
+use std::time::Duration;
+
+use arrow::{
+    array::AsArray,
+    compute::cast,
+    datatypes::{DataType, TimeUnit, TimestampMicrosecondType},
+};
+
+use crate::{
+    change::{ChangeKind, TableChange},
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+/// The column and TTL that rows of a table expire by
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ttl {
+    pub column_name: String,
+    pub ttl: Duration,
+}
+
+impl Table {
+    /// Expire rows once the timestamp in a column is older than the TTL.
+    ///
+    /// Fails if the column isn't a timestamp column.
+    pub fn set_ttl(&mut self, column_name: &str, ttl: Duration) -> Result<()> {
+        let column_index = self.column_index(column_name)?;
+        let data_type = self.schema().field(column_index).data_type().to_owned();
+
+        if !matches!(data_type, DataType::Timestamp(..)) {
+            return Err(DbError::DataType(format!(
+                "TTL column {column_name} must be a timestamp, but is {data_type}"
+            )));
+        }
+
+        self.ttl = Some(Ttl {
+            column_name: column_name.into(),
+            ttl,
+        });
+
+        Ok(())
+    }
+
+    /// Stop expiring rows of the table
+    pub fn drop_ttl(&mut self) {
+        self.ttl = None;
+    }
+
+    /// The indexes of the rows that expired by a time, in microseconds since
+    /// the epoch
+    pub fn expired_rows(&self, now: i64) -> Result<Vec<usize>> {
+        let Some(ttl) = &self.ttl else {
+            return Ok(vec![]);
+        };
+
+        let column = self.column(self.column_index(&ttl.column_name)?)?;
+        let timestamps = cast(&column, &DataType::Timestamp(TimeUnit::Microsecond, None))
+            .map_err(|e| DbError::DataType(format!("Error reading TTL column: {e}")))?;
+        let expires_at = now.saturating_sub(ttl.ttl.as_micros() as i64);
+
+        Ok(timestamps
+            .as_primitive::<TimestampMicrosecondType>()
+            .iter()
+            .enumerate()
+            .filter(|(_, timestamp)| timestamp.is_some_and(|timestamp| timestamp < expires_at))
+            .map(|(row, _)| row)
+            .collect())
+    }
+}
+
+impl Database {
+    /// Delete the expired rows of every table with a TTL column, returning the
+    /// number of rows deleted
+    pub fn expire_rows(&self) -> Result<usize> {
+        let now = chrono::Utc::now().timestamp_micros();
+        let table_names = self
+            .tables
+            .iter()
+            .filter(|table| table.ttl.is_some())
+            .map(|table| table.name.to_owned())
+            .collect::<Vec<_>>();
+        let mut count = 0;
+
+        for table_name in table_names.iter() {
+            let change = {
+                let mut table = get_mut_table!(self, table_name)?;
+                let rows = table.expired_rows(now)?;
+                let before = table.chunks.to_owned();
+                table.delete_rows(&rows)?;
+
+                TableChange {
+                    kind: ChangeKind::Delete,
+                    rows,
+                    before,
+                    after: table.chunks.to_owned(),
+                }
+            };
+
+            if change.rows.is_empty() {
+                continue;
+            }
+
+            count += change.rows.len();
+
+            let version = self.commit_table(table_name)?;
+            self.publish_change(table_name, version, &change)?;
+
+            #[cfg(not(target_arch = "wasm32"))]
+            self.schedule_write(table_name)?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database {
+    /// Delete expired rows in the background at an interval, until the
+    /// database is dropped.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn expire_rows_every(
+        self: &std::sync::Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let database = std::sync::Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                interval.tick().await;
+
+                let Some(database) = database.upgrade() else {
+                    return;
+                };
+
+                // a failed sweep is retried on the next tick
+                let _ = database.expire_rows();
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{Int32Array, TimestampMillisecondArray};
+    use arrow::datatypes::Int32Type;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    fn add_last_seen(database: &mut Database) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let hour = 60 * 60 * 1000;
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .add_column::<TimestampMillisecondArray>(
+                2,
+                "last_seen",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                TimestampMillisecondArray::from(vec![
+                    Some(now),
+                    Some(now - 2 * hour),
+                    None,
+                    Some(now - 3 * hour),
+                ])
+                .into(),
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expire_rows() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        add_last_seen(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        // only timestamp columns can be TTL columns
+        assert!(get_mut_table!(database, "users")
+            .unwrap()
+            .set_ttl("name", Duration::from_secs(60))
+            .is_err());
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_ttl("last_seen", Duration::from_secs(60 * 60))
+            .unwrap();
+
+        assert_eq!(database.expire_rows().unwrap(), 2);
+        assert_eq!(database.expire_rows().unwrap(), 0);
+        assert_eq!(database.table_version("users").unwrap(), 1);
+
+        let users = get_table!(database, "users").unwrap();
+        assert_eq!(
+            users.column(0).unwrap().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 3])
+        );
+        drop(users);
+
+        // expired rows are deleted from queries too
+        let results = database
+            .query("select * from users")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expire_rows_every() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        add_last_seen(&mut database);
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_ttl("last_seen", Duration::from_secs(60 * 60))
+            .unwrap();
+        database.add_all_table_contexts().unwrap();
+
+        let database = Arc::new(database);
+        let sweep = database.expire_rows_every(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 2);
+
+        // the sweep stops once the database is dropped
+        drop(database);
+        tokio::time::timeout(Duration::from_secs(1), sweep)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
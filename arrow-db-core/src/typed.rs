@@ -0,0 +1,268 @@
+//! Typed rows.
+//!
+//! `query_as` deserializes each row of the results of a query into a Rust
+//! struct, so application code gets a `Vec<User>` back instead of downcasting
+//! the arrays of each column:
+//!
+//! ```rust,ignore
+//! #[derive(serde::Deserialize)]
+//! struct User {
+//!     id: i32,
+//!     name: Option<String>,
+//! }
+//!
+//! let users: Vec<User> = database.query_as("select id, name from users").await?;
+//! ```
+//!
+//! `insert_structs` does the reverse, appending structs to a table as rows
+//! without building SQL strings:
+//!
+//! ```rust,ignore
+//! database.insert_structs("users", &[User { id: 5, name: None }])?;
+//! ```
+//!
+//! Fields are matched to columns by name, and NULLs are `None`.  Rows are
+//! converted through their JSON representation, so temporal columns are
+//! (de)serialized as strings, e.g. from `chrono` types, and binary columns as
+//! hex strings.
+
+use std::{collections::HashSet, sync::Arc};
+
+use arrow::{
+    array::RecordBatch,
+    datatypes::{Schema, SchemaRef},
+    json::{writer::JsonArray, ReaderBuilder, WriterBuilder},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    bulk::BulkLoadOptions,
+    database::Database,
+    error::{DbError, Result},
+    get_table,
+};
+
+impl Database {
+    /// Run a query, deserializing each row of its results into a `T`
+    pub async fn query_as<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        let record_batches = self
+            .query(sql)
+            .await?
+            .collect()
+            .await
+            .map_err(|e| DbError::Query(sql.into(), e.to_string()))?;
+
+        deserialize_rows(&record_batches)
+    }
+
+    /// Append structs to a table as rows, returning the number of rows
+    /// appended.
+    ///
+    /// Columns without a field are loaded as NULL, so auto-increment and
+    /// system columns are filled in.  The rows are loaded with `bulk_load`, so
+    /// nothing is appended if any row can't be cast to the table or violates
+    /// its constraints.
+    pub fn insert_structs<T: Serialize>(&self, table_name: &str, rows: &[T]) -> Result<usize> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let schema = get_table!(self, table_name)?.schema();
+        let batch = serialize_rows(&schema, rows)?;
+        let report = self.bulk_load(table_name, &[batch], BulkLoadOptions::default())?;
+
+        if !report.is_valid() {
+            return Err(DbError::ConstraintViolation(format!(
+                "Cannot insert into Table {table_name}: {}",
+                report.violations_message()
+            )));
+        }
+
+        Ok(report.rows_loaded)
+    }
+}
+
+/// Serialize structs into a record batch with the columns of a schema that
+/// they have fields for
+pub fn serialize_rows<T: Serialize>(schema: &SchemaRef, rows: &[T]) -> Result<RecordBatch> {
+    let serialize_error = |e: &dyn ToString| DbError::Serialization(e.to_string());
+
+    let values = rows
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| serialize_error(&e))?;
+    let mut field_names = HashSet::new();
+
+    for value in values.iter() {
+        let Value::Object(object) = value else {
+            return Err(serialize_error(&format!("{value} is not a struct")));
+        };
+
+        field_names.extend(object.keys().map(String::as_str));
+    }
+
+    if let Some(field_name) = field_names
+        .iter()
+        .find(|field_name| schema.field_with_name(field_name).is_err())
+    {
+        return Err(serialize_error(&format!(
+            "Field {field_name} has no column"
+        )));
+    }
+
+    // NULLs are decoded into every column, so NOT NULL columns are reported
+    // as violations of the load
+    let fields = schema
+        .fields()
+        .iter()
+        .filter(|field| field_names.contains(field.name().as_str()))
+        .map(|field| field.as_ref().to_owned().with_nullable(true))
+        .collect::<Vec<_>>();
+
+    let mut decoder = ReaderBuilder::new(Arc::new(Schema::new(fields)))
+        .with_batch_size(values.len().max(1))
+        .build_decoder()
+        .map_err(|e| serialize_error(&e))?;
+    decoder
+        .serialize(&values)
+        .map_err(|e| serialize_error(&e))?;
+
+    decoder
+        .flush()
+        .map_err(|e| serialize_error(&e))?
+        .ok_or_else(|| serialize_error(&"No rows were serialized"))
+}
+
+/// Deserialize each row of record batches into a `T`
+pub fn deserialize_rows<T: DeserializeOwned>(record_batches: &[RecordBatch]) -> Result<Vec<T>> {
+    if record_batches.iter().all(|batch| batch.num_rows() == 0) {
+        return Ok(vec![]);
+    }
+
+    let deserialize_error = |e: &dyn ToString| DbError::Deserialization(e.to_string());
+
+    // NULLs are written explicitly, so they aren't mistaken for missing
+    // columns by fields without a default
+    let mut writer = WriterBuilder::new()
+        .with_explicit_nulls(true)
+        .build::<_, JsonArray>(Vec::new());
+    writer
+        .write_batches(&record_batches.iter().collect::<Vec<_>>())
+        .map_err(|e| deserialize_error(&e))?;
+    writer.finish().map_err(|e| deserialize_error(&e))?;
+
+    serde_json::from_slice(&writer.into_inner()).map_err(|e| deserialize_error(&e))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde::Deserialize;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table,
+    };
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: i32,
+        name: Option<String>,
+    }
+
+    #[tokio::test]
+    async fn test_query_as() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        database
+            .query("insert into users values (5, NULL)")
+            .await
+            .unwrap();
+
+        let users: Vec<User> = database
+            .query_as("select id, name from users where id > 3 order by id")
+            .await
+            .unwrap();
+        assert_eq!(
+            users,
+            vec![
+                User {
+                    id: 4,
+                    name: Some("David".into())
+                },
+                User { id: 5, name: None },
+            ]
+        );
+
+        let users: Vec<User> = database
+            .query_as("select id, name from users where id > 100")
+            .await
+            .unwrap();
+        assert!(users.is_empty());
+
+        // columns that don't match the fields fail to deserialize
+        assert!(matches!(
+            database.query_as::<User>("select name from users").await,
+            Err(DbError::Deserialization(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_insert_structs() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let users = vec![
+            User {
+                id: 5,
+                name: Some("Eve".into()),
+            },
+            User { id: 6, name: None },
+        ];
+        assert_eq!(database.insert_structs("users", &users).unwrap(), 2);
+
+        let inserted: Vec<User> = database
+            .query_as("select id, name from users where id > 4 order by id")
+            .await
+            .unwrap();
+        assert_eq!(inserted, users);
+
+        #[derive(Serialize)]
+        struct Admin {
+            id: i32,
+            level: u8,
+        }
+
+        #[derive(Serialize)]
+        struct Named {
+            name: String,
+        }
+
+        // fields without a column and rows that violate constraints are
+        // rejected, and nothing is appended
+        assert!(matches!(
+            database.insert_structs("users", &[Admin { id: 7, level: 1 }]),
+            Err(DbError::Serialization(_))
+        ));
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_not_null("id")
+            .unwrap();
+        assert!(matches!(
+            database.insert_structs(
+                "users",
+                &[Named {
+                    name: "Frank".into()
+                }]
+            ),
+            Err(DbError::ConstraintViolation(_))
+        ));
+        assert_eq!(database.tables.get("users").unwrap().num_rows(), 6);
+    }
+}
@@ -0,0 +1,291 @@
+//! Upserts.
+//!
+//! `upsert` writes a record batch to a table with a primary key: rows whose
+//! key is already in the table update it, and the other rows are appended,
+//! e.g. to apply a stream of changes from another system.
+//!
+//! Columns are matched by name and cast to the types of the table.  Updated
+//! rows keep the values of the columns the batch doesn't have, and appended
+//! rows are filled in like they are for INSERTs.  Rows are applied in order,
+//! so the last of several rows with the same key wins.
+//!
+//! A batch is applied as a whole: if any row violates a constraint of the
+//! table, nothing is written.  The updates and appends are committed as one
+//! version, and published to subscribers as an update and an insert.
+//! Triggers aren't run, as with bulk loads.
+
+use arrow::{array::RecordBatch, compute::cast};
+use datafusion::scalar::ScalarValue;
+
+use crate::{
+    change::{ChangeKind, TableChange},
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    system::{now, UPDATED_AT_COLUMN},
+};
+
+/// The outcome of an upsert
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpsertReport {
+    pub rows_inserted: usize,
+    pub rows_updated: usize,
+}
+
+impl Database {
+    /// Update the rows of a table whose primary key is in a record batch, and
+    /// append the other rows of the batch
+    pub fn upsert(&self, table_name: &str, batch: &RecordBatch) -> Result<UpsertReport> {
+        let data_type_error = |e: &dyn ToString| DbError::DataType(e.to_string());
+
+        let (report, changes) = {
+            let mut table = get_mut_table!(self, table_name)?;
+            let key_index = table
+                .primary_key_index()?
+                .ok_or_else(|| DbError::PrimaryKeyNotFound(table_name.into()))?;
+            let schema = table.schema();
+            let key_name = schema.field(key_index).name().to_owned();
+
+            // the columns of the batch in the types of the table, along with
+            // the indexes of their table columns
+            let columns = batch
+                .schema()
+                .fields()
+                .iter()
+                .zip(batch.columns())
+                .map(|(field, column)| {
+                    let column_index = table.column_index(field.name())?;
+                    let column = cast(column, schema.field(column_index).data_type())
+                        .map_err(|e| data_type_error(&e))?;
+
+                    Ok((column_index, column))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let key_column = columns
+                .iter()
+                .find(|(column_index, _)| *column_index == key_index)
+                .map(|(_, column)| column.to_owned())
+                .ok_or_else(|| {
+                    DbError::Dml(format!(
+                        "Upserts into Table {table_name} need its primary key column {key_name}"
+                    ))
+                })?;
+
+            // system columns are maintained unless they're in the batch
+            let updated_at = table
+                .column_index(UPDATED_AT_COLUMN)
+                .ok()
+                .filter(|updated_at| {
+                    !columns
+                        .iter()
+                        .any(|(column_index, _)| column_index == updated_at)
+                });
+
+            // rows are staged on a copy, so nothing changes if a row fails
+            let mut staged = table.to_owned();
+            let num_rows = staged.num_rows();
+            let mut updated = vec![];
+
+            for row in 0..batch.num_rows() {
+                let key = ScalarValue::try_from_array(&key_column, row)
+                    .map_err(|e| data_type_error(&e))?;
+                let values = columns
+                    .iter()
+                    .map(|(column_index, column)| {
+                        ScalarValue::try_from_array(column, row)
+                            .map(|value| (*column_index, value))
+                            .map_err(|e| data_type_error(&e))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                match staged.index_lookup(&key_name, &key)?.first() {
+                    Some(&existing) => {
+                        let mut assignments = values
+                            .into_iter()
+                            .filter(|(column_index, _)| *column_index != key_index)
+                            .collect::<Vec<_>>();
+
+                        if let Some(updated_at) = updated_at {
+                            assignments.push((updated_at, now()));
+                        }
+
+                        staged.update_row(existing, &assignments)?;
+
+                        // rows appended by the batch are inserts
+                        if existing < num_rows {
+                            updated.push(existing);
+                        }
+                    }
+                    None => {
+                        let mut row_values = schema
+                            .fields()
+                            .iter()
+                            .map(|field| ScalarValue::try_from(field.data_type()))
+                            .collect::<std::result::Result<Vec<_>, _>>()
+                            .map_err(|e| data_type_error(&e))?;
+
+                        for (column_index, value) in values {
+                            row_values[column_index] = value;
+                        }
+
+                        staged.append_row(row_values)?;
+                    }
+                }
+            }
+
+            updated.sort_unstable();
+            updated.dedup();
+
+            let report = UpsertReport {
+                rows_inserted: staged.num_rows() - num_rows,
+                rows_updated: updated.len(),
+            };
+            let before = std::mem::replace(&mut *table, staged).chunks;
+            let changes = [
+                TableChange {
+                    kind: ChangeKind::Update,
+                    rows: updated,
+                    before: before.to_owned(),
+                    after: table.chunks.to_owned(),
+                },
+                TableChange::inserted(before, &table.chunks),
+            ];
+
+            (report, changes)
+        };
+
+        if report == UpsertReport::default() {
+            return Ok(report);
+        }
+
+        let version = self.commit_table(table_name)?;
+
+        for change in changes.iter().filter(|change| !change.rows.is_empty()) {
+            self.publish_change(table_name, version, change)?;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.schedule_write(table_name)?;
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Int32Array, StringArray},
+        datatypes::{DataType, Field, Int32Type, Schema},
+    };
+    use futures::StreamExt;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_mut_table, get_table,
+    };
+
+    use super::*;
+
+    fn batch(ids: Vec<i32>, names: Vec<Option<&str>>) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, true),
+                Field::new("name", DataType::Utf8, true),
+            ])),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(names)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upsert() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        assert_eq!(
+            database.upsert("users", &batch(vec![1], vec![Some("Al")])),
+            Err(DbError::PrimaryKeyNotFound("users".into()))
+        );
+
+        get_mut_table!(database, "users")
+            .unwrap()
+            .set_primary_key("id")
+            .unwrap();
+        let mut changes = database.subscribe("users").unwrap();
+
+        // the last row with a key wins
+        let report = database
+            .upsert(
+                "users",
+                &batch(
+                    vec![2, 5, 5, 3],
+                    vec![Some("Bobby"), Some("Eve"), Some("Evelyn"), None],
+                ),
+            )
+            .unwrap();
+        assert_eq!(
+            report,
+            UpsertReport {
+                rows_inserted: 1,
+                rows_updated: 2,
+            }
+        );
+
+        let record_batches = database
+            .query("select id, name from users order by id")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            record_batches[0].column(0).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 2, 3, 4, 5])
+        );
+        assert_eq!(
+            record_batches[0].column(1).as_string::<i32>(),
+            &StringArray::from(vec![
+                Some("Alice"),
+                Some("Bobby"),
+                None,
+                Some("David"),
+                Some("Evelyn")
+            ])
+        );
+
+        let update = changes.next().await.unwrap();
+        assert_eq!(update.kind, ChangeKind::Update);
+        assert_eq!(update.rows, vec![1, 2]);
+        let insert = changes.next().await.unwrap();
+        assert_eq!(insert.kind, ChangeKind::Insert);
+        assert_eq!(insert.rows, vec![4]);
+
+        // a batch with a row that violates a constraint writes nothing
+        database
+            .add_check_constraint("users", "small_id", "id < 6")
+            .unwrap();
+        assert!(matches!(
+            database.upsert(
+                "users",
+                &batch(vec![1, 6], vec![Some("Alicia"), Some("Frank")])
+            ),
+            Err(DbError::ConstraintViolation(_))
+        ));
+        assert_eq!(get_table!(database, "users").unwrap().num_rows(), 5);
+        assert_eq!(
+            database
+                .get_row("users", &ScalarValue::Int32(Some(1)))
+                .unwrap()
+                .unwrap()
+                .get("name"),
+            Some(&ScalarValue::Utf8(Some("Alice".into())))
+        );
+    }
+}
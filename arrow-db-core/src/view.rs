@@ -0,0 +1,193 @@
+//! Materialized views.
+//!
+//! A materialized view stores the results of a query as a regular table, so
+//! an expensive query, like an aggregation, is computed once and can then be
+//! served any number of times.  The view is only recomputed when it is
+//! refreshed, with `refresh_view` or `REFRESH TABLE name`.
+
+use std::sync::Arc;
+
+use arrow::{array::RecordBatch, compute::concat_batches};
+use chrono::{DateTime, Utc};
+use datafusion::logical_expr::LogicalPlan;
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    get_mut_table,
+    table::Table,
+};
+
+/// The query of a materialized view
+#[derive(Debug, Clone)]
+pub struct MaterializedView {
+    pub sql: String,
+    pub refreshed_at: DateTime<Utc>,
+}
+
+impl Database {
+    /// Run a query and store its results as a table, returning the number of
+    /// rows stored
+    pub async fn create_materialized_view(&mut self, view_name: &str, sql: &str) -> Result<usize> {
+        if self.tables.contains_key(view_name) {
+            return Err(DbError::TableAlreadyExists(view_name.into()));
+        }
+
+        let record_batch = self.materialize(sql).await?;
+        let num_rows = record_batch.num_rows();
+        let mut table = Table::new(view_name);
+        table.set_record_batch(record_batch);
+
+        self.add_table(table)?;
+        self.add_table_context(view_name)?;
+        self.views.insert(
+            view_name.into(),
+            MaterializedView {
+                sql: sql.into(),
+                refreshed_at: Utc::now(),
+            },
+        );
+
+        Ok(num_rows)
+    }
+
+    /// Re-run the query of a materialized view and swap in the new results,
+    /// returning the number of rows stored
+    pub async fn refresh_view(&self, view_name: &str) -> Result<usize> {
+        let sql = self
+            .views
+            .get(view_name)
+            .map(|view| view.sql.to_owned())
+            .ok_or_else(|| DbError::TableNotFound(format!("Materialized view {view_name}")))?;
+
+        let record_batch = self.materialize(&sql).await?;
+        let num_rows = record_batch.num_rows();
+
+        {
+            let mut view = get_mut_table!(self, view_name)?;
+            view.set_record_batch(record_batch);
+            view.rebuild_indexes()?;
+        }
+
+        self.commit_table(view_name)?;
+
+        if let Some(mut view) = self.views.get_mut(view_name) {
+            view.refreshed_at = Utc::now();
+        }
+
+        Ok(num_rows)
+    }
+
+    /// Check if a table is a materialized view
+    pub fn is_view(&self, table_name: &str) -> bool {
+        self.views.contains_key(table_name)
+    }
+
+    /// Run the query of a view into a single `RecordBatch`.
+    ///
+    /// Query options apply, except `max_rows`, so a view is never truncated.
+    async fn materialize(&self, sql: &str) -> Result<RecordBatch> {
+        let query_error = |e: String| DbError::Query(sql.into(), e);
+
+        self.refresh_expired_tables().await?;
+
+        let plan = self.create_logical_plan(sql).await?;
+
+        if matches!(
+            plan,
+            LogicalPlan::Dml(_)
+                | LogicalPlan::Ddl(_)
+                | LogicalPlan::Statement(_)
+                | LogicalPlan::Copy(_)
+        ) {
+            return Err(query_error("A materialized view must be a query".into()));
+        }
+
+        let plan = self.options().apply_to_plan(plan)?;
+        let df = self
+            .ctx
+            .execute_logical_plan(plan)
+            .await
+            .map_err(|e| query_error(e.to_string()))?;
+        let schema = Arc::new(df.schema().as_arrow().to_owned());
+        let record_batches = df.collect().await.map_err(|e| query_error(e.to_string()))?;
+
+        concat_batches(&schema, &record_batches).map_err(|e| query_error(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{AsArray, Int64Array};
+    use arrow::datatypes::Int64Type;
+
+    use crate::{
+        database::tests::{create_database, seed_database},
+        get_table,
+    };
+
+    use super::*;
+
+    fn user_count(database: &Database) -> Int64Array {
+        get_table!(database, "user_count")
+            .unwrap()
+            .column(0)
+            .unwrap()
+            .as_primitive::<Int64Type>()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_materialized_views() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let num_rows = database
+            .create_materialized_view("user_count", "select count(*) as users from users")
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 1);
+        assert!(database.is_view("user_count"));
+        assert_eq!(user_count(&database), Int64Array::from(vec![4]));
+
+        // views are only recomputed when refreshed
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        assert_eq!(user_count(&database), Int64Array::from(vec![4]));
+
+        database.refresh_view("user_count").await.unwrap();
+        assert_eq!(user_count(&database), Int64Array::from(vec![5]));
+
+        database
+            .query("insert into users values (6, 'Frank')")
+            .await
+            .unwrap();
+        database.query("REFRESH TABLE user_count").await.unwrap();
+
+        let results = database
+            .query("select users from user_count")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0].column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![6])
+        );
+
+        // views must be queries, with unique names
+        assert!(database
+            .create_materialized_view("deleted", "delete from users")
+            .await
+            .is_err());
+        assert!(database
+            .create_materialized_view("users", "select * from users")
+            .await
+            .is_err());
+        assert!(database.refresh_view("users").await.is_err());
+    }
+}
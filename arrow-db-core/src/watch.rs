@@ -0,0 +1,179 @@
+//! Live queries.
+//!
+//! `watch` combines change subscriptions with queries: it returns a stream
+//! of the results of a query, which are emitted once when the stream is
+//! first polled and again whenever a table the query reads changes, e.g. to
+//! keep a UI in sync with the database.
+//!
+//! The query is planned once and re-run in full on every change.  Changes
+//! committed while the query is running are coalesced into a single re-run,
+//! so a burst of writes doesn't queue a result for each of them.  The stream
+//! ends when the database is dropped.
+
+use arrow::array::RecordBatch;
+use datafusion::{
+    common::tree_node::TreeNodeRecursion,
+    logical_expr::{LogicalPlan, LogicalPlanBuilder},
+    prelude::SessionContext,
+    sql::{parser::Statement, sqlparser::ast::Statement as SqlStatement},
+};
+use futures::{
+    future::ready,
+    stream::{self, select_all, BoxStream},
+    StreamExt,
+};
+
+use crate::{
+    database::Database,
+    error::{DbError, Result},
+    prepared::refresh_table_sources,
+};
+
+/// The most changes coalesced into a single re-run of a live query
+const MAX_COALESCED_CHANGES: usize = 1024;
+
+/// A stream of the results of a query, re-emitted whenever a table the
+/// query reads changes
+pub type QueryWatch = BoxStream<'static, Result<Vec<RecordBatch>>>;
+
+impl Database {
+    /// Watch the results of a query, returning a stream that emits them now
+    /// and every time a table the query reads changes
+    pub async fn watch(&self, sql: &str) -> Result<QueryWatch> {
+        let query_error = |e: &dyn ToString| DbError::Query(sql.into(), e.to_string());
+
+        let statement = self.parse_statement(sql)?;
+
+        if !matches!(
+            &statement,
+            Statement::Statement(sql_statement)
+                if matches!(sql_statement.as_ref(), SqlStatement::Query(_))
+        ) {
+            return Err(query_error(&"Only queries can be watched"));
+        }
+
+        let options = self.options();
+        let plan = self.plan_statement(sql, statement).await?;
+        let mut plan = options.apply_to_plan(plan)?;
+
+        if let Some(max_rows) = options.max_rows {
+            plan = LogicalPlanBuilder::from(plan)
+                .limit(0, Some(max_rows))
+                .and_then(|builder| builder.build())
+                .map_err(|e| query_error(&e))?;
+        }
+
+        let changes = scanned_tables(&plan)?
+            .iter()
+            .filter(|table_name| self.tables.contains_key(table_name.as_str()))
+            .map(|table_name| self.subscribe(table_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        if changes.is_empty() {
+            return Err(query_error(&"The query reads no tables to watch"));
+        }
+
+        // the context shares its tables with the database, so the query sees
+        // every committed write
+        let ctx = self.ctx.to_owned();
+        let sql = sql.to_owned();
+
+        let results = stream::once(ready(()))
+            .chain(select_all(changes).map(|_| ()))
+            .ready_chunks(MAX_COALESCED_CHANGES)
+            .then(move |_| {
+                let ctx = ctx.to_owned();
+                let plan = plan.to_owned();
+                let sql = sql.to_owned();
+
+                async move { execute_watched(&ctx, plan, &sql).await }
+            });
+
+        Ok(results.boxed())
+    }
+}
+
+/// The names of the tables a plan scans
+fn scanned_tables(plan: &LogicalPlan) -> Result<Vec<String>> {
+    let mut table_names = vec![];
+
+    plan.apply_with_subqueries(|plan| {
+        if let LogicalPlan::TableScan(scan) = plan {
+            let table_name = scan.table_name.table().to_owned();
+
+            if !table_names.contains(&table_name) {
+                table_names.push(table_name);
+            }
+        }
+
+        Ok(TreeNodeRecursion::Continue)
+    })
+    .map_err(|e| DbError::Query(plan.to_string(), e.to_string()))?;
+
+    Ok(table_names)
+}
+
+/// Run the plan of a live query against the currently registered tables
+async fn execute_watched(
+    ctx: &SessionContext,
+    plan: LogicalPlan,
+    sql: &str,
+) -> Result<Vec<RecordBatch>> {
+    let query_error = |e: &dyn ToString| DbError::Query(sql.into(), e.to_string());
+    let plan = refresh_table_sources(ctx, plan).await?;
+
+    ctx.execute_logical_plan(plan)
+        .await
+        .map_err(|e| query_error(&e))?
+        .collect()
+        .await
+        .map_err(|e| query_error(&e))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::{array::AsArray, datatypes::Int64Type};
+
+    use crate::database::tests::{create_database, seed_database};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watch() {
+        let (mut database, _) = create_database();
+        seed_database(&mut database);
+        database.add_all_table_contexts().unwrap();
+
+        let count = |record_batches: Vec<RecordBatch>| {
+            record_batches[0]
+                .column(0)
+                .as_primitive::<Int64Type>()
+                .value(0)
+        };
+        let mut watch = database.watch("select count(*) from users").await.unwrap();
+        assert_eq!(count(watch.next().await.unwrap().unwrap()), 4);
+
+        database
+            .query("insert into users values (5, 'Eve')")
+            .await
+            .unwrap();
+        assert_eq!(count(watch.next().await.unwrap().unwrap()), 5);
+
+        // changes made before the stream is polled are coalesced
+        database
+            .query("insert into users values (6, 'Frank')")
+            .await
+            .unwrap();
+        database
+            .query("delete from users where id = 1")
+            .await
+            .unwrap();
+        assert_eq!(count(watch.next().await.unwrap().unwrap()), 5);
+
+        assert!(database
+            .watch("insert into users values (7, 'Grace')")
+            .await
+            .is_err());
+        assert!(database.watch("select 1").await.is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Zone maps.
+//!
+//! A zone map is the min and max value of a column in a chunk.  Queries with
+//! range or equality predicates on a column, like `distance > 1000 AND
+//! distance < 3000`, skip the chunks whose zone maps rule the predicate out
+//! instead of scanning every row.
+//!
+//! Chunks are immutable, so a zone map is computed the first time a query
+//! filters on its column and kept until the chunk is rewritten.  Chunks that a
+//! write leaves untouched keep their zone maps.
+
+use std::{
+    cmp::Ordering,
+    sync::{Arc, OnceLock},
+};
+
+use arrow::array::{Array, ArrayRef, RecordBatch};
+use datafusion::{
+    common::DFSchema,
+    logical_expr::{Between, BinaryExpr, Expr, Operator},
+    scalar::ScalarValue,
+};
+
+use crate::{
+    dml::{compare_values, evaluate_scalar},
+    error::Result,
+    row::get_column_value,
+};
+
+/// The min and max values of a column in a chunk, ignoring NULLs.  Both are
+/// NULL if every value is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneMap {
+    pub min: ScalarValue,
+    pub max: ScalarValue,
+}
+
+impl ZoneMap {
+    /// Compute the zone map of a column
+    pub fn new(column: &ArrayRef) -> Result<ZoneMap> {
+        let mut min = ScalarValue::try_from(column.data_type()).unwrap_or(ScalarValue::Null);
+        let mut max = min.to_owned();
+
+        for row in 0..column.len() {
+            if column.is_null(row) {
+                continue;
+            }
+
+            let value = get_column_value(column, row)?;
+
+            if min.is_null() || value.partial_cmp(&min) == Some(Ordering::Less) {
+                min = value.to_owned();
+            }
+
+            if max.is_null() || value.partial_cmp(&max) == Some(Ordering::Greater) {
+                max = value;
+            }
+        }
+
+        Ok(ZoneMap { min, max })
+    }
+
+    /// Whether a comparison of the column with a value can be true for any
+    /// row of the chunk
+    fn may_match(&self, op: Operator, value: &ScalarValue) -> bool {
+        if !matches!(
+            op,
+            Operator::Eq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq
+        ) {
+            return true;
+        }
+
+        // comparisons with NULL are never true
+        if self.min.is_null() || value.is_null() {
+            return false;
+        }
+
+        let (Ok(Some(min)), Ok(Some(max))) = (
+            compare_values(&self.min, value),
+            compare_values(&self.max, value),
+        ) else {
+            return true;
+        };
+
+        match op {
+            Operator::Eq => min != Ordering::Greater && max != Ordering::Less,
+            Operator::Lt => min == Ordering::Less,
+            Operator::LtEq => min != Ordering::Greater,
+            Operator::Gt => max == Ordering::Greater,
+            _ => max != Ordering::Less,
+        }
+    }
+}
+
+/// A chunk of a table, with the zone maps of its columns computed on demand
+#[derive(Debug, Clone)]
+pub(crate) struct ZonedChunk {
+    pub(crate) chunk: RecordBatch,
+    zone_maps: Vec<Arc<OnceLock<Option<ZoneMap>>>>,
+}
+
+impl ZonedChunk {
+    fn new(chunk: RecordBatch) -> ZonedChunk {
+        let zone_maps = (0..chunk.num_columns())
+            .map(|_| Arc::new(OnceLock::new()))
+            .collect();
+
+        ZonedChunk { chunk, zone_maps }
+    }
+
+    /// The zone map of a column, or `None` if its values can't be compared
+    fn zone_map(&self, column_index: usize) -> Option<&ZoneMap> {
+        self.zone_maps[column_index]
+            .get_or_init(|| ZoneMap::new(self.chunk.column(column_index)).ok())
+            .as_ref()
+    }
+
+    /// Whether the chunk holds the same arrays as another chunk
+    fn same_chunk(&self, chunk: &RecordBatch) -> bool {
+        self.chunk.num_rows() == chunk.num_rows()
+            && self
+                .chunk
+                .columns()
+                .iter()
+                .zip(chunk.columns())
+                .all(|(left, right)| Arc::ptr_eq(left, right))
+    }
+
+    /// Whether a filter can be true for any row of the chunk
+    pub(crate) fn may_match(&self, filter: &Expr) -> bool {
+        match filter {
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+                Operator::And => self.may_match(left) && self.may_match(right),
+                Operator::Or => self.may_match(left) || self.may_match(right),
+                op => match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(column), value) => self.compare(&column.name, *op, value),
+                    (value, Expr::Column(column)) => match op.swap() {
+                        Some(op) => self.compare(&column.name, op, value),
+                        None => true,
+                    },
+                    _ => true,
+                },
+            },
+            Expr::Between(Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            }) => match expr.as_ref() {
+                Expr::Column(column) => {
+                    self.compare(&column.name, Operator::GtEq, low)
+                        && self.compare(&column.name, Operator::LtEq, high)
+                }
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+
+    /// Whether comparing a column with the value of an expression can be true
+    /// for any row of the chunk
+    fn compare(&self, column_name: &str, op: Operator, value: &Expr) -> bool {
+        let Ok(column_index) = self.chunk.schema().index_of(column_name) else {
+            return true;
+        };
+
+        let Ok(value) = evaluate_scalar(value, &[], &DFSchema::empty()) else {
+            return true;
+        };
+
+        match self.zone_map(column_index) {
+            Some(zone_map) => zone_map.may_match(op, &value),
+            None => true,
+        }
+    }
+}
+
+/// Zone chunks, keeping the zone maps of chunks at the same position that
+/// weren't rewritten
+pub(crate) fn zone_chunks(previous: &[ZonedChunk], chunks: &[RecordBatch]) -> Vec<ZonedChunk> {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| match previous.get(index) {
+            Some(zoned) if zoned.same_chunk(chunk) => zoned.to_owned(),
+            _ => ZonedChunk::new(chunk.to_owned()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::Int32Array;
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+
+    fn chunk(values: Vec<Option<i32>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "distance",
+            DataType::Int32,
+            true,
+        )]));
+
+        RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(values))]).unwrap()
+    }
+
+    #[test]
+    fn test_zone_maps() {
+        let zoned = ZonedChunk::new(chunk(vec![Some(1200), None, Some(2500), Some(1800)]));
+        assert_eq!(
+            zoned.zone_map(0),
+            Some(&ZoneMap {
+                min: ScalarValue::Int32(Some(1200)),
+                max: ScalarValue::Int32(Some(2500)),
+            })
+        );
+
+        // literals of other integer types compare by value
+        let distance = || col("distance");
+        assert!(zoned.may_match(&distance().gt(lit(1000i64)).and(distance().lt(lit(3000i64)))));
+        assert!(zoned.may_match(&distance().eq(lit(2500))));
+        assert!(!zoned.may_match(&distance().gt(lit(2500))));
+        assert!(!zoned.may_match(&lit(1200).gt(distance())));
+        assert!(!zoned.may_match(&distance().between(lit(0), lit(1000))));
+        assert!(zoned.may_match(&distance().lt(lit(0)).or(distance().eq(lit(1800)))));
+        assert!(zoned.may_match(&distance().not_eq(lit(1200))));
+
+        // chunks of only NULLs never match a comparison
+        let nulls = ZonedChunk::new(chunk(vec![None, None]));
+        assert!(!nulls.may_match(&distance().gt(lit(0))));
+        assert!(nulls.may_match(&distance().is_null()));
+
+        // unchanged chunks keep their zone maps
+        let previous = vec![zoned];
+        let chunks = vec![previous[0].chunk.to_owned(), chunk(vec![Some(1)])];
+        let zoned = zone_chunks(&previous, &chunks);
+        assert!(zoned[0].zone_maps[0].get().is_some());
+        assert!(zoned[1].zone_maps[0].get().is_none());
+
+        let zoned = zone_chunks(&previous, &[chunk(vec![Some(1200), Some(2500)])]);
+        assert!(zoned[0].zone_maps[0].get().is_none());
+    }
+}
@@ -0,0 +1,204 @@
+//! `#[derive(ArrowTable)]` defines an ArrowDB table with a Rust struct.
+//!
+//! Each field of the struct is a column of the table, in field order, and its
+//! Rust type picks the data type of the column, see `ArrowField` in
+//! `arrow-db-core`.  `Option` fields are nullable, and every other field is
+//! NOT NULL.
+//!
+//! ```rust,ignore
+//! #[derive(ArrowTable, Serialize, Deserialize)]
+//! #[arrow_table(name = "users")]
+//! struct User {
+//!     #[arrow_table(primary_key)]
+//!     id: i32,
+//!     #[arrow_table(check = "age >= 0")]
+//!     age: i32,
+//!     name: Option<String>,
+//!     #[arrow_table(json)]
+//!     settings: Option<String>,
+//! }
+//! ```
+//!
+//! The table is named after the struct in snake case unless it's named with
+//! `#[arrow_table(name = "...")]`.  Fields take these attributes:
+//!
+//! | Attribute             | Description                                     |
+//! | --------------------- | ----------------------------------------------- |
+//! | `primary_key`         | The column is the primary key of the table      |
+//! | `json`                | The column is a JSON column                     |
+//! | `check = "<sql>"`     | A CHECK constraint on the column                |
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Implement `arrow_db_core::arrow_table::ArrowTable` for a struct with named
+/// fields
+#[proc_macro_derive(ArrowTable, attributes(arrow_table))]
+pub fn derive_arrow_table(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The attributes of a field
+#[derive(Default)]
+struct ColumnAttributes {
+    primary_key: bool,
+    json: bool,
+    check: Option<LitStr>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "ArrowTable can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "ArrowTable can only be derived for structs with named fields",
+        ));
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let table_name = table_name(input)?;
+    let mut primary_keys = 0;
+    let mut columns = vec![];
+
+    for field in fields.named.iter() {
+        let attributes = column_attributes(field)?;
+        let name = field
+            .ident
+            .as_ref()
+            .map(|ident| ident.to_string().trim_start_matches("r#").to_string())
+            .expect("named fields have names");
+        let ty = &field.ty;
+        let primary_key = attributes.primary_key;
+        let json = attributes.json;
+        let check = match attributes.check {
+            Some(check) => quote!(::std::option::Option::Some(#check)),
+            None => quote!(::std::option::Option::None),
+        };
+
+        if primary_key {
+            primary_keys += 1;
+
+            if primary_keys > 1 {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "A table can only have one primary key",
+                ));
+            }
+        }
+
+        columns.push(quote! {
+            ::arrow_db_core::arrow_table::ColumnDef {
+                name: #name,
+                data_type: <#ty as ::arrow_db_core::arrow_table::ArrowField>::data_type(),
+                nullable: <#ty as ::arrow_db_core::arrow_table::ArrowField>::nullable(),
+                primary_key: #primary_key,
+                json: #json,
+                check: #check,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::arrow_db_core::arrow_table::ArrowTable for #ident #type_generics #where_clause {
+            const TABLE_NAME: &'static str = #table_name;
+
+            fn columns() -> ::std::vec::Vec<::arrow_db_core::arrow_table::ColumnDef> {
+                ::std::vec![#(#columns),*]
+            }
+        }
+    })
+}
+
+/// The name of the table from `#[arrow_table(name = "...")]`, or the name of
+/// the struct in snake case
+fn table_name(input: &DeriveInput) -> syn::Result<String> {
+    let mut table_name = None;
+
+    for attribute in input.attrs.iter() {
+        if !attribute.path().is_ident("arrow_table") {
+            continue;
+        }
+
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                table_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown table attribute, expected `name`"))
+            }
+        })?;
+    }
+
+    Ok(table_name.unwrap_or_else(|| snake_case(&input.ident.to_string())))
+}
+
+/// The attributes of a field from `#[arrow_table(...)]`
+fn column_attributes(field: &syn::Field) -> syn::Result<ColumnAttributes> {
+    let mut attributes = ColumnAttributes::default();
+
+    for attribute in field.attrs.iter() {
+        if !attribute.path().is_ident("arrow_table") {
+            continue;
+        }
+
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("primary_key") {
+                attributes.primary_key = true;
+            } else if meta.path.is_ident("json") {
+                attributes.json = true;
+            } else if meta.path.is_ident("check") {
+                attributes.check = Some(meta.value()?.parse::<LitStr>()?);
+            } else {
+                return Err(meta
+                    .error("unknown column attribute, expected `primary_key`, `json` or `check`"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(attributes)
+}
+
+/// Convert a struct name like `UserRole` to `user_role`
+fn snake_case(name: &str) -> String {
+    let mut snake_case = String::with_capacity(name.len() + 4);
+
+    for (index, character) in name.chars().enumerate() {
+        if character.is_uppercase() {
+            if index > 0 {
+                snake_case.push('_');
+            }
+
+            snake_case.extend(character.to_lowercase());
+        } else {
+            snake_case.push(character);
+        }
+    }
+
+    snake_case
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!(snake_case("User"), "user");
+        assert_eq!(snake_case("UserRole"), "user_role");
+        assert_eq!(snake_case("user_role"), "user_role");
+    }
+}
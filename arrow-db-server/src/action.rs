@@ -0,0 +1,200 @@
+//! Administrative actions.
+//!
+//! Management operations are Flight actions instead of SQL tickets.  Bodies
+//! and results are UTF-8 text or JSON:
+//!
+//! | Action            | Body                        | Results                     |
+//! | ----------------- | --------------------------- | --------------------------- |
+//! | `create_table`    | A `CreateTable` as JSON     | None                        |
+//! | `drop_table`      | The name of the table       | None                        |
+//! | `export_to_disk`  | None                        | None                        |
+//! | `checkpoint`      | None                        | The manifest as JSON        |
+//! | `list_tables`     | None                        | One per table, its name     |
+//! | `list_databases`  | None                        | One per database, its name  |
+//! | `refresh_context` | None                        | None                        |
+//!
+//! Actions run against the database of the request, except `list_databases`,
+//! which lists every database of the server.
+
+use std::str::FromStr;
+
+use arrow_db_core::{export::ExportOptions, Database};
+use arrow_flight::{Action, ActionType};
+use arrow_schema::{DataType, Field, Schema};
+use serde::Deserialize;
+use tonic::Status;
+
+use crate::{databases::Databases, db_to_tonic_err};
+
+pub const CREATE_TABLE: &str = "create_table";
+pub const DROP_TABLE: &str = "drop_table";
+pub const EXPORT_TO_DISK: &str = "export_to_disk";
+pub const CHECKPOINT: &str = "checkpoint";
+pub const LIST_TABLES: &str = "list_tables";
+pub const LIST_DATABASES: &str = "list_databases";
+pub const REFRESH_CONTEXT: &str = "refresh_context";
+
+/// The body of a `create_table` action, e.g.
+///
+/// ```json
+/// {
+///     "name": "users",
+///     "columns": [{ "name": "id", "data_type": "Int32", "not_null": true }],
+///     "primary_key": "id"
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateTable {
+    pub name: String,
+    pub columns: Vec<CreateColumn>,
+    #[serde(default)]
+    pub primary_key: Option<String>,
+}
+
+/// A column of a `create_table` action
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateColumn {
+    pub name: String,
+    /// The Arrow data type of the column as it's displayed, e.g. `Int32`,
+    /// `Utf8` or `Timestamp(Microsecond, None)`
+    pub data_type: String,
+    #[serde(default)]
+    pub not_null: bool,
+}
+
+/// The actions the server supports
+pub fn action_types() -> Vec<ActionType> {
+    [
+        (CREATE_TABLE, "Create an empty table from a JSON definition"),
+        (DROP_TABLE, "Drop the table named by the body"),
+        (EXPORT_TO_DISK, "Export every table to the data directory"),
+        (
+            CHECKPOINT,
+            "Write every table to a new checkpoint, returning its manifest",
+        ),
+        (LIST_TABLES, "List the name of every table"),
+        (LIST_DATABASES, "List the name of every database"),
+        (
+            REFRESH_CONTEXT,
+            "Register every table with the query context again",
+        ),
+    ]
+    .into_iter()
+    .map(|(r#type, description)| ActionType {
+        r#type: r#type.into(),
+        description: description.into(),
+    })
+    .collect()
+}
+
+/// Whether an action only reads, so a read-only server can run it
+pub fn is_read_only(action_type: &str) -> bool {
+    matches!(action_type, LIST_TABLES | LIST_DATABASES | REFRESH_CONTEXT)
+}
+
+/// Run an action against a database of the server, returning its results
+pub async fn do_action(
+    databases: &Databases,
+    database: &Database,
+    action: Action,
+) -> Result<Vec<arrow_flight::Result>, Status> {
+    let body = std::str::from_utf8(&action.body)
+        .map_err(|e| Status::invalid_argument(format!("Invalid action body: {e:?}")))?;
+
+    match action.r#type.as_str() {
+        CREATE_TABLE => {
+            let create_table = serde_json::from_str::<CreateTable>(body)
+                .map_err(|e| Status::invalid_argument(format!("Invalid table: {e}")))?;
+            create(database, create_table)?;
+
+            Ok(vec![])
+        }
+        DROP_TABLE => {
+            database.drop_table(body.trim()).map_err(db_to_tonic_err)?;
+
+            Ok(vec![])
+        }
+        EXPORT_TO_DISK => {
+            database
+                .export_to_disk(&ExportOptions::default())
+                .await
+                .map_err(db_to_tonic_err)?;
+
+            Ok(vec![])
+        }
+        CHECKPOINT => {
+            let manifest = database.checkpoint().await.map_err(db_to_tonic_err)?;
+            let manifest =
+                serde_json::to_string(&manifest).map_err(|e| Status::internal(e.to_string()))?;
+
+            Ok(vec![action_result(manifest)])
+        }
+        LIST_TABLES => {
+            let mut table_names = database
+                .tables
+                .iter()
+                .map(|table| table.key().to_owned())
+                .collect::<Vec<_>>();
+            table_names.sort();
+
+            Ok(table_names.into_iter().map(action_result).collect())
+        }
+        LIST_DATABASES => Ok(databases
+            .manager
+            .database_names()
+            .into_iter()
+            .map(action_result)
+            .collect()),
+        REFRESH_CONTEXT => {
+            database.add_all_table_contexts().map_err(db_to_tonic_err)?;
+
+            Ok(vec![])
+        }
+        action_type => Err(Status::invalid_argument(format!(
+            "Unknown action {action_type}"
+        ))),
+    }
+}
+
+/// Create a table with its constraints.  The table is dropped again if a
+/// constraint can't be declared.
+#[allow(clippy::result_large_err)]
+fn create(database: &Database, create_table: CreateTable) -> Result<(), Status> {
+    let fields = create_table
+        .columns
+        .iter()
+        .map(|column| {
+            DataType::from_str(&column.data_type)
+                .map(|data_type| Field::new(&column.name, data_type, true))
+                .map_err(|e| Status::invalid_argument(format!("Invalid column: {e}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let table_name = create_table.name.as_str();
+    database
+        .create_table(table_name, Schema::new(fields).into())
+        .map_err(db_to_tonic_err)?;
+
+    let constrained = database.get_mut_table(table_name).and_then(|mut table| {
+        if let Some(primary_key) = &create_table.primary_key {
+            table.set_primary_key(primary_key)?;
+        }
+
+        create_table
+            .columns
+            .iter()
+            .filter(|column| column.not_null)
+            .try_for_each(|column| table.set_not_null(&column.name))
+    });
+
+    if let Err(e) = constrained {
+        let _ = database.drop_table(table_name);
+        return Err(db_to_tonic_err(e));
+    }
+
+    Ok(())
+}
+
+fn action_result(body: String) -> arrow_flight::Result {
+    arrow_flight::Result { body: body.into() }
+}
@@ -0,0 +1,148 @@
+//! Command line arguments.
+//!
+//! Every flag can also be set with an `ARROW_DB_*` environment variable, and
+//! flags take precedence over environment variables.
+
+use std::{net::IpAddr, path::PathBuf};
+
+use clap::Parser;
+
+use crate::{
+    auth::{parse_user, DEFAULT_TOKEN_TTL},
+    get::DEFAULT_MAX_MESSAGE_SIZE,
+};
+
+/// An Arrow Flight server for Arrow DB
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// The address to listen on
+    #[arg(long, env = "ARROW_DB_ADDRESS", default_value = "0.0.0.0")]
+    pub address: IpAddr,
+
+    /// The port to listen on
+    #[arg(short, long, env = "ARROW_DB_PORT", default_value_t = 50051)]
+    pub port: u16,
+
+    /// The data directory holding the directories of the databases
+    #[arg(long, env = "ARROW_DB_DATA_PATH", default_value = "../data")]
+    pub data_path: String,
+
+    /// The names of the databases to serve, `,`-separated, which are the
+    /// names of their directories.  The first is the default database.
+    #[arg(
+        short,
+        long = "database",
+        env = "ARROW_DB_DATABASE",
+        value_delimiter = ',',
+        default_value = "MyDb"
+    )]
+    pub databases: Vec<String>,
+
+    /// Reject statements, puts, exchanges and actions that write
+    #[arg(long, env = "ARROW_DB_READ_ONLY")]
+    pub read_only: bool,
+
+    /// Export every database to its directory this often, in seconds, as
+    /// well as on shutdown
+    #[arg(long, env = "ARROW_DB_AUTOSAVE_INTERVAL", value_parser = clap::value_parser!(u64).range(1..))]
+    pub autosave_interval: Option<u64>,
+
+    /// The max size of a FlightData message streamed by `do_get`, in bytes
+    #[arg(long, env = "ARROW_DB_MAX_MESSAGE_SIZE", default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+    pub max_message_size: usize,
+
+    /// Static tokens accepted as bearer credentials, `,`-separated
+    #[arg(long, env = "ARROW_DB_TOKENS", value_delimiter = ',')]
+    pub tokens: Vec<String>,
+
+    /// Users accepted as basic credentials, as `,`-separated
+    /// `username:password` pairs
+    #[arg(long, env = "ARROW_DB_USERS", value_delimiter = ',', value_parser = parse_user)]
+    pub users: Vec<(String, String)>,
+
+    /// How long a token issued by the handshake is accepted for, in seconds
+    #[arg(long, env = "ARROW_DB_TOKEN_TTL", default_value_t = DEFAULT_TOKEN_TTL, value_parser = clap::value_parser!(u64).range(1..))]
+    pub token_ttl: u64,
+
+    /// The PEM certificate of the server, which enables TLS
+    #[arg(long, env = "ARROW_DB_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM private key of the server certificate
+    #[arg(long, env = "ARROW_DB_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// The PEM CA certificate that signs client certificates, which enables
+    /// mutual TLS
+    #[arg(long, env = "ARROW_DB_TLS_CLIENT_CA", requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use clap::error::ErrorKind;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(["arrow-db-server"].iter().chain(args))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let args = parse(&[]).unwrap();
+
+        assert_eq!(args.address.to_string(), "0.0.0.0");
+        assert_eq!(args.port, 50051);
+        assert_eq!(args.data_path, "../data");
+        assert_eq!(args.databases, vec!["MyDb"]);
+        assert!(!args.read_only);
+        assert_eq!(args.autosave_interval, None);
+        assert_eq!(args.max_message_size, DEFAULT_MAX_MESSAGE_SIZE);
+        assert_eq!(args.token_ttl, DEFAULT_TOKEN_TTL);
+        assert!(args.tokens.is_empty());
+        assert!(args.users.is_empty());
+        assert_eq!(args.tls_cert, None);
+    }
+
+    #[test]
+    fn test_lists() {
+        let args = parse(&[
+            "--database",
+            "sales,hr",
+            "--tokens",
+            "token1,token2",
+            "--users",
+            "alice:secret,bob:se:cret",
+        ])
+        .unwrap();
+
+        assert_eq!(args.databases, vec!["sales", "hr"]);
+        assert_eq!(args.tokens, vec!["token1", "token2"]);
+        assert_eq!(
+            args.users,
+            vec![
+                ("alice".into(), "secret".into()),
+                ("bob".into(), "se:cret".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        let kind = |args: &[&str]| parse(args).unwrap_err().kind();
+
+        assert_eq!(kind(&["--users", "alice"]), ErrorKind::ValueValidation);
+        assert_eq!(
+            kind(&["--autosave-interval", "0"]),
+            ErrorKind::ValueValidation
+        );
+        assert_eq!(kind(&["--token-ttl", "0"]), ErrorKind::ValueValidation);
+        assert_eq!(kind(&["--port", "port"]), ErrorKind::ValueValidation);
+        assert_eq!(
+            kind(&["--tls-cert", "server.pem"]),
+            ErrorKind::MissingRequiredArgument
+        );
+    }
+}
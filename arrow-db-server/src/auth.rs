@@ -0,0 +1,408 @@
+//! Authentication.
+//!
+//! Authentication is enabled by configuring static tokens, users with
+//! passwords, or both.  Every call then needs credentials in its
+//! `authorization` header, and calls without them are `Unauthenticated`:
+//!
+//! | Header                                | Description                                        |
+//! | ------------------------------------- | -------------------------------------------------- |
+//! | `Bearer <token>`                      | A static token, or a token issued by the handshake |
+//! | `Basic <base64 of username:password>` | A configured user                                  |
+//!
+//! Users authenticate once with `handshake`, whose response carries an
+//! `authorization: Bearer <token>` header, and send that token with later
+//! calls instead of their password.  Issued tokens expire after the token
+//! TTL, and at most `MAX_ISSUED_TOKENS` are accepted at once, so the oldest
+//! are dropped first.
+//!
+//! Tokens and passwords are compared in constant time, so the time a
+//! comparison takes doesn't reveal how much of a secret was guessed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tonic::{metadata::MetadataMap, Request, Status};
+
+/// Request and response header carrying credentials
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// The default time an issued token is accepted for, in seconds
+pub const DEFAULT_TOKEN_TTL: u64 = 60 * 60;
+
+/// The max number of issued tokens accepted at once
+pub const MAX_ISSUED_TOKENS: usize = 1024;
+
+/// The credentials a request was authenticated with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Token(String),
+    User(String),
+}
+
+/// The tokens and users allowed to call the server
+#[derive(Debug)]
+pub struct Auth {
+    /// Static tokens
+    tokens: HashSet<String>,
+    /// Passwords by username
+    users: HashMap<String, String>,
+    /// Tokens issued by the handshake, with the time they expire
+    issued: RwLock<Vec<(String, Instant)>>,
+    /// How long an issued token is accepted for
+    token_ttl: Duration,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self::new(vec![], vec![])
+    }
+}
+
+impl Auth {
+    pub fn new(
+        tokens: impl IntoIterator<Item = String>,
+        users: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        Self {
+            tokens: tokens.into_iter().collect(),
+            users: users.into_iter().collect(),
+            issued: RwLock::default(),
+            token_ttl: Duration::from_secs(DEFAULT_TOKEN_TTL),
+        }
+    }
+
+    /// Accept tokens issued by the handshake for `token_ttl`
+    pub fn with_token_ttl(mut self, token_ttl: Duration) -> Self {
+        self.token_ttl = token_ttl;
+        self
+    }
+
+    /// Whether calls need credentials
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.users.is_empty()
+    }
+
+    /// Check the credentials in the `authorization` header of a request.
+    ///
+    /// Returns `None` if authentication isn't enabled.
+    #[allow(clippy::result_large_err)]
+    pub fn authenticate(&self, metadata: &MetadataMap) -> Result<Option<Credentials>, Status> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let authorization = metadata
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("There were no credentials"))?;
+
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            if self.is_static(token) || self.is_issued(token) {
+                return Ok(Some(Credentials::Token(token.to_owned())));
+            }
+        } else if let Some(encoded) = authorization.strip_prefix("Basic ") {
+            if let Some((username, password)) = decode_basic(encoded) {
+                if let Some(expected) = self.users.get(&username) {
+                    if constant_time_eq(expected, &password) {
+                        return Ok(Some(Credentials::User(username)));
+                    }
+                }
+            }
+        }
+
+        Err(Status::unauthenticated("Invalid credentials"))
+    }
+
+    /// The token to send with later calls: the token a request was
+    /// authenticated with, or a new token for a user
+    #[allow(clippy::result_large_err)]
+    pub fn handshake_token(&self, metadata: &MetadataMap) -> Result<Option<String>, Status> {
+        let token = match self.authenticate(metadata)? {
+            Some(Credentials::Token(token)) => Some(token),
+            Some(Credentials::User(_)) => Some(self.issue_token()),
+            None => None,
+        };
+
+        Ok(token)
+    }
+
+    /// Issue a new token, which is accepted until it expires
+    fn issue_token(&self) -> String {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let now = Instant::now();
+        let mut issued = self.issued.write().unwrap_or_else(|e| e.into_inner());
+
+        // tokens are issued in order, so the oldest are first
+        issued.retain(|(_, expires)| *expires > now);
+
+        if issued.len() >= MAX_ISSUED_TOKENS {
+            let excess = issued.len() + 1 - MAX_ISSUED_TOKENS;
+            issued.drain(..excess);
+        }
+
+        issued.push((token.to_owned(), now + self.token_ttl));
+
+        token
+    }
+
+    fn is_static(&self, token: &str) -> bool {
+        // every token is compared, so the time taken doesn't depend on which
+        // token matched
+        self.tokens.iter().fold(false, |found, expected| {
+            constant_time_eq(expected, token) | found
+        })
+    }
+
+    fn is_issued(&self, token: &str) -> bool {
+        let now = Instant::now();
+
+        self.issued
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .fold(false, |found, (expected, expires)| {
+                (constant_time_eq(expected, token) && *expires > now) | found
+            })
+    }
+}
+
+/// A tonic interceptor that rejects calls without valid credentials
+#[allow(clippy::result_large_err)]
+pub fn interceptor(
+    auth: Arc<Auth>,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |request: Request<()>| {
+        auth.authenticate(request.metadata())?;
+        Ok(request)
+    }
+}
+
+/// Parse a user configured as a `username:password` pair
+pub fn parse_user(user: &str) -> Result<(String, String), String> {
+    user.split_once(':')
+        .map(|(username, password)| (username.to_owned(), password.to_owned()))
+        .ok_or_else(|| format!("Invalid user {user}, expected username:password"))
+}
+
+/// Decode the `username:password` of basic credentials
+fn decode_basic(encoded: &str) -> Option<(String, String)> {
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_owned(), password.to_owned()))
+}
+
+/// Compare two secrets in a time that only depends on their lengths
+fn constant_time_eq(expected: &str, actual: &str) -> bool {
+    let (expected, actual) = (expected.as_bytes(), actual.as_bytes());
+
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(actual)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow_db_client::{auth::Credentials as ClientCredentials, Client, ClientOptions};
+    use tonic::Code;
+
+    use super::*;
+    use crate::tests::{listen, service};
+
+    fn auth() -> Auth {
+        Auth::new(
+            vec!["token1".to_owned()],
+            vec![("alice".to_owned(), "secret".to_owned())],
+        )
+    }
+
+    fn metadata(authorization: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(AUTHORIZATION_HEADER, authorization.parse().unwrap());
+        metadata
+    }
+
+    fn basic(credentials: &str) -> MetadataMap {
+        metadata(&format!("Basic {}", STANDARD.encode(credentials)))
+    }
+
+    fn code(result: Result<Option<Credentials>, Status>) -> Code {
+        result.unwrap_err().code()
+    }
+
+    #[test]
+    fn test_authenticate_disabled() {
+        let auth = Auth::default();
+
+        assert!(!auth.is_enabled());
+        assert_eq!(auth.authenticate(&MetadataMap::new()).unwrap(), None);
+        assert_eq!(auth.authenticate(&metadata("Bearer nope")).unwrap(), None);
+        assert_eq!(auth.handshake_token(&MetadataMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_authenticate_bearer() {
+        let auth = auth();
+
+        assert_eq!(
+            auth.authenticate(&metadata("Bearer token1")).unwrap(),
+            Some(Credentials::Token("token1".into()))
+        );
+        assert_eq!(
+            code(auth.authenticate(&metadata("Bearer token2"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(auth.authenticate(&metadata("Bearer token"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(auth.authenticate(&metadata("token1"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(auth.authenticate(&MetadataMap::new())),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_authenticate_basic() {
+        let auth = auth();
+
+        assert_eq!(
+            auth.authenticate(&basic("alice:secret")).unwrap(),
+            Some(Credentials::User("alice".into()))
+        );
+        assert_eq!(
+            code(auth.authenticate(&basic("alice:wrong"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(auth.authenticate(&basic("bob:secret"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(auth.authenticate(&metadata("Basic not-base64!"))),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_decode_basic() {
+        assert_eq!(
+            decode_basic(&STANDARD.encode("alice:se:cret")),
+            Some(("alice".into(), "se:cret".into()))
+        );
+        assert_eq!(
+            decode_basic(&STANDARD.encode("alice:")),
+            Some(("alice".into(), "".into()))
+        );
+        assert_eq!(decode_basic(&STANDARD.encode("alice")), None);
+        assert_eq!(decode_basic(&STANDARD.encode([0xff, b':', 0xfe])), None);
+        assert_eq!(decode_basic("not-base64!"), None);
+    }
+
+    #[test]
+    fn test_handshake_token() {
+        let auth = auth();
+
+        // static tokens are sent back as they are
+        assert_eq!(
+            auth.handshake_token(&metadata("Bearer token1")).unwrap(),
+            Some("token1".into())
+        );
+
+        // users are issued a token to send instead of their password
+        let token = auth
+            .handshake_token(&basic("alice:secret"))
+            .unwrap()
+            .unwrap();
+        let bearer = metadata(&format!("Bearer {token}"));
+        assert_eq!(
+            auth.authenticate(&bearer).unwrap(),
+            Some(Credentials::Token(token.to_owned()))
+        );
+        assert_eq!(auth.handshake_token(&bearer).unwrap(), Some(token));
+
+        assert_eq!(
+            auth.handshake_token(&basic("alice:wrong"))
+                .unwrap_err()
+                .code(),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_issued_tokens_expire() {
+        let auth = auth().with_token_ttl(Duration::ZERO);
+        let token = auth
+            .handshake_token(&basic("alice:secret"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            code(auth.authenticate(&metadata(&format!("Bearer {token}")))),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_issued_tokens_are_capped() {
+        let auth = auth();
+        let first = auth.issue_token();
+
+        for _ in 0..MAX_ISSUED_TOKENS {
+            auth.issue_token();
+        }
+
+        assert_eq!(auth.issued.read().unwrap().len(), MAX_ISSUED_TOKENS);
+        assert!(!auth.is_issued(&first));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("secret", "secret"));
+        assert!(!constant_time_eq("secret", "secreT"));
+        assert!(!constant_time_eq("secret", "secrets"));
+        assert!(constant_time_eq("", ""));
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials() {
+        let auth = Arc::new(auth());
+        let url = listen(service(&["MyDb"]).with_auth(Arc::clone(&auth))).await;
+        let connect = |credentials| {
+            let options = ClientOptions {
+                credentials,
+                ..Default::default()
+            };
+            let url = url.to_owned();
+            async move { Client::new_with_options(url, &options).await }
+        };
+
+        assert!(connect(None).await.is_err());
+
+        let mut client = connect(Some(ClientCredentials::Bearer("token1".into())))
+            .await
+            .unwrap();
+        client.query("select * from users").await.unwrap();
+
+        // users are issued a token by the handshake, which later calls send
+        let mut client = connect(Some(ClientCredentials::Basic {
+            username: "alice".into(),
+            password: "secret".into(),
+        }))
+        .await
+        .unwrap();
+        client.query("select * from users").await.unwrap();
+        assert_eq!(auth.issued.read().unwrap().len(), 1);
+    }
+}
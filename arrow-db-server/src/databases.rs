@@ -0,0 +1,130 @@
+//! Serving several databases.
+//!
+//! The server hosts its databases in a `DatabaseManager`, and every request
+//! runs against one of them, so the tables of each database are isolated
+//! from the others.  A request picks its database with:
+//!
+//! - a path descriptor of a database and a table, e.g. `["sales", "orders"]`
+//! - the `x-arrow-db-database` header, e.g. for tickets and actions
+//!
+//! Requests that name neither run against the default database.
+
+use std::sync::Arc;
+
+use arrow_db_core::{manager::DatabaseManager, protocol::DATABASE_HEADER, Database};
+use arrow_flight::FlightDescriptor;
+use tonic::{metadata::MetadataMap, Status};
+
+use crate::{db_to_tonic_err, flights::descriptor_database_name};
+
+/// The databases of the server
+#[derive(Clone)]
+pub struct Databases {
+    pub manager: Arc<DatabaseManager>,
+    /// The database of requests that don't name one
+    pub default_database: String,
+}
+
+impl Databases {
+    /// Get a database by name
+    #[allow(clippy::result_large_err)]
+    pub fn database(&self, name: &str) -> Result<Arc<Database>, Status> {
+        self.manager.database(name).map_err(db_to_tonic_err)
+    }
+
+    /// The database named by the header of a request, or the default database
+    #[allow(clippy::result_large_err)]
+    pub fn request_database(&self, metadata: &MetadataMap) -> Result<Arc<Database>, Status> {
+        let name = match metadata.get(DATABASE_HEADER) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| Status::invalid_argument("Invalid database header"))?,
+            None => &self.default_database,
+        };
+
+        self.database(name)
+    }
+
+    /// The database named by a path descriptor, or else by the header of the
+    /// request
+    #[allow(clippy::result_large_err)]
+    pub fn descriptor_database(
+        &self,
+        metadata: &MetadataMap,
+        descriptor: Option<&FlightDescriptor>,
+    ) -> Result<Arc<Database>, Status> {
+        match descriptor.and_then(descriptor_database_name) {
+            Some(name) => self.database(name),
+            None => self.request_database(metadata),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::RecordBatch;
+    use arrow_flight::{error::FlightError, FlightClient, Ticket};
+    use futures::TryStreamExt;
+    use tonic::Code;
+
+    use super::*;
+    use crate::tests::{code, serve, service};
+
+    /// The number of rows of `users` in a database
+    async fn users(client: &mut FlightClient, database_name: &str) -> Result<usize, FlightError> {
+        client.add_header(DATABASE_HEADER, database_name).unwrap();
+        let batches: Vec<RecordBatch> = client
+            .do_get(Ticket::new("select * from users"))
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(batches.iter().map(RecordBatch::num_rows).sum())
+    }
+
+    #[tokio::test]
+    async fn test_database_header() {
+        let mut client = serve(service(&["MyDb", "Other"])).await;
+
+        client.add_header(DATABASE_HEADER, "Other").unwrap();
+        let _: Vec<RecordBatch> = client
+            .do_get(Ticket::new("insert into users values (1, 'Alice')"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(users(&mut client, "Other").await.unwrap(), 1);
+        assert_eq!(users(&mut client, "MyDb").await.unwrap(), 0);
+        assert_eq!(
+            code(users(&mut client, "Missing").await.unwrap_err()),
+            Code::NotFound
+        );
+    }
+
+    #[test]
+    fn test_descriptor_database() {
+        let databases = service(&["MyDb", "Other"]).databases;
+        let mut metadata = MetadataMap::new();
+        let name = |metadata: &MetadataMap, path: &[&str]| {
+            let descriptor =
+                FlightDescriptor::new_path(path.iter().map(ToString::to_string).collect());
+            databases
+                .descriptor_database(metadata, Some(&descriptor))
+                .map(|database| database.name.to_owned())
+                .map_err(|status| status.code())
+        };
+
+        assert_eq!(name(&metadata, &["users"]).unwrap(), "MyDb");
+        assert_eq!(name(&metadata, &["Other", "users"]).unwrap(), "Other");
+
+        metadata.insert(DATABASE_HEADER, "Other".parse().unwrap());
+        assert_eq!(name(&metadata, &["users"]).unwrap(), "Other");
+        assert_eq!(name(&metadata, &["MyDb", "users"]).unwrap(), "MyDb");
+        assert_eq!(
+            name(&metadata, &["Missing", "users"]).unwrap_err(),
+            Code::NotFound
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Streaming upserts with `do_exchange`.
+//!
+//! An exchange is a stream of FlightData messages whose first message carries
+//! a path descriptor naming a table with a primary key, e.g. `["users"]` or
+//! `["MyDb", "users"]`.
+//! Each record batch of the stream is upserted into the table as it arrives:
+//! rows whose key is already in the table update it, and the other rows are
+//! appended.
+//!
+//! Every batch is answered with a FlightData message without data, whose
+//! `app_metadata` is an `ExchangeResult` as JSON.  A batch that can't be
+//! upserted, e.g. because a row violates a constraint, writes nothing and is
+//! answered with its error, and the exchange carries on with the next batch,
+//! so a continuous ingestion pipeline isn't torn down by one bad batch.
+
+use std::sync::Arc;
+
+use arrow::array::RecordBatch;
+use arrow_db_core::Database;
+use arrow_flight::{decode::FlightRecordBatchStream, error::FlightError, FlightData};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use serde::Serialize;
+use tonic::{metadata::MetadataMap, Status, Streaming};
+
+use crate::{databases::Databases, db_to_tonic_err, flights::descriptor_table_name};
+
+/// The result of upserting a batch of an exchange
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExchangeResult {
+    /// The index of the batch in the exchange
+    pub batch: usize,
+    pub rows_inserted: usize,
+    pub rows_updated: usize,
+    /// Why nothing was written, if the batch couldn't be upserted
+    pub error: Option<String>,
+}
+
+/// Read the table from the first message of an exchange, and upsert the
+/// record batches of the exchange into the table, returning the database of
+/// the table
+#[allow(clippy::result_large_err)]
+pub async fn exchange(
+    databases: &Databases,
+    metadata: &MetadataMap,
+    mut flights: Streaming<FlightData>,
+) -> Result<
+    (
+        Arc<Database>,
+        BoxStream<'static, Result<FlightData, Status>>,
+    ),
+    Status,
+> {
+    let first = flights
+        .message()
+        .await?
+        .ok_or_else(|| Status::invalid_argument("There were no messages in the exchange"))?;
+    let descriptor = first.flight_descriptor.as_ref();
+    let database = databases.descriptor_database(metadata, descriptor)?;
+    let table_name = descriptor_table_name(descriptor)?;
+
+    database.get_table(&table_name).map_err(db_to_tonic_err)?;
+
+    let flights = stream::once(async { Ok(first) }).chain(flights);
+    let upserts = Arc::clone(&database);
+    let output = FlightRecordBatchStream::new_from_flight_data(flights.map_err(FlightError::Tonic))
+        .map_err(Status::from)
+        .enumerate()
+        .map(move |(index, batch)| {
+            batch.and_then(|batch| upsert_batch(&upserts, &table_name, index, &batch))
+        });
+
+    Ok((database, output.boxed()))
+}
+
+/// Upsert a record batch into a table, answering with its result
+#[allow(clippy::result_large_err)]
+fn upsert_batch(
+    database: &Database,
+    table_name: &str,
+    index: usize,
+    batch: &RecordBatch,
+) -> Result<FlightData, Status> {
+    let result = match database.upsert(table_name, batch) {
+        Ok(report) => ExchangeResult {
+            batch: index,
+            rows_inserted: report.rows_inserted,
+            rows_updated: report.rows_updated,
+            error: None,
+        },
+        Err(e) => ExchangeResult {
+            batch: index,
+            error: Some(e.to_string()),
+            ..Default::default()
+        },
+    };
+    let app_metadata = serde_json::to_vec(&result).map_err(|e| Status::internal(e.to_string()))?;
+
+    Ok(FlightData::new().with_app_metadata(app_metadata))
+}
@@ -0,0 +1,173 @@
+//! Describing the flights of the database.
+//!
+//! Every table is a flight: its `FlightInfo` has a path descriptor with the
+//! name of the table, the schema `SELECT *` returns, an estimate of its row
+//! count, and an endpoint whose ticket reads the whole table, so generic
+//! Flight tooling can browse the database.
+//!
+//! A command descriptor holds the SQL of a statement.  Its `FlightInfo` has
+//! the schema of the results of the statement, which is planned but not
+//! executed, and an endpoint whose ticket runs the statement with `do_get`.
+//!
+//! `get_schema` returns the schema of the `FlightInfo` of a descriptor,
+//! without describing the rest of the flight.
+
+use std::sync::Arc;
+
+use arrow_db_core::{error::DbError, system::is_system_column, table::Table, Database};
+use arrow_flight::{
+    flight_descriptor::DescriptorType, FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use arrow_schema::{Schema, SchemaRef};
+use tonic::Status;
+
+use crate::db_to_tonic_err;
+
+/// The name of the table a path descriptor addresses, which is the path of
+/// a table or of a database and a table
+#[allow(clippy::result_large_err)]
+pub fn descriptor_table_name(descriptor: Option<&FlightDescriptor>) -> Result<String, Status> {
+    let descriptor =
+        descriptor.ok_or_else(|| Status::invalid_argument("There was no flight descriptor"))?;
+
+    match (descriptor.r#type(), descriptor.path.as_slice()) {
+        (DescriptorType::Path, [table_name] | [_, table_name]) => Ok(table_name.to_owned()),
+        _ => Err(Status::invalid_argument(
+            "The flight descriptor must be a path with the name of a table",
+        )),
+    }
+}
+
+/// The name of the database a path descriptor addresses, if it has one
+pub fn descriptor_database_name(descriptor: &FlightDescriptor) -> Option<&str> {
+    match (descriptor.r#type(), descriptor.path.as_slice()) {
+        (DescriptorType::Path, [database_name, _]) => Some(database_name),
+        _ => None,
+    }
+}
+
+/// The flight of a descriptor, which is the path of a table or the SQL of a
+/// statement
+pub async fn flight_info(
+    database: &Database,
+    descriptor: FlightDescriptor,
+) -> Result<FlightInfo, Status> {
+    match descriptor.r#type() {
+        DescriptorType::Cmd => sql_flight_info(database, descriptor).await,
+        _ => {
+            let table_name = descriptor_table_name(Some(&descriptor))?;
+            let table = database
+                .tables
+                .get(&table_name)
+                .ok_or_else(|| db_to_tonic_err(DbError::TableNotFound(table_name)))?;
+
+            table_flight_info(&table)
+        }
+    }
+}
+
+/// The schema of the results of the flight of a descriptor
+pub async fn descriptor_schema(
+    database: &Database,
+    descriptor: &FlightDescriptor,
+) -> Result<SchemaRef, Status> {
+    match descriptor.r#type() {
+        DescriptorType::Cmd => sql_schema(database, &descriptor.cmd).await,
+        _ => {
+            let table_name = descriptor_table_name(Some(descriptor))?;
+            let table = database
+                .tables
+                .get(&table_name)
+                .ok_or_else(|| db_to_tonic_err(DbError::TableNotFound(table_name)))?;
+
+            Ok(Arc::new(table_schema(&table)))
+        }
+    }
+}
+
+/// The flights of every table, sorted by table name
+#[allow(clippy::result_large_err)]
+pub fn table_flights(database: &Database) -> Result<Vec<FlightInfo>, Status> {
+    let mut table_names = database
+        .tables
+        .iter()
+        .map(|table| table.key().to_owned())
+        .collect::<Vec<_>>();
+    table_names.sort();
+
+    table_names
+        .iter()
+        .filter_map(|table_name| {
+            // tables dropped while listing are skipped
+            let table = database.tables.get(table_name)?;
+            Some(table_flight_info(&table))
+        })
+        .collect()
+}
+
+/// The flight of a table, which reads the whole table
+#[allow(clippy::result_large_err)]
+pub fn table_flight_info(table: &Table) -> Result<FlightInfo, Status> {
+    // spilled tables keep their schema, so they're described without
+    // loading their rows
+    let num_rows = table.num_rows() + table.num_spilled_rows();
+    let descriptor = FlightDescriptor::new_path(vec![table.name.to_owned()]);
+    let ticket = Ticket::new(select_all_sql(&table.name));
+
+    FlightInfo::new()
+        .try_with_schema(&table_schema(table))
+        .map(|flight_info| {
+            flight_info
+                .with_descriptor(descriptor)
+                .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+                .with_total_records(num_rows as i64)
+        })
+        .map_err(|e| Status::internal(e.to_string()))
+}
+
+/// The flight of a SQL statement, which is planned to find the schema of its
+/// results
+pub async fn sql_flight_info(
+    database: &Database,
+    descriptor: FlightDescriptor,
+) -> Result<FlightInfo, Status> {
+    let schema = sql_schema(database, &descriptor.cmd).await?;
+    let ticket = Ticket::new(descriptor.cmd.to_owned());
+
+    FlightInfo::new()
+        .try_with_schema(&schema)
+        .map(|flight_info| {
+            flight_info
+                .with_descriptor(descriptor)
+                .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+        })
+        .map_err(|e| Status::internal(e.to_string()))
+}
+
+/// The schema `SELECT *` returns for a table, which hides system columns
+fn table_schema(table: &Table) -> Schema {
+    let schema = table.schema();
+    let fields = schema
+        .fields()
+        .iter()
+        .filter(|field| !is_system_column(field.name()))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Schema::new(fields)
+}
+
+/// The schema of the results of the SQL of a command, which is planned but
+/// not executed
+async fn sql_schema(database: &Database, cmd: &[u8]) -> Result<SchemaRef, Status> {
+    let sql = std::str::from_utf8(cmd)
+        .map_err(|e| Status::invalid_argument(format!("Invalid command: {e:?}")))?;
+    let prepared = database.prepare(sql).await.map_err(db_to_tonic_err)?;
+
+    Ok(prepared.schema())
+}
+
+/// The SQL that reads every row of a table, quoting its name
+pub fn select_all_sql(table_name: &str) -> String {
+    format!("SELECT * FROM \"{}\"", table_name.replace('"', "\"\""))
+}
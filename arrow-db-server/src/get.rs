@@ -0,0 +1,156 @@
+//! Streaming the results of a ticket with `do_get`.
+//!
+//! The statements of a ticket run in order, and the results of each are
+//! encoded as their record batches arrive, so the results of queries are never
+//! collected in memory.  The results of each statement start with a schema message whose
+//! `app_metadata` is the index of the statement in the ticket.
+//!
+//! Statements up to the last one that writes run before the response is
+//! sent, so the schema version of the response reflects any schema changes
+//! of the ticket.  Their results are collected, and the read-only statements
+//! after them stream as usual.
+//!
+//! Record batches are split into messages of at most the max message size,
+//! which keeps messages under the limits of gRPC clients.
+
+use std::sync::Arc;
+
+use arrow_db_core::Database;
+use arrow_flight::{encode::FlightDataEncoderBuilder, error::FlightError, FlightData};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use tonic::Status;
+
+use crate::{db_to_tonic_err, is_read_only, to_tonic_err};
+
+/// The default max size of a FlightData message, in bytes
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Run the statements of a ticket one after another, streaming their results
+pub async fn get(
+    database: Arc<Database>,
+    statements: Vec<String>,
+    max_message_size: usize,
+) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
+    let writes = statements
+        .iter()
+        .rposition(|statement| !is_read_only(statement))
+        .map_or(0, |index| index + 1);
+    let mut statements = statements.into_iter().enumerate();
+
+    let written: Vec<FlightData> = results(
+        Arc::clone(&database),
+        statements.by_ref().take(writes).collect(),
+        max_message_size,
+    )
+    .try_collect()
+    .await?;
+    let read = results(database, statements.collect(), max_message_size);
+
+    Ok(stream::iter(written.into_iter().map(Ok))
+        .chain(read)
+        .boxed())
+}
+
+/// Run indexed statements one after another, streaming their results
+fn results(
+    database: Arc<Database>,
+    statements: Vec<(usize, String)>,
+    max_message_size: usize,
+) -> BoxStream<'static, Result<FlightData, Status>> {
+    stream::iter(statements)
+        .then(move |(index, statement)| {
+            let database = Arc::clone(&database);
+            async move { statement_results(&database, index, &statement, max_message_size).await }
+        })
+        .try_flatten()
+        .boxed()
+}
+
+/// Run a statement, encoding its results as they're computed
+async fn statement_results(
+    database: &Database,
+    index: usize,
+    statement: &str,
+    max_message_size: usize,
+) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
+    // statements run through the database, so DML statements write to its
+    // tables
+    let results = database
+        .query(statement)
+        .await
+        .map_err(db_to_tonic_err)?
+        .execute_stream()
+        .await
+        .map_err(to_tonic_err)?;
+
+    // the schema message is sent even if there are no record batches
+    let flights = FlightDataEncoderBuilder::new()
+        .with_schema(results.schema())
+        .with_metadata(index.to_string().into())
+        .with_max_flight_data_size(max_message_size)
+        .build(results.map_err(|e| FlightError::ExternalError(Box::new(e))))
+        .map_err(Status::from)
+        .boxed();
+
+    Ok(flights)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow_db_core::protocol::SCHEMA_VERSION_HEADER;
+    use arrow_flight::{
+        decode::{DecodedFlightData, DecodedPayload, FlightRecordBatchStream},
+        Ticket,
+    };
+
+    use super::*;
+    use crate::tests::{serve, service};
+
+    fn schema_version(stream: &FlightRecordBatchStream) -> String {
+        stream
+            .headers()
+            .get(SCHEMA_VERSION_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_get_schema_version_after_ddl() {
+        let mut client = serve(service(&["MyDb"])).await;
+
+        let stream = client
+            .do_get(Ticket::new("select * from users"))
+            .await
+            .unwrap();
+        let version = schema_version(&stream);
+
+        let stream = client
+            .do_get(Ticket::new(
+                "create table orders (id int); select * from orders",
+            ))
+            .await
+            .unwrap();
+        let changed = schema_version(&stream);
+        assert_ne!(changed, version);
+
+        // the statements after the last one that writes still stream
+        let flights: Vec<DecodedFlightData> = stream.into_inner().try_collect().await.unwrap();
+        let indexes: Vec<_> = flights
+            .iter()
+            .filter(|flight| matches!(flight.payload, DecodedPayload::Schema(_)))
+            .map(|flight| flight.inner.app_metadata.clone())
+            .collect();
+        assert_eq!(indexes, vec!["0", "1"]);
+
+        let stream = client
+            .do_get(Ticket::new("select * from orders"))
+            .await
+            .unwrap();
+        assert_eq!(schema_version(&stream), changed);
+    }
+}
@@ -1,49 +1,167 @@
-use arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator};
-use arrow_schema::Schema;
+mod action;
+mod args;
+mod auth;
+mod databases;
+mod exchange;
+mod flights;
+mod get;
+mod put;
+mod shutdown;
+mod tls;
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use arrow_db_core::Database;
+use args::Args;
+use arrow_db_core::{
+    describe::describe_table_name, error::DbError, manager::DatabaseManager,
+    protocol::SCHEMA_VERSION_HEADER, Database,
+};
 use arrow_flight::{
     flight_service_server::FlightService, flight_service_server::FlightServiceServer, Action,
     ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo, HandshakeRequest,
     HandshakeResponse, PutResult, SchemaResult, Ticket,
 };
 use arrow_flight::{PollInfo, SchemaAsIpc};
+use auth::{Auth, AUTHORIZATION_HEADER};
+use clap::Parser;
+use databases::Databases;
 use datafusion::arrow::error::ArrowError;
-use datafusion::prelude::*;
+use datafusion::sql::parser::{DFParser, Statement as DFStatement};
+use datafusion::sql::sqlparser::ast::Statement as SqlStatement;
 use futures::stream::BoxStream;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status, Streaming};
 
+/// The newest protocol version the server speaks
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Features advertised to clients in the handshake.
+///
+/// Clients check for a capability before relying on it, so new features are
+/// added here as they land, and older clients simply ignore them.
+pub const CAPABILITIES: &[&str] = &[
+    // a do_get ticket may contain several `;`-separated statements
+    "multi_statement_tickets",
+    // responses carry the `x-arrow-db-schema-version` header
+    "schema_version",
+];
+
 #[derive(Clone)]
 pub struct FlightServiceImpl {
-    pub state: Arc<SessionContext>,
+    /// The databases the server hosts
+    pub databases: Databases,
+    /// The max size of a FlightData message streamed by `do_get`, in bytes
+    pub max_message_size: usize,
+    /// The tokens and users allowed to call the server
+    pub auth: Arc<Auth>,
+    /// Whether calls that write are rejected
+    pub read_only: bool,
 }
 
 impl FlightServiceImpl {
-    pub async fn new() -> Result<Self, Status> {
+    /// Serve databases from their directories in a data directory.  The first
+    /// database is the default database of requests that don't name one.
+    pub async fn new(data_path: &str, database_names: &[String]) -> Result<Self, Status> {
+        let default_database = database_names
+            .first()
+            .ok_or_else(|| Status::invalid_argument("There were no databases to serve"))?
+            .to_owned();
+        let manager = Arc::new(DatabaseManager::new());
+
+        for database_name in database_names {
+            manager
+                .open_database_in(data_path, database_name)
+                .await
+                .map_err(db_to_tonic_err)?;
+        }
+
         Ok(Self {
-            state: Arc::new(Self::new_context().await?),
+            databases: Databases {
+                manager,
+                default_database,
+            },
+            max_message_size: get::DEFAULT_MAX_MESSAGE_SIZE,
+            auth: Arc::new(Auth::default()),
+            read_only: false,
         })
     }
 
-    async fn new_context() -> Result<SessionContext, Status> {
-        let database: Database = Database::new_from_disk("MyDb").await.unwrap();
-        database.add_all_table_contexts().unwrap();
+    /// Split the record batches streamed by `do_get` into messages of at most
+    /// `max_message_size` bytes
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
 
-        Ok(database.ctx)
+    /// Require calls to authenticate as one of the tokens or users of `auth`
+    pub fn with_auth(mut self, auth: Arc<Auth>) -> Self {
+        self.auth = auth;
+        self
     }
 
-    pub async fn get_schema(&self) -> Result<Schema, Status> {
-        let schema: Schema = self
-            .state
-            .table("mytable")
-            .await
-            .map_err(to_tonic_err)?
-            .schema()
-            .into();
+    /// Reject statements, puts, exchanges and actions that write
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
 
-        Ok(schema)
+    /// Reject a call that writes if the server is read-only
+    #[allow(clippy::result_large_err)]
+    fn check_writable(&self) -> Result<(), Status> {
+        match self.read_only {
+            true => Err(Status::permission_denied("The database is read-only")),
+            false => Ok(()),
+        }
+    }
+
+    /// Fingerprint the names and schemas of every registered table.
+    ///
+    /// Clients use this version to invalidate cached schemas, so it must change
+    /// whenever a table is added, dropped or altered.
+    pub async fn schema_version(database: &Database) -> Result<u64, Status> {
+        let mut hasher = DefaultHasher::new();
+
+        for catalog_name in database.ctx.catalog_names() {
+            let Some(catalog) = database.ctx.catalog(&catalog_name) else {
+                continue;
+            };
+
+            for schema_name in catalog.schema_names() {
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+
+                let mut table_names = schema.table_names();
+                table_names.sort();
+
+                for table_name in table_names {
+                    if let Some(table) = schema.table(&table_name).await.map_err(to_tonic_err)? {
+                        table_name.hash(&mut hasher);
+                        table.schema().hash(&mut hasher);
+                    }
+                }
+            }
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// Attach the current schema version of a database to a response
+    async fn with_schema_version<T>(
+        database: &Database,
+        mut response: Response<T>,
+    ) -> Result<Response<T>, Status> {
+        let version = Self::schema_version(database).await?;
+        let value = version
+            .to_string()
+            .parse()
+            .map_err(|_| Status::internal("Invalid schema version"))?;
+        response.metadata_mut().insert(SCHEMA_VERSION_HEADER, value);
+
+        Ok(response)
     }
 }
 
@@ -57,117 +175,182 @@ impl FlightService for FlightServiceImpl {
     type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
     type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
 
+    /// Return the schema of a table path descriptor, or of the results of a
+    /// command descriptor holding the SQL of a statement
     async fn get_schema(
         &self,
         request: Request<FlightDescriptor>,
     ) -> Result<Response<SchemaResult>, Status> {
-        let request = request.into_inner();
-        println!("get_schema: {:?}", request);
+        let database = self
+            .databases
+            .descriptor_database(request.metadata(), Some(request.get_ref()))?;
+        let descriptor = request.into_inner();
+        println!("get_schema: {:?}", descriptor);
 
-        let schema = self.get_schema().await?;
+        let schema = flights::descriptor_schema(&database, &descriptor).await?;
         let options = arrow::ipc::writer::IpcWriteOptions::default();
         let schema_ipc = SchemaAsIpc::new(&schema, &options);
         let schema_result: SchemaResult = schema_ipc
             .try_into()
             .map_err(|e: ArrowError| Status::internal(e.to_string()))?;
 
-        Ok(Response::new(schema_result))
+        Self::with_schema_version(&database, Response::new(schema_result)).await
     }
 
     async fn do_get(
         &self,
         request: Request<Ticket>,
     ) -> Result<Response<Self::DoGetStream>, Status> {
+        let database = self.databases.request_database(request.metadata())?;
         let ticket = request.into_inner();
         println!("do_get: {:?}", ticket);
         match std::str::from_utf8(&ticket.ticket) {
             Ok(sql) => {
                 println!("do_get: {sql}");
 
-                let ctx = Arc::clone(&self.state);
-
-                // create the DataFrame
-                let df = ctx.sql(sql).await.map_err(to_tonic_err)?;
-
-                // execute the query
-                let schema = df.schema().clone().into();
-                let results = df.collect().await.map_err(to_tonic_err)?;
-                if results.is_empty() {
-                    return Err(Status::internal("There were no results from ticket"));
+                let statements = ticket_statements(sql);
+                if statements.is_empty() {
+                    return Err(Status::invalid_argument(
+                        "There were no statements in ticket",
+                    ));
                 }
 
-                // add an initial FlightData message that sends schema
-                let options = arrow::ipc::writer::IpcWriteOptions::default();
-                let schema_flight_data = SchemaAsIpc::new(&schema, &options);
-
-                let mut flights = vec![FlightData::from(schema_flight_data)];
-
-                let encoder = IpcDataGenerator::default();
-                let mut tracker = DictionaryTracker::new(false);
-
-                for batch in &results {
-                    let (flight_dictionaries, flight_batch) = encoder
-                        .encoded_batch(batch, &mut tracker, &options)
-                        .map_err(|e: ArrowError| Status::internal(e.to_string()))?;
-
-                    flights.extend(flight_dictionaries.into_iter().map(Into::into));
-                    flights.push(flight_batch.into());
+                if !statements.iter().all(|statement| is_read_only(statement)) {
+                    self.check_writable()?;
                 }
 
-                let output = futures::stream::iter(flights.into_iter().map(Ok));
-                Ok(Response::new(Box::pin(output) as Self::DoGetStream))
+                let output =
+                    get::get(Arc::clone(&database), statements, self.max_message_size).await?;
+                Self::with_schema_version(&database, Response::new(output)).await
             }
             Err(e) => Err(Status::invalid_argument(format!("Invalid ticket: {e:?}"))),
         }
     }
 
+    /// Negotiate the protocol version and advertise the server capabilities.
+    ///
+    /// The client sends the newest version it speaks, and the server replies
+    /// with the version both sides speak, along with its capabilities as a
+    /// comma-separated payload.
+    ///
+    /// With authentication enabled, the response carries the bearer token to
+    /// send with later calls.
     async fn handshake(
         &self,
-        _request: Request<Streaming<HandshakeRequest>>,
+        request: Request<Streaming<HandshakeRequest>>,
     ) -> Result<Response<Self::HandshakeStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        let token = self.auth.handshake_token(request.metadata())?;
+        let handshake = request.into_inner().message().await?;
+
+        let protocol_version = negotiate_version(handshake.map(|request| request.protocol_version));
+        let response = HandshakeResponse {
+            protocol_version,
+            payload: CAPABILITIES.join(",").into(),
+        };
+
+        let output = futures::stream::iter(vec![Ok(response)]);
+        let mut response = Response::new(Box::pin(output) as Self::HandshakeStream);
+
+        if let Some(token) = token {
+            let value = format!("Bearer {token}")
+                .parse()
+                .map_err(|_| Status::internal("Invalid token"))?;
+            response.metadata_mut().insert(AUTHORIZATION_HEADER, value);
+        }
+
+        Ok(response)
     }
 
+    /// List a flight for every table of the database of the request, whose
+    /// ticket reads the whole table.
+    ///
+    /// Criteria aren't supported, so every table is listed.
     async fn list_flights(
         &self,
-        _request: Request<Criteria>,
+        request: Request<Criteria>,
     ) -> Result<Response<Self::ListFlightsStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        let database = self.databases.request_database(request.metadata())?;
+        let flights = flights::table_flights(&database)?;
+
+        let output = futures::stream::iter(flights.into_iter().map(Ok));
+        Self::with_schema_version(
+            &database,
+            Response::new(Box::pin(output) as Self::ListFlightsStream),
+        )
+        .await
     }
 
+    /// Describe the flight of a table path descriptor, or of a command
+    /// descriptor holding the SQL of a statement, whose ticket is the SQL
     async fn get_flight_info(
         &self,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        let database = self
+            .databases
+            .descriptor_database(request.metadata(), Some(request.get_ref()))?;
+        let flight_info = flights::flight_info(&database, request.into_inner()).await?;
+
+        Self::with_schema_version(&database, Response::new(flight_info)).await
     }
 
+    /// Append the record batches of a stream to the table named by the path
+    /// descriptor of its first message, acknowledging each batch with the
+    /// number of rows appended
     async fn do_put(
         &self,
-        _request: Request<Streaming<FlightData>>,
+        request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoPutStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        self.check_writable()?;
+        let (metadata, _, flights) = request.into_parts();
+        let (database, output) = put::put(&self.databases, &metadata, flights).await?;
+
+        Self::with_schema_version(&database, Response::new(output)).await
     }
 
+    /// Run an administrative action, e.g. `create_table` or `checkpoint`
     async fn do_action(
         &self,
-        _request: Request<Action>,
+        request: Request<Action>,
     ) -> Result<Response<Self::DoActionStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        let database = self.databases.request_database(request.metadata())?;
+        let action = request.into_inner();
+        println!("do_action: {}", action.r#type);
+
+        if !action::is_read_only(&action.r#type) {
+            self.check_writable()?;
+        }
+
+        let results = action::do_action(&self.databases, &database, action).await?;
+
+        let output = futures::stream::iter(results.into_iter().map(Ok));
+        Self::with_schema_version(
+            &database,
+            Response::new(Box::pin(output) as Self::DoActionStream),
+        )
+        .await
     }
 
     async fn list_actions(
         &self,
         _request: Request<Empty>,
     ) -> Result<Response<Self::ListActionsStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        let output = futures::stream::iter(action::action_types().into_iter().map(Ok));
+        Ok(Response::new(Box::pin(output) as Self::ListActionsStream))
     }
 
+    /// Upsert the record batches of a stream into the table named by the path
+    /// descriptor of its first message, answering each batch with its row
+    /// counts or error
     async fn do_exchange(
         &self,
-        _request: Request<Streaming<FlightData>>,
+        request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoExchangeStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        self.check_writable()?;
+        let (metadata, _, flights) = request.into_parts();
+        let (database, output) = exchange::exchange(&self.databases, &metadata, flights).await?;
+
+        Self::with_schema_version(&database, Response::new(output)).await
     }
 
     async fn poll_flight_info(
@@ -178,19 +361,277 @@ impl FlightService for FlightServiceImpl {
     }
 }
 
-fn to_tonic_err(e: datafusion::error::DataFusionError) -> Status {
+/// The protocol version to speak with a client, which is the older of the
+/// client and server versions.  Clients that don't send a version get the
+/// server version.
+fn negotiate_version(client_version: Option<u64>) -> u64 {
+    match client_version {
+        Some(0) | None => PROTOCOL_VERSION,
+        Some(client_version) => client_version.min(PROTOCOL_VERSION),
+    }
+}
+
+/// Split the SQL of a ticket into its statements.
+///
+/// Statements only the database understands, like `REFRESH TABLE`, can't be
+/// split, so a ticket that doesn't parse is run as a single statement, which
+/// reports any syntax error.
+fn ticket_statements(sql: &str) -> Vec<String> {
+    match DFParser::parse_sql(sql) {
+        Ok(statements) => statements.iter().map(ToString::to_string).collect(),
+        Err(_) => vec![sql.to_owned()],
+    }
+}
+
+/// Whether a statement only reads, so a read-only server can run it.
+///
+/// `DESCRIBE` is read-only in every form the database runs, including
+/// `DESCRIBE TABLE name`, which doesn't parse.  Other statements that don't
+/// parse, like `REFRESH TABLE`, may write.
+fn is_read_only(statement: &str) -> bool {
+    fn read_only(statement: &DFStatement) -> bool {
+        match statement {
+            DFStatement::Statement(statement) => matches!(
+                statement.as_ref(),
+                SqlStatement::Query(_)
+                    | SqlStatement::ShowTables { .. }
+                    | SqlStatement::ShowColumns { .. }
+                    | SqlStatement::ShowVariable { .. }
+                    | SqlStatement::ExplainTable { .. }
+            ),
+            DFStatement::Explain(explain) => read_only(&explain.statement),
+            _ => false,
+        }
+    }
+
+    if describe_table_name(statement).is_some() {
+        return true;
+    }
+
+    DFParser::parse_sql(statement)
+        .map(|statements| statements.iter().all(read_only))
+        .unwrap_or(false)
+}
+
+pub(crate) fn to_tonic_err(e: datafusion::error::DataFusionError) -> Status {
     Status::internal(format!("{e:?}"))
 }
 
+/// Convert a database error to a status, keeping errors the client can fix
+/// apart from internal errors
+pub(crate) fn db_to_tonic_err(e: DbError) -> Status {
+    match e {
+        DbError::TableNotFound(_)
+        | DbError::ColumnNotFound(_, _)
+        | DbError::DatabaseNotFound(_)
+        | DbError::SchemaNotFound(_) => Status::not_found(e.to_string()),
+        DbError::TableAlreadyExists(_)
+        | DbError::ColumnAlreadyExists(_, _)
+        | DbError::DatabaseAlreadyExists(_)
+        | DbError::SchemaAlreadyExists(_) => Status::already_exists(e.to_string()),
+        DbError::ConstraintViolation(_) | DbError::DataType(_) | DbError::Query(_, _) => {
+            Status::invalid_argument(e.to_string())
+        }
+        DbError::WriteConflict(_, _, _) => Status::aborted(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "0.0.0.0:50051".parse()?;
-    let service = FlightServiceImpl::new().await?;
-    let svc = FlightServiceServer::new(service);
+    let args = Args::parse();
+    let addr = SocketAddr::new(args.address, args.port);
+    let auth = Arc::new(
+        Auth::new(
+            args.tokens.into_iter().filter(|token| !token.is_empty()),
+            args.users,
+        )
+        .with_token_ttl(Duration::from_secs(args.token_ttl)),
+    );
+
+    if !auth.is_enabled() {
+        println!("Authentication is disabled, set --tokens or --users to enable it");
+    }
+
+    let service = FlightServiceImpl::new(&args.data_path, &args.databases)
+        .await?
+        .with_max_message_size(args.max_message_size)
+        .with_auth(Arc::clone(&auth))
+        .with_read_only(args.read_only);
+    let manager = Arc::clone(&service.databases.manager);
+    let svc = FlightServiceServer::with_interceptor(service, auth::interceptor(auth));
+
+    let mut server = Server::builder();
+
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        let client_ca = args.tls_client_ca.as_deref();
+        let tls_config = tls::tls_config(cert, key, client_ca)?;
+
+        match client_ca {
+            Some(_) => println!("Mutual TLS is enabled"),
+            None => println!("TLS is enabled"),
+        }
+
+        server = server.tls_config(tls_config)?;
+    }
+
+    if args.read_only {
+        println!("The database is read-only");
+    }
+
+    // a read-only server never writes to its databases, so there's nothing
+    // to save
+    let autosave = match (args.autosave_interval, args.read_only) {
+        (Some(seconds), false) => {
+            println!("Saving databases every {seconds} seconds");
+            let period = Duration::from_secs(seconds);
+            Some(tokio::spawn(shutdown::autosave(
+                Arc::clone(&manager),
+                period,
+            )))
+        }
+        _ => None,
+    };
 
     println!("Listening on {addr:?}");
 
-    Server::builder().add_service(svc).serve(addr).await?;
+    // stop accepting connections on SIGINT or SIGTERM, and wait for in-flight
+    // calls to finish
+    server
+        .add_service(svc)
+        .serve_with_shutdown(addr, shutdown::shutdown_signal())
+        .await?;
+
+    if let Some(autosave) = autosave {
+        autosave.abort();
+        let _ = autosave.await;
+    }
+
+    if !args.read_only {
+        println!("Saving databases");
+        shutdown::save_databases(&manager).await?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+pub mod tests {
+    use arrow_flight::{error::FlightError, FlightClient};
+    use arrow_schema::{DataType, Field, Schema};
+    use tokio::net::TcpListener;
+    use tonic::{
+        transport::{server::TcpIncoming, Channel},
+        Code,
+    };
+
+    use super::*;
+
+    /// A service hosting in-memory databases, each with an empty `users`
+    /// table.  The first database is the default.
+    pub fn service(database_names: &[&str]) -> FlightServiceImpl {
+        let manager = Arc::new(DatabaseManager::new());
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+
+        for database_name in database_names {
+            manager
+                .create_database(database_name)
+                .unwrap()
+                .create_table("users", Arc::clone(&schema))
+                .unwrap();
+        }
+
+        FlightServiceImpl {
+            databases: Databases {
+                manager,
+                default_database: database_names[0].into(),
+            },
+            max_message_size: get::DEFAULT_MAX_MESSAGE_SIZE,
+            auth: Arc::new(Auth::default()),
+            read_only: false,
+        }
+    }
+
+    /// Serve a service on a free local port, with the authentication of the
+    /// service, returning its URL
+    pub async fn listen(service: FlightServiceImpl) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let interceptor = auth::interceptor(Arc::clone(&service.auth));
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(FlightServiceServer::with_interceptor(service, interceptor))
+                .serve_with_incoming(incoming),
+        );
+
+        format!("http://{addr}")
+    }
+
+    /// Serve a service on a free local port, returning a client of it
+    pub async fn serve(service: FlightServiceImpl) -> FlightClient {
+        let channel = Channel::from_shared(listen(service).await)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        FlightClient::new(channel)
+    }
+
+    /// The status code of a failed call
+    pub fn code(error: FlightError) -> Code {
+        match error {
+            FlightError::Tonic(status) => status.code(),
+            error => panic!("Expected a status, got {error:?}"),
+        }
+    }
+
+    #[test]
+    fn test_ticket_statements() {
+        assert_eq!(
+            ticket_statements("select 1; select 2"),
+            vec!["SELECT 1", "SELECT 2"]
+        );
+        assert_eq!(
+            ticket_statements("refresh table users"),
+            vec!["refresh table users"]
+        );
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        assert!(is_read_only("select * from users"));
+        assert!(is_read_only("explain select * from users"));
+        assert!(is_read_only("show tables"));
+        assert!(is_read_only("describe users"));
+        assert!(is_read_only("desc users"));
+        assert!(is_read_only("describe table users"));
+        assert!(!is_read_only("insert into users values (1, 'Alice')"));
+        assert!(!is_read_only("explain analyze delete from users"));
+        assert!(!is_read_only("refresh table users"));
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let mut client = serve(service(&["MyDb"]).with_read_only(true)).await;
+
+        let error = client
+            .do_get(Ticket::new("insert into users values (1, 'Alice')"))
+            .await
+            .unwrap_err();
+        assert_eq!(code(error), Code::PermissionDenied);
+
+        for ticket in [
+            "select * from users",
+            "describe users",
+            "describe table users",
+        ] {
+            client.do_get(Ticket::new(ticket)).await.unwrap();
+        }
+    }
+}
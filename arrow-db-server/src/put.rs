@@ -0,0 +1,157 @@
+//! Ingesting record batches with `do_put`.
+//!
+//! A put is a stream of FlightData messages whose first message carries a
+//! path descriptor naming the table to append to, e.g. `["users"]`, or its
+//! database and the table, e.g. `["MyDb", "users"]`.  Each
+//! record batch of the stream is appended to the table as it arrives, and
+//! acknowledged with a `PutResult` whose `app_metadata` is the number of rows
+//! appended.
+//!
+//! Batches are appended with `bulk_load`, so columns are matched by name and
+//! cast to the types of the table.  A batch that violates a constraint of the
+//! table ends the put with an error, and appends nothing, but the batches
+//! acknowledged before it stay appended.
+
+use std::sync::Arc;
+
+use arrow::array::RecordBatch;
+use arrow_db_core::{bulk::BulkLoadOptions, Database};
+use arrow_flight::{decode::FlightRecordBatchStream, error::FlightError, FlightData, PutResult};
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt, TryStreamExt,
+};
+use tonic::{metadata::MetadataMap, Status, Streaming};
+
+use crate::{databases::Databases, db_to_tonic_err, flights::descriptor_table_name};
+
+/// Read the table from the first message of a put, and append the record
+/// batches of the put to the table, returning the database of the table
+pub async fn put(
+    databases: &Databases,
+    metadata: &MetadataMap,
+    mut flights: Streaming<FlightData>,
+) -> Result<(Arc<Database>, BoxStream<'static, Result<PutResult, Status>>), Status> {
+    let first = flights
+        .message()
+        .await?
+        .ok_or_else(|| Status::invalid_argument("There were no messages in the put"))?;
+    let descriptor = first.flight_descriptor.as_ref();
+    let database = databases.descriptor_database(metadata, descriptor)?;
+    let table_name = descriptor_table_name(descriptor)?;
+
+    database.get_table(&table_name).map_err(db_to_tonic_err)?;
+
+    let flights = stream::once(async { Ok(first) }).chain(flights);
+
+    Ok((
+        Arc::clone(&database),
+        append_batches(database, table_name, flights),
+    ))
+}
+
+/// Append each record batch of a stream to a table, acknowledging it with the
+/// number of rows appended
+pub fn append_batches(
+    database: Arc<Database>,
+    table_name: String,
+    flights: impl Stream<Item = Result<FlightData, Status>> + Send + 'static,
+) -> BoxStream<'static, Result<PutResult, Status>> {
+    FlightRecordBatchStream::new_from_flight_data(flights.map_err(FlightError::Tonic))
+        .map_err(Status::from)
+        .and_then(move |batch| {
+            let result = append_batch(&database, &table_name, batch);
+            async move { result }
+        })
+        .boxed()
+}
+
+/// Append a record batch to a table
+#[allow(clippy::result_large_err)]
+fn append_batch(
+    database: &Database,
+    table_name: &str,
+    batch: RecordBatch,
+) -> Result<PutResult, Status> {
+    let report = database
+        .bulk_load(table_name, &[batch], BulkLoadOptions::default())
+        .map_err(db_to_tonic_err)?;
+
+    if !report.is_valid() {
+        return Err(Status::invalid_argument(format!(
+            "Cannot append to Table {table_name}: {}",
+            report.violations_message()
+        )));
+    }
+
+    Ok(PutResult {
+        app_metadata: report.rows_loaded.to_string().into(),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use arrow::array::{Int32Array, StringArray};
+    use arrow_flight::{encode::FlightDataEncoderBuilder, FlightDescriptor, Ticket};
+    use arrow_schema::{DataType, Field, Schema};
+    use tonic::Code;
+
+    use super::*;
+    use crate::tests::{code, serve, service};
+
+    fn users() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["Alice", "Bob"])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn flights(path: &[&str]) -> impl Stream<Item = Result<FlightData, FlightError>> {
+        FlightDataEncoderBuilder::new()
+            .with_flight_descriptor(Some(FlightDescriptor::new_path(
+                path.iter().map(ToString::to_string).collect(),
+            )))
+            .build(stream::iter([Ok(users())]))
+    }
+
+    #[tokio::test]
+    async fn test_put_then_get() {
+        let mut client = serve(service(&["MyDb"])).await;
+
+        let results: Vec<PutResult> = client
+            .do_put(flights(&["users"]))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].app_metadata, "2");
+
+        let batches: Vec<RecordBatch> = client
+            .do_get(Ticket::new("select * from users order by id"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(batches, vec![users()]);
+    }
+
+    #[tokio::test]
+    async fn test_put_unknown_table() {
+        let mut client = serve(service(&["MyDb"])).await;
+
+        let error = client.do_put(flights(&["orders"])).await.err().unwrap();
+        assert_eq!(code(error), Code::NotFound);
+    }
+}
@@ -0,0 +1,133 @@
+//! Graceful shutdown and periodic persistence.
+//!
+//! On SIGINT or SIGTERM the server stops accepting connections and waits for
+//! in-flight calls and streams to finish.  Every database is then exported to
+//! its directory before the process exits, so writes made through the server
+//! are loaded again on the next start.
+//!
+//! With an autosave interval, databases are also exported periodically, which
+//! bounds the writes lost if the process is killed without a chance to shut
+//! down.
+
+use std::{sync::Arc, time::Duration};
+
+use arrow_db_core::{error::DbError, export::ExportOptions, manager::DatabaseManager};
+use tokio::time::{interval, MissedTickBehavior};
+
+/// Wait for SIGINT (Ctrl-C) or, on unix, SIGTERM
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            println!("Error listening for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                println!("Error listening for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => println!("Received SIGINT, shutting down"),
+        _ = terminate => println!("Received SIGTERM, shutting down"),
+    }
+}
+
+/// Flush pending writes and export every database to its directory.
+///
+/// Every database is saved even if another fails, and the first error is
+/// returned.
+pub async fn save_databases(manager: &DatabaseManager) -> Result<(), DbError> {
+    let mut result = Ok(());
+
+    for name in manager.database_names() {
+        // the database may have been dropped since it was listed
+        let Ok(database) = manager.database(&name) else {
+            continue;
+        };
+
+        let saved = match database.flush_writes().await {
+            Ok(()) => database.export_to_disk(&ExportOptions::default()).await,
+            Err(e) => Err(e),
+        };
+
+        match saved {
+            Ok(()) => println!("Saved database {name}"),
+            Err(e) => {
+                println!("Error saving database {name}: {e}");
+                result = result.and(Err(e));
+            }
+        }
+    }
+
+    result
+}
+
+/// Save every database once per period, forever
+pub async fn autosave(manager: Arc<DatabaseManager>, period: Duration) {
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // the first tick completes immediately, when the databases were just
+    // loaded
+    ticks.tick().await;
+
+    loop {
+        ticks.tick().await;
+
+        // errors are reported, and the next tick tries again
+        let _ = save_databases(&manager).await;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs;
+
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_databases_error() {
+        let data_dir = std::env::temp_dir().join(format!("arrow-db-{}", uuid::Uuid::new_v4()));
+        let manager = DatabaseManager::new();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+
+        for name in ["Broken", "Saved"] {
+            fs::create_dir_all(data_dir.join(name)).unwrap();
+            manager
+                .open_database_in(data_dir.to_str().unwrap(), name)
+                .await
+                .unwrap()
+                .create_table("users", Arc::clone(&schema))
+                .unwrap();
+        }
+
+        // a file in place of its directory can't be exported to
+        fs::remove_dir(data_dir.join("Broken")).unwrap();
+        fs::write(data_dir.join("Broken"), "").unwrap();
+
+        assert!(save_databases(&manager).await.is_err());
+
+        // the other database is saved anyway
+        let saved = fs::read_dir(data_dir.join("Saved")).unwrap().count();
+        assert_eq!(saved, 1);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+//! TLS.
+//!
+//! The server speaks TLS when it's given a PEM certificate and private key.
+//! With a client CA certificate too, it requires mutual TLS: clients must
+//! present a certificate signed by the CA.
+
+use std::path::Path;
+
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// The TLS configuration of the server, from the paths of PEM files
+pub fn tls_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<ServerTlsConfig, String> {
+    let identity = Identity::from_pem(read_pem(cert)?, read_pem(key)?);
+    let mut config = ServerTlsConfig::new().identity(identity);
+
+    if let Some(client_ca) = client_ca {
+        config = config.client_ca_root(Certificate::from_pem(read_pem(client_ca)?));
+    }
+
+    Ok(config)
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))
+}
@@ -1,15 +1,23 @@
 mod utils;
 
+use arrow_db_core::metrics::QueryResult;
 use arrow_db_core::Database;
 use bytes::Bytes;
 use chrono::Utc;
-use serde_wasm_bindgen;
+use datafusion::prelude::DataFrame;
+use futures::StreamExt;
+use utils::row_to_serializable;
+use utils::scalar_to_js;
 use utils::set_panic_hook;
+use utils::to_scalar_value;
+use utils::to_scalar_values;
 use utils::to_serializable;
+use utils::to_serializable_change;
+use utils::SerializableMetrics;
+use utils::SerializableQueryResult;
 use utils::SerializableRecordBatch;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
-use wasm_bindgen_futures;
 
 #[wasm_bindgen]
 extern "C" {
@@ -24,7 +32,7 @@ extern "C" {
 
 #[wasm_bindgen]
 pub struct ArrowDbWasm {
-    database: Database<'static>,
+    database: Database,
 }
 
 #[wasm_bindgen]
@@ -33,8 +41,7 @@ impl ArrowDbWasm {
     pub fn new(name: String) -> ArrowDbWasm {
         set_panic_hook();
 
-        let name = Box::new(name.to_string());
-        let database = Database::new(Box::leak(name.clone())).unwrap();
+        let database = Database::new(name).unwrap();
 
         ArrowDbWasm { database }
     }
@@ -58,54 +65,266 @@ impl ArrowDbWasm {
         Ok(())
     }
 
+    /// Restore every table of an archive created by `export_archive`,
+    /// returning the names of the restored tables
     #[wasm_bindgen]
-    pub async fn query(&self, sql: String) -> Result<JsValue, JsValue> {
+    pub fn load_archive(&mut self, archive_bytes: Vec<u8>) -> Result<Vec<String>, JsValue> {
         set_panic_hook();
 
         let total = Utc::now();
-        let now = Utc::now();
 
-        let session_id = self.database.ctx.session_id();
-        log(&format!("Session ID: {:?}", session_id));
+        let table_names = self
+            .database
+            .load_archive(&archive_bytes)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        for table_name in table_names.iter() {
+            self.database
+                .add_table_context(table_name)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        }
+
+        let elapsed = Utc::now() - total;
+        log(&format!("Total Time in load_archive(): {:.2?}", elapsed));
+
+        Ok(table_names)
+    }
+
+    /// Export every table into a single archive
+    #[wasm_bindgen]
+    pub fn export_archive(&self) -> Result<Vec<u8>, JsValue> {
+        self.database
+            .export_archive()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Run a query, returning its results serialized as record batches.  The
+    /// metrics of the query are logged to the console.
+    #[wasm_bindgen]
+    pub async fn query(&self, sql: String) -> Result<JsValue, JsValue> {
+        set_panic_hook();
+
+        let result = self
+            .database
+            .query_with_metrics(&sql)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        log(&format!("Query metrics: {:?}", result.metrics));
 
-        let elapsed = Utc::now() - now;
-        let now = Utc::now();
-        log(&format!("Added Table Contexts in {:.2?}", elapsed));
+        serde_wasm_bindgen::to_value(&serialize_query_result(&result))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Run a query, returning `{ results, metrics }`, where `results` is
+    /// serialized like `query` and `metrics` has the times in milliseconds
+    /// and counts of the query, e.g. `metrics.execution_ms`
+    #[wasm_bindgen]
+    pub async fn query_with_metrics(&self, sql: String) -> Result<JsValue, JsValue> {
+        set_panic_hook();
+
+        let result = self
+            .database
+            .query_with_metrics(&sql)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        let query_result = SerializableQueryResult {
+            results: serialize_query_result(&result),
+            metrics: SerializableMetrics::from(&result.metrics),
+        };
+
+        serde_wasm_bindgen::to_value(&query_result).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Run a query with an array of values bound to its `?` or `$n`
+    /// placeholders, e.g. `query_params("select * from users where id = ?", [1])`.
+    ///
+    /// Values can be numbers, strings, booleans or null.
+    #[wasm_bindgen]
+    pub async fn query_params(&self, sql: String, params: JsValue) -> Result<JsValue, JsValue> {
+        set_panic_hook();
+
+        let total = Utc::now();
+        let params = to_scalar_values(params)?;
 
         let data_frame = self
             .database
-            .query(&sql)
+            .query_params(&sql, &params)
             .await
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-        let elapsed = Utc::now() - now;
-        let now = Utc::now();
-        log(&format!("Queried file {:.2?}", elapsed));
+        let results = serialize_data_frame(data_frame).await?;
+
+        let elapsed = Utc::now() - total;
+        log(&format!("Total Time in query_params(): {:.2?}", elapsed));
+
+        Ok(results)
+    }
+
+    /// Read the row of a table with a primary key without running a query,
+    /// serialized like query results, or `null` if there is no such row
+    #[wasm_bindgen]
+    pub fn get_row(&self, table_name: String, key: JsValue) -> Result<JsValue, JsValue> {
+        set_panic_hook();
+
+        let key = to_scalar_value(key)?;
+        let row = self
+            .database
+            .get_row(&table_name, &key)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        match row {
+            Some(row) => serde_wasm_bindgen::to_value(&row_to_serializable(&row)?)
+                .map_err(|e| JsValue::from_str(&e.to_string())),
+            None => Ok(JsValue::NULL),
+        }
+    }
 
-        let headers = data_frame.schema().clone().strip_qualifiers().field_names();
+    /// Read a window of rows of a table without running a query, serialized
+    /// like query results, e.g. to page through a table
+    #[wasm_bindgen]
+    pub fn read_rows(
+        &self,
+        table_name: String,
+        offset: usize,
+        length: usize,
+    ) -> Result<JsValue, JsValue> {
+        set_panic_hook();
 
-        let elapsed = Utc::now() - now;
-        let now = Utc::now();
-        log(&format!("Read in headers in {:.2?}", elapsed));
+        let record_batch = self
+            .database
+            .read_rows(&table_name, offset..offset + length)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let headers = record_batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>();
 
-        let record_batches = data_frame.collect().await.map_err(|e| e.to_string())?;
+        serde_wasm_bindgen::to_value(&to_serializable(&headers, &record_batch))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
 
-        let elapsed = Utc::now() - now;
-        let now = Utc::now();
-        log(&format!("Collected record batches in {:.2?}", elapsed));
+    /// Read a page of up to `page_size` rows of the results of a query,
+    /// ordered by a unique column, e.g. for infinite scrolling.
+    ///
+    /// Returns `{ rows, cursor }`, where `rows` is serialized like query
+    /// results, and `cursor` is passed as `after` to read the next page, or
+    /// is `null` after the last page.  Pass `null` to read the first page.
+    #[wasm_bindgen]
+    pub async fn query_cursor(
+        &self,
+        sql: String,
+        order_key: String,
+        after: JsValue,
+        page_size: usize,
+    ) -> Result<JsValue, JsValue> {
+        set_panic_hook();
 
-        let serializable_record_batches = record_batches
+        let after = match after.is_null() || after.is_undefined() {
+            true => None,
+            false => Some(to_scalar_value(after)?),
+        };
+        let page = self
+            .database
+            .query_cursor(&sql, &order_key, after, page_size)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let headers = page
+            .record_batch
+            .schema()
+            .fields()
             .iter()
-            .map(|batch| to_serializable(&headers, batch))
-            .collect::<Vec<SerializableRecordBatch>>();
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>();
 
-        let elapsed = Utc::now() - now;
-        log(&format!("Serialized record batches in {:.2?}", elapsed));
+        let result = js_sys::Object::new();
+        let rows = serde_wasm_bindgen::to_value(&to_serializable(&headers, &page.record_batch))
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let cursor = match page.cursor {
+            Some(cursor) => scalar_to_js(&cursor)?,
+            None => JsValue::NULL,
+        };
+        js_sys::Reflect::set(&result, &"rows".into(), &rows)?;
+        js_sys::Reflect::set(&result, &"cursor".into(), &cursor)?;
 
-        let elapsed = Utc::now() - total;
-        log(&format!("Total Time: {:.2?}", elapsed));
+        Ok(result.into())
+    }
+
+    /// Call `callback` with every change to a table, as `{ table, kind,
+    /// version, before, after }`, where `kind` is `insert`, `update` or
+    /// `delete`, and `before` and `after` are the changed rows, serialized like
+    /// query results.
+    #[wasm_bindgen]
+    pub fn subscribe(&self, table_name: String, callback: js_sys::Function) -> Result<(), JsValue> {
+        let mut changes = self
+            .database
+            .subscribe(&table_name)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(event) = changes.next().await {
+                let change = serde_wasm_bindgen::to_value(&to_serializable_change(&event));
 
-        Ok(serde_wasm_bindgen::to_value(&serializable_record_batches).unwrap())
+                match change {
+                    Ok(change) => {
+                        if let Err(e) = callback.call1(&JsValue::NULL, &change) {
+                            log(&format!("Error in subscriber of {table_name}: {e:?}"));
+                        }
+                    }
+                    Err(e) => log(&format!("Error serializing change: {e}")),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Call `callback` with the results of a query, serialized like `query`,
+    /// now and every time a table the query reads changes
+    #[wasm_bindgen]
+    pub async fn watch(&self, sql: String, callback: js_sys::Function) -> Result<(), JsValue> {
+        let mut results = self
+            .database
+            .watch(&sql)
+            .await
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(result) = results.next().await {
+                let rows = result.map_err(|e| e.to_string()).and_then(|record_batches| {
+                    let serializable_record_batches = record_batches
+                        .iter()
+                        .map(|batch| {
+                            let headers = batch
+                                .schema()
+                                .fields()
+                                .iter()
+                                .map(|field| field.name().to_owned())
+                                .collect::<Vec<_>>();
+
+                            to_serializable(&headers, batch)
+                        })
+                        .collect::<Vec<SerializableRecordBatch>>();
+
+                    serde_wasm_bindgen::to_value(&serializable_record_batches)
+                        .map_err(|e| e.to_string())
+                });
+
+                match rows {
+                    Ok(rows) => {
+                        if let Err(e) = callback.call1(&JsValue::NULL, &rows) {
+                            log(&format!("Error in watcher of {sql}: {e:?}"));
+                        }
+                    }
+                    Err(e) => log(&format!("Error watching {sql}: {e}")),
+                }
+            }
+        });
+
+        Ok(())
     }
 
     #[wasm_bindgen]
@@ -122,17 +341,54 @@ impl ArrowDbWasm {
         self.database
             .tables
             .iter()
-            .map(|k| {
-                format!(
-                    "{}: {}",
-                    k.key(),
-                    k.value().record_batch.schema().to_string()
-                )
-            })
+            .map(|k| format!("{}: {}", k.key(), k.value().schema()))
             .collect()
     }
 }
 
+/// Serialize the record batches of a query result
+fn serialize_query_result(result: &QueryResult) -> Vec<SerializableRecordBatch> {
+    let headers = result
+        .schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_owned())
+        .collect::<Vec<_>>();
+
+    result
+        .record_batches
+        .iter()
+        .map(|batch| to_serializable(&headers, batch))
+        .collect()
+}
+
+/// Collect the results of a query into serializable record batches
+async fn serialize_data_frame(data_frame: DataFrame) -> Result<JsValue, JsValue> {
+    let now = Utc::now();
+
+    let headers = data_frame.schema().clone().strip_qualifiers().field_names();
+
+    let elapsed = Utc::now() - now;
+    let now = Utc::now();
+    log(&format!("Read in headers in {:.2?}", elapsed));
+
+    let record_batches = data_frame.collect().await.map_err(|e| e.to_string())?;
+
+    let elapsed = Utc::now() - now;
+    let now = Utc::now();
+    log(&format!("Collected record batches in {:.2?}", elapsed));
+
+    let serializable_record_batches = record_batches
+        .iter()
+        .map(|batch| to_serializable(&headers, batch))
+        .collect::<Vec<SerializableRecordBatch>>();
+
+    let elapsed = Utc::now() - now;
+    log(&format!("Serialized record batches in {:.2?}", elapsed));
+
+    Ok(serde_wasm_bindgen::to_value(&serializable_record_batches).unwrap())
+}
+
 #[cfg(test)]
 mod tests {
     // use super::*;
@@ -6,8 +6,12 @@ use arrow::{
         Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, RecordBatch, StringArray,
     },
     datatypes::{DataType, Date32Type, Date64Type},
+    util::display::{ArrayFormatter, FormatOptions},
 };
+use arrow_db_core::{change::ChangeEvent, constraint::RowView, metrics::QueryMetrics};
+use datafusion::scalar::ScalarValue;
 use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
 
 use crate::log;
 
@@ -16,6 +20,57 @@ pub struct SerializableRecordBatch {
     data: Vec<Vec<Option<String>>>,
 }
 
+/// The metrics of a query, with times in milliseconds
+#[derive(Serialize)]
+pub struct SerializableMetrics {
+    planning_ms: f64,
+    execution_ms: f64,
+    rows_scanned: usize,
+    output_rows: usize,
+    batches: usize,
+    peak_memory: usize,
+}
+
+impl From<&QueryMetrics> for SerializableMetrics {
+    fn from(metrics: &QueryMetrics) -> Self {
+        SerializableMetrics {
+            planning_ms: metrics.planning_time.as_secs_f64() * 1000.0,
+            execution_ms: metrics.execution_time.as_secs_f64() * 1000.0,
+            rows_scanned: metrics.rows_scanned,
+            output_rows: metrics.output_rows,
+            batches: metrics.batches,
+            peak_memory: metrics.peak_memory,
+        }
+    }
+}
+
+/// The results of a query with its metrics
+#[derive(Serialize)]
+pub struct SerializableQueryResult {
+    pub results: Vec<SerializableRecordBatch>,
+    pub metrics: SerializableMetrics,
+}
+
+/// A change to the rows of a table, passed to JavaScript subscribers
+#[derive(Serialize)]
+pub struct SerializableChange {
+    table: String,
+    kind: String,
+    version: u64,
+    before: Option<SerializableRecordBatch>,
+    after: Option<SerializableRecordBatch>,
+}
+
+/// A query parameter passed from JavaScript
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Param {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -28,7 +83,7 @@ pub fn set_panic_hook() {
 }
 
 pub fn to_serializable(
-    headers: &Vec<String>,
+    headers: &[String],
     record_batch: &RecordBatch,
 ) -> SerializableRecordBatch {
     let headers = headers
@@ -58,6 +113,9 @@ pub fn to_serializable(
                     DataType::Boolean => arrow_to_string::<BooleanArray>(array, row),
                     DataType::Date32 => arrow_date_to_string::<Date32Array>(array, row),
                     DataType::Date64 => arrow_date_to_string::<Date64Array>(array, row),
+                    DataType::Timestamp(_, _) | DataType::Time32(_) | DataType::Time64(_) => {
+                        arrow_temporal_to_string(array, row)
+                    }
                     _ => {
                         unsupported.insert(array.data_type());
                         None
@@ -74,6 +132,29 @@ pub fn to_serializable(
     SerializableRecordBatch { data }
 }
 
+/// Convert a change event into its serializable form, with the changed rows
+/// serialized like query results
+pub fn to_serializable_change(event: &ChangeEvent) -> SerializableChange {
+    let rows = |batch: &RecordBatch| {
+        let headers = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>();
+
+        to_serializable(&headers, batch)
+    };
+
+    SerializableChange {
+        table: event.table_name.to_owned(),
+        kind: format!("{:?}", event.kind).to_lowercase(),
+        version: event.version,
+        before: event.before.as_ref().map(rows),
+        after: event.after.as_ref().map(rows),
+    }
+}
+
 /// Convert an Arrow native type to a string
 pub fn arrow_to_string<'a, T>(array: &'a ArrayRef, row: usize) -> Option<String>
 where
@@ -103,3 +184,81 @@ where
         _ => None,
     }
 }
+
+/// Convert a timestamp or time to an ISO-8601 string, e.g.
+/// `2024-01-01T12:30:00` or `12:30:00`.  Timestamps with a time zone include
+/// the offset.
+pub fn arrow_temporal_to_string(array: &ArrayRef, row: usize) -> Option<String> {
+    ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())
+        .ok()
+        .map(|formatter| formatter.value(row).to_string())
+}
+
+/// Convert an array of JavaScript values into query parameters.
+///
+/// Whole numbers become `Int64`s and other numbers `Float64`s, and `null`
+/// becomes an untyped NULL.
+pub fn to_scalar_values(params: JsValue) -> Result<Vec<ScalarValue>, JsValue> {
+    let params: Vec<Option<Param>> = serde_wasm_bindgen::from_value(params)
+        .map_err(|e| JsValue::from_str(&format!("Invalid query parameters: {e}")))?;
+
+    Ok(params.into_iter().map(param_to_scalar).collect())
+}
+
+/// Convert a JavaScript value into a scalar value, like a query parameter
+pub fn to_scalar_value(value: JsValue) -> Result<ScalarValue, JsValue> {
+    let param: Option<Param> = serde_wasm_bindgen::from_value(value)
+        .map_err(|e| JsValue::from_str(&format!("Invalid value: {e}")))?;
+
+    Ok(param_to_scalar(param))
+}
+
+/// Convert a scalar value into a JavaScript value that `to_scalar_value`
+/// converts back, e.g. a cursor.  Values without a JavaScript type become
+/// strings.
+pub fn scalar_to_js(value: &ScalarValue) -> Result<JsValue, JsValue> {
+    let param = match value {
+        value if value.is_null() => return Ok(JsValue::NULL),
+        ScalarValue::Boolean(Some(value)) => Param::Boolean(*value),
+        ScalarValue::Int8(Some(value)) => Param::Integer(i64::from(*value)),
+        ScalarValue::Int16(Some(value)) => Param::Integer(i64::from(*value)),
+        ScalarValue::Int32(Some(value)) => Param::Integer(i64::from(*value)),
+        ScalarValue::Int64(Some(value)) => Param::Integer(*value),
+        ScalarValue::Float32(Some(value)) => Param::Float(f64::from(*value)),
+        ScalarValue::Float64(Some(value)) => Param::Float(*value),
+        value => Param::String(value.to_string()),
+    };
+
+    serde_wasm_bindgen::to_value(&param).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn param_to_scalar(param: Option<Param>) -> ScalarValue {
+    match param {
+        Some(Param::Boolean(value)) => ScalarValue::Boolean(Some(value)),
+        Some(Param::Integer(value)) => ScalarValue::Int64(Some(value)),
+        Some(Param::Float(value)) => ScalarValue::Float64(Some(value)),
+        Some(Param::String(value)) => ScalarValue::Utf8(Some(value)),
+        None => ScalarValue::Null,
+    }
+}
+
+/// Convert a row read by its primary key into a serializable record batch
+/// of one row
+pub fn row_to_serializable(row: &RowView) -> Result<SerializableRecordBatch, JsValue> {
+    let columns = row
+        .values
+        .iter()
+        .map(|value| value.to_array())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let record_batch = RecordBatch::try_new(row.schema.to_owned(), columns)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let headers = row
+        .schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_owned())
+        .collect::<Vec<_>>();
+
+    Ok(to_serializable(&headers, &record_batch))
+}